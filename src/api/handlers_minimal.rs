@@ -1,61 +1,175 @@
 //! Minimal API Handlers - Working version without complex dependencies
 
-use crate::api::ApiResponse;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::api::auth_simple::{extract_api_key_from_headers, SimpleAuthManager};
+use crate::api::{ApiError, ApiResponse, ApiState};
+use crate::checker::{self, Checker};
 use crate::config::SharedConfig;
+use crate::dnsbl::{DnsblCheckResults, DnsblChecker, DnsblConfig};
+use crate::performance::PERFORMANCE_MONITOR;
+use crate::providers::{self, ProxyProvider};
+use crate::proxy::Proxy;
+use crate::server::connection_pool::{ConnectionPool, PoolGlobalStats, PoolStats};
+use crate::server::audit::AUDIT_LOG;
+use crate::server::latency::LATENCIES;
+use crate::server::proxy_pool::LIVE_PROXIES;
+#[cfg(feature = "sqlite")]
+use crate::store::StoredProxy;
 use axum::{
-    extract::{Path, State},
-    response::Json,
+    extract::{rejection::JsonRejection, Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        Json,
+    },
 };
+use futures_util::Stream;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
+use parking_lot::RwLock;
+use tokio::sync::{OnceCell, Semaphore};
+
+/// Status of the service or of one of its subsystems
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Healthy,
+    Degraded,
+    Unhealthy,
+    /// Turned off in the configuration, does not affect the overall status
+    Disabled,
+}
+
+/// Outcome of one subsystem check of the health endpoint
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthCheck {
+    pub status: HealthStatus,
+    pub message: String,
+    /// Failing makes the service unhealthy rather than degraded
+    pub critical: bool,
+}
+
+impl HealthCheck {
+    fn new(healthy: bool, critical: bool, message: impl Into<String>) -> Self {
+        Self {
+            status: if healthy { HealthStatus::Healthy } else { HealthStatus::Unhealthy },
+            message: message.into(),
+            critical,
+        }
+    }
+
+    fn disabled(message: impl Into<String>) -> Self {
+        Self {
+            status: HealthStatus::Disabled,
+            message: message.into(),
+            critical: false,
+        }
+    }
+}
+
+/// Overall status of the checks and the HTTP status reporting it: unhealthy (503) when
+/// a critical check fails, degraded when another one does, healthy otherwise
+pub fn overall_health(checks: &BTreeMap<&str, HealthCheck>) -> (HealthStatus, StatusCode) {
+    let failed = |critical: bool| {
+        checks
+            .values()
+            .any(|check| check.status == HealthStatus::Unhealthy && check.critical == critical)
+    };
+    if failed(true) {
+        (HealthStatus::Unhealthy, StatusCode::SERVICE_UNAVAILABLE)
+    } else if failed(false) {
+        (HealthStatus::Degraded, StatusCode::OK)
+    } else {
+        (HealthStatus::Healthy, StatusCode::OK)
+    }
+}
 
 /// Health check handler
-pub async fn health_check() -> Json<ApiResponse<serde_json::Value>> {
+pub async fn health_check(State(state): State<ApiState>) -> (StatusCode, Json<ApiResponse<serde_json::Value>>) {
     let uptime = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .unwrap_or_default()
         .as_secs();
 
+    let live_proxies = LIVE_PROXIES.len();
+    let working_judges = checker::working_judges().await;
+    let dnsbl_config = state.shared_config.read().dnsbl.clone();
+    let dnsbl = if dnsbl_config.enabled {
+        let probe = state
+            .dnsbl_probe
+            .get_or_try_init(|| {
+                DnsblChecker::with_config(DnsblConfig {
+                    timeout_secs: dnsbl_config.timeout_secs,
+                    ..DnsblConfig::default()
+                })
+            })
+            .await;
+        match probe {
+            Ok(probe) => match probe.test_connectivity().await {
+                Ok(true) => HealthCheck::new(true, false, "DNS lookups succeed"),
+                Ok(false) => HealthCheck::new(false, false, "DNS lookups fail, DNSBL checks are skipped"),
+                Err(e) => HealthCheck::new(false, false, format!("DNS connectivity test failed: {}", e)),
+            },
+            Err(e) => HealthCheck::new(false, false, format!("Failed to create DNSBL checker: {}", e)),
+        }
+    } else {
+        HealthCheck::disabled("DNSBL checks are disabled")
+    };
+
+    let checks = BTreeMap::from([
+        (
+            "proxy_pool",
+            HealthCheck::new(live_proxies > 0, true, format!("{} live proxies waiting", live_proxies)),
+        ),
+        (
+            "judges",
+            HealthCheck::new(working_judges > 0, true, format!("{} working judges", working_judges)),
+        ),
+        ("dnsbl", dnsbl),
+        ("config", HealthCheck::new(true, true, "Configuration is loaded")),
+    ]);
+    let (status, code) = overall_health(&checks);
+
     let health = json!({
-        "status": "healthy",
+        "status": status,
         "version": env!("CARGO_PKG_VERSION"),
         "uptime_seconds": uptime,
         "timestamp": chrono::Utc::now(),
-        "checks": {
-            "proxy_pool": {
-                "status": "healthy",
-                "message": "Proxy pool is operational",
-                "response_time_ms": 5
-            },
-            "config": {
-                "status": "healthy",
-                "message": "Configuration is loaded",
-                "response_time_ms": 2
-            }
-        }
+        "checks": checks,
     });
 
-    Json(ApiResponse::success(health))
+    (code, Json(ApiResponse::success(health)))
 }
 
 /// Get current configuration
 pub async fn get_config(
     State(shared_config): State<SharedConfig>,
-) -> Json<ApiResponse<serde_json::Value>> {
-    let config = shared_config.read();
-    Json(ApiResponse::success(serde_json::to_value(&*config).unwrap()))
+) -> Result<Json<ApiResponse<serde_json::Value>>, ApiError> {
+    let config = serde_json::to_value(&*shared_config.read())
+        .map_err(|e| ApiError::Internal(format!("Failed to serialize configuration: {}", e)))?;
+    Ok(Json(ApiResponse::success(config)))
 }
 
 /// Update configuration (simplified)
 pub async fn update_config(
     State(_shared_config): State<SharedConfig>,
-    Json(_request): Json<serde_json::Value>,
-) -> Json<ApiResponse<String>> {
+    request: Result<Json<serde_json::Value>, JsonRejection>,
+) -> Result<Json<ApiResponse<String>>, ApiError> {
+    let Json(request) = request?;
+    if !request.is_object() {
+        return Err(ApiError::BadRequest("Configuration update must be a JSON object".to_string()));
+    }
     // Simplified implementation
-    Json(ApiResponse::success("Configuration updated successfully".to_string()))
+    Ok(Json(ApiResponse::success("Configuration updated successfully".to_string())))
 }
 
 /// Get metrics
 pub async fn get_metrics() -> Json<ApiResponse<serde_json::Value>> {
+    let proxy_metrics = PERFORMANCE_MONITOR.get_metrics().await.proxy_metrics;
     let metrics = json!({
         "total_proxies": 1000,
         "working_proxies": 950,
@@ -69,14 +183,286 @@ pub async fn get_metrics() -> Json<ApiResponse<serde_json::Value>> {
         "memory_usage_mb": 45.0,
         "cpu_usage_percent": 12.5,
         "active_connections": 250,
+        "country_success_rates": proxy_metrics.country_success_rates,
+        "provider_breakers": crate::providers::breaker_states(),
         "last_updated": chrono::Utc::now(),
     });
 
     Json(ApiResponse::success(metrics))
 }
 
+/// State of the metrics reset endpoint
+#[derive(Clone)]
+pub struct MetricsResetState {
+    /// Keys accepted when authentication is enabled
+    auth: Option<Arc<SimpleAuthManager>>,
+}
+
+impl MetricsResetState {
+    pub fn new(auth: Option<Arc<SimpleAuthManager>>) -> Self {
+        Self { auth }
+    }
+}
+
+/// Zero the accumulated performance metrics, e.g. between two benchmark runs
+pub async fn reset_metrics(
+    State(state): State<MetricsResetState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<String>>, ApiError> {
+    if let Some(auth) = &state.auth {
+        match extract_api_key_from_headers(&headers) {
+            Some(key) if auth.validate_key(&key).await => auth.update_last_used(&key).await,
+            _ => return Err(ApiError::Unauthorized("A valid API key is required".to_string())),
+        }
+    }
+    PERFORMANCE_MONITOR.reset().await;
+    Ok(Json(ApiResponse::success("Metrics reset".to_string())))
+}
+
+/// State of the live metrics stream
+#[derive(Clone)]
+pub struct MetricsStreamState {
+    interval: Duration,
+}
+
+impl MetricsStreamState {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval: interval.max(Duration::from_secs(1)),
+        }
+    }
+}
+
+/// Stream the performance summary as server-sent events, one right away then one
+/// per interval. The stream lives in the response body, so it stops as soon as
+/// the client disconnects and the body is dropped.
+pub async fn metrics_stream(
+    State(state): State<MetricsStreamState>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let mut ticker = tokio::time::interval(state.interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+    let events = futures_util::stream::unfold(ticker, |mut ticker| async move {
+        ticker.tick().await;
+        let summary = PERFORMANCE_MONITOR.get_performance_summary().await;
+        let event = Event::default().event("metrics").json_data(&summary).unwrap_or_else(|e| {
+            log::warn!("Failed to serialize metrics event: {}", e);
+            Event::default().event("error").data(e.to_string())
+        });
+        Some((Ok(event), ticker))
+    });
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+/// Get proxy check statistics, with attempts, successes and success rate per protocol
+pub async fn get_stats() -> Json<ApiResponse<serde_json::Value>> {
+    let proxy_metrics = PERFORMANCE_MONITOR.get_metrics().await.proxy_metrics;
+    let stats = json!({
+        "total_checks": proxy_metrics.total_checks,
+        "successful_checks": proxy_metrics.successful_checks,
+        "failed_checks": proxy_metrics.failed_checks,
+        "avg_check_time_ms": proxy_metrics.avg_check_time_ms,
+        "protocols": proxy_metrics.protocol_stats(),
+    });
+
+    Json(ApiResponse::success(stats))
+}
+
+/// Get global connection pool statistics
+pub async fn get_pool_stats(
+    State(connection_pool): State<Arc<ConnectionPool>>,
+) -> Json<ApiResponse<PoolGlobalStats>> {
+    Json(ApiResponse::success(connection_pool.get_global_stats().await))
+}
+
+/// Get connection pool statistics for a single proxy (`host:port`)
+pub async fn get_proxy_pool_stats(
+    State(connection_pool): State<Arc<ConnectionPool>>,
+    Path(proxy): Path<String>,
+) -> Result<Json<ApiResponse<PoolStats>>, ApiError> {
+    match connection_pool.get_proxy_stats(&proxy).await {
+        Some(stats) => Ok(Json(ApiResponse::success(stats))),
+        None => Err(ApiError::NotFound(format!("No pooled connections for {}", proxy))),
+    }
+}
+
+/// State of the bulk DNSBL endpoint, the checker is created on first use with the
+/// timeout and DNS servers of the configuration
+#[derive(Clone)]
+pub struct DnsblBulkState {
+    checker: Arc<OnceCell<DnsblChecker>>,
+    shared_config: SharedConfig,
+    max_ips: usize,
+}
+
+impl DnsblBulkState {
+    pub fn new(max_ips: usize, shared_config: SharedConfig) -> Self {
+        Self {
+            checker: Arc::new(OnceCell::new()),
+            shared_config,
+            max_ips,
+        }
+    }
+}
+
+/// Bulk DNSBL check request
+#[derive(Debug, Deserialize)]
+pub struct DnsblBulkRequest {
+    pub ips: Vec<String>,
+}
+
+/// Bulk DNSBL check outcome for one IP, either a result or an error
+#[derive(Debug, Serialize)]
+pub struct DnsblBulkEntry {
+    pub ip: String,
+    pub result: Option<DnsblCheckResults>,
+    pub error: Option<String>,
+}
+
+/// Check a batch of IPs against DNSBL lists
+pub async fn dnsbl_bulk_check(
+    State(state): State<DnsblBulkState>,
+    Json(request): Json<DnsblBulkRequest>,
+) -> Result<Json<ApiResponse<Vec<DnsblBulkEntry>>>, ApiError> {
+    if request.ips.len() > state.max_ips {
+        return Err(ApiError::PayloadTooLarge(format!(
+            "Batch of {} IPs exceeds the maximum of {}",
+            request.ips.len(),
+            state.max_ips
+        )));
+    }
+
+    let valid_ips: Vec<String> = request
+        .ips
+        .iter()
+        .filter(|ip| DnsblChecker::validate_ip_format(ip))
+        .cloned()
+        .collect();
+
+    let mut results = Vec::new();
+    if !valid_ips.is_empty() {
+        let checker = state
+            .checker
+            .get_or_try_init(|| {
+                let dnsbl_config = state.shared_config.read().dnsbl.clone();
+                DnsblChecker::with_config(DnsblConfig {
+                    timeout_secs: dnsbl_config.timeout_secs,
+                    dns_servers: dnsbl_config.dns_servers,
+                    ..DnsblConfig::default()
+                })
+            })
+            .await;
+        match checker {
+            Ok(checker) => results = checker.clone().check_ips(&valid_ips).await,
+            Err(e) => {
+                log::error!("Failed to initialize DNSBL checker: {}", e);
+                return Err(ApiError::ServiceUnavailable("DNSBL checker unavailable".to_string()));
+            }
+        }
+    }
+
+    let entries = request
+        .ips
+        .into_iter()
+        .map(|ip| {
+            if !DnsblChecker::validate_ip_format(&ip) {
+                return DnsblBulkEntry { ip, result: None, error: Some("Invalid IPv4 address".to_string()) };
+            }
+            match results.iter().position(|r| r.ip == ip) {
+                Some(index) => DnsblBulkEntry { ip, result: Some(results[index].clone()), error: None },
+                None => DnsblBulkEntry { ip, result: None, error: Some("DNSBL check failed".to_string()) },
+            }
+        })
+        .collect();
+
+    Ok(Json(ApiResponse::success(entries)))
+}
+
+/// Providers fetched at once by an on-demand refresh, as in the refresh cycle
+const PROVIDER_REFRESH_CONNECTIONS: usize = 3;
+
+/// State of the provider refresh endpoint
+#[derive(Clone)]
+pub struct ProviderRefreshState {
+    shared_config: SharedConfig,
+    providers: Option<Vec<Arc<dyn ProxyProvider>>>,
+    /// Keys accepted when authentication is enabled
+    auth: Option<Arc<SimpleAuthManager>>,
+}
+
+impl ProviderRefreshState {
+    pub fn new(state: &ApiState, auth: Option<Arc<SimpleAuthManager>>) -> Self {
+        Self {
+            shared_config: state.shared_config.clone(),
+            providers: state.refresh_providers.clone(),
+            auth,
+        }
+    }
+}
+
+/// What each provider yielded since startup, to spot the sources worth dropping
+pub async fn get_provider_stats() -> Json<ApiResponse<serde_json::Value>> {
+    let stats: serde_json::Map<String, serde_json::Value> = providers::provider_stats()
+        .into_iter()
+        .map(|(name, stats)| {
+            let mut entry = json!(stats);
+            entry["dead_rate"] = json!(stats.dead_rate());
+            (name, entry)
+        })
+        .collect();
+    Json(ApiResponse::success(serde_json::Value::Object(stats)))
+}
+
+/// Poll the providers now instead of waiting for the next cycle. Refreshes asked
+/// for while one runs share its result.
+pub async fn refresh_providers(
+    State(state): State<ProviderRefreshState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<serde_json::Value>>, ApiError> {
+    if let Some(auth) = &state.auth {
+        match extract_api_key_from_headers(&headers) {
+            Some(key) if auth.validate_key(&key).await => auth.update_last_used(&key).await,
+            _ => return Err(ApiError::Unauthorized("A valid API key is required".to_string())),
+        }
+    }
+
+    let (policy, watermarks, breaker, fetch_retry) = {
+        let general = &state.shared_config.read().general;
+        (
+            providers::RefreshPolicy::new(
+                Duration::from_secs(general.provider_refresh_secs.max(1)),
+                general.provider_refresh_jitter_percent,
+            ),
+            providers::QueueWatermarks::new(general.queue_high_watermark, general.queue_low_watermark),
+            providers::BreakerPolicy::new(
+                general.provider_failure_threshold,
+                Duration::from_secs(general.provider_cooldown_secs),
+            ),
+            providers::FetchRetryPolicy::new(
+                general.provider_fetch_retries,
+                Duration::from_millis(general.provider_fetch_backoff_ms),
+                Duration::from_secs(general.provider_fetch_timeout_secs),
+            ),
+        )
+    };
+    providers::set_fetch_retry(fetch_retry);
+    let enqueued = match state.providers {
+        Some(list) => providers::refresh_providers(list, PROVIDER_REFRESH_CONNECTIONS, watermarks, breaker).await,
+        None => providers::refresh_all_providers(PROVIDER_REFRESH_CONNECTIONS, policy, watermarks, breaker).await,
+    };
+    Ok(Json(ApiResponse::success(json!({ "enqueued": enqueued }))))
+}
+
 /// List proxies (simplified)
-pub async fn list_proxies() -> Json<ApiResponse<serde_json::Value>> {
+#[cfg_attr(not(feature = "sqlite"), allow(unused_variables))]
+pub async fn list_proxies(State(state): State<ApiState>) -> Result<Json<ApiResponse<serde_json::Value>>, ApiError> {
+    #[cfg(feature = "sqlite")]
+    if let Some(store) = &state.store {
+        let proxies = store.list().await.map_err(|e| ApiError::Internal(e.to_string()))?;
+        let proxies = proxies.iter().map(stored_proxy_json).collect();
+        return Ok(Json(ApiResponse::success(serde_json::Value::Array(proxies))));
+    }
+
     let proxies = json!([
         {
             "id": "proxy-1",
@@ -98,11 +484,29 @@ pub async fn list_proxies() -> Json<ApiResponse<serde_json::Value>> {
         }
     ]);
 
-    Json(ApiResponse::success(proxies))
+    Ok(Json(ApiResponse::success(proxies)))
 }
 
-/// Get proxy by ID
-pub async fn get_proxy(Path(proxy_id): Path<String>) -> Json<ApiResponse<serde_json::Value>> {
+/// Get proxy by ID, `host:port` for stored proxies
+#[cfg_attr(not(feature = "sqlite"), allow(unused_variables))]
+pub async fn get_proxy(
+    State(state): State<ApiState>,
+    Path(proxy_id): Path<String>,
+) -> Result<Json<ApiResponse<serde_json::Value>>, ApiError> {
+    #[cfg(feature = "sqlite")]
+    if let Some(store) = &state.store {
+        let not_found = || ApiError::NotFound(format!("Proxy {} not found", proxy_id));
+        let (host, port) = proxy_id.rsplit_once(':').ok_or_else(not_found)?;
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let port = port.parse().map_err(|_| not_found())?;
+        let proxy = store
+            .get(host, port)
+            .await
+            .map_err(|e| ApiError::Internal(e.to_string()))?
+            .ok_or_else(not_found)?;
+        return Ok(Json(ApiResponse::success(stored_proxy_json(&proxy))));
+    }
+
     if proxy_id == "proxy-1" {
         let proxy = json!({
             "id": "proxy-1",
@@ -114,24 +518,245 @@ pub async fn get_proxy(Path(proxy_id): Path<String>) -> Json<ApiResponse<serde_j
             "response_time_ms": 150,
             "created_at": chrono::Utc::now()
         });
-        Json(ApiResponse::success(proxy))
+        Ok(Json(ApiResponse::success(proxy)))
     } else {
-        Json(ApiResponse::error("Proxy not found"))
+        Err(ApiError::NotFound(format!("Proxy {} not found", proxy_id)))
     }
 }
 
+/// Min, average, 95th percentile and max of the recent latencies of the `host:port` proxy
+pub async fn get_proxy_latency(Path(proxy_id): Path<String>) -> Result<Json<ApiResponse<serde_json::Value>>, ApiError> {
+    let not_found = || ApiError::NotFound(format!("No latency recorded for proxy {}", proxy_id));
+    let (host, port) = proxy_id.rsplit_once(':').ok_or_else(not_found)?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let port = port.parse().map_err(|_| not_found())?;
+    let stats = LATENCIES.stats(host, port).ok_or_else(not_found)?;
+    Ok(Json(ApiResponse::success(json!(stats))))
+}
+
+/// Entries returned by `GET /audit` without a `limit`
+const DEFAULT_AUDIT_LIMIT: usize = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct AuditQuery {
+    pub limit: Option<usize>,
+}
+
+/// Most recent requests served by the proxy server, newest first
+pub async fn get_audit(Query(query): Query<AuditQuery>) -> Json<ApiResponse<serde_json::Value>> {
+    let limit = query.limit.unwrap_or(DEFAULT_AUDIT_LIMIT);
+    Json(ApiResponse::success(json!(AUDIT_LOG.recent(limit))))
+}
+
+#[cfg(feature = "sqlite")]
+fn stored_proxy_json(proxy: &StoredProxy) -> serde_json::Value {
+    let mut value = json!(proxy);
+    value["id"] = json!(proxy.id());
+    value
+}
+
+/// Proxy creation request
+#[derive(Debug, Deserialize)]
+pub struct CreateProxyRequest {
+    pub host: String,
+    pub port: u16,
+    pub protocols: Option<Vec<String>>,
+}
+
+/// Proxy update request, absent fields are left unchanged
+#[derive(Debug, Deserialize)]
+pub struct UpdateProxyRequest {
+    pub protocols: Option<Vec<String>>,
+}
+
 /// Create new proxy
 pub async fn create_proxy(
-    Json(_request): Json<serde_json::Value>,
-) -> Json<ApiResponse<serde_json::Value>> {
+    request: Result<Json<CreateProxyRequest>, JsonRejection>,
+) -> Result<Json<ApiResponse<serde_json::Value>>, ApiError> {
+    let Json(request) = request?;
+    if request.host.trim().is_empty() {
+        return Err(ApiError::BadRequest("Proxy host must not be empty".to_string()));
+    }
+    if request.port == 0 {
+        return Err(ApiError::BadRequest("Proxy port must be between 1 and 65535".to_string()));
+    }
+
     let proxy = json!({
         "id": "proxy-new",
-        "host": "192.168.1.102",
-        "port": 8080,
-        "protocols": ["HTTP"],
+        "host": request.host,
+        "port": request.port,
+        "protocols": request.protocols.unwrap_or_else(|| vec!["HTTP".to_string()]),
         "country": "Unknown",
         "is_working": false,
         "created_at": chrono::Utc::now()
     });
-    Json(ApiResponse::success(proxy))
-}
\ No newline at end of file
+    Ok(Json(ApiResponse::success(proxy)))
+}
+
+/// Update proxy
+pub async fn update_proxy(
+    state: State<ApiState>,
+    Path(proxy_id): Path<String>,
+    request: Result<Json<UpdateProxyRequest>, JsonRejection>,
+) -> Result<Json<ApiResponse<serde_json::Value>>, ApiError> {
+    let Json(request) = request?;
+    let Json(response) = get_proxy(state, Path(proxy_id)).await?;
+    let mut proxy = response.data.unwrap_or_default();
+    if let Some(protocols) = request.protocols {
+        proxy["protocols"] = json!(protocols);
+    }
+    Ok(Json(ApiResponse::success(proxy)))
+}
+
+/// Validations running at once, the others wait for a slot
+const MAX_CONCURRENT_VALIDATIONS: usize = 16;
+/// Proxies a single `POST /proxies/validate` may submit
+pub const MAX_VALIDATION_BATCH: usize = 100;
+/// Tasks are forgotten this long after they finished, or were created when still pending
+const VALIDATION_TASK_TTL: chrono::Duration = chrono::Duration::hours(1);
+
+/// Outcome of a validation task
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum ValidationStatus {
+    Pending,
+    Working { protocols: Vec<String>, avg_response_time: f64 },
+    Failed { reason: String },
+}
+
+/// Validation of one proxy submitted to `POST /proxies/validate`
+#[derive(Debug, Clone, Serialize)]
+pub struct ValidationTask {
+    pub id: String,
+    pub proxy: String,
+    #[serde(flatten)]
+    pub status: ValidationStatus,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub finished_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Validation tasks by id, the checker is created on first use
+#[derive(Clone)]
+pub struct ValidationTasks {
+    checker: Arc<OnceCell<Checker>>,
+    tasks: Arc<RwLock<HashMap<String, ValidationTask>>>,
+    slots: Arc<Semaphore>,
+}
+
+impl Default for ValidationTasks {
+    fn default() -> Self {
+        Self {
+            checker: Arc::new(OnceCell::new()),
+            tasks: Arc::new(RwLock::new(HashMap::new())),
+            slots: Arc::new(Semaphore::new(MAX_CONCURRENT_VALIDATIONS)),
+        }
+    }
+}
+
+impl ValidationTasks {
+    /// Validate with `checker` instead of one built on first use
+    pub fn with_checker(checker: Checker) -> Self {
+        Self {
+            checker: Arc::new(OnceCell::new_with(Some(checker))),
+            ..Self::default()
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<ValidationTask> {
+        self.expire(chrono::Utc::now());
+        self.tasks.read().get(id).cloned()
+    }
+
+    /// Forget the tasks older than `VALIDATION_TASK_TTL`
+    fn expire(&self, now: chrono::DateTime<chrono::Utc>) {
+        self.tasks
+            .write()
+            .retain(|_, task| now - task.finished_at.unwrap_or(task.created_at) < VALIDATION_TASK_TTL);
+    }
+
+    /// Register a pending task for `proxy` and check it in the background
+    fn submit(&self, proxy: String, host: String, port: u16) -> ValidationTask {
+        let now = chrono::Utc::now();
+        let task = ValidationTask {
+            id: uuid::Uuid::new_v4().to_string(),
+            proxy,
+            status: ValidationStatus::Pending,
+            created_at: now,
+            finished_at: None,
+        };
+        self.expire(now);
+        self.tasks.write().insert(task.id.clone(), task.clone());
+
+        let tasks = self.clone();
+        let id = task.id.clone();
+        tokio::spawn(async move {
+            let _slot = tasks.slots.acquire().await;
+            let status = tasks.validate(&host, port).await;
+            if let Some(task) = tasks.tasks.write().get_mut(&id) {
+                task.status = status;
+                task.finished_at = Some(chrono::Utc::now());
+            }
+        });
+        task
+    }
+
+    async fn validate(&self, host: &str, port: u16) -> ValidationStatus {
+        let Some(mut proxy) = Proxy::create(host, port, vec![]).await else {
+            return ValidationStatus::Failed { reason: format!("Could not resolve {}", host) };
+        };
+        let mut checker = self.checker.get_or_init(Checker::new).await.clone();
+        if checker.check_proxy(&mut proxy).await {
+            ValidationStatus::Working {
+                protocols: proxy.types.iter().map(|(proto, _)| proto.clone()).collect(),
+                avg_response_time: proxy.avg_resp_time(),
+            }
+        } else {
+            let reason = proxy.logs.last().map_or("No protocol passed the checks", |(_, msg, _)| msg.as_str());
+            ValidationStatus::Failed { reason: reason.to_string() }
+        }
+    }
+}
+
+/// Queue the given `host:port` proxies for validation, one task per proxy
+pub async fn validate_proxies(
+    State(tasks): State<ValidationTasks>,
+    request: Result<Json<Vec<String>>, JsonRejection>,
+) -> Result<Json<ApiResponse<Vec<ValidationTask>>>, ApiError> {
+    let Json(proxies) = request?;
+    if proxies.len() > MAX_VALIDATION_BATCH {
+        return Err(ApiError::BadRequest(format!(
+            "{} proxies submitted, at most {} per request",
+            proxies.len(),
+            MAX_VALIDATION_BATCH
+        )));
+    }
+    let mut targets = Vec::with_capacity(proxies.len());
+    for proxy in proxies {
+        let target = proxy
+            .rsplit_once(':')
+            .and_then(|(host, port)| Some((host.to_string(), port.parse::<u16>().ok().filter(|port| *port != 0)?)))
+            .filter(|(host, _)| !host.is_empty());
+        match target {
+            Some((host, port)) => targets.push((proxy, host, port)),
+            None => return Err(ApiError::BadRequest(format!("Invalid proxy {}, expected host:port", proxy))),
+        }
+    }
+
+    log::info!("Queueing validation of {} proxies", targets.len());
+    let submitted = targets
+        .into_iter()
+        .map(|(proxy, host, port)| tasks.submit(proxy, host, port))
+        .collect();
+    Ok(Json(ApiResponse::success(submitted)))
+}
+
+/// Status of a validation task
+pub async fn get_task(
+    State(tasks): State<ValidationTasks>,
+    Path(task_id): Path<String>,
+) -> Result<Json<ApiResponse<ValidationTask>>, ApiError> {
+    tasks
+        .get(&task_id)
+        .map(|task| Json(ApiResponse::success(task)))
+        .ok_or_else(|| ApiError::NotFound(format!("Task {} not found", task_id)))
+}
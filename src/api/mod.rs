@@ -12,8 +12,24 @@ pub mod auth_simple;
 pub use handlers_minimal::*;
 pub use server::ApiServer;
 
+use std::sync::Arc;
+
+use axum::{
+    extract::{rejection::JsonRejection, FromRef},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
 use serde::{Deserialize, Serialize};
 
+use crate::api::auth_simple::SimpleAuthManager;
+use crate::checker::Checker;
+use crate::config::SharedConfig;
+use crate::providers::ProxyProvider;
+use crate::server::connection_pool::{ConnectionPool, PoolConfig};
+#[cfg(feature = "sqlite")]
+use crate::store::ProxyStore;
+
 /// API Configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ApiConfig {
@@ -24,6 +40,14 @@ pub struct ApiConfig {
     pub rate_limit: u32,
     pub cors_origins: Vec<String>,
     pub request_timeout_ms: u64,
+    /// Maximum number of IPs accepted by the bulk DNSBL endpoint
+    pub dnsbl_bulk_max_ips: usize,
+    /// Seconds between two events of the live metrics stream
+    pub metrics_stream_interval_secs: u64,
+    /// PEM certificate chain, serves HTTPS together with `tls_key_path`
+    pub tls_cert_path: Option<String>,
+    /// PEM private key matching `tls_cert_path`
+    pub tls_key_path: Option<String>,
 }
 
 impl Default for ApiConfig {
@@ -36,8 +60,93 @@ impl Default for ApiConfig {
             rate_limit: 1000,
             cors_origins: vec!["*".to_string()],
             request_timeout_ms: 30000,
+            dnsbl_bulk_max_ips: 100,
+            metrics_stream_interval_secs: 5,
+            tls_cert_path: None,
+            tls_key_path: None,
+        }
+    }
+}
+
+/// Shared state handed to the API handlers
+#[derive(Clone)]
+pub struct ApiState {
+    pub shared_config: SharedConfig,
+    pub connection_pool: Arc<ConnectionPool>,
+    pub validation_tasks: ValidationTasks,
+    /// Checker probing DNS connectivity for the health endpoint, created on first use
+    pub dnsbl_probe: Arc<tokio::sync::OnceCell<crate::dnsbl::DnsblChecker>>,
+    /// Providers polled by an on-demand refresh, all the registered ones when `None`
+    pub refresh_providers: Option<Vec<Arc<dyn ProxyProvider>>>,
+    /// API keys checked by the protected endpoints when authentication is enabled
+    pub auth_manager: Arc<SimpleAuthManager>,
+    /// Persistent store answering the proxy endpoints when `--db` is given
+    #[cfg(feature = "sqlite")]
+    pub store: Option<Arc<ProxyStore>>,
+}
+
+impl ApiState {
+    pub fn new(shared_config: SharedConfig) -> Self {
+        Self {
+            shared_config,
+            connection_pool: Arc::new(ConnectionPool::new(PoolConfig::default())),
+            validation_tasks: ValidationTasks::default(),
+            dnsbl_probe: Arc::new(tokio::sync::OnceCell::new()),
+            refresh_providers: None,
+            auth_manager: Arc::new(SimpleAuthManager::new()),
+            #[cfg(feature = "sqlite")]
+            store: None,
         }
     }
+
+    /// Report stats of the given connection pool, typically the proxy server's one
+    pub fn with_connection_pool(mut self, connection_pool: Arc<ConnectionPool>) -> Self {
+        self.connection_pool = connection_pool;
+        self
+    }
+
+    /// Run proxy validations with `checker`, by default one is built on first use
+    pub fn with_validation_checker(mut self, checker: Checker) -> Self {
+        self.validation_tasks = ValidationTasks::with_checker(checker);
+        self
+    }
+
+    /// Poll only `providers` on an on-demand refresh
+    pub fn with_refresh_providers(mut self, providers: Vec<Arc<dyn ProxyProvider>>) -> Self {
+        self.refresh_providers = Some(providers);
+        self
+    }
+
+    /// Accept the keys of `auth_manager` on the protected endpoints
+    pub fn with_auth_manager(mut self, auth_manager: Arc<SimpleAuthManager>) -> Self {
+        self.auth_manager = auth_manager;
+        self
+    }
+
+    /// List and look up proxies from the persistent store
+    #[cfg(feature = "sqlite")]
+    pub fn with_store(mut self, store: Arc<ProxyStore>) -> Self {
+        self.store = Some(store);
+        self
+    }
+}
+
+impl FromRef<ApiState> for SharedConfig {
+    fn from_ref(state: &ApiState) -> Self {
+        state.shared_config.clone()
+    }
+}
+
+impl FromRef<ApiState> for Arc<ConnectionPool> {
+    fn from_ref(state: &ApiState) -> Self {
+        state.connection_pool.clone()
+    }
+}
+
+impl FromRef<ApiState> for ValidationTasks {
+    fn from_ref(state: &ApiState) -> Self {
+        state.validation_tasks.clone()
+    }
 }
 
 /// API Response wrapper for consistent responses
@@ -72,6 +181,52 @@ impl<T> ApiResponse<T> {
     }
 }
 
+/// Handler error, rendered as an `ApiResponse` error body with the matching status
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ApiError {
+    #[error("{0}")]
+    BadRequest(String),
+    #[error("{0}")]
+    Unauthorized(String),
+    #[error("{0}")]
+    Forbidden(String),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    PayloadTooLarge(String),
+    #[error("{0}")]
+    ServiceUnavailable(String),
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl ApiError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::ServiceUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status(), Json(ApiResponse::<()>::error(self.to_string()))).into_response()
+    }
+}
+
+/// Malformed or mistyped JSON bodies get the same error shape as handler errors
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        ApiError::BadRequest(rejection.body_text())
+    }
+}
+
 /// Pagination parameters
 #[derive(Debug, Deserialize)]
 pub struct PaginationParams {
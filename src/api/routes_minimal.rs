@@ -1,33 +1,80 @@
 //! Minimal API Routes - Working version without complex middleware
 
-use crate::api::handlers_minimal::*;
+use crate::api::{handlers_minimal::*, ApiError, ApiState};
 use axum::{
-    routing::get,
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
     Router,
 };
 use std::sync::Arc;
-use tower_http::cors::{Any, CorsLayer};
+use std::time::Duration;
+
+/// Seconds browsers may cache a preflight answer
+const CORS_MAX_AGE_SECS: u64 = 600;
 
 /// Create the main API router (minimal working version)
 pub fn create_api_router(
-    _config: Arc<crate::api::ApiConfig>,
-    shared_config: crate::config::SharedConfig,
+    config: Arc<crate::api::ApiConfig>,
+    state: ApiState,
 ) -> Router {
     let api_router = Router::new()
         // Health and status endpoints
         .route("/health", get(health_check))
         .route("/metrics", get(get_metrics))
+        .route(
+            "/metrics/stream",
+            get(metrics_stream).with_state(MetricsStreamState::new(Duration::from_secs(
+                config.metrics_stream_interval_secs,
+            ))),
+        )
+        .route(
+            "/metrics/reset",
+            post(reset_metrics).with_state(MetricsResetState::new(
+                config.enable_auth.then(|| state.auth_manager.clone()),
+            )),
+        )
+        .route("/stats", get(get_stats))
 
         // Proxy endpoints
         .route("/proxies", get(list_proxies).post(create_proxy))
-        .route("/proxies/:id", get(get_proxy))
+        .route("/proxies/validate", post(validate_proxies))
+        .route("/proxies/:id", get(get_proxy).put(update_proxy))
+        .route("/proxies/:id/latency", get(get_proxy_latency))
+        .route("/tasks/:id", get(get_task))
+        .route("/audit", get(get_audit))
+
+        // Connection pool endpoints
+        .route("/pool/stats", get(get_pool_stats))
+        .route("/pool/stats/:proxy", get(get_proxy_pool_stats))
+
+        // DNSBL endpoints
+        .route(
+            "/dnsbl/bulk",
+            post(dnsbl_bulk_check).with_state(DnsblBulkState::new(
+                config.dnsbl_bulk_max_ips,
+                state.shared_config.clone(),
+            )),
+        )
+
+        // Provider endpoints
+        .route("/providers/stats", get(get_provider_stats))
+        .route(
+            "/providers/refresh",
+            post(refresh_providers).with_state(ProviderRefreshState::new(
+                &state,
+                config.enable_auth.then(|| state.auth_manager.clone()),
+            )),
+        )
 
         // Configuration endpoints
         .route("/config", get(get_config).post(update_config))
 
         // Apply basic middleware
-        .layer(cors_layer())
-        .with_state(shared_config);
+        .layer(middleware::from_fn_with_state(CorsPolicy::new(&config), cors))
+        .with_state(state);
 
     // Create main router
     Router::new()
@@ -36,17 +83,85 @@ pub fn create_api_router(
         .route("/", get(root_info))
 }
 
-/// CORS configuration
-fn cors_layer() -> CorsLayer {
-    CorsLayer::new()
-        .allow_origin(Any)
-        .allow_methods([axum::http::Method::GET, axum::http::Method::POST, axum::http::Method::PUT, axum::http::Method::DELETE])
-        .allow_headers([
-            axum::http::header::AUTHORIZATION,
-            axum::http::header::ACCEPT,
-            axum::http::header::CONTENT_TYPE,
-        ])
-        .allow_credentials(false)
+/// Origins allowed to call the API from a browser, from `ApiConfig.cors_origins`
+#[derive(Debug, Clone)]
+pub struct CorsPolicy {
+    origins: Vec<String>,
+    any_origin: bool,
+    /// Credentialed requests are allowed when authentication is enabled
+    credentials: bool,
+}
+
+impl CorsPolicy {
+    /// Exact origins or `*`. With authentication enabled, `*` is refused since
+    /// it would let any site send credentialed requests.
+    pub fn new(config: &crate::api::ApiConfig) -> Self {
+        let wildcard = config.cors_origins.iter().any(|origin| origin == "*");
+        if wildcard && config.enable_auth {
+            log::warn!("Ignoring CORS origin '*' with authentication enabled, list the allowed origins instead");
+        }
+        Self {
+            origins: config
+                .cors_origins
+                .iter()
+                .filter(|origin| *origin != "*")
+                .map(|origin| origin.trim_end_matches('/').to_string())
+                .collect(),
+            any_origin: wildcard && !config.enable_auth,
+            credentials: config.enable_auth,
+        }
+    }
+
+    pub fn allows(&self, origin: &str) -> bool {
+        self.any_origin || self.origins.iter().any(|allowed| allowed == origin)
+    }
+
+    /// `Access-Control-*` headers answering a request from the allowed `origin`
+    fn apply(&self, headers: &mut HeaderMap, origin: &HeaderValue) {
+        if self.any_origin && !self.credentials {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, HeaderValue::from_static("*"));
+        } else {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin.clone());
+            headers.append(header::VARY, HeaderValue::from_static("origin"));
+        }
+        if self.credentials {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_CREDENTIALS, HeaderValue::from_static("true"));
+        }
+    }
+}
+
+/// Enforce the CORS policy: requests from other origins are refused and
+/// preflight requests are answered without reaching the routes.
+/// Requests without an `Origin` header, e.g. from scripts, pass through.
+async fn cors(State(policy): State<CorsPolicy>, request: Request, next: Next) -> Response {
+    let Some(origin) = request.headers().get(header::ORIGIN).cloned() else {
+        return next.run(request).await;
+    };
+    if !origin.to_str().is_ok_and(|origin| policy.allows(origin)) {
+        log::debug!("Refused API request from origin {:?}", origin);
+        return ApiError::Forbidden("Origin not allowed".to_string()).into_response();
+    }
+
+    let preflight = request.method() == Method::OPTIONS
+        && request.headers().contains_key(header::ACCESS_CONTROL_REQUEST_METHOD);
+    let mut response = if preflight {
+        let mut response = StatusCode::NO_CONTENT.into_response();
+        let headers = response.headers_mut();
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_METHODS,
+            HeaderValue::from_static("GET, POST, PUT, DELETE"),
+        );
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_HEADERS,
+            HeaderValue::from_static("authorization, accept, content-type"),
+        );
+        headers.insert(header::ACCESS_CONTROL_MAX_AGE, HeaderValue::from(CORS_MAX_AGE_SECS));
+        response
+    } else {
+        next.run(request).await
+    };
+    policy.apply(response.headers_mut(), &origin);
+    response
 }
 
 /// Create API documentation router
@@ -84,7 +199,60 @@ async fn openapi_json() -> axum::response::Json<serde_json::Value> {
                     "tags": ["Health"],
                     "responses": {
                         "200": {
-                            "description": "Healthy response"
+                            "description": "Healthy or degraded, a non-critical check failed"
+                        },
+                        "503": {
+                            "description": "Unhealthy, the proxy pool, judges or configuration failed"
+                        }
+                    }
+                }
+            },
+            "/metrics/stream": {
+                "get": {
+                    "summary": "Live performance summary",
+                    "tags": ["Health"],
+                    "responses": {
+                        "200": {
+                            "description": "Server-sent events, each `metrics` event carrying the performance summary as JSON"
+                        }
+                    }
+                }
+            },
+            "/metrics/reset": {
+                "post": {
+                    "summary": "Reset performance metrics",
+                    "tags": ["Health"],
+                    "responses": {
+                        "200": {
+                            "description": "All counters are back to zero"
+                        },
+                        "401": {
+                            "description": "Authentication is enabled and no valid API key was given"
+                        }
+                    }
+                }
+            },
+            "/providers/stats": {
+                "get": {
+                    "summary": "Proxies yielded by each provider",
+                    "tags": ["Providers"],
+                    "responses": {
+                        "200": {
+                            "description": "Per provider: `fetched`, `unique` after deduplication, `working` once checked and `dead_rate`, the share of unique proxies not found working"
+                        }
+                    }
+                }
+            },
+            "/providers/refresh": {
+                "post": {
+                    "summary": "Fetch the providers now",
+                    "tags": ["Providers"],
+                    "responses": {
+                        "200": {
+                            "description": "Number of new proxies queued, as `enqueued`"
+                        },
+                        "401": {
+                            "description": "Authentication is enabled and no valid API key was given"
                         }
                     }
                 }
@@ -99,6 +267,39 @@ async fn openapi_json() -> axum::response::Json<serde_json::Value> {
                         }
                     }
                 }
+            },
+            "/proxies/{id}/latency": {
+                "get": {
+                    "summary": "Recent latencies of a proxy",
+                    "tags": ["Proxies"],
+                    "responses": {
+                        "200": {
+                            "description": "Min, average, 95th percentile and max of the last latencies, in milliseconds"
+                        },
+                        "404": {
+                            "description": "No check or forward through this `host:port` succeeded yet"
+                        }
+                    }
+                }
+            },
+            "/audit": {
+                "get": {
+                    "summary": "Recent requests served by the proxy server",
+                    "tags": ["Server"],
+                    "parameters": [
+                        {
+                            "name": "limit",
+                            "in": "query",
+                            "schema": { "type": "integer", "default": 100 },
+                            "description": "Maximum number of entries, newest first"
+                        }
+                    ],
+                    "responses": {
+                        "200": {
+                            "description": "Timestamp, client IP, target host, upstream proxy and status of each request"
+                        }
+                    }
+                }
             }
         }
     });
@@ -106,7 +307,7 @@ async fn openapi_json() -> axum::response::Json<serde_json::Value> {
 }
 
 /// Root endpoint handler
-async fn root_info() -> axum::response::Json<serde_json::Value> {
+pub async fn root_info() -> axum::response::Json<serde_json::Value> {
     axum::response::Json(serde_json::json!({
         "name": "Proxy.rs API",
         "version": env!("CARGO_PKG_VERSION"),
@@ -116,8 +317,13 @@ async fn root_info() -> axum::response::Json<serde_json::Value> {
             "docs": "/docs",
             "health": "/api/v1/health",
             "proxies": "/api/v1/proxies",
+            "tasks": "/api/v1/tasks",
             "config": "/api/v1/config",
-            "metrics": "/api/v1/metrics"
+            "metrics": "/api/v1/metrics",
+            "metrics_stream": "/api/v1/metrics/stream",
+            "stats": "/api/v1/stats",
+            "pool_stats": "/api/v1/pool/stats",
+            "providers_refresh": "/api/v1/providers/refresh"
         },
         "features": [
             "High-performance async/await",
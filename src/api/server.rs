@@ -1,66 +1,79 @@
 //! API Server - Main REST API server (minimal working version)
 
-use crate::api::{ApiConfig, routes_minimal::*};
+use crate::api::{ApiConfig, ApiState, routes_minimal::*};
 use crate::config::SharedConfig;
 use axum::{
     extract::Json,
-    routing::get,
     Router,
 };
-use std::net::SocketAddr;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use hyper_util::service::TowerToHyperService;
+use std::fs::File;
+use std::io::BufReader;
 use std::sync::Arc;
 use tokio::net::TcpListener;
+use tokio_rustls::rustls::{Certificate, PrivateKey, ServerConfig};
+use tokio_rustls::TlsAcceptor;
 
 /// High-performance API server
 pub struct ApiServer {
     config: Arc<ApiConfig>,
-    shared_config: SharedConfig,
     app: Router,
 }
 
 impl ApiServer {
     /// Create new API server instance
     pub fn new(config: ApiConfig, shared_config: SharedConfig) -> Self {
+        Self::with_state(config, ApiState::new(shared_config))
+    }
+
+    /// Create API server instance with explicit shared state
+    pub fn with_state(config: ApiConfig, state: ApiState) -> Self {
         let config = Arc::new(config);
-        let app = Self::create_app(config.clone(), shared_config.clone());
+        let app = Self::create_app(config.clone(), state);
 
-        Self {
-            config,
-            shared_config,
-            app,
-        }
+        Self { config, app }
     }
 
     /// Create Axum application with all routes and middleware
-    fn create_app(config: Arc<ApiConfig>, shared_config: SharedConfig) -> Router {
-        // API routes
-        let api_router = create_api_router(config.clone(), shared_config);
-
-        // Combine all routers
-        Router::new()
-            .nest("/api/v1", api_router)
-            .nest("/", create_docs_router())
-            // Root endpoint with API info
-            .route("/", get(root_info))
+    fn create_app(config: Arc<ApiConfig>, state: ApiState) -> Router {
+        // API, docs and root routes
+        create_api_router(config, state)
     }
 
     /// Start the API server
     pub async fn start(self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let addr = SocketAddr::from(([127, 0, 0, 1], self.config.port));
-        let listener = TcpListener::bind(addr).await?;
+        let tls_config = self.tls_config()?;
+        let listener = TcpListener::bind((self.config.host.as_str(), self.config.port)).await?;
 
+        let scheme = if tls_config.is_some() { "https" } else { "http" };
         log::info!(
-            "🚀 API Server starting on http://{}:{}/api/v1",
+            "🚀 API Server starting on {}://{}:{}/api/v1",
+            scheme,
             self.config.host,
             self.config.port
         );
-        log::info!("📚 API Documentation: http://{}:{}/docs", self.config.host, self.config.port);
+        log::info!("📚 API Documentation: {}://{}:{}/docs", scheme, self.config.host, self.config.port);
         log::info!("🔑 Authentication: {}", if self.config.enable_auth { "Enabled" } else { "Disabled" });
         log::info!("⚡ Rate limiting: {} requests/minute", self.config.rate_limit);
 
-        axum::serve(listener, self.app).await?;
+        match tls_config {
+            Some(tls_config) => serve_tls(listener, self.app, tls_config).await,
+            None => {
+                axum::serve(listener, self.app).await?;
+                Ok(())
+            }
+        }
+    }
 
-        Ok(())
+    /// TLS settings from `tls_cert_path`/`tls_key_path`, `None` to serve plain HTTP
+    pub fn tls_config(&self) -> Result<Option<Arc<ServerConfig>>, Box<dyn std::error::Error + Send + Sync>> {
+        match (&self.config.tls_cert_path, &self.config.tls_key_path) {
+            (Some(cert_path), Some(key_path)) => Ok(Some(load_tls_config(cert_path, key_path)?)),
+            (None, None) => Ok(None),
+            _ => Err("TLS requires both tls_cert_path and tls_key_path".into()),
+        }
     }
 
     /// Get server configuration
@@ -68,36 +81,74 @@ impl ApiServer {
         &self.config
     }
 
-    /// Get shared configuration reference
-    pub fn shared_config(&self) -> &SharedConfig {
-        &self.shared_config
+    /// Get the router serving this API
+    pub fn app(&self) -> Router {
+        self.app.clone()
     }
 }
 
-/// Root endpoint handler
-async fn root_info() -> Json<serde_json::Value> {
-    Json(serde_json::json!({
-        "name": "Proxy.rs API",
-        "version": env!("CARGO_PKG_VERSION"),
-        "description": "High-performance proxy management API",
-        "endpoints": {
-            "api": "/api/v1",
-            "docs": "/docs",
-            "health": "/api/v1/health",
-            "proxies": "/api/v1/proxies",
-            "config": "/api/v1/config",
-            "metrics": "/api/v1/metrics"
-        },
-        "features": [
-            "High-performance async/await",
-            "Hot-reload configuration",
-            "Real-time metrics",
-            "JWT authentication",
-            "Rate limiting",
-            "CORS support",
-            "OpenAPI documentation"
-        ]
-    }))
+/// Build a rustls server config from a PEM certificate chain and private key
+fn load_tls_config(cert_path: &str, key_path: &str) -> Result<Arc<ServerConfig>, Box<dyn std::error::Error + Send + Sync>> {
+    let open = |path: &str| {
+        File::open(path)
+            .map(BufReader::new)
+            .map_err(|e| format!("Failed to read TLS file {}: {}", path, e))
+    };
+
+    let certs = rustls_pemfile::certs(&mut open(cert_path)?)
+        .map_err(|e| format!("Invalid TLS certificate {}: {}", cert_path, e))?;
+    if certs.is_empty() {
+        return Err(format!("No certificate found in {}", cert_path).into());
+    }
+
+    let key = rustls_pemfile::read_all(&mut open(key_path)?)
+        .map_err(|e| format!("Invalid TLS key {}: {}", key_path, e))?
+        .into_iter()
+        .find_map(|item| match item {
+            rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::ECKey(key) => Some(key),
+            _ => None,
+        })
+        .ok_or_else(|| format!("No private key found in {}", key_path))?;
+
+    let mut config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certs.into_iter().map(Certificate).collect(), PrivateKey(key))
+        .map_err(|e| format!("Invalid TLS certificate/key pair: {}", e))?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    Ok(Arc::new(config))
+}
+
+/// Accept TLS connections and serve the router on each of them
+async fn serve_tls(
+    listener: TcpListener,
+    app: Router,
+    tls_config: Arc<ServerConfig>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let acceptor = TlsAcceptor::from(tls_config);
+    loop {
+        let (stream, addr) = listener.accept().await?;
+        let acceptor = acceptor.clone();
+        let service = TowerToHyperService::new(app.clone());
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    log::debug!("TLS handshake with {} failed: {}", addr, e);
+                    return;
+                }
+            };
+            if let Err(e) = auto::Builder::new(TokioExecutor::new())
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                log::debug!("API connection from {} failed: {}", addr, e);
+            }
+        });
+    }
 }
 
 /// 404 Not Found handler
@@ -133,6 +184,7 @@ pub async fn api_health_check() -> Json<crate::api::ApiResponse<serde_json::Valu
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::api::{overall_health, HealthCheck, HealthStatus};
     use crate::config::DynamicConfig;
 
     #[tokio::test]
@@ -146,6 +198,102 @@ mod tests {
         assert!(!server.config().enable_auth);
     }
 
+    fn tls_fixture(name: &str) -> String {
+        format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    #[test]
+    fn test_tls_config_from_fixture() {
+        let shared_config = Arc::new(parking_lot::RwLock::new(DynamicConfig::new()));
+        let config = ApiConfig {
+            tls_cert_path: Some(tls_fixture("api_cert.pem")),
+            tls_key_path: Some(tls_fixture("api_key.pem")),
+            ..ApiConfig::default()
+        };
+        let server = ApiServer::new(config, shared_config.clone());
+        assert!(server.tls_config().unwrap().is_some());
+
+        // Plain HTTP without TLS settings
+        let server = ApiServer::new(ApiConfig::default(), shared_config.clone());
+        assert!(server.tls_config().unwrap().is_none());
+
+        let half = ApiConfig { tls_cert_path: Some(tls_fixture("api_cert.pem")), ..ApiConfig::default() };
+        assert!(ApiServer::new(half, shared_config.clone()).tls_config().is_err());
+
+        let missing = ApiConfig {
+            tls_cert_path: Some(tls_fixture("missing.pem")),
+            tls_key_path: Some(tls_fixture("api_key.pem")),
+            ..ApiConfig::default()
+        };
+        let err = ApiServer::new(missing, shared_config).tls_config().unwrap_err();
+        assert!(err.to_string().contains("missing.pem"));
+    }
+
+    fn health_checks(statuses: [(HealthStatus, bool); 3]) -> std::collections::BTreeMap<&'static str, HealthCheck> {
+        ["proxy_pool", "judges", "dnsbl"]
+            .into_iter()
+            .zip(statuses)
+            .map(|(name, (status, critical))| {
+                (name, HealthCheck { status, message: String::new(), critical })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_overall_health() {
+        use axum::http::StatusCode;
+
+        let healthy = health_checks([
+            (HealthStatus::Healthy, true),
+            (HealthStatus::Healthy, true),
+            (HealthStatus::Disabled, false),
+        ]);
+        assert_eq!(overall_health(&healthy), (HealthStatus::Healthy, StatusCode::OK));
+
+        // A failing non-critical check only degrades the service
+        let degraded = health_checks([
+            (HealthStatus::Healthy, true),
+            (HealthStatus::Healthy, true),
+            (HealthStatus::Unhealthy, false),
+        ]);
+        assert_eq!(overall_health(&degraded), (HealthStatus::Degraded, StatusCode::OK));
+
+        let unhealthy = health_checks([
+            (HealthStatus::Unhealthy, true),
+            (HealthStatus::Healthy, true),
+            (HealthStatus::Unhealthy, false),
+        ]);
+        assert_eq!(overall_health(&unhealthy), (HealthStatus::Unhealthy, StatusCode::SERVICE_UNAVAILABLE));
+    }
+
+    #[tokio::test]
+    async fn test_health_endpoint_reports_missing_judges() {
+        use axum::body::Body;
+        use axum::http::{Request, StatusCode};
+        use http_body_util::BodyExt;
+        use tower::ServiceExt;
+
+        let mut config = DynamicConfig::new();
+        config.dnsbl.enabled = false;
+        let server = ApiServer::new(ApiConfig::default(), Arc::new(parking_lot::RwLock::new(config)));
+
+        let response = server
+            .app()
+            .oneshot(Request::builder().uri("/api/v1/health").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        // No judge was pretested in this process
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["data"]["status"], "unhealthy");
+        assert_eq!(json["data"]["checks"]["judges"]["status"], "unhealthy");
+        assert_eq!(json["data"]["checks"]["judges"]["message"], "0 working judges");
+        assert_eq!(json["data"]["checks"]["dnsbl"]["status"], "disabled");
+        assert_eq!(json["data"]["checks"]["config"]["status"], "healthy");
+    }
+
     #[tokio::test]
     async fn test_root_info() {
         let response = root_info().await;
@@ -156,6 +304,461 @@ mod tests {
         assert!(info["endpoints"]["api"].is_string());
         assert!(info["features"].is_array());
     }
+
+    #[tokio::test]
+    async fn test_pool_stats_endpoint() {
+        use axum::body::Body;
+        use axum::http::{Request, StatusCode};
+        use http_body_util::BodyExt;
+        use tower::ServiceExt;
+
+        let shared_config = Arc::new(parking_lot::RwLock::new(DynamicConfig::new()));
+        let server = ApiServer::new(ApiConfig::default(), shared_config);
+
+        let response = server
+            .app()
+            .oneshot(Request::builder().uri("/api/v1/pool/stats").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["success"], true);
+        for field in [
+            "total_pools",
+            "total_connections",
+            "active_connections",
+            "cache_hits",
+            "cache_misses",
+            "connections_created",
+            "connections_reused",
+        ] {
+            assert!(json["data"][field].is_number(), "missing field {}", field);
+        }
+
+        let response = server
+            .app()
+            .oneshot(Request::builder().uri("/api/v1/pool/stats/203.0.113.9:8080").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["success"], false);
+    }
+
+    async fn post_dnsbl_bulk(server: &ApiServer, ips: serde_json::Value) -> (axum::http::StatusCode, serde_json::Value) {
+        use axum::body::Body;
+        use axum::http::Request;
+        use http_body_util::BodyExt;
+        use tower::ServiceExt;
+
+        let request = Request::builder()
+            .method("POST")
+            .uri("/api/v1/dnsbl/bulk")
+            .header("content-type", "application/json")
+            .body(Body::from(serde_json::json!({ "ips": ips }).to_string()))
+            .unwrap();
+        let response = server.app().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    /// DNS server stub answering every query with NXDOMAIN, so no IP is listed
+    async fn nxdomain_dns_stub() -> u16 {
+        let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let port = socket.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let mut buf = [0; 512];
+            while let Ok((len, peer)) = socket.recv_from(&mut buf).await {
+                if len < 12 {
+                    continue;
+                }
+                // The query itself with the response, recursion available and NXDOMAIN flags
+                let mut response = buf[..len].to_vec();
+                response[2] = 0x80 | (response[2] & 0x01);
+                response[3] = 0x83;
+                let _ = socket.send_to(&response, peer).await;
+            }
+        });
+        port
+    }
+
+    #[tokio::test]
+    async fn test_dnsbl_bulk_mixed_ips() {
+        let shared_config = Arc::new(parking_lot::RwLock::new(DynamicConfig::new()));
+        {
+            let mut config = shared_config.write();
+            config.dnsbl.timeout_secs = 2;
+            config.dnsbl.dns_servers = vec![format!("127.0.0.1:{}", nxdomain_dns_stub().await)];
+        }
+        let server = ApiServer::new(ApiConfig::default(), shared_config);
+
+        let (status, json) = post_dnsbl_bulk(&server, serde_json::json!(["8.8.8.8", "not-an-ip"])).await;
+        assert_eq!(status, axum::http::StatusCode::OK);
+
+        let entries = json["data"].as_array().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["ip"], "8.8.8.8");
+        assert_eq!(entries[0]["result"]["is_malicious"], false);
+        assert_eq!(entries[0]["result"]["listed_count"], 0);
+        assert_eq!(entries[0]["result"]["error_count"], 0);
+        assert!(entries[0]["error"].is_null());
+        assert_eq!(entries[1]["ip"], "not-an-ip");
+        assert!(entries[1]["result"].is_null());
+        assert!(entries[1]["error"].is_string());
+    }
+
+    #[tokio::test]
+    async fn test_stats_endpoint_reports_protocols() {
+        use axum::body::Body;
+        use axum::http::Request;
+        use http_body_util::BodyExt;
+        use tower::ServiceExt;
+
+        crate::performance::PERFORMANCE_MONITOR
+            .record_proxy_check(std::time::Duration::from_millis(10), true, false, None, &[("HTTP".to_string(), true)])
+            .await;
+        let shared_config = Arc::new(parking_lot::RwLock::new(DynamicConfig::new()));
+        let server = ApiServer::new(ApiConfig::default(), shared_config);
+
+        let response = server
+            .app()
+            .oneshot(Request::builder().uri("/api/v1/stats").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let http = &json["data"]["protocols"]["HTTP"];
+        assert!(http["attempts"].as_u64().unwrap() >= 1);
+        assert!(http["successes"].as_u64().unwrap() >= 1);
+        assert!(http["success_rate"].is_number());
+    }
+
+    async fn send_json(
+        server: &ApiServer,
+        method: &str,
+        uri: &str,
+        body: &str,
+    ) -> (axum::http::StatusCode, serde_json::Value) {
+        use axum::body::Body;
+        use axum::http::Request;
+        use http_body_util::BodyExt;
+        use tower::ServiceExt;
+
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = server.app().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_bad_create_proxy_returns_json_error() {
+        let shared_config = Arc::new(parking_lot::RwLock::new(DynamicConfig::new()));
+        let server = ApiServer::new(ApiConfig::default(), shared_config);
+
+        for body in [
+            r#"{"host": "10.0.0.1", "port": 0}"#,
+            r#"{"host": "", "port": 8080}"#,
+            r#"{"port": 8080}"#,
+            r#"{"host": "10.0.0.1", "port": 70000}"#,
+            "not json",
+        ] {
+            let (status, json) = send_json(&server, "POST", "/api/v1/proxies", body).await;
+            assert_eq!(status, axum::http::StatusCode::BAD_REQUEST, "{}", body);
+            assert_eq!(json["success"], false);
+            assert!(json["error"].is_string());
+            assert!(json["data"].is_null());
+        }
+
+        let (status, json) = send_json(&server, "POST", "/api/v1/proxies", r#"{"host": "10.0.0.1", "port": 8080}"#).await;
+        assert_eq!(status, axum::http::StatusCode::OK);
+        assert_eq!(json["data"]["port"], 8080);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_proxy_update_is_not_found() {
+        let shared_config = Arc::new(parking_lot::RwLock::new(DynamicConfig::new()));
+        let server = ApiServer::new(ApiConfig::default(), shared_config);
+
+        let (status, json) = send_json(&server, "PUT", "/api/v1/proxies/proxy-9", r#"{"protocols": ["HTTPS"]}"#).await;
+        assert_eq!(status, axum::http::StatusCode::NOT_FOUND);
+        assert_eq!(json["success"], false);
+
+        let (status, json) = send_json(&server, "PUT", "/api/v1/proxies/proxy-1", r#"{"protocols": ["HTTPS"]}"#).await;
+        assert_eq!(status, axum::http::StatusCode::OK);
+        assert_eq!(json["data"]["protocols"], serde_json::json!(["HTTPS"]));
+    }
+
+    #[tokio::test]
+    async fn test_validation_task_reaches_terminal_status() {
+        let shared_config = Arc::new(parking_lot::RwLock::new(DynamicConfig::new()));
+        let mut checker = crate::checker::Checker::with_ext_ip("127.0.0.1".to_string());
+        checker.precheck_timeout = Some(std::time::Duration::from_secs(1));
+        let state = ApiState::new(shared_config).with_validation_checker(checker);
+        let server = ApiServer::with_state(ApiConfig::default(), state);
+        // Nothing listens on a port freed right away
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+
+        let (status, _) = send_json(&server, "POST", "/api/v1/proxies/validate", r#"["127.0.0.1"]"#).await;
+        assert_eq!(status, axum::http::StatusCode::BAD_REQUEST);
+        let oversized = vec!["127.0.0.1:8080"; crate::api::handlers_minimal::MAX_VALIDATION_BATCH + 1];
+        let body = serde_json::json!(oversized).to_string();
+        let (status, _) = send_json(&server, "POST", "/api/v1/proxies/validate", &body).await;
+        assert_eq!(status, axum::http::StatusCode::BAD_REQUEST);
+
+        let body = serde_json::json!([format!("127.0.0.1:{}", port)]).to_string();
+        let (status, json) = send_json(&server, "POST", "/api/v1/proxies/validate", &body).await;
+        assert_eq!(status, axum::http::StatusCode::OK);
+        let task_id = json["data"][0]["id"].as_str().unwrap().to_string();
+
+        let uri = format!("/api/v1/tasks/{}", task_id);
+        let mut task = serde_json::Value::Null;
+        for _ in 0..100 {
+            let (status, json) = send_json(&server, "GET", &uri, "").await;
+            assert_eq!(status, axum::http::StatusCode::OK);
+            task = json["data"].clone();
+            if task["status"] != "pending" {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        assert_eq!(task["status"], "failed");
+        assert!(task["reason"].as_str().unwrap().starts_with("Precheck"));
+        assert!(task["finished_at"].is_string());
+
+        let (status, _) = send_json(&server, "GET", "/api/v1/tasks/unknown", "").await;
+        assert_eq!(status, axum::http::StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_stream_sends_summary() {
+        use axum::body::Body;
+        use axum::http::{header, Request, StatusCode};
+        use http_body_util::BodyExt;
+        use tower::ServiceExt;
+
+        let shared_config = Arc::new(parking_lot::RwLock::new(DynamicConfig::new()));
+        let server = ApiServer::new(ApiConfig::default(), shared_config);
+
+        let response = server
+            .app()
+            .oneshot(Request::builder().uri("/api/v1/metrics/stream").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/event-stream");
+
+        // The first event is sent right away
+        let mut body = response.into_body();
+        let frame = tokio::time::timeout(std::time::Duration::from_secs(2), body.frame())
+            .await
+            .expect("no event received")
+            .unwrap()
+            .unwrap();
+        let event = String::from_utf8(frame.into_data().unwrap().to_vec()).unwrap();
+        assert!(event.starts_with("event: metrics\n"), "{}", event);
+        let data = event.lines().find_map(|line| line.strip_prefix("data: ")).unwrap();
+        let summary: serde_json::Value = serde_json::from_str(data).unwrap();
+        assert!(summary["overall_score"].is_number());
+        assert!(summary["recommendations"].is_array());
+
+        // Disconnecting drops the stream
+        drop(body);
+    }
+
+    #[tokio::test]
+    async fn test_dnsbl_bulk_cap() {
+        let shared_config = Arc::new(parking_lot::RwLock::new(DynamicConfig::new()));
+        let config = ApiConfig {
+            dnsbl_bulk_max_ips: 2,
+            ..ApiConfig::default()
+        };
+        let server = ApiServer::new(config, shared_config);
+
+        let (status, json) = post_dnsbl_bulk(&server, serde_json::json!(["1.1.1.1", "2.2.2.2", "3.3.3.3"])).await;
+        assert_eq!(status, axum::http::StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(json["success"], false);
+    }
+
+    struct RefreshMockProvider;
+
+    impl crate::providers::ProxyProvider for RefreshMockProvider {
+        fn name(&self) -> &str {
+            "refresh-mock"
+        }
+
+        fn fetch(&self) -> futures_util::future::BoxFuture<'_, Vec<crate::proxy::Proxy>> {
+            Box::pin(async {
+                let mut proxies = vec![];
+                for port in [3128, 8080] {
+                    proxies.extend(crate::proxy::Proxy::create("192.0.2.81", port, vec!["HTTP".to_string()]).await);
+                }
+                proxies
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_provider_refresh_reports_enqueued() {
+        use axum::body::Body;
+        use axum::http::{Request, StatusCode};
+        use http_body_util::BodyExt;
+        use tower::ServiceExt;
+
+        let _fetching = crate::providers::FETCH_TEST.lock().await;
+        let mock: Vec<Arc<dyn crate::providers::ProxyProvider>> = vec![Arc::new(RefreshMockProvider)];
+        let shared_config = Arc::new(parking_lot::RwLock::new(DynamicConfig::new()));
+        let auth = Arc::new(crate::api::auth_simple::SimpleAuthManager::new());
+        let key = auth.create_key("refresh".to_string(), vec!["write".to_string()]).await;
+        let config = ApiConfig {
+            enable_auth: true,
+            ..ApiConfig::default()
+        };
+        let state = ApiState::new(shared_config).with_refresh_providers(mock).with_auth_manager(auth);
+        let server = ApiServer::with_state(config, state);
+        let refresh = |key: Option<&str>| {
+            let mut request = Request::builder().method("POST").uri("/api/v1/providers/refresh");
+            if let Some(key) = key {
+                request = request.header("authorization", format!("Bearer {}", key));
+            }
+            request.body(Body::empty()).unwrap()
+        };
+
+        let response = server.app().oneshot(refresh(None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = server.app().oneshot(refresh(Some("proxy-rs-unknown"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = server.app().oneshot(refresh(Some(&key))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["data"]["enqueued"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_reset_uses_the_api_keys() {
+        use axum::body::Body;
+        use axum::http::{Request, StatusCode};
+        use tower::ServiceExt;
+
+        let shared_config = Arc::new(parking_lot::RwLock::new(DynamicConfig::new()));
+        let auth = Arc::new(crate::api::auth_simple::SimpleAuthManager::new());
+        let key = auth.create_key("metrics".to_string(), vec!["admin".to_string()]).await;
+        let config = ApiConfig {
+            enable_auth: true,
+            ..ApiConfig::default()
+        };
+        let server = ApiServer::with_state(config, ApiState::new(shared_config).with_auth_manager(auth));
+        let reset = |key: &str| {
+            Request::builder()
+                .method("POST")
+                .uri("/api/v1/metrics/reset")
+                .header("authorization", format!("Bearer {}", key))
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = server.app().oneshot(reset("proxy-rs-unknown")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = server.app().oneshot(reset(&key)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    fn cors_server(origins: &[&str], enable_auth: bool) -> ApiServer {
+        let shared_config = Arc::new(parking_lot::RwLock::new(DynamicConfig::new()));
+        let config = ApiConfig {
+            cors_origins: origins.iter().map(|origin| origin.to_string()).collect(),
+            enable_auth,
+            ..ApiConfig::default()
+        };
+        ApiServer::new(config, shared_config)
+    }
+
+    fn from_origin(method: &str, origin: &str) -> axum::http::request::Builder {
+        axum::http::Request::builder()
+            .method(method)
+            .uri("/api/v1/pool/stats")
+            .header("origin", origin)
+    }
+
+    #[tokio::test]
+    async fn test_cors_allowed_origin() {
+        use axum::body::Body;
+        use axum::http::StatusCode;
+        use tower::ServiceExt;
+
+        let server = cors_server(&["https://dashboard.example.com"], false);
+        let request = from_origin("GET", "https://dashboard.example.com").body(Body::empty()).unwrap();
+        let response = server.app().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["access-control-allow-origin"], "https://dashboard.example.com");
+        assert_eq!(response.headers()["vary"], "origin");
+
+        let server = cors_server(&["*"], false);
+        let request = from_origin("GET", "https://anywhere.example.com").body(Body::empty()).unwrap();
+        let response = server.app().oneshot(request).await.unwrap();
+        assert_eq!(response.headers()["access-control-allow-origin"], "*");
+        assert!(!response.headers().contains_key("access-control-allow-credentials"));
+    }
+
+    #[tokio::test]
+    async fn test_cors_disallowed_origin() {
+        use axum::body::Body;
+        use axum::http::StatusCode;
+        use tower::ServiceExt;
+
+        let server = cors_server(&["https://dashboard.example.com"], false);
+        let request = from_origin("GET", "https://evil.example.com").body(Body::empty()).unwrap();
+        let response = server.app().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(!response.headers().contains_key("access-control-allow-origin"));
+
+        // A wildcard never grants credentialed access
+        let server = cors_server(&["*"], true);
+        let request = from_origin("GET", "https://anywhere.example.com").body(Body::empty()).unwrap();
+        let response = server.app().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        // Requests without an origin are not browser cross-origin calls
+        let request = axum::http::Request::builder().uri("/api/v1/pool/stats").body(Body::empty()).unwrap();
+        let response = server.app().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_cors_preflight() {
+        use axum::body::Body;
+        use axum::http::StatusCode;
+        use tower::ServiceExt;
+
+        let server = cors_server(&["https://dashboard.example.com"], true);
+        let request = from_origin("OPTIONS", "https://dashboard.example.com")
+            .header("access-control-request-method", "POST")
+            .header("access-control-request-headers", "authorization")
+            .body(Body::empty())
+            .unwrap();
+        let response = server.app().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let headers = response.headers();
+        assert_eq!(headers["access-control-allow-origin"], "https://dashboard.example.com");
+        assert_eq!(headers["access-control-allow-credentials"], "true");
+        assert!(headers["access-control-allow-methods"].to_str().unwrap().contains("POST"));
+        assert!(headers["access-control-allow-headers"].to_str().unwrap().contains("authorization"));
+        assert_eq!(headers["access-control-max-age"], "600");
+    }
 }
 
 /// Create and start API server with default configuration
@@ -165,6 +768,15 @@ pub async fn start_default_api_server(shared_config: SharedConfig) -> Result<(),
     server.start().await
 }
 
+/// Create and start API server with custom configuration and shared state
+pub async fn start_api_server_with_state(
+    config: ApiConfig,
+    state: ApiState,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let server = ApiServer::with_state(config, state);
+    server.start().await
+}
+
 /// Create and start API server with custom configuration
 pub async fn start_api_server_with_config(
     config: ApiConfig,
@@ -1,6 +1,8 @@
 use clap::builder::PossibleValue;
 use clap::{Args, Parser, Subcommand};
 
+use crate::output::OutputTarget;
+
 // Constants
 const HELP_TEXT: &str = "Suggestions and bug reports are greatly appreciated:
 https://github.com/duan78/proxy-rs/issues";
@@ -27,10 +29,100 @@ pub struct Cli {
     )]
     pub log_level: String,
 
+    /// Logging output format
+    #[arg(long = "log-format", default_value = "text",
+        value_parser([
+            PossibleValue::new("text"),
+            PossibleValue::new("json")
+        ])
+    )]
+    pub log_format: String,
+
     /// Disable version checking
     #[arg(long)]
     pub skip_version_check: bool,
 
+    /// File where the performance summary is published for the `stats` subcommand,
+    /// proxy-rs-metrics.json in the user's data directory by default
+    #[arg(long = "metrics-file")]
+    pub metrics_file: Option<std::path::PathBuf>,
+
+    /// Seconds between two writes of the metrics file, 0 disables it
+    #[arg(long = "metrics-interval", default_value = "30")]
+    pub metrics_interval: u64,
+
+    /// Seconds between provider refresh cycles (overrides general.provider_refresh_secs)
+    #[arg(long = "refresh-interval", value_parser = clap::value_parser!(u64).range(1..))]
+    pub refresh_interval: Option<u64>,
+
+    /// Random spread of the refresh interval in percent (overrides general.provider_refresh_jitter_percent)
+    #[arg(long = "refresh-jitter", value_parser = clap::value_parser!(u64).range(0..=100))]
+    pub refresh_jitter: Option<u64>,
+
+    /// Number of checked proxies buffered between the checkers and the output
+    #[arg(long = "output-capacity", default_value = "50", value_parser = clap::value_parser!(u64).range(1..))]
+    pub output_capacity: u64,
+
+    /// What checkers do when the output buffer is full: wait, or drop the oldest buffered result
+    #[arg(long = "output-overflow", default_value = "block",
+        value_parser([
+            PossibleValue::new("block"),
+            PossibleValue::new("drop-oldest")
+        ])
+    )]
+    pub output_overflow: String,
+
+    /// Refuse to start with a malformed config file or unknown fields in it instead of
+    /// falling back to the defaults
+    #[arg(long = "strict-config")]
+    pub strict_config: bool,
+
+    /// Seconds after which to stop gracefully, writing out what was found so far
+    #[arg(long = "max-runtime")]
+    pub max_runtime: Option<u64>,
+
+    /// Seed judge, client and random User-Agent selection to make runs reproducible (random by default)
+    #[arg(long = "rng-seed")]
+    pub rng_seed: Option<u64>,
+
+    /// File with one User-Agent per line, sent to judges instead of the built-in one
+    #[arg(long = "user-agents")]
+    pub user_agents: Option<std::path::PathBuf>,
+
+    /// How the next User-Agent is picked from --user-agents
+    #[arg(long = "user-agent-rotation", default_value = "round-robin",
+        value_parser([
+            PossibleValue::new("round-robin"),
+            PossibleValue::new("random")
+        ])
+    )]
+    pub user_agent_rotation: String,
+
+    /// Do not start the REST API (overrides api.enabled)
+    #[arg(long = "no-api")]
+    pub no_api: bool,
+
+    /// Address the REST API listens on (overrides api.host)
+    #[arg(long = "api-host")]
+    pub api_host: Option<String>,
+
+    /// Port the REST API listens on (overrides api.port)
+    #[arg(long = "api-port", value_parser = clap::value_parser!(u16).range(1..))]
+    pub api_port: Option<u16>,
+
+    /// PEM certificate chain to serve the REST API over HTTPS (overrides api.tls_cert_path)
+    #[arg(long = "api-tls-cert", value_name = "PATH", requires = "api_tls_key")]
+    pub api_tls_cert: Option<String>,
+
+    /// PEM private key of --api-tls-cert (overrides api.tls_key_path)
+    #[arg(long = "api-tls-key", value_name = "PATH", requires = "api_tls_cert")]
+    pub api_tls_key: Option<String>,
+
+    /// SQLite database keeping every working proxy found, also served by the API
+    #[cfg(feature = "sqlite")]
+    #[arg(long = "db")]
+    pub db: Option<std::path::PathBuf>,
+
     #[command(subcommand)]
     pub sub: Commands,
 }
@@ -46,6 +138,40 @@ pub enum Commands {
 
     /// Run a local proxy server
     Serve(ServeArgs),
+
+    /// Print the performance summary of a running instance and exit
+    Stats(StatsArgs),
+
+    /// Work with the configuration file without starting the service
+    Config(ConfigArgs),
+}
+
+#[derive(Args, Debug, Clone)]
+#[command(after_help = HELP_TEXT)]
+pub struct ConfigArgs {
+    #[command(subcommand)]
+    pub action: ConfigAction,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ConfigAction {
+    /// Check a configuration file and print the first problem found, exiting non-zero
+    /// when there is one
+    Validate {
+        /// Configuration file to check
+        path: std::path::PathBuf,
+    },
+}
+
+/// A running instance rewrites its summary to `--metrics-file` every 30 seconds;
+/// `stats` reads that file, so both must be given the same `--metrics-file`
+/// when it is not the default one in the user's data directory
+#[derive(Args, Debug, Clone)]
+#[command(after_help = HELP_TEXT)]
+pub struct StatsArgs {
+    /// Print the summary as JSON
+    #[arg(long)]
+    pub json: bool,
 }
 
 #[derive(Args, Debug, Clone)]
@@ -55,6 +181,18 @@ pub struct GrabArgs {
     #[arg(short, long, num_args(1..))]
     pub countries: Vec<String>,
 
+    /// List of ISO country codes whose proxies should be rejected, applied after --countries
+    #[arg(long = "exclude-countries", num_args(1..))]
+    pub exclude_countries: Vec<String>,
+
+    /// List of region names or codes where should be located proxies (case-insensitive)
+    #[arg(long, num_args(1..))]
+    pub regions: Vec<String>,
+
+    /// List of city names where should be located proxies (case-insensitive)
+    #[arg(long, num_args(1..))]
+    pub cities: Vec<String>,
+
     /// The maximum number of working proxies
     #[arg(short, long, default_value = "0")]
     pub limit: usize,
@@ -70,16 +208,55 @@ pub struct GrabArgs {
     )]
     pub format: String,
 
-    /// Save found proxies to file. By default, output to console
+    /// Line printed for each proxy instead of --format, with the placeholders {ip}, {port},
+    /// {country}, {protocols}, {latency} (milliseconds) and {anonymity}
+    #[arg(long)]
+    pub template: Option<String>,
+
+    /// Save found proxies to file, repeat to write several files. `path:format`
+    /// (default, text, txt or json) writes that file in its own format. By default,
+    /// output to console
     #[arg(short, long)]
-    pub outfile: Option<std::path::PathBuf>,
+    pub outfile: Vec<OutputTarget>,
+
+    /// POST each found proxy as JSON to this URL, repeat for several endpoints. Failed
+    /// deliveries are retried, then logged and skipped
+    #[arg(long)]
+    pub webhook: Vec<String>,
+
+    /// Periodically save the queue and output progress to this file
+    #[arg(long = "session-file")]
+    pub session_file: Option<std::path::PathBuf>,
+
+    /// Continue from the progress saved in the session file
+    #[arg(long, requires = "session_file")]
+    pub resume: bool,
+
+    /// Print a status line (checked, working, rate, ETA with --limit) on stderr every few seconds
+    #[arg(long, conflicts_with = "quiet")]
+    pub progress: bool,
+
+    /// Print only the proxies, without logs
+    #[arg(short, long)]
+    pub quiet: bool,
+
+    /// Emit the proxies sorted by score, latency or country. Results are held in memory
+    /// and only written once collection ends (at --limit, end of input or shutdown)
+    #[arg(long,
+        value_parser([
+            PossibleValue::new("score"),
+            PossibleValue::new("latency"),
+            PossibleValue::new("country")
+        ])
+    )]
+    pub sort: Option<String>,
 }
 
 #[derive(Args, Debug, Clone)]
 #[command(after_help = HELP_TEXT)]
 pub struct FindArgs {
-    /// Type(s) (protocols) that need to be check on support by proxy
-    #[arg(long, required = true, num_args(1..),
+    /// Type(s) (protocols) that need to be check on support by proxy. With --discover, all by default
+    #[arg(long, required_unless_present = "discover", num_args(1..),
         value_parser([
             PossibleValue::new("HTTP"),
             PossibleValue::new("HTTPS"),
@@ -91,10 +268,28 @@ pub struct FindArgs {
     )]
     pub types: Vec<String>,
 
-    /// Path to the file with proxies. If specified, used instead of providers
+    /// Whether a proxy must support any or all of the types
+    #[arg(long = "type-match", default_value = "any",
+        value_parser([
+            PossibleValue::new("any"),
+            PossibleValue::new("all")
+        ])
+    )]
+    pub type_match: String,
+
+    /// Path to the file with proxies, `-` for stdin. If specified, used instead of providers.
+    /// A line may carry tags: `ip:port#paid,residential`
     #[arg(long, num_args(1..))]
     pub files: Vec<std::path::PathBuf>,
 
+    /// Tags given to the file proxies whose line has none, e.g. --tag paid,residential
+    #[arg(long = "tag", value_delimiter = ',')]
+    pub tags: Vec<String>,
+
+    /// Maximum number of hosts a CIDR line (e.g. 10.0.0.0/24:8080) in the files may expand to
+    #[arg(long = "max-cidr-hosts", default_value = "1024")]
+    pub max_cidr_hosts: usize,
+
     /// Level(s) of anonymity (for HTTP only). By default, any level
     #[arg(long, num_args(1..),
         value_parser([
@@ -109,6 +304,38 @@ pub struct FindArgs {
     #[arg(long, default_value = "1")]
     pub max_tries: usize,
 
+    /// Number of judges to try when a judge answers with a server error before declaring the proxy bad
+    #[arg(long = "judge-retries", default_value = "3")]
+    pub judge_retries: usize,
+
+    /// Milliseconds to wait for the TCP connect done before checking protocols, 0 (default) to skip it
+    #[arg(long = "precheck-timeout", default_value = "0")]
+    pub precheck_timeout: u64,
+
+    /// Flag proxies altering page content, costs one more request per working proxy
+    #[arg(long = "check-integrity", default_value = "false")]
+    pub check_integrity: bool,
+
+    /// Page working proxies must also fetch with a 2xx, e.g. the site they are meant for
+    #[arg(long = "validate-url")]
+    pub validate_url: Option<url::Url>,
+
+    /// Substring or regex marking the --validate-url page as a block or CAPTCHA page, even on a 200
+    #[arg(long = "block-signature", requires = "validate_url")]
+    pub block_signature: Option<String>,
+
+    /// Echo-IP page checked through proxies when no judge works [default: http://api.ipify.org/]
+    #[arg(long = "fallback-endpoint")]
+    pub fallback_endpoint: Option<String>,
+
+    /// Probe each proxy's handshakes first and only check the protocols it answers
+    #[arg(long, default_value = "false")]
+    pub discover: bool,
+
+    /// Milliseconds given to each discovery handshake
+    #[arg(long = "discover-timeout", default_value = "1000", value_parser = clap::value_parser!(u64).range(1..))]
+    pub discover_timeout: u64,
+
     /// Flag indicating that the proxy must support cookies
     #[arg(long, default_value = "false")]
     pub support_cookies: bool,
@@ -129,6 +356,10 @@ pub struct FindArgs {
     #[arg(long = "dnsbl-max-concurrent", default_value = "10")]
     pub dnsbl_max_concurrent: usize,
 
+    /// Maximum number of proxies checked against DNSBL lists at the same time
+    #[arg(long = "dnsbl-max-concurrent-ips", default_value = "20")]
+    pub dnsbl_max_concurrent_ips: usize,
+
     /// DNSBL cache TTL in seconds
     #[arg(long = "dnsbl-cache-ttl", default_value = "3600")]
     pub dnsbl_cache_ttl_secs: u64,
@@ -145,10 +376,51 @@ pub struct FindArgs {
     #[arg(long = "dnsbl-exclude", value_delimiter = ',')]
     pub dnsbl_excluded_lists: Vec<String>,
 
+    /// Resolve DNSBL queries over DNS-over-HTTPS, falling back to plain DNS if unreachable
+    #[arg(long = "dnsbl-doh")]
+    pub dnsbl_doh: bool,
+
+    /// IPs or CIDR ranges always treated as clean by DNSBL checks (comma-separated)
+    #[arg(long = "dnsbl-whitelist", value_delimiter = ',')]
+    pub dnsbl_whitelist: Vec<String>,
+
+    /// Number of DNS answers kept by the DNSBL resolver cache
+    #[arg(long = "dnsbl-resolver-cache-size", default_value = "1024")]
+    pub dnsbl_resolver_cache_size: usize,
+
+    /// Attempts per DNSBL query before giving up
+    #[arg(long = "dnsbl-resolver-attempts", default_value = "2")]
+    pub dnsbl_resolver_attempts: usize,
+
+    /// DNS servers queried for DNSBL lookups instead of the public ones, as ip or
+    /// ip:port (comma-separated)
+    #[arg(long = "dnsbl-dns-servers", value_delimiter = ',')]
+    pub dnsbl_dns_servers: Vec<String>,
+
+    /// Query every DNSBL list instead of stopping once --dnsbl-threshold listings were found
+    #[arg(long = "dnsbl-full-scan")]
+    pub dnsbl_full_scan: bool,
+
     /// List of ISO country codes where should be located proxies
     #[arg(short, long, num_args(1..))]
     pub countries: Vec<String>,
 
+    /// List of ISO country codes whose proxies should be rejected, applied after --countries
+    #[arg(long = "exclude-countries", num_args(1..))]
+    pub exclude_countries: Vec<String>,
+
+    /// List of region names or codes where should be located proxies (case-insensitive)
+    #[arg(long, num_args(1..))]
+    pub regions: Vec<String>,
+
+    /// List of city names where should be located proxies (case-insensitive)
+    #[arg(long, num_args(1..))]
+    pub cities: Vec<String>,
+
+    /// Drop proxies whose average response time exceeds this many milliseconds
+    #[arg(long = "max-response-time")]
+    pub max_response_time: Option<u64>,
+
     /// The maximum number of working proxies
     #[arg(short, long, default_value = "0")]
     pub limit: usize,
@@ -164,9 +436,48 @@ pub struct FindArgs {
     )]
     pub format: String,
 
-    /// Save found proxies to file. By default, output to console
+    /// Line printed for each proxy instead of --format, with the placeholders {ip}, {port},
+    /// {country}, {protocols}, {latency} (milliseconds) and {anonymity}
+    #[arg(long)]
+    pub template: Option<String>,
+
+    /// Save found proxies to file, repeat to write several files. `path:format`
+    /// (default, text, txt or json) writes that file in its own format. By default,
+    /// output to console
+    #[arg(short, long)]
+    pub outfile: Vec<OutputTarget>,
+
+    /// POST each found proxy as JSON to this URL, repeat for several endpoints. Failed
+    /// deliveries are retried, then logged and skipped
+    #[arg(long)]
+    pub webhook: Vec<String>,
+
+    /// Periodically save the queue and output progress to this file
+    #[arg(long = "session-file")]
+    pub session_file: Option<std::path::PathBuf>,
+
+    /// Continue from the progress saved in the session file
+    #[arg(long, requires = "session_file")]
+    pub resume: bool,
+
+    /// Print a status line (checked, working, rate, ETA with --limit) on stderr every few seconds
+    #[arg(long, conflicts_with = "quiet")]
+    pub progress: bool,
+
+    /// Print only the proxies, without logs
     #[arg(short, long)]
-    pub outfile: Option<std::path::PathBuf>,
+    pub quiet: bool,
+
+    /// Emit the proxies sorted by score, latency or country. Results are held in memory
+    /// and only written once collection ends (at --limit, end of input or shutdown)
+    #[arg(long,
+        value_parser([
+            PossibleValue::new("score"),
+            PossibleValue::new("latency"),
+            PossibleValue::new("country")
+        ])
+    )]
+    pub sort: Option<String>,
 }
 
 #[derive(Args, Debug, Clone)]
@@ -180,6 +491,10 @@ pub struct ServeArgs {
     #[arg(long, default_value = "8080")]
     pub port: u16,
 
+    /// Listen on this Unix domain socket instead of --host and --port (Unix only)
+    #[arg(long = "unix-socket")]
+    pub unix_socket: Option<std::path::PathBuf>,
+
     /// Type(s) (protocols) that need to be check on support by proxy
     #[arg(long, required = true, num_args(1..),
         value_parser([
@@ -192,10 +507,28 @@ pub struct ServeArgs {
     )]
     pub types: Vec<String>,
 
-    /// Path to the file with proxies. If specified, used instead of providers
+    /// Whether a proxy must support any or all of the types
+    #[arg(long = "type-match", default_value = "any",
+        value_parser([
+            PossibleValue::new("any"),
+            PossibleValue::new("all")
+        ])
+    )]
+    pub type_match: String,
+
+    /// Path to the file with proxies, `-` for stdin. If specified, used instead of providers.
+    /// A line may carry tags: `ip:port#paid,residential`
     #[arg(long, num_args(1..))]
     pub files: Vec<std::path::PathBuf>,
 
+    /// Tags given to the file proxies whose line has none, e.g. --tag paid,residential
+    #[arg(long = "tag", value_delimiter = ',')]
+    pub tags: Vec<String>,
+
+    /// Maximum number of hosts a CIDR line (e.g. 10.0.0.0/24:8080) in the files may expand to
+    #[arg(long = "max-cidr-hosts", default_value = "1024")]
+    pub max_cidr_hosts: usize,
+
     /// Level(s) of anonymity (for HTTP only). By default, any level
     #[arg(long, num_args(1..),
         value_parser([
@@ -210,6 +543,30 @@ pub struct ServeArgs {
     #[arg(long, default_value = "1")]
     pub max_tries: usize,
 
+    /// Number of judges to try when a judge answers with a server error before declaring the proxy bad
+    #[arg(long = "judge-retries", default_value = "3")]
+    pub judge_retries: usize,
+
+    /// Milliseconds to wait for the TCP connect done before checking protocols, 0 (default) to skip it
+    #[arg(long = "precheck-timeout", default_value = "0")]
+    pub precheck_timeout: u64,
+
+    /// Flag proxies altering page content, costs one more request per working proxy
+    #[arg(long = "check-integrity", default_value = "false")]
+    pub check_integrity: bool,
+
+    /// Page working proxies must also fetch with a 2xx, e.g. the site they are meant for
+    #[arg(long = "validate-url")]
+    pub validate_url: Option<url::Url>,
+
+    /// Substring or regex marking the --validate-url page as a block or CAPTCHA page, even on a 200
+    #[arg(long = "block-signature", requires = "validate_url")]
+    pub block_signature: Option<String>,
+
+    /// Echo-IP page checked through proxies when no judge works [default: http://api.ipify.org/]
+    #[arg(long = "fallback-endpoint")]
+    pub fallback_endpoint: Option<String>,
+
     /// Enable DNSBL checking for proxy security
     #[arg(long, default_value = "false")]
     pub dnsbl_check: bool,
@@ -222,6 +579,10 @@ pub struct ServeArgs {
     #[arg(long = "dnsbl-max-concurrent", default_value = "10")]
     pub dnsbl_max_concurrent: usize,
 
+    /// Maximum number of proxies checked against DNSBL lists at the same time
+    #[arg(long = "dnsbl-max-concurrent-ips", default_value = "20")]
+    pub dnsbl_max_concurrent_ips: usize,
+
     /// DNSBL cache TTL in seconds
     #[arg(long = "dnsbl-cache-ttl", default_value = "3600")]
     pub dnsbl_cache_ttl_secs: u64,
@@ -238,11 +599,116 @@ pub struct ServeArgs {
     #[arg(long = "dnsbl-exclude", value_delimiter = ',')]
     pub dnsbl_excluded_lists: Vec<String>,
 
+    /// Resolve DNSBL queries over DNS-over-HTTPS, falling back to plain DNS if unreachable
+    #[arg(long = "dnsbl-doh")]
+    pub dnsbl_doh: bool,
+
+    /// IPs or CIDR ranges always treated as clean by DNSBL checks (comma-separated)
+    #[arg(long = "dnsbl-whitelist", value_delimiter = ',')]
+    pub dnsbl_whitelist: Vec<String>,
+
+    /// Number of DNS answers kept by the DNSBL resolver cache
+    #[arg(long = "dnsbl-resolver-cache-size", default_value = "1024")]
+    pub dnsbl_resolver_cache_size: usize,
+
+    /// Attempts per DNSBL query before giving up
+    #[arg(long = "dnsbl-resolver-attempts", default_value = "2")]
+    pub dnsbl_resolver_attempts: usize,
+
+    /// DNS servers queried for DNSBL lookups instead of the public ones, as ip or
+    /// ip:port (comma-separated)
+    #[arg(long = "dnsbl-dns-servers", value_delimiter = ',')]
+    pub dnsbl_dns_servers: Vec<String>,
+
+    /// Query every DNSBL list instead of stopping once --dnsbl-threshold listings were found
+    #[arg(long = "dnsbl-full-scan")]
+    pub dnsbl_full_scan: bool,
+
     /// List of ISO country codes where should be located proxies
     #[arg(short, long, num_args(1..))]
     pub countries: Vec<String>,
 
+    /// List of ISO country codes whose proxies should be rejected, applied after --countries
+    #[arg(long = "exclude-countries", num_args(1..))]
+    pub exclude_countries: Vec<String>,
+
     /// Maximum average response time in milliseconds
     #[arg(long = "max-avg-resp-time", default_value = "8000")]
     pub max_avg_resp_time: u32,
+
+    /// Number of consecutive forwarding failures before a proxy is evicted from the pool
+    #[arg(long = "max-failures", default_value = "3")]
+    pub max_failures: i32,
+
+    /// Drop proxies from the pool when not verified working within this many seconds
+    #[arg(long = "max-age")]
+    pub max_age: Option<u64>,
+
+    /// Seconds between rechecks of the proxies waiting to enter the pool, 0 to disable
+    #[arg(long = "recheck-interval", default_value = "300")]
+    pub recheck_interval: u64,
+
+    /// Trusted proxy (ip:port) served without a check and never evicted, repeatable.
+    /// It is assumed to support every --types
+    #[arg(long = "pin", value_name = "IP:PORT")]
+    pub pin: Vec<std::net::SocketAddr>,
+
+    /// Concurrent connections allowed per client IP, extra ones get a 429. 0 disables,
+    /// overrides server.max_connections_per_client
+    #[arg(long = "max-connections-per-client", value_name = "NUMBER")]
+    pub max_connections_per_client: Option<usize>,
+
+    /// Keep routing a client (by IP or X-Proxy-Session header) to the same proxy for this many seconds. 0 disables
+    #[arg(long = "sticky-ttl", default_value = "0")]
+    pub sticky_ttl: u64,
+
+    /// Number of upstream proxies to chain for each request (1 uses a single proxy)
+    #[arg(long = "chain-length", default_value = "1", value_parser = clap::value_parser!(u8).range(1..))]
+    pub chain_length: u8,
+
+    /// Maximum size in bytes of a proxied response body, larger responses get a 502
+    #[arg(long = "max-response-bytes", default_value = "16777216")]
+    pub max_response_bytes: usize,
+
+    /// Keep CONNECT tunnels a client left unused for this many seconds and reuse them for the same target. 0 disables
+    #[arg(long = "tunnel-idle-secs", default_value = "0")]
+    pub tunnel_idle_secs: u64,
+
+    /// Number of healthiest proxies to open connections to before serving, 0 disables
+    #[arg(long = "warmup-proxies", default_value = "5")]
+    pub warmup_proxies: usize,
+
+    /// Idle connections opened to each warmed up proxy
+    #[arg(long = "warmup-connections", default_value = "2")]
+    pub warmup_connections: usize,
+
+    /// Protocol spoken by clients of the local listener
+    #[arg(long, default_value = "http",
+        value_parser([
+            PossibleValue::new("http"),
+            PossibleValue::new("socks5")
+        ])
+    )]
+    pub protocol: String,
+
+    /// Echo each forwarded request id back in an X-Proxy-Rs-Request-Id response header
+    #[arg(long = "request-id-header")]
+    pub request_id_header: bool,
+
+    /// Replace the User-Agent of forwarded requests with one from --user-agents
+    #[arg(long = "rewrite-user-agent")]
+    pub rewrite_user_agent: bool,
+
+    /// Only let clients reach these domains (globs like *.example.com), added to the config file's list
+    #[arg(long = "allow-domains", value_delimiter = ',')]
+    pub allow_domains: Vec<String>,
+
+    /// Refuse clients access to these domains, even when also allowed
+    #[arg(long = "deny-domains", value_delimiter = ',')]
+    pub deny_domains: Vec<String>,
+
+    /// Run collection and checks, print the proxies that would be served and exit without
+    /// listening, pooling or recording them in the database
+    #[arg(long = "dry-run")]
+    pub dry_run: bool,
 }
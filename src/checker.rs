@@ -1,47 +1,157 @@
-use std::{collections::BTreeMap, sync::Arc, time::Duration};
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 use dashmap::DashSet;
 use futures_util::{stream::FuturesUnordered, StreamExt};
+use http_body_util::{BodyExt, Empty};
+use hyper::Request;
 use lazy_static::lazy_static;
 use parking_lot::Mutex;
-use rand::{seq::SliceRandom, thread_rng};
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
 use regex::Regex;
 use tokio::{sync::{RwLock, Semaphore}, time};
+use url::{Position, Url};
 
 // Constants
 const DEFAULT_SEMAPHORE_LIMIT: usize = 20;
 const JUDGE_LOOKUP_TIMEOUT_SECS: u64 = 15;
+/// How often judges are pretested again after startup
+pub const JUDGE_REFRESH_INTERVAL: Duration = Duration::from_secs(300);
+/// Echo-IP page used in degraded mode, when no judge works
+pub const DEFAULT_FALLBACK_ENDPOINT: &str = "http://api.ipify.org/";
+/// Protocols the plain HTTP fallback endpoint can validate
+const DEGRADED_PROTOCOLS: [&str; 4] = ["HTTP", "CONNECT:80", "SOCKS4", "SOCKS5"];
+/// How long a working verdict stays in the validation cache
+pub const VALIDATION_TTL_WORKING: Duration = Duration::from_secs(120);
+/// How long a failed proxy is skipped before being checked again
+pub const VALIDATION_TTL_FAILING: Duration = Duration::from_secs(1800);
 
 use crate::{
+    config::dynamic::default_leak_headers,
     dnsbl::{DnsblChecker, DnsblConfig},
     judge::Judge,
     judge_optimized::{OptimizedJudgeManager, JudgeInfo},
+    performance::PERFORMANCE_MONITOR,
     negotiators::{
-        connect_25::Connect25Negotiator, connect_80::Connect80Negotiator, http::HttpNegotiator,
+        connect_25::Connect25Negotiator, connect_80::Connect80Negotiator, discovery, http::HttpNegotiator,
         https::HttpsNegotiator, socks4::Socks4Negotiator, socks5::Socks5Negotiator,
     },
     proxy::Proxy,
     resolver::Resolver,
+    server::{
+        latency::LATENCIES,
+        multi_cache::{CacheLevel, ProxyValidationCache, ProxyValidationResult},
+    },
     utils::{
         geolite_database::DOWNLOADING,
-        http::{get_headers, response::ResponseParser},
+        http::{forwarded, get_headers, hyper_client, response::ResponseParser, user_agents::UserAgents},
         vec_of_strings,
     },
 };
 
 lazy_static! {
     static ref ENABLE_PROTOCOLS: Mutex<DashSet<String>> = Mutex::new(DashSet::new());
-    static ref JUDGES: Arc<RwLock<std::collections::HashMap<String, Vec<Judge>>>> =
-        Arc::new(RwLock::new(std::collections::HashMap::new()));
     static ref OPTIMIZED_JUDGE_MANAGER: Arc<RwLock<OptimizedJudgeManager>> =
         Arc::new(RwLock::new(OptimizedJudgeManager::new()));
+    /// Order in which judges are tried
+    static ref JUDGE_RNG: Mutex<StdRng> = Mutex::new(StdRng::from_entropy());
+    /// Successful judge uses since the last pretest, folded in by `refresh_judges` so
+    /// checks never wait on the judge manager's write lock
+    static ref JUDGE_SUCCESSES: Mutex<HashMap<String, u32>> = Mutex::new(HashMap::new());
+}
+
+/// Make judge and client selection reproducible, must run before `check_judges`
+pub async fn set_rng_seed(seed: u64) {
+    *OPTIMIZED_JUDGE_MANAGER.write().await = OptimizedJudgeManager::with_seed(seed);
+    *JUDGE_RNG.lock() = StdRng::seed_from_u64(seed);
 }
 
-pub async fn check_judges(ssl: bool, ext_ip: String, mut expected_types: Vec<String>) {
+/// Number of judges found working by `check_judges`, across schemes
+pub async fn working_judges() -> usize {
+    let stats = OPTIMIZED_JUDGE_MANAGER.read().await.get_stats();
+    stats.http_working + stats.smtp_working
+}
+
+pub async fn check_judges(ssl: bool, ext_ip: String, expected_types: Vec<String>, degraded: Arc<AtomicBool>) {
     let stime = time::Instant::now();
 
     log::info!("🚀 Initialisation du système de judges optimisé...");
 
+    // Pré-tester tous les judges en parallèle
+    {
+        let mut manager = OPTIMIZED_JUDGE_MANAGER.write().await;
+        manager.pretest_judges(&ext_ip).await;
+    }
+
+    let (working_count, no_judges_protocols) = enable_judged_protocols(&expected_types).await;
+
+    // Afficher les statistiques
+    let manager = OPTIMIZED_JUDGE_MANAGER.read().await;
+    let stats = manager.get_stats();
+    log::info!("🎯 Judges optimisés: {}", stats);
+
+    degraded.store(working_count == 0, Ordering::Relaxed);
+    if working_count == 0 {
+        log::warn!("❌ Aucun judge fonctionnel trouvé!");
+        log::warn!("⚠️  Mode dégradé: joignabilité seule via l'endpoint de secours, sans niveau d'anonymat");
+        return;
+    }
+
+    if !no_judges_protocols.is_empty() {
+        log::warn!("⚠️  Protocoles sans judges: {:?}", no_judges_protocols);
+        log::info!("ℹ️  Ces protocoles seront désactivés pour la validation");
+    }
+
+    log::info!("🚀 {} judges optimisés opérationnels, Runtime: {:?}",
+               working_count, stime.elapsed());
+}
+
+/// Pretest the judges again every `interval`, so a judge taken out of rotation by an
+/// outage comes back once it answers, and fold in the judge uses since the last run.
+/// The pretest runs on a copy of the manager, checks keep using the current judges.
+/// `degraded` follows whether any judge still works.
+pub async fn refresh_judges(ext_ip: String, expected_types: Vec<String>, degraded: Arc<AtomicBool>, interval: Duration) {
+    let mut ticker = time::interval(interval);
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        let mut manager = OPTIMIZED_JUDGE_MANAGER.read().await.clone();
+        manager.pretest_judges(&ext_ip).await;
+        for (url, successes) in std::mem::take(&mut *JUDGE_SUCCESSES.lock()) {
+            for _ in 0..successes {
+                manager.record_judge_use(&url, true);
+            }
+        }
+        *OPTIMIZED_JUDGE_MANAGER.write().await = manager;
+
+        let (working_count, _) = enable_judged_protocols(&expected_types).await;
+        update_degraded(&degraded, working_count);
+        log::info!("🎯 Judges re-testés: {}", OPTIMIZED_JUDGE_MANAGER.read().await.get_stats());
+    }
+}
+
+/// Enter degraded mode when no judge works any more, leave it once one does
+fn update_degraded(degraded: &AtomicBool, working_count: usize) {
+    let was_degraded = degraded.swap(working_count == 0, Ordering::Relaxed);
+    match (was_degraded, working_count) {
+        (false, 0) => log::warn!("⚠️  Plus aucun judge fonctionnel, passage en mode dégradé"),
+        (true, 1..) => log::info!("✅ {} judges de nouveau fonctionnels, fin du mode dégradé", working_count),
+        _ => {}
+    }
+}
+
+/// Enable the protocols of `expected_types` that have a working judge. Returns the
+/// number of types with one and the types without any.
+async fn enable_judged_protocols(expected_types: &[String]) -> (usize, Vec<String>) {
+    let mut expected_types = expected_types.to_vec();
+
     // Normaliser les types de protocoles
     if !expected_types.contains(&"SMTP".to_string())
         && expected_types.contains(&"CONNECT:25".to_string())
@@ -57,12 +167,6 @@ pub async fn check_judges(ssl: bool, ext_ip: String, mut expected_types: Vec<Str
         expected_types.push("HTTP".to_string());
     }
 
-    // Pré-tester tous les judges en parallèle
-    {
-        let mut manager = OPTIMIZED_JUDGE_MANAGER.write().await;
-        manager.pretest_judges(&ext_ip).await;
-    }
-
     let mut working_count = 0;
     let mut no_judges_protocols = Vec::new();
 
@@ -89,24 +193,39 @@ pub async fn check_judges(ssl: bool, ext_ip: String, mut expected_types: Vec<Str
         }
     }
 
-    // Afficher les statistiques
-    let manager = OPTIMIZED_JUDGE_MANAGER.read().await;
-    let stats = manager.get_stats();
-    log::info!("🎯 Judges optimisés: {}", stats);
+    (working_count, no_judges_protocols)
+}
 
-    if working_count == 0 {
-        log::warn!("❌ Aucun judge fonctionnel trouvé!");
-        log::warn!("⚠️  Le serveur démarrera en mode dégradé (sans validation automatique)");
-        return;
-    }
+/// How the requested types decide whether a proxy works
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TypeMatchMode {
+    /// At least one requested type passed
+    #[default]
+    Any,
+    /// Every requested type that was checked passed
+    All,
+}
 
-    if !no_judges_protocols.is_empty() {
-        log::warn!("⚠️  Protocoles sans judges: {:?}", no_judges_protocols);
-        log::info!("ℹ️  Ces protocoles seront désactivés pour la validation");
+impl std::str::FromStr for TypeMatchMode {
+    type Err = String;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode.to_ascii_lowercase().as_str() {
+            "any" => Ok(TypeMatchMode::Any),
+            "all" => Ok(TypeMatchMode::All),
+            other => Err(format!("Unknown type match mode: {}, expected any or all", other)),
+        }
     }
+}
 
-    log::info!("🚀 {} judges optimisés opérationnels, Runtime: {:?}",
-               working_count, stime.elapsed());
+impl TypeMatchMode {
+    /// Whether the per-protocol results accept the proxy
+    pub fn accepts(self, results: &[(String, bool)]) -> bool {
+        match self {
+            TypeMatchMode::Any => results.iter().any(|(_, passed)| *passed),
+            TypeMatchMode::All => !results.is_empty() && results.iter().all(|(_, passed)| *passed),
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -120,8 +239,47 @@ pub struct Checker {
     pub support_cookie: bool,
 
     pub expected_types: Vec<String>,
+    /// Whether a proxy needs one or all of `expected_types`
+    pub type_match_mode: TypeMatchMode,
     pub expected_levels: Vec<String>,
     pub expected_countries: Vec<String>,
+    pub excluded_countries: Vec<String>,
+    pub expected_regions: Vec<String>,
+    pub expected_cities: Vec<String>,
+
+    /// Number of judges tried for a protocol before the proxy is declared bad
+    pub max_judge_retries: usize,
+    /// Headers that reveal a proxy when a judge echoes them back
+    pub leak_headers: Vec<String>,
+    /// Timeout of the TCP connect done before any judge work, `None` skips it
+    pub precheck_timeout: Option<Duration>,
+    /// Host header of HTTPS validation requests instead of the judge host
+    pub test_host: Option<String>,
+    /// SNI of the HTTPS validation handshake, defaults to `test_host` then the judge host.
+    /// The tunnel still goes to the judge. Without `verify_ssl` the certificate is accepted
+    /// through `danger_accept_invalid_certs` whatever its name; with it, the certificate must
+    /// match this SNI, so the judge has to serve the target's certificate.
+    pub test_sni: Option<String>,
+    /// User-Agents sent to judges instead of the built-in one, shared by clones
+    pub user_agents: Option<Arc<UserAgents>>,
+    /// Fetch a judge page directly and through working proxies to flag the ones
+    /// altering content, at the cost of one more request per proxy
+    pub check_integrity: bool,
+    /// Page echoing the caller IP, fetched through proxies when no judge works
+    pub fallback_endpoint: String,
+    /// Set while no judge works, proxies are then only checked for reachability
+    /// against `fallback_endpoint`. Shared by clones.
+    pub degraded: Arc<AtomicBool>,
+    /// Probe the handshakes of each proxy first and only check the protocols it
+    /// answers, each probe bounded by this timeout. `None` checks every expected type.
+    pub discover_timeout: Option<Duration>,
+    /// Recent verdicts shared by clones. A proxy that failed within
+    /// `VALIDATION_TTL_FAILING` is rejected without being checked again.
+    pub validation_cache: Option<Arc<ProxyValidationCache>>,
+    /// Page working proxies must fetch with a 2xx, e.g. the site they will be used for
+    pub validate_url: Option<Url>,
+    /// Marks the page from `validate_url` as a block or CAPTCHA page, even on a 200
+    pub block_signature: Option<Regex>,
 
     pub ext_ip: String,
     ip_re: Regex,
@@ -129,10 +287,40 @@ pub struct Checker {
     // DNSBL checking
     pub dnsbl_checker: Option<DnsblChecker>,
     pub dnsbl_config: DnsblConfig,
+    /// Bounds the DNSBL checks in flight across clones, apart from the connection semaphore
+    dnsbl_ip_limit: Arc<Semaphore>,
 }
 
 impl Checker {
+    /// Check `proxy`, unless `validation_cache` remembers it failing recently. Working
+    /// verdicts are cached too but always rechecked, as the cache does not keep the
+    /// protocols a proxy passed.
     pub async fn check_proxy(&mut self, proxy: &mut Proxy) -> bool {
+        let Some(cache) = self.validation_cache.clone() else {
+            return self.validate(proxy).await;
+        };
+        let key = proxy.as_text();
+        if cache.get(&key).await.is_some_and(|cached| !cached.is_working) {
+            proxy.is_working = false;
+            proxy.log("Failed a recent check, skipped", None, None);
+            return false;
+        }
+
+        let is_working = self.validate(proxy).await;
+        let result = ProxyValidationResult {
+            is_working,
+            response_time_ms: (proxy.avg_resp_time() * 1000.0) as u64,
+            error_count: proxy.error_stat.values().sum::<i32>() as u32,
+            last_checked: std::time::SystemTime::now(),
+            dnsbl_clean: !proxy.error_stat.contains_key("dnsbl_malicious"),
+        };
+        let ttl = if is_working { VALIDATION_TTL_WORKING } else { VALIDATION_TTL_FAILING };
+        cache.put_with_ttl(key, result, ttl, CacheLevel::L3).await;
+        is_working
+    }
+
+    async fn validate(&mut self, proxy: &mut Proxy) -> bool {
+        let stime = time::Instant::now();
         let expected_types = vec_of_strings![
             "CONNECT:80",
             "CONNECT:25",
@@ -142,6 +330,17 @@ impl Checker {
             "HTTP"
         ]; // proxy.expected_types.clone();
 
+        // A closed port fails fast instead of using the full timeout of every protocol
+        if let Some(limit) = self.precheck_timeout {
+            if !proxy.precheck(limit).await {
+                proxy.is_working = false;
+                PERFORMANCE_MONITOR
+                    .record_proxy_check(stime.elapsed(), false, false, Some(&proxy.geo.iso_code), &[])
+                    .await;
+                return false;
+            }
+        }
+
         // Perform DNSBL check first if enabled (early termination opportunity)
         if self.dnsbl_config.enabled {
             if let Err(e) = self.check_dnsbl(proxy).await {
@@ -151,22 +350,60 @@ impl Checker {
                 // If DNSBL marked proxy as malicious, we can skip further checks
                 if !proxy.is_working {
                     log::debug!("Skipping protocol checks for {} - DNSBL marked as malicious", proxy.host);
+                    PERFORMANCE_MONITOR
+                        .record_proxy_check(stime.elapsed(), false, true, Some(&proxy.geo.iso_code), &[])
+                        .await;
                     return false;
                 }
             }
         }
 
+        // Only the protocols the proxy answers in are worth a judge check
+        let discovered: Option<Vec<&str>> = match self.discover_timeout {
+            Some(probe_timeout) => {
+                let target = Judge::new(&self.fallback_endpoint).host;
+                proxy.discovered_protocols = discovery::discover_protocols(proxy, &target, probe_timeout).await;
+                Some(proxy.discovered_protocols.iter().flat_map(|p| discovery::types_for(p)).copied().collect())
+            }
+            None => None,
+        };
+
+        let degraded = self.degraded.load(Ordering::Relaxed);
+        let enabled = |proto: &String| {
+            let spoken = discovered.as_ref().is_none_or(|types| types.contains(&proto.as_str()));
+            spoken
+                && if degraded {
+                    DEGRADED_PROTOCOLS.contains(&proto.as_str())
+                } else {
+                    ENABLE_PROTOCOLS.lock().contains(proto)
+                }
+        };
+
         // Optimized protocol checking with early termination
         let mut result = Vec::with_capacity(expected_types.len());
         for proto in &expected_types {
+            // A requested type that cannot be checked, e.g. without a working judge, fails
+            if self.type_match_mode == TypeMatchMode::All && self.expected_types.contains(proto) && !enabled(proto) {
+                log::debug!("{} fails required protocol {}: it cannot be checked", proxy.host, proto);
+                result.push((proto.clone(), false));
+                break;
+            }
             if self.expected_types.contains(proto)
-                && ENABLE_PROTOCOLS.lock().contains(proto)
-                && (self.expected_countries.is_empty()
-                    || self.expected_countries.contains(&proxy.geo.iso_code))
+                && enabled(proto)
+                && proxy.geo.matches(
+                    &self.expected_countries,
+                    &self.expected_regions,
+                    &self.expected_cities,
+                )
+                && !proxy.geo.is_excluded(&self.excluded_countries)
             {
                 let mut is_working = false;
                 for _ in 0..self.max_tries {
-                    is_working = self.check_proto(proxy, proto).await;
+                    is_working = if degraded {
+                        self.check_proto_degraded(proxy, proto).await
+                    } else {
+                        self.check_proto(proxy, proto).await
+                    };
                     if is_working {
                         break;
                     }
@@ -174,13 +411,10 @@ impl Checker {
                 
                 // Early termination for HTTP if level checking fails
                 if proto == "HTTP" && is_working && !self.expected_levels.is_empty() {
-                    is_working = proxy.types.iter().any(|(_, level)| {
-                        if let Some(ref lvl) = level {
-                            self.expected_levels.contains(lvl)
-                        } else {
-                            false
-                        }
-                    });
+                    is_working = proxy
+                        .anonymity_level
+                        .as_ref()
+                        .is_some_and(|lvl| self.expected_levels.contains(lvl));
                     
                     // If this is the first protocol and it failed level check, 
                     // we might want to continue with other protocols
@@ -189,7 +423,7 @@ impl Checker {
                     }
                 }
                 
-                result.push(is_working);
+                result.push((proto.clone(), is_working));
                 
                 // Early termination: if we found a working protocol and 
                 // we're not in strict mode (checking all types), we can stop
@@ -197,10 +431,35 @@ impl Checker {
                     log::debug!("Early termination for {} - found working protocol: {}", proxy.host, proto);
                     break;
                 }
+                // One failed type already rejects the proxy when all are required
+                if !is_working && self.type_match_mode == TypeMatchMode::All {
+                    log::debug!("Early termination for {} - required protocol failed: {}", proxy.host, proto);
+                    break;
+                }
             }
         }
 
-        proxy.is_working = result.iter().any(|i| *i);
+        proxy.is_working = self.type_match_mode.accepts(&result);
+        let passed: Vec<&str> = result.iter().filter(|(_, ok)| *ok).map(|(proto, _)| proto.as_str()).collect();
+        if !passed.is_empty() {
+            proxy.log(&format!("Passed types: {}", passed.join(", ")), None, None);
+        }
+        if proxy.is_working && !degraded && self.validate_url.is_some() {
+            proxy.is_working = self.check_target(proxy).await;
+        }
+        if proxy.is_working {
+            proxy.last_verified = Some(chrono::Utc::now());
+            proxy.degraded = degraded;
+            LATENCIES.record(&proxy.host, proxy.port, Duration::from_secs_f64(proxy.avg_resp_time()));
+            if self.check_integrity && !degraded {
+                if let Some(judge) = self.get_judges("HTTP").await.first() {
+                    self.check_content_integrity(proxy, judge).await;
+                }
+            }
+        }
+        PERFORMANCE_MONITOR
+            .record_proxy_check(stime.elapsed(), proxy.is_working, false, Some(&proxy.geo.iso_code), &result)
+            .await;
         proxy.is_working
     }
 
@@ -208,10 +467,20 @@ impl Checker {
     pub async fn check_dnsbl(&mut self, proxy: &mut Proxy) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         if let Some(ref mut dnsbl_checker) = self.dnsbl_checker {
             log::debug!("Starting DNSBL check for proxy: {}", proxy.host);
-            
+
+            let _permit = Arc::clone(&self.dnsbl_ip_limit).acquire_owned().await?;
             let dnsbl_results = dnsbl_checker.check_ip(&proxy.host).await?;
-            
+            if dnsbl_results.is_inconclusive(self.dnsbl_config.max_error_rate) {
+                proxy.log("DNSBL: inconclusive", None, Some("dnsbl_inconclusive".to_string()));
+                return Err(format!(
+                    "{}/{} DNSBL lists could not be queried, result inconclusive",
+                    dnsbl_results.error_count, dnsbl_results.total_checked
+                )
+                .into());
+            }
+
             // Store DNSBL results in proxy for logging and reporting
+            proxy.dnsbl_listed = Some(dnsbl_results.listed_count);
             let dnsbl_summary = format!(
                 "DNSBL: {}/{} lists checked, {} malicious",
                 dnsbl_results.listed_count,
@@ -239,6 +508,7 @@ impl Checker {
     /// Enable DNSBL checking with custom configuration
     pub async fn enable_dnsbl(&mut self, config: DnsblConfig) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         self.dnsbl_config = config.clone();
+        self.dnsbl_ip_limit = Arc::new(Semaphore::new(config.max_concurrent_ips.max(1)));
         
         if config.enabled {
             log::info!("Initializing DNSBL checker with timeout: {}s, threshold: {}", 
@@ -263,6 +533,34 @@ impl Checker {
         Ok(())
     }
 
+    /// Apply a hot-reloaded DNSBL section, keeping the settings it does not cover and
+    /// whether DNSBL runs at all unless the edit flipped it
+    pub async fn reload_dnsbl(
+        &mut self,
+        reload: &crate::config::hot_reload::DnsblReload,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let update = &reload.config;
+        let config = DnsblConfig {
+            enabled: reload.enabled.unwrap_or(self.dnsbl_config.enabled),
+            timeout_secs: update.timeout_secs,
+            max_concurrent: update.max_concurrent,
+            cache_ttl_secs: update.cache_ttl_secs,
+            malicious_threshold: update.malicious_threshold as usize,
+            max_error_rate: update.max_error_rate,
+            dns_servers: update.dns_servers.clone(),
+            ..self.dnsbl_config.clone()
+        };
+
+        match self.dnsbl_checker.as_mut() {
+            Some(dnsbl_checker) if config.enabled => {
+                dnsbl_checker.update_config(config.clone()).await?;
+                self.dnsbl_config = config;
+                Ok(())
+            }
+            _ => self.enable_dnsbl(config).await,
+        }
+    }
+
     /// Get DNSBL statistics
     pub fn get_dnsbl_stats(&self) -> Option<&crate::dnsbl::cache::CacheStats> {
         self.dnsbl_checker.as_ref().map(|checker| checker.get_cache_stats())
@@ -270,54 +568,229 @@ impl Checker {
 
     pub async fn check_proto(&mut self, proxy: &mut Proxy, proto: &String) -> bool {
         proxy.negotiator_proto = proto.to_string();
-        let mut is_working = false;
-        if let Some(judge) = self.get_judge(proto).await {
-            proxy.log(format!("Selected judge: {}", judge).as_str(), None, None);
+        let judges = self.get_judges(proto).await;
+        self.check_proto_with_judges(proxy, proto, judges).await
+    }
 
-            if proto != "HTTPS" && !proxy.connect().await {
-                proxy.close().await;
-                return false;
-            }
+    /// Degraded check of a protocol without judges: the proxy must fetch the fallback
+    /// endpoint and get back a page holding an IP. The anonymity level stays unknown.
+    async fn check_proto_degraded(&mut self, proxy: &mut Proxy, proto: &String) -> bool {
+        proxy.negotiator_proto = proto.to_string();
+        let endpoint = Judge::new(&self.fallback_endpoint);
+        proxy.log(format!("Degraded check through: {}", endpoint).as_str(), None, None);
 
-            let (negotiate_success, use_full_path, check_anon_lvl) =
-                self.negotiate(proxy, &judge, proto).await;
-            if !negotiate_success {
-                proxy.close().await;
-                return false;
-            }
+        if !proxy.connect().await {
+            proxy.close().await;
+            return false;
+        }
+        let (negotiate_success, use_full_path, _) = self.negotiate(proxy, &endpoint, proto).await;
+        if !negotiate_success {
+            proxy.close().await;
+            return false;
+        }
+        let path = endpoint.url.path();
+        let target = if use_full_path {
+            format!("http://{}{}", endpoint.host, path)
+        } else {
+            path.to_string()
+        };
+        let request = format!("GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n", target, endpoint.host);
+        proxy.send(request.as_bytes()).await;
+        let response = proxy.recv_all().await.map(|data| ResponseParser::parse(&data));
+        proxy.close().await;
 
-            if proto == "CONNECT:25" {
-                proxy.types.push((proto.to_string(), None));
-                return true;
+        let reachable = response
+            .is_some_and(|response| response.status_code == Some(200) && self.ip_re.is_match(&response.body));
+        if reachable {
+            proxy.types.push((proto.to_string(), None));
+        } else {
+            proxy.log("Request: failed", None, Some("request_failed".to_string()));
+        }
+        reachable
+    }
+
+    /// Check a protocol against successive judges. After a 5xx the next judge is
+    /// tried, up to `max_judge_retries` judges; no answer at all is the proxy's
+    /// failure, as when it cannot connect. A 5xx is not held
+    /// against the judge, it cannot be told apart from a fault of the proxy: only the
+    /// direct pretest of `refresh_judges` takes judges out of rotation.
+    async fn check_proto_with_judges(&mut self, proxy: &mut Proxy, proto: &String, judges: Vec<Judge>) -> bool {
+        for judge in judges.iter().take(self.max_judge_retries.max(1)) {
+            match self.check_proto_with_judge(proxy, proto, judge).await {
+                JudgeOutcome::Working => {
+                    *JUDGE_SUCCESSES.lock().entry(judge.url.to_string()).or_default() += 1;
+                    return true;
+                }
+                JudgeOutcome::ProxyFailed => return false,
+                JudgeOutcome::JudgeFailed => {
+                    proxy.log(format!("Judge failed: {}, trying next judge", judge).as_str(), None, None);
+                }
             }
+        }
+        false
+    }
 
-            let path = judge.url.path().to_string();
-            let (raw_request, headers, rv) =
-                self.build_raw_request(&judge.host, &path, use_full_path, None);
+    async fn check_proto_with_judge(&mut self, proxy: &mut Proxy, proto: &String, judge: &Judge) -> JudgeOutcome {
+        proxy.log(format!("Selected judge: {}", judge).as_str(), None, None);
 
-            proxy.send(raw_request.as_bytes()).await;
-            if let Some(data) = proxy.recv_all().await {
-                proxy.log("Request: success", None, None);
-                let mut anonimity_lvl = None;
-                let response = ResponseParser::parse(data.as_slice());
+        if proto != "HTTPS" && !proxy.connect().await {
+            proxy.close().await;
+            return JudgeOutcome::ProxyFailed;
+        }
 
-                //log::warn!("=====\n{raw_request}\n{0}", response.raw);
+        let (negotiate_success, use_full_path, check_anon_lvl) =
+            self.negotiate(proxy, judge, proto).await;
+        if !negotiate_success {
+            proxy.close().await;
+            return JudgeOutcome::ProxyFailed;
+        }
 
-                if self.get_response_status(&response, headers, rv) {
-                    if check_anon_lvl {
-                        anonimity_lvl = Some(self.get_anonimity_level(&response, &judge.marks));
-                    }
+        if proto == "CONNECT:25" {
+            proxy.types.push((proto.to_string(), None));
+            return JudgeOutcome::Working;
+        }
+
+        let path = judge.url.path().to_string();
+        let host = match (proto.as_str(), &self.test_host) {
+            ("HTTPS", Some(test_host)) => test_host,
+            _ => &judge.host,
+        };
+        let (raw_request, headers, rv) = self.build_raw_request(host, &path, use_full_path, None);
+
+        proxy.send(raw_request.as_bytes()).await;
+        let outcome = if let Some(data) = proxy.recv_all().await {
+            proxy.log("Request: success", None, None);
+            let mut anonimity_lvl = None;
+            let response = ResponseParser::parse(data.as_slice());
 
-                    is_working = true;
-                    proxy.types.push((proto.to_string(), anonimity_lvl));
+            //log::warn!("=====\n{raw_request}\n{0}", response.raw);
+
+            if response.status_code.unwrap_or(0) >= 500 {
+                JudgeOutcome::JudgeFailed
+            } else if self.get_response_status(&response, headers, rv) {
+                if check_anon_lvl {
+                    let level = self.get_anonimity_level(&response.body, &judge.marks, &judge.baseline);
+                    proxy.anonymity_level = Some(level.clone());
+                    anonimity_lvl = Some(level);
                 }
-                proxy.close().await;
+
+                proxy.types.push((proto.to_string(), anonimity_lvl));
+                JudgeOutcome::Working
             } else {
-                proxy.log("Request: failed", None, Some("request_failed".to_string()));
+                JudgeOutcome::ProxyFailed
             }
+        } else {
+            proxy.log("Request: failed", None, Some("request_failed".to_string()));
+            JudgeOutcome::ProxyFailed
+        };
+        proxy.close().await;
+        outcome
+    }
+
+    /// Fetch the judge page directly and through the proxy and set `modifies_content`
+    /// when their markup differs. Text between tags is left out since judges echo the
+    /// request there, addresses and headers included. Only plain HTTP protocols can be
+    /// compared; `None` when the proxy has none of them or either fetch fails.
+    async fn check_content_integrity(&self, proxy: &mut Proxy, judge: &Judge) -> Option<bool> {
+        let proto = ["HTTP", "CONNECT:80", "SOCKS5", "SOCKS4"]
+            .into_iter()
+            .find(|proto| proxy.types.iter().any(|(working, _)| working == proto))?
+            .to_string();
+        let direct = fetch_direct(judge, self.timeout).await?;
+
+        proxy.negotiator_proto = proto.clone();
+        if !proxy.connect().await {
+            proxy.close().await;
+            return None;
         }
+        let (negotiate_success, use_full_path, _) = self.negotiate(proxy, judge, &proto).await;
+        if !negotiate_success {
+            proxy.close().await;
+            return None;
+        }
+        let path = judge.url.path();
+        let target = if use_full_path {
+            format!("http://{}{}", judge.host, path)
+        } else {
+            path.to_string()
+        };
+        let request = format!("GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n", target, judge.host);
+        proxy.send(request.as_bytes()).await;
+        let response = proxy.recv_all().await.map(|data| ResponseParser::parse(&data));
+        proxy.close().await;
 
-        is_working
+        let response = response.filter(|response| response.status_code == Some(200))?;
+        let modifies_content = markup_digest(&response.body) != markup_digest(&direct);
+        if modifies_content {
+            proxy.log("Integrity: content modified", None, None);
+        }
+        proxy.modifies_content = modifies_content;
+        Some(modifies_content)
+    }
+
+    /// Fetch `validate_url` through the proxy, which then fails when the answer is an
+    /// error status or matches `block_signature`. A redirect passes, the site answered.
+    /// The outcome is kept in `blocked`.
+    async fn check_target(&self, proxy: &mut Proxy) -> bool {
+        let Some(url) = &self.validate_url else {
+            return true;
+        };
+        let target = Judge::new(url.as_str());
+        let Some((status, body)) = self.fetch_through(proxy, &target).await else {
+            proxy.blocked = None;
+            proxy.log("Target: failed", None, Some("target_failed".to_string()));
+            return false;
+        };
+        let blocked = if !(200..400).contains(&status) {
+            Some(format!("Target: blocked with status {}", status))
+        } else if self.block_signature.as_ref().is_some_and(|sig| sig.is_match(&body)) {
+            Some(format!("Target: blocked, status {} with the block signature", status))
+        } else {
+            None
+        };
+        proxy.blocked = Some(blocked.is_some());
+        match blocked {
+            Some(msg) => {
+                proxy.log(&msg, None, Some("target_blocked".to_string()));
+                false
+            }
+            None => true,
+        }
+    }
+
+    /// Status and body of `target` fetched through the proxy, over HTTPS for an `https`
+    /// URL and plain HTTP protocols otherwise. `None` when the proxy passed none of
+    /// them or the fetch fails.
+    async fn fetch_through(&self, proxy: &mut Proxy, target: &Judge) -> Option<(u16, String)> {
+        let protos: &[&str] = if target.scheme == "HTTPS" {
+            &["HTTPS"]
+        } else {
+            &["HTTP", "CONNECT:80", "SOCKS5", "SOCKS4"]
+        };
+        let proto = protos
+            .iter()
+            .find(|proto| proxy.types.iter().any(|(working, _)| working == *proto))?
+            .to_string();
+
+        proxy.negotiator_proto = proto.clone();
+        if proto != "HTTPS" && !proxy.connect().await {
+            proxy.close().await;
+            return None;
+        }
+        let (negotiate_success, use_full_path, _) = self.negotiate(proxy, target, &proto).await;
+        if !negotiate_success {
+            proxy.close().await;
+            return None;
+        }
+        let host = target.url[Position::BeforeHost..Position::AfterPort].to_string();
+        let path = target.url[Position::BeforePath..Position::AfterQuery].to_string();
+        let (request, _, _) = self.build_raw_request(&host, &path, use_full_path, None);
+        proxy.send(request.as_bytes()).await;
+        let response = proxy.recv_all().await.map(|data| ResponseParser::parse(&data));
+        proxy.close().await;
+
+        let response = response?;
+        Some((response.status_code?, response.body))
     }
 
     async fn negotiate(
@@ -355,7 +828,11 @@ impl Checker {
                 negotiator.check_anon_lvl,
             )
         } else if proto == "HTTPS" {
-            let negotiator = HttpsNegotiator::default();
+            let negotiator = HttpsNegotiator {
+                sni: self.test_sni.clone().or_else(|| self.test_host.clone()),
+                ..Default::default()
+            };
+            proxy.verify_ssl = self.verify_ssl;
             (
                 negotiator.negotiate(proxy, judge).await,
                 negotiator.use_full_path,
@@ -397,16 +874,41 @@ impl Checker {
         via
     }
 
-    fn get_anonimity_level(
-        &self,
-        response: &ResponseParser,
-        marks: &BTreeMap<String, usize>,
-    ) -> String {
-        let content = response.body.to_lowercase();
+    /// Name of the first leak header found in a lowercased judge response but not in
+    /// its direct `baseline`, judges echo headers either as sent (`x-real-ip`) or CGI
+    /// style (`http_x_real_ip`)
+    fn find_leak_header(&self, content: &str, baseline: &str) -> Option<&str> {
+        self.leak_headers
+            .iter()
+            .find(|header| {
+                let name = header.to_lowercase();
+                let cgi_name = format!("http_{}", name.replace('-', "_"));
+                let echoed = |text: &str| text.contains(&cgi_name) || text.contains(&format!("{}:", name));
+                echoed(content) && !echoed(baseline)
+            })
+            .map(String::as_str)
+    }
+
+    /// Whether a `Forwarded` header echoed by the judge names our real IP in a `for=`,
+    /// which also catches IPv6 addresses the IP regex misses
+    fn forwarded_leaks_ext_ip(&self, content: &str) -> bool {
+        forwarded::echoed_values(content)
+            .into_iter()
+            .flat_map(forwarded::forwarded_for)
+            .any(|node| node.eq_ignore_ascii_case(&self.ext_ip))
+    }
+
+    /// Classify a judge response body: Transparent when our real IP leaks, Anonymous
+    /// when via/proxy marks or leak headers beyond the judge's own show up, High otherwise
+    fn get_anonimity_level(&self, body: &str, marks: &BTreeMap<String, usize>, baseline: &str) -> String {
+        let content = body.to_lowercase();
         let extracted_ips = self.extract_ips_from_content(&content);
 
-        if extracted_ips.contains(&self.ext_ip) {
+        if extracted_ips.contains(&self.ext_ip) || self.forwarded_leaks_ext_ip(&content) {
             "Transparent".to_string()
+        } else if let Some(header) = self.find_leak_header(&content, baseline) {
+            log::debug!("Judge response carries leak header {}", header);
+            "Anonymous".to_string()
         } else if self.is_proxy_detected(&content, marks) {
             "Anonymous".to_string()
         } else {
@@ -459,7 +961,19 @@ impl Checker {
             format!("{} {} HTTP/1.1\r\n", self.method, path)
         };
 
-        let (mut headers, rv) = get_headers(true);
+        let (mut headers, mut rv) = get_headers(true);
+        if let Some(user_agent) = self.user_agents.as_ref().and_then(|agents| agents.next()) {
+            // The built-in User-Agent carries the value judges must echo back, a
+            // configured one is sent as is and the Referer carries it instead, when
+            // Referer support is checked. Otherwise nothing carries it.
+            headers.insert("User-Agent".to_string(), user_agent);
+            if self.support_referer {
+                headers.insert("Referer".to_string(), format!("https://google.com/?q={}", rv));
+            } else {
+                headers.remove("Referer");
+                rv.clear();
+            }
+        }
         let data = data.unwrap_or("".to_string());
         headers.insert("Host".to_string(), host.to_string());
         headers.insert("Connection".to_string(), "close".to_string());
@@ -479,7 +993,8 @@ impl Checker {
         (request, headers, rv)
     }
 
-    async fn get_judge(&mut self, proto: &str) -> Option<Judge> {
+    /// Working judges for a protocol in random order, waiting for the judges pretest if needed
+    async fn get_judges(&mut self, proto: &str) -> Vec<Judge> {
         let mut scheme = "HTTP".to_string();
         if proto.eq("HTTPS") {
             scheme = "HTTPS".to_string();
@@ -488,35 +1003,82 @@ impl Checker {
         }
 
         let t = time::Instant::now();
-        while !JUDGES.read().await.contains_key(&scheme) {
+        loop {
+            let mut judges: Vec<Judge> = {
+                let manager = OPTIMIZED_JUDGE_MANAGER.read().await;
+                manager
+                    .get_working_judges(&scheme, usize::MAX)
+                    .await
+                    .into_iter()
+                    .filter(|info| info.scheme == scheme)
+                    .map(Judge::from)
+                    .collect()
+            };
+            if !judges.is_empty() {
+                judges.shuffle(&mut *JUDGE_RNG.lock());
+                return judges;
+            }
+
             if t.elapsed() >= Duration::from_secs(JUDGE_LOOKUP_TIMEOUT_SECS) {
                 log::error!("Timeout error: no judges found");
                 while *DOWNLOADING.lock() {
                     continue;
                 }
-                // Return None instead of exiting to allow graceful error handling
-                return None;
+                // Return no judge instead of exiting to allow graceful error handling
+                return Vec::new();
             }
+            time::sleep(Duration::from_millis(100)).await;
         }
+    }
+}
 
-        {
-            let judges_map = JUDGES.read().await;
-            if let Some(v) = judges_map.get(&scheme) {
-                if let Some(judge) = v.choose(&mut thread_rng()) {
-                    return Some(judge.clone());
-                }
-            }
-        }
+/// Body of the judge page fetched without a proxy, `None` unless it answers 200
+async fn fetch_direct(judge: &Judge, timeout: i32) -> Option<String> {
+    let request = Request::builder().uri(judge.url.as_str()).body(Empty::new()).ok()?;
+    let response = time::timeout(Duration::from_secs(timeout.max(1) as u64), hyper_client().request(request))
+        .await
+        .ok()?
+        .ok()?;
+    if !response.status().is_success() {
+        return None;
+    }
+    let body = response.into_body().collect().await.ok()?.to_bytes();
+    Some(String::from_utf8_lossy(&body).to_string())
+}
 
-        None
+/// Hash of the tags of an HTML page, lowercased and in order
+fn markup_digest(body: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    let mut rest = body;
+    while let Some(start) = rest.find('<') {
+        let Some(end) = rest[start..].find('>') else {
+            break;
+        };
+        rest[start..start + end + 1].to_lowercase().hash(&mut hasher);
+        rest = &rest[start + end + 1..];
     }
+    hasher.finish()
+}
+
+/// Result of checking a proxy through one judge
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum JudgeOutcome {
+    Working,
+    /// The proxy failed, another judge would not help
+    ProxyFailed,
+    /// The judge answered with a server error, another judge may succeed
+    JudgeFailed,
 }
 
 impl Checker {
     pub async fn new() -> Self {
         let resolver = Resolver::new();
         let ext_ip = resolver.get_real_ext_ip().await;
-        
+        Self::with_ext_ip(ext_ip)
+    }
+
+    /// Create a checker for an already known external IP
+    pub fn with_ext_ip(ext_ip: String) -> Self {
         Checker {
             verify_ssl: false,
             timeout: 5,
@@ -525,8 +1087,25 @@ impl Checker {
             support_cookie: false,
             support_referer: false,
             expected_types: vec![],
+            type_match_mode: TypeMatchMode::Any,
             expected_countries: vec![],
+            excluded_countries: vec![],
+            expected_regions: vec![],
+            expected_cities: vec![],
             expected_levels: vec![],
+            max_judge_retries: 3,
+            leak_headers: default_leak_headers(),
+            precheck_timeout: None,
+            test_host: None,
+            test_sni: None,
+            user_agents: None,
+            check_integrity: false,
+            fallback_endpoint: DEFAULT_FALLBACK_ENDPOINT.to_string(),
+            degraded: Arc::new(AtomicBool::new(false)),
+            discover_timeout: None,
+            validation_cache: None,
+            validate_url: None,
+            block_signature: None,
             ip_re: match Regex::new(r#"\d+\.\d+\.\d+\.\d+"#) {
                 Ok(regex) => regex,
                 Err(e) => {
@@ -534,9 +1113,489 @@ impl Checker {
                     Regex::new(r#".*"#).unwrap() // fallback regex
                 }
             },
-            ext_ip: ext_ip.clone(),
+            ext_ip,
             dnsbl_checker: None,
             dnsbl_config: DnsblConfig::default(),
+            dnsbl_ip_limit: Arc::new(Semaphore::new(DnsblConfig::default().max_concurrent_ips)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// HTTP proxy stub answering 503 for `dead.judge`, closing without an answer for
+    /// `silent.judge` and echoing the request for other judges
+    async fn proxy_stub() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buf = [0; 1024];
+                    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                        match stream.read(&mut buf).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => request.extend_from_slice(&buf[..n]),
+                        }
+                    }
+                    let request = String::from_utf8_lossy(&request).to_string();
+                    if request.contains("silent.judge") {
+                        return;
+                    }
+                    let response = if request.contains("dead.judge") {
+                        "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\n\r\n".to_string()
+                    } else {
+                        let body = format!("REMOTE_ADDR = 203.0.113.7\n{}", request);
+                        format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}", body.len(), body)
+                    };
+                    let _ = stream.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        port
+    }
+
+    #[tokio::test]
+    async fn test_retry_with_next_judge() {
+        let port = proxy_stub().await;
+        let mut checker = Checker::with_ext_ip("198.51.100.1".to_string());
+        let mut proxy = Proxy::create("127.0.0.1", port, vec!["HTTP".to_string()]).await.unwrap();
+        let proto = "HTTP".to_string();
+        let judges = vec![Judge::new("http://dead.judge/azenv.php"), Judge::new("http://live.judge/azenv.php")];
+
+        assert!(checker.check_proto_with_judges(&mut proxy, &proto, judges.clone()).await);
+        assert_eq!(proxy.types, vec![("HTTP".to_string(), Some("High".to_string()))]);
+        assert_eq!(proxy.anonymity_level.as_deref(), Some("High"));
+
+        // Without retries the dead judge is the only one tried
+        checker.max_judge_retries = 1;
+        let mut proxy = Proxy::create("127.0.0.1", port, vec!["HTTP".to_string()]).await.unwrap();
+        assert!(!checker.check_proto_with_judges(&mut proxy, &proto, judges.clone()).await);
+
+        // No answer is the proxy's failure, the next judge is not tried
+        checker.max_judge_retries = 3;
+        let mut proxy = Proxy::create("127.0.0.1", port, vec!["HTTP".to_string()]).await.unwrap();
+        let silent = vec![Judge::new("http://silent.judge/azenv.php"), judges[1].clone()];
+        assert!(!checker.check_proto_with_judges(&mut proxy, &proto, silent).await);
+        assert!(proxy.types.is_empty());
+
+        // Only the success is recorded, the failure may be the proxy's
+        let successes = JUDGE_SUCCESSES.lock();
+        assert!(successes.get(judges[1].url.as_str()).is_some_and(|&count| count >= 1));
+        assert!(!successes.contains_key(judges[0].url.as_str()));
+    }
+
+    fn judge_marks(via: usize, proxy: usize) -> BTreeMap<String, usize> {
+        BTreeMap::from([("via".to_string(), via), ("proxy".to_string(), proxy)])
+    }
+
+    #[test]
+    fn test_check_requests_cycle_user_agents() {
+        use crate::utils::http::user_agents::Rotation;
+
+        let mut checker = Checker::with_ext_ip("198.51.100.1".to_string());
+        checker.support_referer = true;
+        let agents = vec!["Mozilla/5.0 (X11; Linux x86_64)".to_string(), "curl/8.5.0".to_string()];
+        checker.user_agents = Some(Arc::new(UserAgents::new(agents.clone(), Rotation::RoundRobin)));
+        // Clones share the rotation
+        let clone = checker.clone();
+
+        let (host, path) = ("judge.example".to_string(), "/azenv.php".to_string());
+        let mut sent = vec![];
+        for checker in [&checker, &clone, &checker] {
+            let (request, headers, rv) = checker.build_raw_request(&host, &path, false, None);
+            assert!(request.contains(&format!("User-Agent: {}\r\n", headers["User-Agent"])));
+            assert!(headers["Referer"].ends_with(&rv));
+            sent.push(headers["User-Agent"].clone());
         }
+        assert_eq!(sent, [agents[0].clone(), agents[1].clone(), agents[0].clone()]);
+
+        // No Referer unless its support is checked
+        checker.support_referer = false;
+        let (request, headers, rv) = checker.build_raw_request(&host, &path, false, None);
+        assert!(!headers.contains_key("Referer"));
+        assert!(!request.contains("Referer"));
+        assert!(rv.is_empty());
+
+        // Without a configured list the built-in User-Agent carries the value
+        checker.user_agents = None;
+        let (_, headers, rv) = checker.build_raw_request(&host, &path, false, None);
+        assert!(headers["User-Agent"].starts_with("proxy-rs/"));
+        assert!(headers["User-Agent"].ends_with(&rv));
+    }
+
+    #[test]
+    fn test_anonymity_high() {
+        let checker = Checker::with_ext_ip("198.51.100.1".to_string());
+        let body = "REMOTE_ADDR = 203.0.113.7\nHTTP_USER_AGENT = proxy-rs/0.4.0/1234\nHTTP_VIA = judge-frontend";
+        // The judge's own "via" must not count against the proxy
+        assert_eq!(checker.get_anonimity_level(body, &judge_marks(1, 0), ""), "High");
+    }
+
+    #[test]
+    fn test_anonymity_anonymous() {
+        let checker = Checker::with_ext_ip("198.51.100.1".to_string());
+        let body = "REMOTE_ADDR = 203.0.113.7\nHTTP_VIA = 1.1 squid\nHTTP_PROXY_CONNECTION = keep-alive";
+        assert_eq!(checker.get_anonimity_level(body, &judge_marks(0, 0), ""), "Anonymous");
+    }
+
+    #[test]
+    fn test_anonymity_transparent() {
+        let checker = Checker::with_ext_ip("198.51.100.1".to_string());
+        let body = "REMOTE_ADDR = 203.0.113.7\nHTTP_X_FORWARDED_FOR = 198.51.100.1\nHTTP_VIA = 1.1 squid";
+        assert_eq!(checker.get_anonimity_level(body, &judge_marks(0, 0), ""), "Transparent");
+    }
+
+    #[test]
+    fn test_leak_headers_downgrade() {
+        let checker = Checker::with_ext_ip("198.51.100.1".to_string());
+        let leaks = [
+            "HTTP_X_FORWARDED_FOR = 10.0.0.1",
+            "HTTP_X_REAL_IP = 10.0.0.1",
+            "HTTP_FORWARDED = for=10.0.0.1",
+            "HTTP_CLIENT_IP = 10.0.0.1",
+            "Client-IP: unknown",
+        ];
+        for leak in leaks {
+            let body = format!("REMOTE_ADDR = 203.0.113.7\n{}", leak);
+            assert_eq!(checker.get_anonimity_level(&body, &judge_marks(0, 0), ""), "Anonymous", "{}", leak);
+        }
+
+        // The real IP in any leak header makes the proxy transparent
+        let body = "REMOTE_ADDR = 203.0.113.7\nHTTP_X_REAL_IP = 198.51.100.1";
+        assert_eq!(checker.get_anonimity_level(body, &judge_marks(0, 0), ""), "Transparent");
+
+        // Only the configured headers count
+        let mut checker = checker;
+        checker.leak_headers = vec!["X-Custom-Origin".to_string()];
+        let body = "REMOTE_ADDR = 203.0.113.7\nHTTP_X_REAL_IP = 10.0.0.1";
+        assert_eq!(checker.get_anonimity_level(body, &judge_marks(0, 0), ""), "High");
+        let body = "REMOTE_ADDR = 203.0.113.7\nHTTP_X_CUSTOM_ORIGIN = 10.0.0.1";
+        assert_eq!(checker.get_anonimity_level(body, &judge_marks(0, 0), ""), "Anonymous");
+    }
+
+    #[test]
+    fn test_judge_own_headers_are_not_leaks() {
+        let checker = Checker::with_ext_ip("198.51.100.1".to_string());
+        // A judge behind a CDN already echoes a forwarded-for in its direct response
+        let baseline = "remote_addr = 192.0.2.10\nhttp_x_forwarded_for = 192.0.2.10\nhttp_x_real_ip = 192.0.2.10";
+        let body = "REMOTE_ADDR = 192.0.2.10\nHTTP_X_FORWARDED_FOR = 203.0.113.7\nHTTP_X_REAL_IP = 203.0.113.7";
+        assert_eq!(checker.get_anonimity_level(body, &judge_marks(0, 0), baseline), "High");
+
+        // A header the judge does not add on its own still gives the proxy away
+        let body = format!("{}\nHTTP_CLIENT_IP = 10.0.0.1", body);
+        assert_eq!(checker.get_anonimity_level(&body, &judge_marks(0, 0), baseline), "Anonymous");
+    }
+
+    #[test]
+    fn test_forwarded_header_leaks() {
+        let checker = Checker::with_ext_ip("2001:db8:cafe::17".to_string());
+        let leaks = [
+            "HTTP_FORWARDED = for=\"[2001:db8:cafe::17]:4711\"",
+            "HTTP_FORWARDED = for=192.0.2.43, for=\"[2001:DB8:CAFE::17]\";proto=https",
+            "Forwarded: for=\"2001:db8:cafe::17\";by=_proxy",
+        ];
+        for leak in leaks {
+            let body = format!("REMOTE_ADDR = 203.0.113.7\n{}", leak);
+            assert_eq!(checker.get_anonimity_level(&body, &judge_marks(0, 0), ""), "Transparent", "{}", leak);
+        }
+
+        // Other hops and obfuscated identifiers only reveal a proxy
+        for header in ["HTTP_FORWARDED = for=_hidden;by=_proxy", "HTTP_FORWARDED = for=unknown, for=\"[2001:db8::1]\""] {
+            let body = format!("REMOTE_ADDR = 203.0.113.7\n{}", header);
+            assert_eq!(checker.get_anonimity_level(&body, &judge_marks(0, 0), ""), "Anonymous", "{}", header);
+        }
+    }
+
+    /// Server name of a TLS ClientHello, read from its SNI extension
+    fn client_hello_sni(hello: &[u8]) -> Option<String> {
+        // Record and handshake headers, version, random
+        let mut pos = 5 + 4 + 2 + 32;
+        pos += 1 + *hello.get(pos)? as usize;
+        pos += 2 + u16::from_be_bytes([*hello.get(pos)?, *hello.get(pos + 1)?]) as usize;
+        pos += 1 + *hello.get(pos)? as usize;
+        let end = pos + 2 + u16::from_be_bytes([*hello.get(pos)?, *hello.get(pos + 1)?]) as usize;
+        pos += 2;
+        while pos + 4 <= end {
+            let kind = u16::from_be_bytes([hello[pos], hello[pos + 1]]);
+            let len = u16::from_be_bytes([hello[pos + 2], hello[pos + 3]]) as usize;
+            if kind == 0 {
+                // List length, name type, name length
+                let name_len = u16::from_be_bytes([*hello.get(pos + 7)?, *hello.get(pos + 8)?]) as usize;
+                let name = hello.get(pos + 9..pos + 9 + name_len)?;
+                return Some(String::from_utf8_lossy(name).to_string());
+            }
+            pos += 4 + len;
+        }
+        None
+    }
+
+    #[tokio::test]
+    async fn test_https_check_sends_configured_sni() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (seen_tx, seen_rx) = tokio::sync::oneshot::channel();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0; 4096];
+            let n = stream.read(&mut buf).await.unwrap();
+            let connect = String::from_utf8_lossy(&buf[..n]).to_string();
+            stream.write_all(b"HTTP/1.1 200 Connection established\r\n\r\n").await.unwrap();
+            let n = stream.read(&mut buf).await.unwrap();
+            let _ = seen_tx.send((connect, client_hello_sni(&buf[..n])));
+        });
+
+        let mut checker = Checker::with_ext_ip("198.51.100.1".to_string());
+        checker.test_sni = Some("target.example".to_string());
+        let mut proxy = Proxy::create("127.0.0.1", port, vec!["HTTPS".to_string()]).await.unwrap();
+        let proto = "HTTPS".to_string();
+        let judges = vec![Judge::new("https://judge.example/azenv.php")];
+        assert!(!checker.check_proto_with_judges(&mut proxy, &proto, judges).await);
+
+        let (connect, sni) = seen_rx.await.unwrap();
+        // The tunnel goes to the judge, only the handshake names the target
+        assert!(connect.starts_with("CONNECT judge.example:443 "));
+        assert_eq!(sni.as_deref(), Some("target.example"));
+    }
+
+    #[tokio::test]
+    async fn test_dnsbl_checks_are_bounded_across_clones() {
+        let mut checker = Checker::with_ext_ip("198.51.100.1".to_string());
+        let config = DnsblConfig {
+            enabled: true,
+            max_concurrent_ips: 2,
+            whitelisted_ips: vec!["127.0.0.0/8".to_string()],
+            ..DnsblConfig::default()
+        };
+        checker.enable_dnsbl(config).await.unwrap();
+        assert_eq!(checker.dnsbl_ip_limit.available_permits(), 2);
+
+        // Two checks in flight on other clones hold every permit
+        let in_flight = Arc::clone(&checker.clone().dnsbl_ip_limit).acquire_many_owned(2).await.unwrap();
+        let mut proxy = Proxy::create("127.0.0.1", 8080, vec![]).await.unwrap();
+        let mut third = checker.clone();
+        assert!(time::timeout(Duration::from_millis(100), third.check_dnsbl(&mut proxy)).await.is_err());
+
+        drop(in_flight);
+        time::timeout(Duration::from_secs(1), third.check_dnsbl(&mut proxy))
+            .await
+            .expect("DNSBL check still waiting after permits were released")
+            .unwrap();
+        assert!(proxy.logs.iter().any(|(_, msg, _)| msg.contains("DNSBL")));
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_dnsbl_fails_soft() {
+        // Nothing answers DNS on a port freed right away
+        let port = std::net::UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let mut checker = Checker::with_ext_ip("198.51.100.1".to_string());
+        let config = DnsblConfig {
+            enabled: true,
+            timeout_secs: 1,
+            resolver_attempts: 1,
+            specific_lists: vec!["zen".to_string()],
+            dns_servers: vec![format!("127.0.0.1:{}", port)],
+            ..DnsblConfig::default()
+        };
+        checker.enable_dnsbl(config).await.unwrap();
+
+        let mut proxy = Proxy::create("192.0.2.1", 8080, vec![]).await.unwrap();
+        proxy.is_working = true;
+        let err = checker.check_dnsbl(&mut proxy).await.unwrap_err();
+        assert!(err.to_string().contains("inconclusive"), "{}", err);
+        assert!(proxy.is_working);
+        assert_eq!(proxy.error_stat.get("dnsbl_inconclusive"), Some(&1));
+    }
+
+    #[tokio::test]
+    async fn test_reload_keeps_dnsbl_choice_of_the_run() {
+        use crate::config::{dynamic::DynamicConfig, hot_reload::DnsblReload};
+
+        // Run started without --dnsbl-check, the reloaded file has `enabled = true`
+        let mut checker = Checker::with_ext_ip("198.51.100.1".to_string());
+        let mut config = DynamicConfig::new().dnsbl;
+        config.timeout_secs = 9;
+        config.dns_servers = vec!["10.0.0.53".to_string()];
+        checker.reload_dnsbl(&DnsblReload { config, enabled: None }).await.unwrap();
+        assert!(!checker.dnsbl_config.enabled);
+        assert!(checker.dnsbl_checker.is_none());
+        assert_eq!(checker.dnsbl_config.timeout_secs, 9);
+        assert_eq!(checker.dnsbl_config.dns_servers, ["10.0.0.53"]);
+
+        // An edit turning DNSBL off applies to a run that had it on
+        checker.dnsbl_config.enabled = true;
+        let config = DynamicConfig::new().dnsbl;
+        checker.reload_dnsbl(&DnsblReload { config, enabled: Some(false) }).await.unwrap();
+        assert!(!checker.dnsbl_config.enabled);
+    }
+
+    /// Server answering every request with `body`, returns its port
+    async fn page_stub(body: &'static str) -> u16 {
+        status_stub("200 OK", body).await
+    }
+
+    /// Server answering every request with `status` and `body`, returns its port
+    async fn status_stub(status: &'static str, body: &'static str) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut buf = [0; 1024];
+                    let _ = stream.read(&mut buf).await;
+                    let response = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\n\r\n{}", status, body.len(), body);
+                    let _ = stream.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        port
+    }
+
+    #[tokio::test]
+    async fn test_integrity_check_flags_modified_content() {
+        let judge_port = page_stub("<html><body><pre>REMOTE_ADDR = 198.51.100.1</pre></body></html>").await;
+        let judge = Judge::new(&format!("http://127.0.0.1:{}/azenv.php", judge_port));
+        let checker = Checker::with_ext_ip("198.51.100.1".to_string());
+
+        // Same markup, only the echoed text differs
+        let honest = page_stub("<html><body><pre>REMOTE_ADDR = 203.0.113.7</pre></body></html>").await;
+        let mut proxy = Proxy::create("127.0.0.1", honest, vec!["HTTP".to_string()]).await.unwrap();
+        proxy.types.push(("HTTP".to_string(), Some("High".to_string())));
+        assert_eq!(checker.check_content_integrity(&mut proxy, &judge).await, Some(false));
+        assert!(!proxy.modifies_content);
+
+        let injecting = page_stub(
+            "<html><body><pre>REMOTE_ADDR = 203.0.113.7</pre><script src=\"//ads.example/a.js\"></script></body></html>",
+        )
+        .await;
+        let mut proxy = Proxy::create("127.0.0.1", injecting, vec!["HTTP".to_string()]).await.unwrap();
+        proxy.types.push(("HTTP".to_string(), Some("High".to_string())));
+        assert_eq!(checker.check_content_integrity(&mut proxy, &judge).await, Some(true));
+        assert!(proxy.modifies_content);
+    }
+
+    #[tokio::test]
+    async fn test_captcha_page_rejects_proxy() {
+        let mut checker = Checker::with_ext_ip("198.51.100.1".to_string());
+        checker.validate_url = Some(Url::parse("http://shop.example/products?page=1").unwrap());
+        checker.block_signature = Some(Regex::new("(?i)captcha").unwrap());
+
+        // The proxy answers 200, but with a challenge instead of the page
+        let blocking = page_stub("<html><body>Please solve the CAPTCHA to continue</body></html>").await;
+        let mut proxy = Proxy::create("127.0.0.1", blocking, vec!["HTTP".to_string()]).await.unwrap();
+        proxy.types.push(("HTTP".to_string(), Some("High".to_string())));
+        assert!(!checker.check_target(&mut proxy).await);
+        assert_eq!(proxy.blocked, Some(true));
+        assert!(proxy.error_stat.contains_key("target_blocked"));
+
+        let clean = page_stub("<html><body>Products</body></html>").await;
+        let mut proxy = Proxy::create("127.0.0.1", clean, vec!["HTTP".to_string()]).await.unwrap();
+        proxy.types.push(("HTTP".to_string(), Some("High".to_string())));
+        assert!(checker.check_target(&mut proxy).await);
+        assert_eq!(proxy.blocked, Some(false));
+
+        // A redirect to a locale or login page still reached the site
+        let redirecting = status_stub("302 Found", "").await;
+        let mut proxy = Proxy::create("127.0.0.1", redirecting, vec!["HTTP".to_string()]).await.unwrap();
+        proxy.types.push(("HTTP".to_string(), Some("High".to_string())));
+        assert!(checker.check_target(&mut proxy).await);
+
+        let forbidden = status_stub("403 Forbidden", "Access denied").await;
+        let mut proxy = Proxy::create("127.0.0.1", forbidden, vec!["HTTP".to_string()]).await.unwrap();
+        proxy.types.push(("HTTP".to_string(), Some("High".to_string())));
+        assert!(!checker.check_target(&mut proxy).await);
+        assert!(proxy.logs.iter().any(|(_, msg, _)| msg == "Target: blocked with status 403"));
+    }
+
+    #[test]
+    fn test_type_match_mode_with_one_of_two_types() {
+        let results = vec![("SOCKS5".to_string(), true), ("HTTPS".to_string(), false)];
+        assert!(TypeMatchMode::Any.accepts(&results));
+        assert!(!TypeMatchMode::All.accepts(&results));
+
+        let both = vec![("SOCKS5".to_string(), true), ("HTTPS".to_string(), true)];
+        assert!(TypeMatchMode::All.accepts(&both));
+        assert!(!TypeMatchMode::All.accepts(&[]));
+        assert_eq!("ALL".parse::<TypeMatchMode>(), Ok(TypeMatchMode::All));
+        assert!("most".parse::<TypeMatchMode>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_degraded_mode_reports_reachable_proxies() {
+        // No judge works, the proxy stub answers the fallback request with an IP
+        let port = page_stub("203.0.113.7").await;
+        let mut checker = Checker::with_ext_ip("198.51.100.1".to_string());
+        update_degraded(&checker.degraded, 0);
+        checker.expected_types = vec!["HTTP".to_string()];
+        checker.fallback_endpoint = "http://echo.invalid/ip".to_string();
+        let mut proxy = Proxy::create("127.0.0.1", port, vec!["HTTP".to_string()]).await.unwrap();
+
+        let working = checker.check_proxy(&mut proxy).await;
+        let no_ip = page_stub("unavailable").await;
+        let mut broken = Proxy::create("127.0.0.1", no_ip, vec!["HTTP".to_string()]).await.unwrap();
+        let broken_working = checker.check_proxy(&mut broken).await;
+        // HTTPS needs a judge, a proxy required to pass it cannot be accepted
+        checker.expected_types.push("HTTPS".to_string());
+        checker.type_match_mode = TypeMatchMode::All;
+        let mut https = Proxy::create("127.0.0.1", port, vec![]).await.unwrap();
+        let https_working = checker.check_proxy(&mut https).await;
+
+        assert!(working);
+        assert!(proxy.degraded);
+        assert_eq!(proxy.types, vec![("HTTP".to_string(), None)]);
+        assert_eq!(proxy.anonymity_level, None);
+        assert!(!broken_working);
+        assert!(!broken.degraded);
+        assert!(!https_working);
+
+        // A judge working again ends degraded mode for every clone
+        let clone = checker.clone();
+        update_degraded(&checker.degraded, 1);
+        assert!(!clone.degraded.load(Ordering::Relaxed));
+    }
+
+    #[tokio::test]
+    async fn test_closed_port_fails_precheck_fast() {
+        let port = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().port();
+        let mut checker = Checker::with_ext_ip("198.51.100.1".to_string());
+        checker.expected_types = vec!["HTTP".to_string()];
+        checker.precheck_timeout = Some(Duration::from_secs(1));
+        let mut proxy = Proxy::create("127.0.0.1", port, vec!["HTTP".to_string()]).await.unwrap();
+
+        // Without judges a protocol check would wait for them for JUDGE_LOOKUP_TIMEOUT_SECS
+        let started = time::Instant::now();
+        assert!(!checker.check_proxy(&mut proxy).await);
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert_eq!(proxy.error_stat.get("precheck_failed"), Some(&1));
+        assert!(!proxy.logs.iter().any(|(_, msg, _)| msg.starts_with("Selected judge")));
+    }
+
+    #[tokio::test]
+    async fn test_failed_proxy_is_served_from_negative_cache() {
+        use crate::server::multi_cache::{MultiCache, MultiCacheConfig};
+
+        let port = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().port();
+        let mut checker = Checker::with_ext_ip("198.51.100.1".to_string());
+        checker.expected_types = vec!["HTTP".to_string()];
+        checker.precheck_timeout = Some(Duration::from_secs(1));
+        let cache = Arc::new(MultiCache::new(MultiCacheConfig::default()));
+        checker.validation_cache = Some(Arc::clone(&cache));
+
+        let mut proxy = Proxy::create("127.0.0.1", port, vec!["HTTP".to_string()]).await.unwrap();
+        assert!(!checker.check_proxy(&mut proxy).await);
+        assert_eq!(proxy.error_stat.get("precheck_failed"), Some(&1));
+        let cached = cache.get(&proxy.as_text()).await.unwrap();
+        assert!(!cached.is_working);
+
+        // The recheck is answered by the cache, without even the precheck
+        let mut again = Proxy::create("127.0.0.1", port, vec!["HTTP".to_string()]).await.unwrap();
+        assert!(!checker.clone().check_proxy(&mut again).await);
+        assert!(again.error_stat.is_empty());
+        assert!(again.logs.iter().any(|(_, msg, _)| msg == "Failed a recent check, skipped"));
     }
 }
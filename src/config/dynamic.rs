@@ -8,6 +8,8 @@ pub struct DynamicConfig {
     pub dnsbl: DnsblConfig,
     pub server: ServerConfig,
     pub protocols: ProtocolConfig,
+    #[serde(default)]
+    pub api: ApiSettings,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -16,6 +18,82 @@ pub struct GeneralConfig {
     pub default_timeout: u64,
     pub rate_limit_delay_ms: u64,
     pub log_level: String,
+    /// Seconds between two provider refresh cycles
+    #[serde(default = "default_provider_refresh_secs")]
+    pub provider_refresh_secs: u64,
+    /// Random spread applied to the refresh interval, in percent of it
+    #[serde(default = "default_provider_refresh_jitter_percent")]
+    pub provider_refresh_jitter_percent: u64,
+    /// Headers whose presence in a judge response reveals the request went through a proxy
+    #[serde(default = "default_leak_headers")]
+    pub leak_headers: Vec<String>,
+    /// Queued proxies at which providers pause fetching
+    #[serde(default = "default_queue_high_watermark")]
+    pub queue_high_watermark: usize,
+    /// Queued proxies below which paused providers resume
+    #[serde(default = "default_queue_low_watermark")]
+    pub queue_low_watermark: usize,
+    /// Consecutive empty fetches after which a provider is skipped
+    #[serde(default = "default_provider_failure_threshold")]
+    pub provider_failure_threshold: u32,
+    /// Seconds a tripped provider is skipped before being probed again
+    #[serde(default = "default_provider_cooldown_secs")]
+    pub provider_cooldown_secs: u64,
+    /// Retries of a provider page fetch that timed out or got a 5xx
+    #[serde(default = "default_provider_fetch_retries")]
+    pub provider_fetch_retries: u32,
+    /// Milliseconds before the first fetch retry, doubled for each later one
+    #[serde(default = "default_provider_fetch_backoff_ms")]
+    pub provider_fetch_backoff_ms: u64,
+    /// Seconds a single provider page fetch may take
+    #[serde(default = "default_provider_fetch_timeout_secs")]
+    pub provider_fetch_timeout_secs: u64,
+    /// Resident memory in megabytes near which providers pause and caches are trimmed, 0 for no ceiling
+    #[serde(default)]
+    pub memory_ceiling_mb: u64,
+}
+
+fn default_provider_refresh_secs() -> u64 {
+    60
+}
+
+fn default_provider_refresh_jitter_percent() -> u64 {
+    10
+}
+
+fn default_queue_high_watermark() -> usize {
+    50_000
+}
+
+fn default_queue_low_watermark() -> usize {
+    25_000
+}
+
+fn default_provider_failure_threshold() -> u32 {
+    3
+}
+
+fn default_provider_cooldown_secs() -> u64 {
+    600
+}
+
+fn default_provider_fetch_retries() -> u32 {
+    2
+}
+
+fn default_provider_fetch_backoff_ms() -> u64 {
+    500
+}
+
+fn default_provider_fetch_timeout_secs() -> u64 {
+    5
+}
+
+pub fn default_leak_headers() -> Vec<String> {
+    ["X-Forwarded-For", "X-Real-IP", "Forwarded", "Client-IP"]
+        .iter()
+        .map(|header| header.to_string())
+        .collect()
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -25,6 +103,12 @@ pub struct DnsblConfig {
     pub max_concurrent: usize,
     pub cache_ttl_secs: u64,
     pub malicious_threshold: u32,
+    /// Percentage of failed list queries above which a clean result is not trusted
+    #[serde(default = "crate::dnsbl::default_max_error_rate")]
+    pub max_error_rate: f64,
+    /// Resolvers queried instead of the public ones, as `ip` or `ip:port`
+    #[serde(default)]
+    pub dns_servers: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -32,6 +116,92 @@ pub struct ServerConfig {
     pub max_clients: usize,
     pub port: u16,
     pub timeout: u64,
+    /// Domain globs clients of the proxy server may reach, empty allows all
+    #[serde(default)]
+    pub allowed_domains: Vec<String>,
+    /// Domain globs clients may never reach, even when also allowed
+    #[serde(default)]
+    pub denied_domains: Vec<String>,
+    /// Header changes applied in order to forwarded requests
+    #[serde(default)]
+    pub header_rules: Vec<HeaderRule>,
+    /// Ports CONNECT and SOCKS5 tunnels may reach, others get a 403, empty allows all
+    #[serde(default = "default_allowed_connect_ports")]
+    pub allowed_connect_ports: Vec<u16>,
+    /// Served requests kept for `GET /audit`, 0 disables the audit log
+    #[serde(default = "default_audit_log_size")]
+    pub audit_log_size: usize,
+    /// Concurrent connections allowed per client IP, extra ones get a 429, 0 disables the cap
+    #[serde(default)]
+    pub max_connections_per_client: usize,
+}
+
+fn default_allowed_connect_ports() -> Vec<u16> {
+    crate::server::target_filter::DEFAULT_CONNECT_PORTS.to_vec()
+}
+
+fn default_audit_log_size() -> usize {
+    crate::server::audit::DEFAULT_AUDIT_LOG_SIZE
+}
+
+/// Header change on forwarded requests, written in TOML as
+/// `{ action = "remove", name = "Via" }` or
+/// `{ action = "set", name = "Accept-Language", value = "en-US" }`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum HeaderRule {
+    Set { name: String, value: String },
+    Remove { name: String },
+}
+
+/// Embedded REST API, read at startup only
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiSettings {
+    /// Start the API next to grab, find and serve
+    #[serde(default = "default_api_enabled")]
+    pub enabled: bool,
+    #[serde(default = "default_api_host")]
+    pub host: String,
+    #[serde(default = "default_api_port")]
+    pub port: u16,
+    /// Origins allowed to call the API from a browser, exact matches or `*`
+    #[serde(default = "default_api_cors_origins")]
+    pub cors_origins: Vec<String>,
+    /// PEM certificate chain served over HTTPS, together with `tls_key_path`
+    #[serde(default)]
+    pub tls_cert_path: Option<String>,
+    /// PEM private key of `tls_cert_path`
+    #[serde(default)]
+    pub tls_key_path: Option<String>,
+}
+
+fn default_api_enabled() -> bool {
+    true
+}
+
+fn default_api_host() -> String {
+    "127.0.0.1".to_string()
+}
+
+fn default_api_port() -> u16 {
+    3000
+}
+
+fn default_api_cors_origins() -> Vec<String> {
+    vec!["*".to_string()]
+}
+
+impl Default for ApiSettings {
+    fn default() -> Self {
+        Self {
+            enabled: default_api_enabled(),
+            host: default_api_host(),
+            port: default_api_port(),
+            cors_origins: default_api_cors_origins(),
+            tls_cert_path: None,
+            tls_key_path: None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -52,6 +222,17 @@ impl DynamicConfig {
                 default_timeout: 8,
                 rate_limit_delay_ms: 500,
                 log_level: "info".to_string(),
+                provider_refresh_secs: default_provider_refresh_secs(),
+                provider_refresh_jitter_percent: default_provider_refresh_jitter_percent(),
+                leak_headers: default_leak_headers(),
+                queue_high_watermark: default_queue_high_watermark(),
+                queue_low_watermark: default_queue_low_watermark(),
+                provider_failure_threshold: default_provider_failure_threshold(),
+                provider_cooldown_secs: default_provider_cooldown_secs(),
+                provider_fetch_retries: default_provider_fetch_retries(),
+                provider_fetch_backoff_ms: default_provider_fetch_backoff_ms(),
+                provider_fetch_timeout_secs: default_provider_fetch_timeout_secs(),
+                memory_ceiling_mb: 0,
             },
             dnsbl: DnsblConfig {
                 enabled: true,
@@ -59,11 +240,19 @@ impl DynamicConfig {
                 max_concurrent: 10,
                 cache_ttl_secs: 3600,
                 malicious_threshold: 2,
+                max_error_rate: crate::dnsbl::default_max_error_rate(),
+                dns_servers: Vec::new(),
             },
             server: ServerConfig {
                 max_clients: 1000,
                 port: 8080,
                 timeout: 30,
+                allowed_domains: Vec::new(),
+                denied_domains: Vec::new(),
+                header_rules: Vec::new(),
+                allowed_connect_ports: default_allowed_connect_ports(),
+                audit_log_size: default_audit_log_size(),
+                max_connections_per_client: 0,
             },
             protocols: ProtocolConfig {
                 http: true,
@@ -73,6 +262,7 @@ impl DynamicConfig {
                 connect_25: true,
                 connect_80: true,
             },
+            api: ApiSettings::default(),
         }
     }
 
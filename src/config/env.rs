@@ -0,0 +1,241 @@
+//! Environment variable overrides applied on top of the TOML configuration
+//!
+//! Supported variables:
+//!
+//! | Variable | Field |
+//! |---|---|
+//! | `PROXY_RS_GENERAL_MAX_CONNECTIONS` | `general.max_connections` |
+//! | `PROXY_RS_GENERAL_DEFAULT_TIMEOUT` | `general.default_timeout` |
+//! | `PROXY_RS_GENERAL_RATE_LIMIT_DELAY_MS` | `general.rate_limit_delay_ms` |
+//! | `PROXY_RS_GENERAL_LOG_LEVEL` | `general.log_level` |
+//! | `PROXY_RS_GENERAL_PROVIDER_REFRESH_SECS` | `general.provider_refresh_secs` |
+//! | `PROXY_RS_GENERAL_PROVIDER_REFRESH_JITTER_PERCENT` | `general.provider_refresh_jitter_percent` |
+//! | `PROXY_RS_GENERAL_LEAK_HEADERS` | `general.leak_headers` (comma-separated) |
+//! | `PROXY_RS_GENERAL_QUEUE_HIGH_WATERMARK` | `general.queue_high_watermark` |
+//! | `PROXY_RS_GENERAL_QUEUE_LOW_WATERMARK` | `general.queue_low_watermark` |
+//! | `PROXY_RS_GENERAL_PROVIDER_FAILURE_THRESHOLD` | `general.provider_failure_threshold` |
+//! | `PROXY_RS_GENERAL_PROVIDER_COOLDOWN_SECS` | `general.provider_cooldown_secs` |
+//! | `PROXY_RS_GENERAL_PROVIDER_FETCH_RETRIES` | `general.provider_fetch_retries` |
+//! | `PROXY_RS_GENERAL_PROVIDER_FETCH_BACKOFF_MS` | `general.provider_fetch_backoff_ms` |
+//! | `PROXY_RS_GENERAL_PROVIDER_FETCH_TIMEOUT_SECS` | `general.provider_fetch_timeout_secs` |
+//! | `PROXY_RS_GENERAL_MEMORY_CEILING_MB` | `general.memory_ceiling_mb` |
+//! | `PROXY_RS_DNSBL_ENABLED` | `dnsbl.enabled` |
+//! | `PROXY_RS_DNSBL_TIMEOUT_SECS` | `dnsbl.timeout_secs` |
+//! | `PROXY_RS_DNSBL_MAX_CONCURRENT` | `dnsbl.max_concurrent` |
+//! | `PROXY_RS_DNSBL_CACHE_TTL_SECS` | `dnsbl.cache_ttl_secs` |
+//! | `PROXY_RS_DNSBL_MALICIOUS_THRESHOLD` | `dnsbl.malicious_threshold` |
+//! | `PROXY_RS_DNSBL_MAX_ERROR_RATE` | `dnsbl.max_error_rate` |
+//! | `PROXY_RS_DNSBL_DNS_SERVERS` | `dnsbl.dns_servers` (comma-separated) |
+//! | `PROXY_RS_SERVER_MAX_CLIENTS` | `server.max_clients` |
+//! | `PROXY_RS_SERVER_PORT` | `server.port` |
+//! | `PROXY_RS_SERVER_TIMEOUT` | `server.timeout` |
+//! | `PROXY_RS_SERVER_AUDIT_LOG_SIZE` | `server.audit_log_size` |
+//! | `PROXY_RS_SERVER_MAX_CONNECTIONS_PER_CLIENT` | `server.max_connections_per_client` |
+//! | `PROXY_RS_PROTOCOLS_HTTP` | `protocols.http` |
+//! | `PROXY_RS_PROTOCOLS_HTTPS` | `protocols.https` |
+//! | `PROXY_RS_PROTOCOLS_SOCKS4` | `protocols.socks4` |
+//! | `PROXY_RS_PROTOCOLS_SOCKS5` | `protocols.socks5` |
+//! | `PROXY_RS_PROTOCOLS_CONNECT_25` | `protocols.connect_25` |
+//! | `PROXY_RS_PROTOCOLS_CONNECT_80` | `protocols.connect_80` |
+//! | `PROXY_RS_API_ENABLED` | `api.enabled` |
+//! | `PROXY_RS_API_HOST` | `api.host` |
+//! | `PROXY_RS_API_PORT` | `api.port` |
+//! | `PROXY_RS_API_CORS_ORIGINS` | `api.cors_origins` (comma-separated) |
+//! | `PROXY_RS_API_TLS_CERT_PATH` | `api.tls_cert_path` |
+//! | `PROXY_RS_API_TLS_KEY_PATH` | `api.tls_key_path` |
+//!
+//! Booleans accept `true/false`, `1/0`, `yes/no` and `on/off`.
+
+use std::str::FromStr;
+
+use super::DynamicConfig;
+
+/// Prefix shared by all override variables
+pub const ENV_PREFIX: &str = "PROXY_RS_";
+
+/// Apply overrides from the process environment
+pub fn apply_env_overrides(config: &mut DynamicConfig) -> usize {
+    apply_overrides(config, std::env::vars())
+}
+
+/// Apply overrides from `(name, value)` pairs, returns how many were applied.
+/// Unknown names are ignored, invalid values are logged and leave the field unchanged.
+pub fn apply_overrides<I>(config: &mut DynamicConfig, vars: I) -> usize
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    let mut applied = 0;
+    for (name, value) in vars {
+        let Some(key) = name.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+        let value = value.trim();
+        let ok = match key {
+            "GENERAL_MAX_CONNECTIONS" => set(&mut config.general.max_connections, &name, value),
+            "GENERAL_DEFAULT_TIMEOUT" => set(&mut config.general.default_timeout, &name, value),
+            "GENERAL_RATE_LIMIT_DELAY_MS" => set(&mut config.general.rate_limit_delay_ms, &name, value),
+            "GENERAL_LOG_LEVEL" => set(&mut config.general.log_level, &name, value),
+            "GENERAL_PROVIDER_REFRESH_SECS" => set(&mut config.general.provider_refresh_secs, &name, value),
+            "GENERAL_PROVIDER_REFRESH_JITTER_PERCENT" => {
+                set(&mut config.general.provider_refresh_jitter_percent, &name, value)
+            }
+            "GENERAL_LEAK_HEADERS" => {
+                config.general.leak_headers = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|header| !header.is_empty())
+                    .map(str::to_string)
+                    .collect();
+                true
+            }
+            "GENERAL_QUEUE_HIGH_WATERMARK" => set(&mut config.general.queue_high_watermark, &name, value),
+            "GENERAL_QUEUE_LOW_WATERMARK" => set(&mut config.general.queue_low_watermark, &name, value),
+            "GENERAL_PROVIDER_FAILURE_THRESHOLD" => {
+                set(&mut config.general.provider_failure_threshold, &name, value)
+            }
+            "GENERAL_PROVIDER_COOLDOWN_SECS" => set(&mut config.general.provider_cooldown_secs, &name, value),
+            "GENERAL_PROVIDER_FETCH_RETRIES" => set(&mut config.general.provider_fetch_retries, &name, value),
+            "GENERAL_PROVIDER_FETCH_BACKOFF_MS" => set(&mut config.general.provider_fetch_backoff_ms, &name, value),
+            "GENERAL_PROVIDER_FETCH_TIMEOUT_SECS" => {
+                set(&mut config.general.provider_fetch_timeout_secs, &name, value)
+            }
+            "GENERAL_MEMORY_CEILING_MB" => set(&mut config.general.memory_ceiling_mb, &name, value),
+            "DNSBL_ENABLED" => set_bool(&mut config.dnsbl.enabled, &name, value),
+            "DNSBL_TIMEOUT_SECS" => set(&mut config.dnsbl.timeout_secs, &name, value),
+            "DNSBL_MAX_CONCURRENT" => set(&mut config.dnsbl.max_concurrent, &name, value),
+            "DNSBL_CACHE_TTL_SECS" => set(&mut config.dnsbl.cache_ttl_secs, &name, value),
+            "DNSBL_MALICIOUS_THRESHOLD" => set(&mut config.dnsbl.malicious_threshold, &name, value),
+            "DNSBL_MAX_ERROR_RATE" => set(&mut config.dnsbl.max_error_rate, &name, value),
+            "DNSBL_DNS_SERVERS" => {
+                config.dnsbl.dns_servers = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|server| !server.is_empty())
+                    .map(str::to_string)
+                    .collect();
+                true
+            }
+            "SERVER_MAX_CLIENTS" => set(&mut config.server.max_clients, &name, value),
+            "SERVER_PORT" => set(&mut config.server.port, &name, value),
+            "SERVER_TIMEOUT" => set(&mut config.server.timeout, &name, value),
+            "SERVER_AUDIT_LOG_SIZE" => set(&mut config.server.audit_log_size, &name, value),
+            "SERVER_MAX_CONNECTIONS_PER_CLIENT" => set(&mut config.server.max_connections_per_client, &name, value),
+            "PROTOCOLS_HTTP" => set_bool(&mut config.protocols.http, &name, value),
+            "PROTOCOLS_HTTPS" => set_bool(&mut config.protocols.https, &name, value),
+            "PROTOCOLS_SOCKS4" => set_bool(&mut config.protocols.socks4, &name, value),
+            "PROTOCOLS_SOCKS5" => set_bool(&mut config.protocols.socks5, &name, value),
+            "PROTOCOLS_CONNECT_25" => set_bool(&mut config.protocols.connect_25, &name, value),
+            "PROTOCOLS_CONNECT_80" => set_bool(&mut config.protocols.connect_80, &name, value),
+            "API_ENABLED" => set_bool(&mut config.api.enabled, &name, value),
+            "API_HOST" => set(&mut config.api.host, &name, value),
+            "API_PORT" => set(&mut config.api.port, &name, value),
+            "API_TLS_CERT_PATH" => {
+                config.api.tls_cert_path = Some(value.to_string());
+                true
+            }
+            "API_TLS_KEY_PATH" => {
+                config.api.tls_key_path = Some(value.to_string());
+                true
+            }
+            "API_CORS_ORIGINS" => {
+                config.api.cors_origins = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|origin| !origin.is_empty())
+                    .map(str::to_string)
+                    .collect();
+                true
+            }
+            _ => false,
+        };
+        if ok {
+            log::debug!("Config override from {}", name);
+            applied += 1;
+        }
+    }
+    applied
+}
+
+fn set<T: FromStr>(field: &mut T, name: &str, value: &str) -> bool {
+    match value.parse() {
+        Ok(parsed) => {
+            *field = parsed;
+            true
+        }
+        Err(_) => {
+            log::warn!("Ignoring invalid value '{}' for {}", value, name);
+            false
+        }
+    }
+}
+
+fn set_bool(field: &mut bool, name: &str, value: &str) -> bool {
+    let parsed = match value.to_lowercase().as_str() {
+        "true" | "1" | "yes" | "on" => Some(true),
+        "false" | "0" | "no" | "off" => Some(false),
+        _ => None,
+    };
+    match parsed {
+        Some(parsed) => {
+            *field = parsed;
+            true
+        }
+        None => {
+            log::warn!("Ignoring invalid value '{}' for {}", value, name);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_overrides_with_coercion() {
+        let mut config = DynamicConfig::new();
+        let applied = apply_overrides(
+            &mut config,
+            vars(&[
+                ("PROXY_RS_SERVER_PORT", "9090"),
+                ("PROXY_RS_DNSBL_ENABLED", "0"),
+                ("PROXY_RS_PROTOCOLS_SOCKS4", "off"),
+                ("PROXY_RS_GENERAL_LOG_LEVEL", "debug"),
+                ("PROXY_RS_API_PORT", "3100"),
+                ("PROXY_RS_DNSBL_DNS_SERVERS", "10.0.0.53, 10.0.0.54:5353"),
+                ("HOME", "/root"),
+            ]),
+        );
+
+        assert_eq!(applied, 6);
+        assert_eq!(config.server.port, 9090);
+        assert!(!config.dnsbl.enabled);
+        assert!(!config.protocols.socks4);
+        assert_eq!(config.general.log_level, "debug");
+        assert_eq!(config.api.port, 3100);
+        assert_eq!(config.dnsbl.dns_servers, ["10.0.0.53", "10.0.0.54:5353"]);
+    }
+
+    #[test]
+    fn test_invalid_values_keep_existing() {
+        let mut config = DynamicConfig::new();
+        let applied = apply_overrides(
+            &mut config,
+            vars(&[
+                ("PROXY_RS_SERVER_PORT", "70000"),
+                ("PROXY_RS_DNSBL_ENABLED", "maybe"),
+                ("PROXY_RS_DNSBL_MAX_CONCURRENT", "-1"),
+                ("PROXY_RS_UNKNOWN", "1"),
+            ]),
+        );
+
+        assert_eq!(applied, 0);
+        assert_eq!(config.server.port, 8080);
+        assert!(config.dnsbl.enabled);
+        assert_eq!(config.dnsbl.max_concurrent, 10);
+    }
+}
@@ -0,0 +1,199 @@
+//! Loading the TOML configuration file with errors pointing at the offending line
+
+use std::path::Path;
+
+use thiserror::Error;
+
+use super::parser::{validate_config, InvalidSetting};
+use super::DynamicConfig;
+use crate::utils::error::ProxyError;
+
+#[derive(Debug, Error)]
+pub enum ConfigFileError {
+    #[error("Failed to read config file {path}: {source}")]
+    Read {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("{path}:{line}:{column}: {message}")]
+    Invalid {
+        path: String,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    /// A key the configuration does not know, usually a typo
+    #[error("{path}:{line}: unknown field `{field}`")]
+    UnknownField { path: String, line: usize, field: String },
+    /// A value that parses but is out of range or inconsistent with another one
+    #[error("{path}:{line}: {setting}")]
+    OutOfRange { path: String, line: usize, setting: InvalidSetting },
+}
+
+impl From<ConfigFileError> for ProxyError {
+    fn from(err: ConfigFileError) -> Self {
+        ProxyError::Config(err.to_string())
+    }
+}
+
+/// Read and parse the config file at `path`. Keys the configuration does not know
+/// are ignored by the parser and returned alongside it as `UnknownField` errors.
+pub fn load(path: &Path) -> Result<(DynamicConfig, Vec<ConfigFileError>), ConfigFileError> {
+    let content = std::fs::read_to_string(path).map_err(|source| ConfigFileError::Read {
+        path: path.display().to_string(),
+        source,
+    })?;
+    parse(&path.display().to_string(), &content)
+}
+
+/// Load the config file at `path` as strictly as `--strict-config` does, then check
+/// the ranges of its values. Returns the first problem found.
+pub fn validate(path: &Path) -> Result<DynamicConfig, ConfigFileError> {
+    let content = std::fs::read_to_string(path).map_err(|source| ConfigFileError::Read {
+        path: path.display().to_string(),
+        source,
+    })?;
+    check(&path.display().to_string(), &content)
+}
+
+fn check(path: &str, content: &str) -> Result<DynamicConfig, ConfigFileError> {
+    let (config, unknown) = parse(path, content)?;
+    if let Some(err) = unknown.into_iter().next() {
+        return Err(err);
+    }
+    validate_config(&config).map_err(|setting| ConfigFileError::OutOfRange {
+        path: path.to_string(),
+        line: key_line(content, Some(setting.section), setting.key),
+        setting,
+    })?;
+    Ok(config)
+}
+
+fn parse(path: &str, content: &str) -> Result<(DynamicConfig, Vec<ConfigFileError>), ConfigFileError> {
+    let config: DynamicConfig = toml::from_str(content).map_err(|e| {
+        let (line, column) = position(content, e.span().map_or(0, |span| span.start));
+        ConfigFileError::Invalid {
+            path: path.to_string(),
+            line,
+            column,
+            message: e.message().to_string(),
+        }
+    })?;
+
+    // Whatever does not survive a round trip through the config was not understood
+    let (Ok(toml::Value::Table(given)), Ok(toml::Value::Table(known))) =
+        (toml::from_str::<toml::Value>(content), toml::Value::try_from(&config))
+    else {
+        return Ok((config, Vec::new()));
+    };
+    let mut unknown = Vec::new();
+    for (section, value) in &given {
+        match (value, known.get(section)) {
+            (_, None) => unknown.push((None, section.as_str())),
+            (toml::Value::Table(keys), Some(toml::Value::Table(known_keys))) => unknown.extend(
+                keys.keys()
+                    .filter(|key| !known_keys.contains_key(*key))
+                    .map(|key| (Some(section.as_str()), key.as_str())),
+            ),
+            _ => {}
+        }
+    }
+    let unknown = unknown
+        .into_iter()
+        .map(|(section, key)| ConfigFileError::UnknownField {
+            path: path.to_string(),
+            line: key_line(content, section, key),
+            field: section.map_or(key.to_string(), |section| format!("{}.{}", section, key)),
+        })
+        .collect();
+    Ok((config, unknown))
+}
+
+/// 1-based line and column of a byte offset
+fn position(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset.min(content.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().map_or(0, |line| line.chars().count()) + 1;
+    (line, column)
+}
+
+/// Line declaring `key` in `section` (a top-level key or table when `None`), 0 if not found
+fn key_line(content: &str, section: Option<&str>, key: &str) -> usize {
+    let mut current = None;
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if let Some(header) = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+            if section.is_none() && header.trim() == key {
+                return index + 1;
+            }
+            current = Some(header.trim());
+        } else if current == section
+            && line.strip_prefix(key).is_some_and(|rest| rest.trim_start().starts_with('='))
+        {
+            return index + 1;
+        }
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_text() -> String {
+        toml::to_string(&DynamicConfig::new()).unwrap()
+    }
+
+    #[test]
+    fn test_invalid_value_points_at_line() {
+        let content = config_text().replace("port = 8080", "port = \"eighty\"");
+        let line = content.lines().position(|line| line.starts_with("port =")).unwrap() + 1;
+
+        let err = parse("proxy-rs.toml", &content).unwrap_err();
+        match &err {
+            ConfigFileError::Invalid { line: at, column, .. } => {
+                assert_eq!(*at, line);
+                assert_eq!(*column, 8);
+            }
+            other => panic!("unexpected error {:?}", other),
+        }
+        assert!(err.to_string().starts_with(&format!("proxy-rs.toml:{}:8:", line)));
+    }
+
+    #[test]
+    fn test_unknown_fields_are_reported() {
+        let content = config_text().replace("[server]\n", "[server]\nmax_clent = 10\n") + "\n[extra]\nkey = 1\n";
+        let line = content.lines().position(|line| line.starts_with("max_clent =")).unwrap() + 1;
+
+        let (config, unknown) = parse("proxy-rs.toml", &content).unwrap();
+        assert_eq!(config.server.max_clients, 1000);
+        let messages: Vec<String> = unknown.iter().map(ToString::to_string).collect();
+        assert_eq!(messages.len(), 2);
+        assert!(messages.contains(&format!("proxy-rs.toml:{}: unknown field `server.max_clent`", line)));
+        assert!(messages.iter().any(|message| message.ends_with("unknown field `extra`")));
+    }
+
+    #[test]
+    fn test_check_accepts_defaults() {
+        let config = check("proxy-rs.toml", &config_text()).unwrap();
+        assert_eq!(config.server.port, 8080);
+    }
+
+    #[test]
+    fn test_check_rejects_out_of_range_port() {
+        let content = config_text().replace("port = 8080", "port = 0");
+        let line = content.lines().position(|line| line.starts_with("port =")).unwrap() + 1;
+
+        let err = check("proxy-rs.toml", &content).unwrap_err();
+        assert!(matches!(&err, ConfigFileError::OutOfRange { setting, .. } if setting.key == "port"));
+        assert_eq!(
+            err.to_string(),
+            format!("proxy-rs.toml:{}: server.port: must be between 1 and 65535", line)
+        );
+
+        // Beyond u16 the value does not even parse
+        let content = config_text().replace("port = 8080", "port = 70000");
+        assert!(matches!(check("proxy-rs.toml", &content), Err(ConfigFileError::Invalid { line: at, .. }) if at == line));
+    }
+}
@@ -1,9 +1,26 @@
 use crate::config::{DynamicConfig, ConfigSection};
+use crate::config::dynamic::DnsblConfig;
+use lazy_static::lazy_static;
 use parking_lot::RwLock;
+use std::hash::{DefaultHasher, Hash, Hasher};
 use std::path::Path;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 use tokio::sync::mpsc::{self, Sender, Receiver};
+use tokio::sync::watch;
+
+lazy_static! {
+    /// Latest DNSBL section applied by hot reload, checkers subscribe to pick it up
+    pub static ref DNSBL_RELOAD: watch::Sender<Option<DnsblReload>> = watch::channel(None).0;
+}
+
+/// A hot-reloaded DNSBL section
+#[derive(Debug, Clone)]
+pub struct DnsblReload {
+    pub config: DnsblConfig,
+    /// `enabled` when the edit flipped it, `None` keeps the run's own choice
+    pub enabled: Option<bool>,
+}
 
 #[derive(Debug, Clone)]
 pub enum WatcherEvent {
@@ -17,6 +34,8 @@ pub enum WatcherEvent {
 
 pub struct ConfigWatcher {
     config_path: std::path::PathBuf,
+    poll_interval: Duration,
+    debounce: Duration,
 }
 
 impl ConfigWatcher {
@@ -31,62 +50,93 @@ impl ConfigWatcher {
 
         Ok(ConfigWatcher {
             config_path,
+            poll_interval: Duration::from_secs(1),
+            debounce: Duration::from_millis(100),
         })
     }
 
+    /// Check the file every `poll_interval`, and once it changed wait until it has
+    /// been left alone for `debounce` so a burst of writes is read once
+    #[cfg(test)]
+    pub fn with_intervals(mut self, poll_interval: Duration, debounce: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self.debounce = debounce;
+        self
+    }
+
     pub fn start_watching(self) -> Receiver<WatcherEvent> {
         let (tx, rx) = mpsc::channel(100);
         let config_path = self.config_path.clone();
+        let poll_interval = self.poll_interval;
+        let debounce = self.debounce;
 
         // Start a simple file watcher using polling
         tokio::spawn(async move {
-            let mut last_modified = match std::fs::metadata(&config_path) {
-                Ok(meta) => meta.modified().unwrap_or_else(|_| std::time::SystemTime::UNIX_EPOCH),
-                Err(_) => std::time::SystemTime::UNIX_EPOCH,
-            };
+            let mut last_modified = modified_time(&config_path);
+            let last_content = std::fs::read_to_string(&config_path).ok();
+            let mut last_digest = last_content.as_deref().map(content_digest);
+            let mut last_config = last_content
+                .and_then(|content| toml::from_str::<DynamicConfig>(&content).ok())
+                .unwrap_or_else(DynamicConfig::new);
 
             loop {
-                tokio::time::sleep(Duration::from_secs(1)).await;
-
-                match std::fs::metadata(&config_path) {
-                    Ok(meta) => {
-                        let current_modified = meta.modified().unwrap_or_else(|_| std::time::SystemTime::UNIX_EPOCH);
-
-                        if current_modified != last_modified {
-                            last_modified = current_modified;
-
-                            // File was modified
-                            log::debug!("Config file modification detected");
-
-                            // Small delay to ensure file write is complete
-                            tokio::time::sleep(Duration::from_millis(100)).await;
-
-                            let change_result = Self::read_config_changes(&config_path);
-                            match change_result {
-                                Ok(change) => {
-                                    log::info!("Config section '{}' changed", change.section);
-                                    if let Err(e) = tx.send(WatcherEvent::ConfigChanged {
-                                        section: change.section,
-                                        old_value: change.old_value,
-                                        new_value: change.new_value,
-                                    }).await {
-                                        log::error!("Failed to send watcher event: {}", e);
-                                        break;
-                                    }
-                                }
-                                Err(e) => {
-                                    let error_msg = format!("Config read error: {}", e);
-                                    log::error!("{}", error_msg);
-                                    if let Err(send_err) = tx.send(WatcherEvent::Error(error_msg)).await {
-                                        log::error!("Failed to send error event: {}", send_err);
-                                        break;
-                                    }
-                                }
+                tokio::time::sleep(poll_interval).await;
+
+                let current_modified = modified_time(&config_path);
+                if current_modified == last_modified {
+                    continue;
+                }
+
+                // Wait for the writes to settle before reading the file
+                log::debug!("Config file modification detected");
+                last_modified = current_modified;
+                loop {
+                    tokio::time::sleep(debounce).await;
+                    let settled = modified_time(&config_path);
+                    if settled == last_modified {
+                        break;
+                    }
+                    last_modified = settled;
+                }
+
+                let content = match std::fs::read_to_string(&config_path) {
+                    Ok(content) => content,
+                    Err(e) => {
+                        log::warn!("Failed to read config file: {}", e);
+                        continue;
+                    }
+                };
+
+                // Touched but not edited, nothing to reload
+                let digest = content_digest(&content);
+                if last_digest == Some(digest) {
+                    log::debug!("Config file content unchanged, skipping reload");
+                    continue;
+                }
+                last_digest = Some(digest);
+
+                match Self::read_config_changes(&content, &last_config) {
+                    Ok((config, changes)) => {
+                        last_config = config;
+                        for change in changes {
+                            log::info!("Config section '{}' changed", change.section);
+                            if let Err(e) = tx.send(WatcherEvent::ConfigChanged {
+                                section: change.section,
+                                old_value: change.old_value,
+                                new_value: change.new_value,
+                            }).await {
+                                log::error!("Failed to send watcher event: {}", e);
+                                return;
                             }
                         }
                     }
                     Err(e) => {
-                        log::warn!("Failed to read config file metadata: {}", e);
+                        let error_msg = format!("Config read error: {}", e);
+                        log::error!("{}", error_msg);
+                        if let Err(send_err) = tx.send(WatcherEvent::Error(error_msg)).await {
+                            log::error!("Failed to send error event: {}", send_err);
+                            break;
+                        }
                     }
                 }
             }
@@ -95,23 +145,42 @@ impl ConfigWatcher {
         rx
     }
 
-    fn read_config_changes(config_path: &std::path::Path) -> Result<ConfigChange, Box<dyn std::error::Error + Send + Sync>> {
-        let config_content = std::fs::read_to_string(config_path)?;
-
-        // For this implementation, we'll just return a generic change notification
+    /// Parse the config file content and list the sections that differ from `previous`
+    fn read_config_changes(
+        config_content: &str,
+        previous: &DynamicConfig,
+    ) -> Result<(DynamicConfig, Vec<ConfigChange>), Box<dyn std::error::Error + Send + Sync>> {
         log::info!("Configuration file content updated");
 
-        let config: DynamicConfig = toml::from_str(&config_content)?;
+        let config: DynamicConfig = toml::from_str(config_content)?;
 
-        // Return a simple change notification
-        Ok(ConfigChange {
-            section: ConfigSection::General,
-            old_value: serde_json::json!({}), // Empty old value
-            new_value: serde_json::to_value(&config.general)?,
-        })
+        let mut changes = Vec::new();
+        for section in [ConfigSection::General, ConfigSection::Dnsbl, ConfigSection::Server, ConfigSection::Protocols] {
+            let old_value = previous.get_section_as_json(section.clone());
+            let new_value = config.get_section_as_json(section.clone());
+            if old_value != new_value {
+                changes.push(ConfigChange { section, old_value, new_value });
+            }
+        }
+
+        Ok((config, changes))
     }
 }
 
+/// Modification time of the config file, the epoch when it cannot be read
+fn modified_time(config_path: &Path) -> SystemTime {
+    std::fs::metadata(config_path)
+        .and_then(|meta| meta.modified())
+        .unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
+/// Hash of the config file content, equal across rewrites of the same bytes
+fn content_digest(content: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    hasher.finish()
+}
+
 #[derive(Debug)]
 struct ConfigChange {
     section: ConfigSection,
@@ -131,7 +200,7 @@ pub async fn start_config_watcher<P: AsRef<Path>>(
     // Process config change events
     while let Some(event) = event_rx.recv().await {
         match event {
-            WatcherEvent::ConfigChanged { section, new_value, .. } => {
+            WatcherEvent::ConfigChanged { section, old_value, new_value } => {
                 log::info!("Updating config section: {}", section);
 
                 // Update the shared configuration
@@ -152,7 +221,7 @@ pub async fn start_config_watcher<P: AsRef<Path>>(
                     let config = shared_config.read();
                     config.clone()
                 };
-                if let Err(e) = apply_config_changes(&config_clone, &section).await {
+                if let Err(e) = apply_config_changes(&config_clone, &section, &old_value).await {
                     log::error!("Failed to apply config changes: {}", e);
                 }
             }
@@ -168,6 +237,7 @@ pub async fn start_config_watcher<P: AsRef<Path>>(
 async fn apply_config_changes(
     config: &DynamicConfig,
     section: &ConfigSection,
+    old_value: &serde_json::Value,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     match section {
         ConfigSection::General => {
@@ -184,8 +254,16 @@ async fn apply_config_changes(
             log::info!("  enabled: {}", config.dnsbl.enabled);
             log::info!("  timeout_secs: {}", config.dnsbl.timeout_secs);
             log::info!("  malicious_threshold: {}", config.dnsbl.malicious_threshold);
+            log::info!("  max_error_rate: {}%", config.dnsbl.max_error_rate);
+            log::info!("  dns_servers: {:?}", config.dnsbl.dns_servers);
 
-            // Update DNSBL checker configuration
+            // Running checkers rebuild their DNSBL client on their next iteration
+            let enabled = config.dnsbl.enabled;
+            let flipped = old_value.get("enabled") != Some(&serde_json::Value::Bool(enabled));
+            DNSBL_RELOAD.send_replace(Some(DnsblReload {
+                config: config.dnsbl.clone(),
+                enabled: flipped.then_some(enabled),
+            }));
         }
         ConfigSection::Server => {
             log::info!("Applying server config changes:");
@@ -208,4 +286,43 @@ async fn apply_config_changes(
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_touch_without_edit_is_not_a_reload() {
+        let path = std::env::temp_dir().join(format!("proxy-rs-watch-{}.toml", std::process::id()));
+        let content = toml::to_string(&DynamicConfig::new()).unwrap();
+        std::fs::write(&path, &content).unwrap();
+
+        let (tx, _) = mpsc::channel(1);
+        let mut events = ConfigWatcher::new(&path, tx)
+            .unwrap()
+            .with_intervals(Duration::from_millis(20), Duration::from_millis(20))
+            .start_watching();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // Same bytes with a newer mtime
+        let touched = SystemTime::now() + Duration::from_secs(5);
+        std::fs::File::options().write(true).open(&path).unwrap().set_modified(touched).unwrap();
+        assert!(tokio::time::timeout(Duration::from_millis(300), events.recv()).await.is_err());
+
+        // A burst of edits settles into one reload of the last one
+        for port in [8081, 8082, 8083] {
+            std::fs::write(&path, content.replace("port = 8080", &format!("port = {}", port))).unwrap();
+        }
+        let event = tokio::time::timeout(Duration::from_secs(2), events.recv()).await.unwrap().unwrap();
+        match event {
+            WatcherEvent::ConfigChanged { section, new_value, .. } => {
+                assert_eq!(section, ConfigSection::Server);
+                assert_eq!(new_value["port"], 8083);
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert!(tokio::time::timeout(Duration::from_millis(300), events.recv()).await.is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,4 +1,6 @@
 pub mod dynamic;
+pub mod env;
+pub mod file;
 pub mod hot_reload;
 pub mod parser;
 
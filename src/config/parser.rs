@@ -16,6 +16,17 @@ pub struct TomlGeneralConfig {
     pub default_timeout: Option<u64>,
     pub rate_limit_delay_ms: Option<u64>,
     pub log_level: Option<String>,
+    pub provider_refresh_secs: Option<u64>,
+    pub provider_refresh_jitter_percent: Option<u64>,
+    pub leak_headers: Option<Vec<String>>,
+    pub queue_high_watermark: Option<usize>,
+    pub queue_low_watermark: Option<usize>,
+    pub provider_failure_threshold: Option<u32>,
+    pub provider_cooldown_secs: Option<u64>,
+    pub provider_fetch_retries: Option<u32>,
+    pub provider_fetch_backoff_ms: Option<u64>,
+    pub provider_fetch_timeout_secs: Option<u64>,
+    pub memory_ceiling_mb: Option<u64>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -25,6 +36,8 @@ pub struct TomlDnsblConfig {
     pub max_concurrent: Option<usize>,
     pub cache_ttl_secs: Option<u64>,
     pub malicious_threshold: Option<u32>,
+    pub max_error_rate: Option<f64>,
+    pub dns_servers: Option<Vec<String>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -32,6 +45,8 @@ pub struct TomlServerConfig {
     pub max_clients: Option<usize>,
     pub port: Option<u16>,
     pub timeout: Option<u64>,
+    pub audit_log_size: Option<usize>,
+    pub max_connections_per_client: Option<usize>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -119,6 +134,39 @@ fn merge_general_config(existing: &mut GeneralConfig, update: &TomlGeneralConfig
     if let Some(ref log_level) = update.log_level {
         existing.log_level = log_level.clone();
     }
+    if let Some(provider_refresh_secs) = update.provider_refresh_secs {
+        existing.provider_refresh_secs = provider_refresh_secs;
+    }
+    if let Some(jitter_percent) = update.provider_refresh_jitter_percent {
+        existing.provider_refresh_jitter_percent = jitter_percent;
+    }
+    if let Some(ref leak_headers) = update.leak_headers {
+        existing.leak_headers = leak_headers.clone();
+    }
+    if let Some(high_watermark) = update.queue_high_watermark {
+        existing.queue_high_watermark = high_watermark;
+    }
+    if let Some(low_watermark) = update.queue_low_watermark {
+        existing.queue_low_watermark = low_watermark;
+    }
+    if let Some(failure_threshold) = update.provider_failure_threshold {
+        existing.provider_failure_threshold = failure_threshold;
+    }
+    if let Some(cooldown_secs) = update.provider_cooldown_secs {
+        existing.provider_cooldown_secs = cooldown_secs;
+    }
+    if let Some(fetch_retries) = update.provider_fetch_retries {
+        existing.provider_fetch_retries = fetch_retries;
+    }
+    if let Some(fetch_backoff_ms) = update.provider_fetch_backoff_ms {
+        existing.provider_fetch_backoff_ms = fetch_backoff_ms;
+    }
+    if let Some(fetch_timeout_secs) = update.provider_fetch_timeout_secs {
+        existing.provider_fetch_timeout_secs = fetch_timeout_secs;
+    }
+    if let Some(memory_ceiling_mb) = update.memory_ceiling_mb {
+        existing.memory_ceiling_mb = memory_ceiling_mb;
+    }
 }
 
 fn merge_dnsbl_config(existing: &mut DnsblConfig, update: &TomlDnsblConfig) {
@@ -137,6 +185,12 @@ fn merge_dnsbl_config(existing: &mut DnsblConfig, update: &TomlDnsblConfig) {
     if let Some(malicious_threshold) = update.malicious_threshold {
         existing.malicious_threshold = malicious_threshold;
     }
+    if let Some(max_error_rate) = update.max_error_rate {
+        existing.max_error_rate = max_error_rate;
+    }
+    if let Some(dns_servers) = &update.dns_servers {
+        existing.dns_servers = dns_servers.clone();
+    }
 }
 
 fn merge_server_config(existing: &mut ServerConfig, update: &TomlServerConfig) {
@@ -149,6 +203,12 @@ fn merge_server_config(existing: &mut ServerConfig, update: &TomlServerConfig) {
     if let Some(timeout) = update.timeout {
         existing.timeout = timeout;
     }
+    if let Some(audit_log_size) = update.audit_log_size {
+        existing.audit_log_size = audit_log_size;
+    }
+    if let Some(max_connections_per_client) = update.max_connections_per_client {
+        existing.max_connections_per_client = max_connections_per_client;
+    }
 }
 
 fn merge_protocols_config(existing: &mut ProtocolConfig, update: &TomlProtocolConfig) {
@@ -172,6 +232,100 @@ fn merge_protocols_config(existing: &mut ProtocolConfig, update: &TomlProtocolCo
     }
 }
 
+/// A setting that parses but that the service cannot run with
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidSetting {
+    pub section: &'static str,
+    pub key: &'static str,
+    pub message: String,
+}
+
+impl std::fmt::Display for InvalidSetting {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}: {}", self.section, self.key, self.message)
+    }
+}
+
+/// Check the ranges and consistency of the settings, reporting the first problem found
+pub fn validate_config(config: &DynamicConfig) -> Result<(), InvalidSetting> {
+    let invalid = |section, key, message: &str| {
+        Err(InvalidSetting { section, key, message: message.to_string() })
+    };
+    let general = &config.general;
+    if general.max_connections == 0 {
+        return invalid("general", "max_connections", "must be greater than 0");
+    }
+    if general.default_timeout == 0 {
+        return invalid("general", "default_timeout", "must be greater than 0");
+    }
+    if !["off", "error", "warn", "info", "debug", "trace"].contains(&general.log_level.to_lowercase().as_str()) {
+        return invalid("general", "log_level", "must be one of off, error, warn, info, debug or trace");
+    }
+    if general.provider_refresh_secs == 0 {
+        return invalid("general", "provider_refresh_secs", "must be greater than 0");
+    }
+    if general.provider_refresh_jitter_percent > 100 {
+        return invalid("general", "provider_refresh_jitter_percent", "must be at most 100");
+    }
+    if general.queue_high_watermark == 0 {
+        return invalid("general", "queue_high_watermark", "must be greater than 0");
+    }
+    if general.queue_low_watermark >= general.queue_high_watermark {
+        return invalid("general", "queue_low_watermark", "must be below queue_high_watermark");
+    }
+    if general.provider_failure_threshold == 0 {
+        return invalid("general", "provider_failure_threshold", "must be greater than 0");
+    }
+    if general.provider_fetch_timeout_secs == 0 {
+        return invalid("general", "provider_fetch_timeout_secs", "must be greater than 0");
+    }
+
+    let dnsbl = &config.dnsbl;
+    if dnsbl.timeout_secs == 0 {
+        return invalid("dnsbl", "timeout_secs", "must be greater than 0");
+    }
+    if dnsbl.max_concurrent == 0 {
+        return invalid("dnsbl", "max_concurrent", "must be greater than 0");
+    }
+    if dnsbl.malicious_threshold == 0 {
+        return invalid("dnsbl", "malicious_threshold", "must be greater than 0");
+    }
+    if !(0.0..=100.0).contains(&dnsbl.max_error_rate) {
+        return invalid("dnsbl", "max_error_rate", "must be a percentage between 0 and 100");
+    }
+
+    let server = &config.server;
+    if server.port == 0 {
+        return invalid("server", "port", "must be between 1 and 65535");
+    }
+    if server.max_clients == 0 {
+        return invalid("server", "max_clients", "must be greater than 0");
+    }
+    if server.timeout == 0 {
+        return invalid("server", "timeout", "must be greater than 0");
+    }
+    if server.allowed_connect_ports.contains(&0) {
+        return invalid("server", "allowed_connect_ports", "ports must be between 1 and 65535");
+    }
+    for rule in &server.header_rules {
+        if let Err(e) = crate::server::header_rules::validate(rule) {
+            return invalid("server", "header_rules", &e);
+        }
+    }
+
+    let api = &config.api;
+    if api.host.trim().is_empty() {
+        return invalid("api", "host", "must not be empty");
+    }
+    if api.port == 0 {
+        return invalid("api", "port", "must be between 1 and 65535");
+    }
+    if api.tls_cert_path.is_some() != api.tls_key_path.is_some() {
+        return invalid("api", "tls_cert_path", "must be set together with tls_key_path");
+    }
+    Ok(())
+}
+
 #[derive(Debug)]
 pub struct ConfigChange {
     pub section: ConfigSection,
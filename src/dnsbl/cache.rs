@@ -5,6 +5,11 @@ use std::time::{Duration, Instant};
 
 use crate::dnsbl::{DnsblCheckResults, DnsblConfig};
 
+/// Hits needed for each extension of a cached entry's lifetime
+const HOT_ENTRY_HITS: u32 = 5;
+/// Hot entries live at most this many times their base TTL
+const MAX_TTL_MULTIPLIER: u32 = 4;
+
 /// Cache entry for DNSBL check results
 #[derive(Debug, Clone)]
 struct CacheEntry {
@@ -14,6 +19,31 @@ struct CacheEntry {
     created_at: Instant,
     /// When this entry expires
     expires_at: Instant,
+    /// TTL the entry was stored with
+    ttl: Duration,
+    /// Number of cache hits on this entry
+    hits: u32,
+}
+
+impl CacheEntry {
+    fn new(results: DnsblCheckResults, ttl: Duration) -> Self {
+        let now = Instant::now();
+        Self {
+            results,
+            created_at: now,
+            expires_at: now + ttl,
+            ttl,
+            hits: 0,
+        }
+    }
+
+    /// Count a hit, every `HOT_ENTRY_HITS` hits add one base TTL to the lifetime
+    /// up to `MAX_TTL_MULTIPLIER` times the base TTL
+    fn record_hit(&mut self) {
+        self.hits = self.hits.saturating_add(1);
+        let multiplier = (1 + self.hits / HOT_ENTRY_HITS).min(MAX_TTL_MULTIPLIER);
+        self.expires_at = self.expires_at.max(self.created_at + self.ttl * multiplier);
+    }
 }
 
 /// DNSBL result cache
@@ -65,8 +95,9 @@ impl DnsblCache {
         // Clean expired entries first
         self.cleanup_expired();
         
-        if let Some(entry) = self.cache.get(ip) {
+        if let Some(entry) = self.cache.get_mut(ip) {
             if entry.expires_at > Instant::now() {
+                entry.record_hit();
                 self.stats.hits += 1;
                 log::debug!("DNSBL cache hit for IP: {}", ip);
                 return Some(entry.results.clone());
@@ -89,12 +120,7 @@ impl DnsblCache {
             self.evict_lru();
         }
         
-        let now = Instant::now();
-        let entry = CacheEntry {
-            results: results.clone(),
-            created_at: now,
-            expires_at: now + self.default_ttl,
-        };
+        let entry = CacheEntry::new(results, self.default_ttl);
         
         self.cache.insert(ip.clone(), entry);
         self.stats.additions += 1;
@@ -110,12 +136,7 @@ impl DnsblCache {
             self.evict_lru();
         }
         
-        let now = Instant::now();
-        let entry = CacheEntry {
-            results: results.clone(),
-            created_at: now,
-            expires_at: now + ttl,
-        };
+        let entry = CacheEntry::new(results, ttl);
         
         self.cache.insert(ip.clone(), entry);
         self.stats.additions += 1;
@@ -290,6 +311,13 @@ impl DnsblCacheManager {
         }
     }
     
+    /// Apply a new configuration, cached results are kept and the new TTL
+    /// applies to entries stored from now on
+    pub fn update_config(&mut self, config: DnsblConfig) {
+        self.cache.default_ttl = Duration::from_secs(config.cache_ttl_secs);
+        self.config = config;
+    }
+
     /// Get cached results, with automatic cleanup
     pub fn get(&mut self, ip: &str) -> Option<DnsblCheckResults> {
         self.maybe_cleanup();
@@ -343,6 +371,7 @@ mod tests {
             listed,
             reason: None,
             response_time_ms: 100,
+            errored: false,
         });
         results.update_malicious_status(1);
         results
@@ -455,4 +484,33 @@ mod tests {
         assert_eq!(stats.hits, 1);
         assert_eq!(stats.additions, 1);
     }
+
+    #[test]
+    fn test_hot_entries_live_longer() {
+        let config = DnsblConfig {
+            cache_ttl_secs: 60,
+            ..Default::default()
+        };
+        let mut manager = DnsblCacheManager::new(config);
+        manager.put("192.168.1.1".to_string(), create_test_results("192.168.1.1", false));
+        manager.put("192.168.1.2".to_string(), create_test_results("192.168.1.2", false));
+        manager.put("192.168.1.3".to_string(), create_test_results("192.168.1.3", true));
+
+        for _ in 0..100 {
+            manager.get("192.168.1.1");
+        }
+        manager.get("192.168.1.2");
+
+        let expires_in = |manager: &DnsblCacheManager, ip: &str| {
+            manager.cache.cache[ip].expires_at.saturating_duration_since(Instant::now())
+        };
+        let hot = expires_in(&manager, "192.168.1.1");
+        let cold = expires_in(&manager, "192.168.1.2");
+        assert!(cold <= Duration::from_secs(60));
+        assert!(hot > Duration::from_secs(60 * (MAX_TTL_MULTIPLIER as u64 - 1)));
+        // Extension is capped
+        assert!(hot <= Duration::from_secs(60 * MAX_TTL_MULTIPLIER as u64));
+        // Malicious results keep their doubled TTL
+        assert!(expires_in(&manager, "192.168.1.3") > Duration::from_secs(60));
+    }
 }
@@ -1,10 +1,13 @@
 //! Main DNSBL checker that orchestrates the DNSBL checking process
 
+use std::net::IpAddr;
 use std::time::Instant;
 
 use crate::dnsbl::{
     DnsblCacheManager, DnsblCheckResults, DnsblClient, DnsblConfig, DnsblList, DnsblLists,
+    DnsblResult, ResolverSettings, ResolverStats,
 };
+use crate::utils::cidr::Cidr;
 
 /// Main DNSBL checker that coordinates all DNSBL operations
 #[derive(Debug)]
@@ -17,6 +20,8 @@ pub struct DnsblChecker {
     lists: DnsblLists,
     /// Configuration
     config: DnsblConfig,
+    /// `config.whitelisted_ips` parsed, invalid entries left out
+    whitelist: Vec<Cidr>,
 }
 
 // Ensure DnsblChecker is Send and Clone
@@ -31,6 +36,7 @@ impl Clone for DnsblChecker {
             cache_manager: DnsblCacheManager::new(config.clone()),
             lists: self.lists.clone(),
             config,
+            whitelist: self.whitelist.clone(),
         }
     }
 }
@@ -43,35 +49,87 @@ impl DnsblChecker {
     
     /// Create new DNSBL checker with custom configuration
     pub async fn with_config(config: DnsblConfig) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
-        let client = DnsblClient::with_optimized_config(
-            std::time::Duration::from_secs(config.timeout_secs),
-            true, // Use fast DNS servers for optimal performance
-        ).await?;
-        
+        let client = Self::build_client(&config).await?;
         let cache_manager = DnsblCacheManager::new(config.clone());
         let lists = DnsblLists::new();
+        let whitelist = parse_whitelist(&config.whitelisted_ips);
         
         Ok(Self {
             client,
             cache_manager,
             lists,
             config,
+            whitelist,
         })
     }
+
+    /// Create the DNS client matching the timeout and resolver settings of `config`
+    async fn build_client(config: &DnsblConfig) -> Result<DnsblClient, Box<dyn std::error::Error + Send + Sync>> {
+        let timeout = std::time::Duration::from_secs(config.timeout_secs);
+        let settings = ResolverSettings::from(config);
+        let client = if config.dns_over_https {
+            match DnsblClient::with_doh(timeout, settings).await {
+                Ok(client) if client.test_connectivity().await.unwrap_or(false) => client,
+                Ok(_) => {
+                    log::warn!("DNS-over-HTTPS resolver unreachable, falling back to UDP DNS");
+                    DnsblClient::with_name_servers(timeout, &config.dns_servers, settings).await?
+                }
+                Err(e) => {
+                    log::warn!("Failed to initialize DNS-over-HTTPS resolver: {}, falling back to UDP DNS", e);
+                    DnsblClient::with_name_servers(timeout, &config.dns_servers, settings).await?
+                }
+            }
+        } else {
+            // Configured resolvers, or fast public DNS servers for optimal performance
+            DnsblClient::with_name_servers(timeout, &config.dns_servers, settings).await?
+        };
+        let mut servers: Vec<_> = client.resolver_config().name_servers().iter().map(|ns| ns.socket_addr).collect();
+        servers.dedup();
+        log::debug!("DNSBL resolver using name servers {:?}", servers);
+        Ok(client)
+    }
     
     /// Check a single IP address against configured DNSBL lists
     pub async fn check_ip(&mut self, ip: &str) -> Result<DnsblCheckResults, Box<dyn std::error::Error + Send + Sync>> {
         let start_time = Instant::now();
         
         log::info!("Starting DNSBL check for IP: {}", ip);
+
+        // Whitelisted IPs are clean regardless of any listing
+        if self.is_whitelisted(ip) {
+            log::debug!("IP {} is whitelisted, skipping DNSBL check", ip);
+            let mut check_results = DnsblCheckResults::new(ip.to_string());
+            check_results.results.push(crate::dnsbl::DnsblResult {
+                list_name: "whitelist".to_string(),
+                listed: false,
+                reason: Some("whitelisted".to_string()),
+                response_time_ms: 0,
+                errored: false,
+            });
+            return Ok(check_results);
+        }
         
+        // DNSBL zones are queried with reversed IPv4 octets, IPv6 addresses are not listed
+        if ip.parse::<std::net::IpAddr>().is_ok_and(|addr| addr.is_ipv6()) {
+            log::debug!("IP {} is IPv6, skipping DNSBL check", ip);
+            let mut check_results = DnsblCheckResults::new(ip.to_string());
+            check_results.results.push(crate::dnsbl::DnsblResult {
+                list_name: "ipv6".to_string(),
+                listed: false,
+                reason: Some("IPv6 not supported by DNSBL lists".to_string()),
+                response_time_ms: 0,
+                errored: false,
+            });
+            return Ok(check_results);
+        }
+
         // Check cache first
         if let Some(cached_results) = self.cache_manager.get(ip) {
             log::debug!("DNSBL check for {} completed from cache", ip);
             return Ok(cached_results);
         }
         
-        // Perform checks with early termination - clone needed for borrow checker
+        // Perform checks, with early termination unless a full scan is configured
         let results = self.check_ip_with_early_termination(ip).await;
         
         // Create results object
@@ -88,22 +146,32 @@ impl DnsblChecker {
         
         let total_time = start_time.elapsed();
         log::info!(
-            "DNSBL check for {} completed in {}ms - Listed: {}/{}, Malicious: {}",
+            "DNSBL check for {} completed in {}ms - Listed: {}/{}, Errored: {}, Malicious: {}",
             ip,
             total_time.as_millis(),
             check_results.listed_count,
             check_results.total_checked,
+            check_results.error_count,
             check_results.is_malicious
         );
         
         Ok(check_results)
     }
     
-    /// Check IP with early termination for performance optimization
+    /// Whether the IP matches an entry of the configured whitelist
+    pub fn is_whitelisted(&self, ip: &str) -> bool {
+        let Ok(ip) = ip.parse::<IpAddr>() else {
+            return false;
+        };
+        self.whitelist.iter().any(|cidr| cidr.contains(&ip))
+    }
+
+    /// Check IP with early termination for performance optimization, or against
+    /// every list with `full_scan`
     async fn check_ip_with_early_termination(
         &mut self,
         ip: &str,
-    ) -> Vec<crate::dnsbl::DnsblResult> {
+    ) -> Vec<DnsblResult> {
         // Get the lists to check, sorted by priority for optimal performance
         let specific_lists = self.config.specific_lists.clone();
         let excluded_lists = self.config.excluded_lists.clone();
@@ -116,13 +184,10 @@ impl DnsblChecker {
             log::warn!("No DNSBL lists configured for checking");
             return Vec::new();
         }
-        use futures_util::stream::{FuturesUnordered, StreamExt};
+        use futures_util::stream::FuturesUnordered;
         
-        let mut results = Vec::new();
-        let mut listed_count = 0;
-        let mut futures = FuturesUnordered::new();
+        let futures = FuturesUnordered::new();
         let semaphore = std::sync::Arc::new(tokio::sync::Semaphore::new(self.config.max_concurrent));
-        let threshold = self.config.malicious_threshold;
 
         // Create tasks for each list in priority order
         for (index, list) in lists.iter().enumerate() {
@@ -133,88 +198,42 @@ impl DnsblChecker {
                 continue;
             };
             let client = self.client.clone();
+            let latencies = self.lists.latencies().clone();
             let ip = ip.to_string();
             let list = (*list).clone(); // Dereference and clone
 
             futures.push(tokio::spawn(async move {
                 let _permit = permit;
                 let result = client.check_ip_against_list(&ip, &list).await;
+                // Slow lists sink within their priority on later checks
+                if let Ok(result) = &result {
+                    latencies.record(&list.id, result.response_time_ms);
+                }
                 (index, result)
             }));
         }
-        
-        // Process results as they complete
-        while let Some(result) = futures.next().await {
-            match result {
-                Ok((index, Ok(dnsbl_result))) => {
-                    if dnsbl_result.listed {
-                        listed_count += 1;
-                        log::debug!("IP {} listed in {} (priority: {})", ip, dnsbl_result.list_name, index);
-                        
-                        // Early termination: if we've reached the threshold, we can stop
-                        if listed_count >= threshold {
-                            log::info!("Early termination for IP {} - threshold reached ({}/{})", 
-                                ip, listed_count, threshold);
-                            
-                            // Add the current result and break
-                            results.push(dnsbl_result);
-                            break;
-                        }
-                    }
-                    results.push(dnsbl_result);
-                }
-                Ok((_, Err(e))) => {
-                    log::warn!("DNSBL query failed: {}", e);
-                    // Create a failed result but don't count towards threshold
-                    results.push(crate::dnsbl::DnsblResult {
-                        list_name: "unknown".to_string(),
-                        listed: false,
-                        reason: Some(format!("Query failed: {}", e)),
-                        response_time_ms: 0,
-                    });
-                }
-                Err(e) => {
-                    log::warn!("DNSBL task failed: {}", e);
-                }
-            }
-        }
-        
-        // Sort results back to original order for consistency
-        results.sort_by_key(|r| r.list_name.clone());
-        results
+
+        collect_results(ip, futures, self.config.malicious_threshold, self.config.full_scan).await
     }
     
-    /// Check multiple IP addresses
+    /// Check multiple IP addresses, at most `max_concurrent_ips` at a time.
+    /// Results follow the order of `ips`, IPs whose check failed are left out.
     pub async fn check_ips(&mut self, ips: &[String]) -> Vec<DnsblCheckResults> {
-        use futures_util::stream::{FuturesUnordered, StreamExt};
-        
-        let mut futures = FuturesUnordered::new();
-        
-        // Create tasks for each IP
-        for ip in ips {
-            let mut checker = self.clone();
-            let ip = ip.clone();
-            
-            futures.push(tokio::spawn(async move {
-                checker.check_ip(&ip).await
-            }));
-        }
-        
-        // Collect results
-        let mut results = Vec::new();
-        while let Some(result) = futures.next().await {
-            match result {
-                Ok(Ok(check_results)) => results.push(check_results),
-                Ok(Err(e)) => {
-                    log::error!("DNSBL check failed: {}", e);
-                }
-                Err(e) => {
-                    log::error!("DNSBL task failed: {}", e);
-                }
+        let checker = &*self;
+        bounded_in_order(ips.iter().cloned(), self.config.max_concurrent_ips, |ip| {
+            let mut checker = checker.clone();
+            async move { checker.check_ip(&ip).await }
+        })
+        .await
+        .into_iter()
+        .filter_map(|result| match result {
+            Ok(check_results) => Some(check_results),
+            Err(e) => {
+                log::error!("DNSBL check failed: {}", e);
+                None
             }
-        }
-        
-        results
+        })
+        .collect()
     }
     
     /// Check if an IP is malicious (convenience method)
@@ -248,11 +267,30 @@ impl DnsblChecker {
         &self.config
     }
     
-    /// Update configuration
-    pub fn update_config(&mut self, config: DnsblConfig) {
-        self.config = config.clone();
-        // Note: We don't update the client or cache manager here as it would require
-        // recreating them. In a production system, you might want to handle this better.
+    /// Update configuration, rebuilding the DNS client when the timeout or
+    /// resolver settings change. Cached results are kept.
+    pub async fn update_config(&mut self, config: DnsblConfig) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if config.timeout_secs != self.config.timeout_secs
+            || config.dns_over_https != self.config.dns_over_https
+            || config.dns_servers != self.config.dns_servers
+        {
+            self.client = Self::build_client(&config).await?;
+            log::info!(
+                "DNSBL client rebuilt with timeout: {}s, DoH: {}",
+                config.timeout_secs, config.dns_over_https
+            );
+        }
+        self.cache_manager.update_config(config.clone());
+        if config.whitelisted_ips != self.config.whitelisted_ips {
+            self.whitelist = parse_whitelist(&config.whitelisted_ips);
+        }
+        self.config = config;
+        Ok(())
+    }
+
+    /// Query timeout of the current DNS client
+    pub fn client_timeout(&self) -> std::time::Duration {
+        self.client.timeout()
     }
     
     /// Get available DNSBL lists
@@ -281,6 +319,7 @@ impl DnsblChecker {
                 enabled: true,
                 timeout_secs: 10,
                 max_concurrent: 5,
+                max_concurrent_ips: 10,
                 cache_ttl_secs: 7200, // 2 hours
                 malicious_threshold: 1, // Any listing = malicious
                 specific_lists: vec![
@@ -289,20 +328,36 @@ impl DnsblChecker {
                     "dronebl".to_string(),
                 ],
                 excluded_lists: vec![],
+                dns_over_https: false,
+                whitelisted_ips: vec![],
+                resolver_cache_size: 1024,
+                resolver_attempts: 2,
+                dns_servers: vec![],
+                full_scan: false,
+                max_error_rate: 50.0,
             },
             DnsblUseCase::Balanced => DnsblConfig {
                 enabled: true,
                 timeout_secs: 5,
                 max_concurrent: 10,
+                max_concurrent_ips: 20,
                 cache_ttl_secs: 3600, // 1 hour
                 malicious_threshold: 2, // 2+ listings = malicious
                 specific_lists: vec![],
                 excluded_lists: vec!["pbl".to_string()], // Exclude policy lists
+                dns_over_https: false,
+                whitelisted_ips: vec![],
+                resolver_cache_size: 1024,
+                resolver_attempts: 2,
+                dns_servers: vec![],
+                full_scan: false,
+                max_error_rate: 50.0,
             },
             DnsblUseCase::Performance => DnsblConfig {
                 enabled: true,
                 timeout_secs: 3,
                 max_concurrent: 20,
+                max_concurrent_ips: 50,
                 cache_ttl_secs: 1800, // 30 minutes
                 malicious_threshold: 3, // 3+ listings = malicious
                 specific_lists: vec![
@@ -310,20 +365,48 @@ impl DnsblChecker {
                     "barracuda".to_string(),
                 ],
                 excluded_lists: vec![],
+                dns_over_https: false,
+                whitelisted_ips: vec![],
+                resolver_cache_size: 1024,
+                resolver_attempts: 2,
+                dns_servers: vec![],
+                full_scan: false,
+                max_error_rate: 50.0,
             },
             DnsblUseCase::Testing => DnsblConfig {
                 enabled: true,
                 timeout_secs: 1,
                 max_concurrent: 1,
+                max_concurrent_ips: 1,
                 cache_ttl_secs: 60, // 1 minute
                 malicious_threshold: 1,
                 specific_lists: vec!["zen".to_string()],
                 excluded_lists: vec![],
+                dns_over_https: false,
+                whitelisted_ips: vec![],
+                resolver_cache_size: 1024,
+                resolver_attempts: 2,
+                dns_servers: vec![],
+                full_scan: false,
+                max_error_rate: 50.0,
             },
         }
     }
 }
 
+/// Parse the whitelist entries once, warning about those that are not an IP or a CIDR range
+fn parse_whitelist(entries: &[String]) -> Vec<Cidr> {
+    entries
+        .iter()
+        .filter_map(|entry| {
+            entry
+                .parse::<Cidr>()
+                .map_err(|e| log::warn!("Ignoring invalid DNSBL whitelist entry: {}", e))
+                .ok()
+        })
+        .collect()
+}
+
 /// DNSBL use cases for recommended configurations
 #[derive(Debug, Clone, Copy)]
 pub enum DnsblUseCase {
@@ -352,6 +435,8 @@ pub struct DnsblSummary {
     pub avg_response_time_ms: f64,
     /// Cache hit rate
     pub cache_hit_rate: f64,
+    /// Resolver cache usage, only filled by `summarize`
+    pub resolver: ResolverStats,
 }
 
 impl DnsblChecker {
@@ -377,8 +462,89 @@ impl DnsblChecker {
             total_lists_checked,
             avg_response_time_ms,
             cache_hit_rate,
+            resolver: ResolverStats::default(),
+        }
+    }
+
+    /// Summary of `results` including this checker's cache and resolver statistics
+    pub fn summarize(&self, results: &[DnsblCheckResults]) -> DnsblSummary {
+        let stats = self.get_cache_stats();
+        let lookups = stats.hits + stats.misses;
+        let cache_hit_rate = if lookups == 0 {
+            0.0
+        } else {
+            stats.hits as f64 / lookups as f64 * 100.0
+        };
+        DnsblSummary {
+            resolver: self.client.resolver_stats(),
+            ..Self::generate_summary(results, cache_hit_rate)
+        }
+    }
+}
+
+/// Gather DNSBL answers as they complete. Unless `full_scan`, stop at the
+/// `threshold`-th listing since later answers cannot change the verdict.
+async fn collect_results<S, E>(ip: &str, mut checks: S, threshold: usize, full_scan: bool) -> Vec<DnsblResult>
+where
+    S: futures_util::Stream<Item = Result<(usize, Result<DnsblResult, E>), tokio::task::JoinError>> + Unpin,
+    E: std::fmt::Display,
+{
+    use futures_util::StreamExt;
+
+    let mut results = Vec::new();
+    let mut listed_count = 0;
+    while let Some(result) = checks.next().await {
+        match result {
+            Ok((index, Ok(dnsbl_result))) => {
+                if dnsbl_result.listed {
+                    listed_count += 1;
+                    log::debug!("IP {} listed in {} (priority: {})", ip, dnsbl_result.list_name, index);
+                    
+                    // Early termination: if we've reached the threshold, we can stop
+                    if !full_scan && listed_count >= threshold {
+                        log::info!("Early termination for IP {} - threshold reached ({}/{})", 
+                            ip, listed_count, threshold);
+                        
+                        // Add the current result and break
+                        results.push(dnsbl_result);
+                        break;
+                    }
+                }
+                results.push(dnsbl_result);
+            }
+            Ok((_, Err(e))) => {
+                log::warn!("DNSBL query failed: {}", e);
+                // Create a failed result but don't count towards threshold
+                results.push(crate::dnsbl::DnsblResult {
+                    list_name: "unknown".to_string(),
+                    listed: false,
+                    reason: Some(format!("Query failed: {}", e)),
+                    response_time_ms: 0,
+                    errored: true,
+                });
+            }
+            Err(e) => {
+                log::warn!("DNSBL task failed: {}", e);
+            }
         }
     }
+
+    // Sort results back to original order for consistency
+    results.sort_by_key(|r| r.list_name.clone());
+    results
+}
+
+/// Run `check` over `items` with at most `limit` futures in flight, keeping the input order.
+/// Futures are created lazily, so memory stays bounded whatever the number of items.
+async fn bounded_in_order<I, R, F, Fut>(items: I, limit: usize, check: F) -> Vec<R>
+where
+    I: IntoIterator,
+    F: FnMut(I::Item) -> Fut,
+    Fut: std::future::Future<Output = R>,
+{
+    use futures_util::stream::{self, StreamExt};
+
+    stream::iter(items).map(check).buffered(limit.max(1)).collect().await
 }
 
 #[cfg(test)]
@@ -397,16 +563,151 @@ mod tests {
             enabled: true,
             timeout_secs: 3,
             max_concurrent: 5,
+            max_concurrent_ips: 10,
             cache_ttl_secs: 1800,
             malicious_threshold: 2,
             specific_lists: vec![],
             excluded_lists: vec![],
+            dns_over_https: false,
+            whitelisted_ips: vec![],
+            resolver_cache_size: 1024,
+            resolver_attempts: 2,
+            dns_servers: vec![],
+            full_scan: false,
+            max_error_rate: 50.0,
         };
         
         let checker = DnsblChecker::with_config(config).await;
         assert!(checker.is_ok());
     }
+
+    #[tokio::test]
+    async fn test_update_config_rebuilds_client() {
+        let mut checker = DnsblChecker::new().await.unwrap();
+        assert_eq!(checker.client_timeout(), std::time::Duration::from_secs(5));
+
+        let config = DnsblConfig {
+            timeout_secs: 2,
+            cache_ttl_secs: 60,
+            ..checker.get_config().clone()
+        };
+        checker.update_config(config).await.unwrap();
+
+        assert_eq!(checker.client_timeout(), std::time::Duration::from_secs(2));
+        assert_eq!(checker.get_config().timeout_secs, 2);
+        assert_eq!(checker.get_config().cache_ttl_secs, 60);
+    }
     
+    #[tokio::test]
+    async fn test_bounded_in_order_caps_in_flight() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let items: Vec<usize> = (0..10_000).collect();
+        let in_flight = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+        let results = bounded_in_order(items.clone(), 8, |item| {
+            let (in_flight, peak) = (&in_flight, &peak);
+            async move {
+                let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                tokio::task::yield_now().await;
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                item
+            }
+        })
+        .await;
+
+        assert_eq!(results, items);
+        assert!(peak.load(Ordering::SeqCst) <= 8);
+        assert!(peak.load(Ordering::SeqCst) > 1);
+    }
+
+    #[tokio::test]
+    async fn test_check_ips_large_batch_keeps_order() {
+        let config = DnsblConfig {
+            max_concurrent_ips: 4,
+            whitelisted_ips: vec!["10.0.0.0/8".to_string()],
+            ..DnsblConfig::default()
+        };
+        let mut checker = DnsblChecker::with_config(config).await.unwrap();
+        let ips: Vec<String> = (0..5_000).map(|i| format!("10.0.{}.{}", i / 256, i % 256)).collect();
+
+        let results = checker.check_ips(&ips).await;
+        assert_eq!(results.len(), ips.len());
+        assert!(results.iter().zip(&ips).all(|(result, ip)| &result.ip == ip));
+    }
+
+    #[tokio::test]
+    async fn test_full_scan_checks_every_list() {
+        type Answer = Result<(usize, Result<DnsblResult, String>), tokio::task::JoinError>;
+
+        // An IP listed in 3 of 5 lists, answers arriving in list order
+        let answers = || {
+            let answers: Vec<Answer> = (0..5)
+                .map(|index| {
+                    Ok((index, Ok(DnsblResult {
+                        list_name: format!("list{}", index),
+                        listed: index % 2 == 0,
+                        reason: None,
+                        response_time_ms: 1,
+                        errored: false,
+                    })))
+                })
+                .collect();
+            futures_util::stream::iter(answers)
+        };
+        let checked = |results: Vec<DnsblResult>| {
+            let mut check_results = DnsblCheckResults::new("192.0.2.1".to_string());
+            for result in results {
+                check_results.add_result(result);
+            }
+            check_results.update_malicious_status(2);
+            check_results
+        };
+
+        let early = checked(collect_results("192.0.2.1", answers(), 2, false).await);
+        assert_eq!((early.total_checked, early.listed_count), (3, 2));
+        assert!(early.is_malicious);
+
+        let full = checked(collect_results("192.0.2.1", answers(), 2, true).await);
+        assert_eq!((full.total_checked, full.listed_count), (5, 3));
+        assert!(full.is_malicious);
+    }
+
+    #[tokio::test]
+    async fn test_failed_queries_are_errors_not_listings() {
+        type Answer = Result<(usize, Result<DnsblResult, String>), tokio::task::JoinError>;
+
+        // Every query fails, as when the network is down
+        let answers: Vec<Answer> = (0..5)
+            .map(|index| Ok((index, Err(format!("list{} unreachable", index)))))
+            .collect();
+        let results = collect_results("192.0.2.1", futures_util::stream::iter(answers), 2, false).await;
+
+        let mut check_results = DnsblCheckResults::new("192.0.2.1".to_string());
+        for result in results {
+            check_results.add_result(result);
+        }
+        check_results.update_malicious_status(2);
+
+        assert!(!check_results.is_malicious);
+        assert_eq!(check_results.listed_count, 0);
+        assert_eq!(check_results.total_checked, 5);
+        assert_eq!(check_results.error_count, check_results.total_checked);
+        assert!(check_results.is_inconclusive(50.0));
+        assert!(!check_results.is_inconclusive(100.0));
+    }
+
+    #[tokio::test]
+    async fn test_ipv6_is_skipped() {
+        let mut checker = DnsblChecker::new().await.unwrap();
+        let results = checker.check_ip("2001:db8::1").await.unwrap();
+        assert!(!results.is_malicious);
+        assert_eq!(results.results.len(), 1);
+        assert_eq!(results.results[0].list_name, "ipv6");
+        assert_eq!(results.total_checked, 0);
+    }
+
     #[tokio::test]
     async fn test_ip_validation() {
         assert!(DnsblChecker::validate_ip_format("192.168.1.1"));
@@ -487,6 +788,7 @@ mod tests {
         assert_eq!(summary.total_lists_checked, 10);
         assert_eq!(summary.avg_response_time_ms, 1250.0);
         assert_eq!(summary.cache_hit_rate, 75.0);
+        assert_eq!(summary.resolver.lookups, 0);
     }
     
     #[tokio::test]
@@ -500,4 +802,48 @@ mod tests {
         assert!(!result.is_malicious); // Should not be malicious
         assert!(result.total_checked > 0); // Should have checked some lists
     }
+
+    #[tokio::test]
+    async fn test_whitelisted_exact_ip() {
+        let mut config = DnsblChecker::get_recommended_config(DnsblUseCase::Testing);
+        config.whitelisted_ips = vec!["203.0.113.7".to_string()];
+        let mut checker = DnsblChecker::with_config(config).await.expect("Failed to create DNSBL checker");
+
+        let result = checker.check_ip("203.0.113.7").await.expect("Failed to check IP");
+        assert!(!result.is_malicious);
+        assert_eq!(result.total_checked, 0);
+        assert_eq!(result.results.len(), 1);
+        assert_eq!(result.results[0].reason.as_deref(), Some("whitelisted"));
+    }
+
+    #[tokio::test]
+    async fn test_whitelisted_cidr_range() {
+        let mut config = DnsblChecker::get_recommended_config(DnsblUseCase::Testing);
+        config.whitelisted_ips = vec!["198.51.100.0/24".to_string(), "not-an-ip".to_string()];
+        let mut checker = DnsblChecker::with_config(config).await.expect("Failed to create DNSBL checker");
+
+        assert!(checker.is_whitelisted("198.51.100.42"));
+        assert!(!checker.is_whitelisted("198.51.101.1"));
+
+        let result = checker.check_ip("198.51.100.42").await.expect("Failed to check IP");
+        assert_eq!(result.results[0].reason.as_deref(), Some("whitelisted"));
+
+        // A reloaded whitelist replaces the parsed one
+        let mut config = checker.get_config().clone();
+        config.whitelisted_ips = vec!["198.51.101.0/24".to_string()];
+        checker.update_config(config).await.unwrap();
+        assert!(!checker.is_whitelisted("198.51.100.42"));
+        assert!(checker.is_whitelisted("198.51.101.1"));
+    }
+
+    #[tokio::test]
+    async fn test_non_whitelisted_ip_is_checked() {
+        let mut config = DnsblChecker::get_recommended_config(DnsblUseCase::Testing);
+        config.whitelisted_ips = vec!["198.51.100.0/24".to_string()];
+        let mut checker = DnsblChecker::with_config(config).await.expect("Failed to create DNSBL checker");
+
+        let result = checker.check_ip("8.8.8.8").await.expect("Failed to check IP");
+        assert!(result.total_checked > 0);
+        assert!(result.results.iter().all(|r| r.reason.as_deref() != Some("whitelisted")));
+    }
 }
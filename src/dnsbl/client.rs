@@ -1,19 +1,116 @@
 //! DNS client for DNSBL queries
 
+use std::collections::{HashSet, VecDeque};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
-use crate::dnsbl::{DnsblList, DnsblResult, DnsblResponseFormat};
+use parking_lot::Mutex;
+
+use crate::dnsbl::{DnsblConfig, DnsblList, DnsblResult, DnsblResponseFormat};
 use hickory_resolver::{
-    config::{ResolverConfig, ResolverOpts},
+    config::{NameServerConfigGroup, ResolverConfig, ResolverOpts},
         AsyncResolver,
 };
 use hickory_resolver::name_server::TokioConnectionProvider;
+use hickory_resolver::error::{ResolveError, ResolveErrorKind};
+
+/// Resolver cache and retry settings applied by every client constructor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResolverSettings {
+    /// Number of answers kept by the resolver cache
+    pub cache_size: usize,
+    /// Attempts per query before giving up
+    pub attempts: usize,
+}
+
+impl Default for ResolverSettings {
+    fn default() -> Self {
+        Self {
+            cache_size: crate::dnsbl::default_resolver_cache_size(),
+            attempts: crate::dnsbl::default_resolver_attempts(),
+        }
+    }
+}
+
+impl From<&DnsblConfig> for ResolverSettings {
+    fn from(config: &DnsblConfig) -> Self {
+        Self {
+            cache_size: config.resolver_cache_size,
+            attempts: config.resolver_attempts,
+        }
+    }
+}
+
+/// Resolver cache usage. The resolver does not expose its cache, so hits are
+/// estimated by counting queries repeated while their answer could still be cached.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ResolverStats {
+    /// Distinct queries currently assumed to be cached
+    pub entries: usize,
+    /// Queries sent through the resolver
+    pub lookups: u64,
+    /// Approximate percentage of queries answered from the cache
+    pub hit_rate: f64,
+}
+
+/// Recently sent queries, bounded like the resolver cache
+#[derive(Debug, Default)]
+struct QueryLog {
+    capacity: usize,
+    recent: HashSet<String>,
+    order: VecDeque<String>,
+    lookups: u64,
+    repeats: u64,
+}
+
+impl QueryLog {
+    fn with_capacity(capacity: usize) -> Self {
+        Self {
+            capacity,
+            ..Default::default()
+        }
+    }
+
+    fn record(&mut self, query: String) {
+        self.lookups += 1;
+        if self.recent.contains(&query) {
+            self.repeats += 1;
+            return;
+        }
+        if self.capacity == 0 {
+            return;
+        }
+        if self.order.len() >= self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.recent.remove(&oldest);
+            }
+        }
+        self.recent.insert(query.clone());
+        self.order.push_back(query);
+    }
+
+    fn stats(&self) -> ResolverStats {
+        ResolverStats {
+            entries: self.recent.len(),
+            lookups: self.lookups,
+            hit_rate: if self.lookups == 0 {
+                0.0
+            } else {
+                self.repeats as f64 / self.lookups as f64 * 100.0
+            },
+        }
+    }
+}
 
 /// DNS client for performing DNSBL queries
 #[derive(Debug, Clone)]
 pub struct DnsblClient {
     resolver: AsyncResolver<TokioConnectionProvider>,
+    config: ResolverConfig,
+    opts: ResolverOpts,
     timeout: Duration,
+    queries: Arc<Mutex<QueryLog>>,
 }
 
 impl DnsblClient {
@@ -22,43 +119,117 @@ impl DnsblClient {
         Self::with_timeout(Duration::from_secs(5)).await
     }
     
+    /// Query timeout of this client
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Options the resolver was built with
+    pub fn resolver_opts(&self) -> &ResolverOpts {
+        &self.opts
+    }
+
+    /// Name servers and search domains the resolver was built with
+    pub fn resolver_config(&self) -> &ResolverConfig {
+        &self.config
+    }
+
+    /// Approximate resolver cache usage since the client was created
+    pub fn resolver_stats(&self) -> ResolverStats {
+        self.queries.lock().stats()
+    }
+
     /// Create new DNSBL client with custom timeout
     pub async fn with_timeout(timeout: Duration) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
-        Self::with_optimized_config(timeout, false).await
+        Self::with_optimized_config(timeout, false, ResolverSettings::default()).await
     }
     
     /// Create new DNSBL client with optimized configuration for speed
     pub async fn with_optimized_config(
         timeout: Duration,
         use_fast_dns: bool,
+        settings: ResolverSettings,
     ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
-        let resolver = if use_fast_dns {
+        let (config, opts) = if use_fast_dns {
             // Use fast public DNS servers for optimal performance
-            let name_servers = vec![
-                "1.1.1.1:53".parse().expect("Invalid Cloudflare DNS address"), // Cloudflare
-                "8.8.8.8:53".parse().expect("Invalid Google DNS address"), // Google
-                "1.0.0.1:53".parse().expect("Invalid Cloudflare backup DNS address"), // Cloudflare backup
+            let name_servers: [IpAddr; 3] = [
+                IpAddr::from([1, 1, 1, 1]), // Cloudflare
+                IpAddr::from([8, 8, 8, 8]), // Google
+                IpAddr::from([1, 0, 0, 1]), // Cloudflare backup
             ];
 
             let config = ResolverConfig::from_parts(
                 None,
-                name_servers,
                 vec![],
+                NameServerConfigGroup::from_ips_clear(&name_servers, 53, true),
             );
 
-            let mut opts = ResolverOpts::default();
-            opts.timeout = timeout;
-            opts.attempts = 2; // Limit retry attempts for speed
-            opts.rotate = true; // Rotate between DNS servers for load balancing
-            opts.ndots = 1; // Optimize for short domain names
-            opts.cache_size = 1024; // Increase cache size
-
-            AsyncResolver::tokio(config, opts)
+            (config, Self::optimized_opts(timeout, settings))
         } else {
-            AsyncResolver::tokio(ResolverConfig::default(), ResolverOpts::default())
+            let mut opts = ResolverOpts::default();
+            opts.attempts = settings.attempts;
+            opts.cache_size = settings.cache_size;
+            (ResolverConfig::default(), opts)
         };
         
-        Ok(Self { resolver, timeout })
+        Ok(Self::build(config, opts, timeout))
+    }
+
+    /// Create new DNSBL client querying the `servers` given as `ip` or `ip:port`.
+    /// Entries that do not parse are skipped with a warning; when none is left the
+    /// fast public servers of `with_optimized_config` are used.
+    pub async fn with_name_servers(
+        timeout: Duration,
+        servers: &[String],
+        settings: ResolverSettings,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let addrs = parse_name_servers(servers);
+        if addrs.is_empty() {
+            if !servers.is_empty() {
+                log::warn!("No usable DNSBL DNS server in {:?}, using the public ones", servers);
+            }
+            return Self::with_optimized_config(timeout, true, settings).await;
+        }
+
+        let mut name_servers = NameServerConfigGroup::with_capacity(addrs.len() * 2);
+        for addr in &addrs {
+            name_servers.merge(NameServerConfigGroup::from_ips_clear(&[addr.ip()], addr.port(), true));
+        }
+        let config = ResolverConfig::from_parts(None, vec![], name_servers);
+        Ok(Self::build(config, Self::optimized_opts(timeout, settings), timeout))
+    }
+
+    /// Create new DNSBL client resolving over DNS-over-HTTPS (Cloudflare, then Google)
+    pub async fn with_doh(
+        timeout: Duration,
+        settings: ResolverSettings,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let mut name_servers = NameServerConfigGroup::cloudflare_https();
+        name_servers.merge(NameServerConfigGroup::google_https());
+
+        let config = ResolverConfig::from_parts(None, vec![], name_servers);
+        Ok(Self::build(config, Self::optimized_opts(timeout, settings), timeout))
+    }
+
+    fn build(config: ResolverConfig, opts: ResolverOpts, timeout: Duration) -> Self {
+        Self {
+            resolver: AsyncResolver::tokio(config.clone(), opts.clone()),
+            config,
+            queries: Arc::new(Mutex::new(QueryLog::with_capacity(opts.cache_size))),
+            opts,
+            timeout,
+        }
+    }
+
+    /// Resolver options tuned for many short DNSBL lookups
+    fn optimized_opts(timeout: Duration, settings: ResolverSettings) -> ResolverOpts {
+        let mut opts = ResolverOpts::default();
+        opts.timeout = timeout;
+        opts.attempts = settings.attempts; // Few retries keep slow lists from stalling checks
+        opts.rotate = true; // Rotate between DNS servers for load balancing
+        opts.ndots = 1; // Optimize for short domain names
+        opts.cache_size = settings.cache_size;
+        opts
     }
     
     /// Check a single IP against a single DNSBL list
@@ -78,6 +249,7 @@ impl DnsblClient {
                     listed: false,
                     reason: Some(format!("Invalid IP format: {}", e)),
                     response_time_ms: start_time.elapsed().as_millis() as u64,
+                    errored: true,
                 });
             }
         };
@@ -144,6 +316,7 @@ impl DnsblClient {
                         listed: false,
                         reason: Some(format!("Query failed: {}", e)),
                         response_time_ms: 0,
+                        errored: true,
                     });
                 }
                 Err(e) => {
@@ -162,6 +335,7 @@ impl DnsblClient {
         list: &DnsblList,
         start_time: Instant,
     ) -> Result<DnsblResult, Box<dyn std::error::Error + Send + Sync>> {
+        self.queries.lock().record(format!("A {}", query_domain));
         match tokio::time::timeout(self.timeout, self.resolver.ipv4_lookup(query_domain)).await {
             Ok(Ok(lookup)) => {
                 let response_time = start_time.elapsed().as_millis() as u64;
@@ -172,6 +346,7 @@ impl DnsblClient {
                     listed,
                     reason: if listed { Some("Listed in DNSBL".to_string()) } else { None },
                     response_time_ms: response_time,
+                    errored: false,
                 })
             }
             Ok(Err(e)) => {
@@ -181,6 +356,7 @@ impl DnsblClient {
                     listed: false,
                     reason: Some(format!("DNS lookup failed: {}", e)),
                     response_time_ms: response_time,
+                    errored: !is_not_listed(&e),
                 })
             }
             Err(_) => {
@@ -190,6 +366,7 @@ impl DnsblClient {
                     listed: false,
                     reason: Some("DNS lookup timeout".to_string()),
                     response_time_ms: response_time,
+                    errored: true,
                 })
             }
         }
@@ -202,6 +379,7 @@ impl DnsblClient {
         list: &DnsblList,
         start_time: Instant,
     ) -> Result<DnsblResult, Box<dyn std::error::Error + Send + Sync>> {
+        self.queries.lock().record(format!("A {}", query_domain));
         match tokio::time::timeout(self.timeout, self.resolver.ipv4_lookup(query_domain)).await {
             Ok(Ok(lookup)) => {
                 let response_time = start_time.elapsed().as_millis() as u64;
@@ -215,6 +393,7 @@ impl DnsblClient {
                         listed: true,
                         reason: Some(reason),
                         response_time_ms: response_time,
+                        errored: false,
                     })
                 } else {
                     Ok(DnsblResult {
@@ -222,6 +401,7 @@ impl DnsblClient {
                         listed: false,
                         reason: None,
                         response_time_ms: response_time,
+                        errored: false,
                     })
                 }
             }
@@ -232,6 +412,7 @@ impl DnsblClient {
                     listed: false,
                     reason: Some(format!("DNS lookup failed: {}", e)),
                     response_time_ms: response_time,
+                    errored: !is_not_listed(&e),
                 })
             }
             Err(_) => {
@@ -241,6 +422,7 @@ impl DnsblClient {
                     listed: false,
                     reason: Some("DNS lookup timeout".to_string()),
                     response_time_ms: response_time,
+                    errored: true,
                 })
             }
         }
@@ -253,6 +435,7 @@ impl DnsblClient {
         list: &DnsblList,
         start_time: Instant,
     ) -> Result<DnsblResult, Box<dyn std::error::Error + Send + Sync>> {
+        self.queries.lock().record(format!("TXT {}", query_domain));
         match tokio::time::timeout(self.timeout, self.resolver.txt_lookup(query_domain)).await {
             Ok(Ok(lookup)) => {
                 let response_time = start_time.elapsed().as_millis() as u64;
@@ -266,6 +449,7 @@ impl DnsblClient {
                         listed: true,
                         reason: Some(reason),
                         response_time_ms: response_time,
+                        errored: false,
                     })
                 } else {
                     Ok(DnsblResult {
@@ -273,6 +457,7 @@ impl DnsblClient {
                         listed: false,
                         reason: None,
                         response_time_ms: response_time,
+                        errored: false,
                     })
                 }
             }
@@ -283,6 +468,7 @@ impl DnsblClient {
                     listed: false,
                     reason: Some(format!("DNS lookup failed: {}", e)),
                     response_time_ms: response_time,
+                    errored: !is_not_listed(&e),
                 })
             }
             Err(_) => {
@@ -292,6 +478,7 @@ impl DnsblClient {
                     listed: false,
                     reason: Some("DNS lookup timeout".to_string()),
                     response_time_ms: response_time,
+                    errored: true,
                 })
             }
         }
@@ -356,6 +543,32 @@ impl DnsblClient {
     }
 }
 
+/// An NXDOMAIN or empty answer is how a DNSBL says "not listed", any other failure is an error
+fn is_not_listed(error: &ResolveError) -> bool {
+    matches!(error.kind(), ResolveErrorKind::NoRecordsFound { .. })
+}
+
+/// Resolver addresses of `servers`, `ip` (port 53), `ip:port` or `[ipv6]:port`,
+/// skipping and logging the entries that are neither
+pub fn parse_name_servers(servers: &[String]) -> Vec<SocketAddr> {
+    servers
+        .iter()
+        .filter_map(|server| {
+            let server = server.trim();
+            let addr = server
+                .parse::<SocketAddr>()
+                .or_else(|_| server.parse::<IpAddr>().map(|ip| SocketAddr::new(ip, 53)));
+            match addr {
+                Ok(addr) => Some(addr),
+                Err(_) => {
+                    log::warn!("Ignoring invalid DNSBL DNS server {:?}", server);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
 // Note: Default implementation removed as it requires async initialization
 // Use DnsblClient::new() or DnsblClient::with_timeout() instead
 
@@ -370,6 +583,53 @@ mod tests {
         assert!(client.is_ok());
     }
     
+    #[tokio::test]
+    async fn test_custom_resolver_settings() {
+        let settings = ResolverSettings {
+            cache_size: 64,
+            attempts: 5,
+        };
+        let fast = DnsblClient::with_optimized_config(Duration::from_secs(3), true, settings).await.unwrap();
+        assert_eq!(fast.resolver_opts().cache_size, 64);
+        assert_eq!(fast.resolver_opts().attempts, 5);
+        assert_eq!(fast.resolver_opts().timeout, Duration::from_secs(3));
+
+        let system = DnsblClient::with_optimized_config(Duration::from_secs(3), false, settings).await.unwrap();
+        assert_eq!(system.resolver_opts().cache_size, 64);
+        assert_eq!(system.resolver_opts().attempts, 5);
+    }
+
+    #[tokio::test]
+    async fn test_custom_name_servers() {
+        let servers = vec!["10.0.0.53".to_string(), "[2001:db8::53]:5353".to_string(), "resolver.local".to_string()];
+        let client = DnsblClient::with_name_servers(Duration::from_secs(2), &servers, ResolverSettings::default())
+            .await
+            .unwrap();
+        let mut addrs: Vec<SocketAddr> = client.resolver_config().name_servers().iter().map(|ns| ns.socket_addr).collect();
+        addrs.dedup();
+        assert_eq!(addrs, ["10.0.0.53:53".parse::<SocketAddr>().unwrap(), "[2001:db8::53]:5353".parse().unwrap()]);
+        assert_eq!(client.resolver_opts().timeout, Duration::from_secs(2));
+
+        // Nothing usable falls back to the public servers
+        let fallback = DnsblClient::with_name_servers(Duration::from_secs(2), &["nope".to_string()], ResolverSettings::default())
+            .await
+            .unwrap();
+        assert!(fallback.resolver_config().name_servers().iter().any(|ns| ns.socket_addr.ip() == IpAddr::from([1, 1, 1, 1])));
+    }
+
+    #[test]
+    fn test_query_log_is_bounded_by_cache_size() {
+        let mut log = QueryLog::with_capacity(2);
+        for query in ["a", "b", "a", "c", "a"] {
+            log.record(query.to_string());
+        }
+        let stats = log.stats();
+        // "a" is evicted by "c", so only its first repeat counts as a hit
+        assert_eq!(stats.entries, 2);
+        assert_eq!(stats.lookups, 5);
+        assert_eq!(stats.hit_rate, 20.0);
+    }
+
     #[tokio::test]
     async fn test_connectivity() {
         let client = DnsblClient::new().await.unwrap();
@@ -377,6 +637,14 @@ mod tests {
         // Should be true in most environments, but don't fail test if not
         log::info!("DNS connectivity: {}", connectivity);
     }
+
+    #[tokio::test]
+    async fn test_doh_connectivity() {
+        let client = DnsblClient::with_doh(Duration::from_secs(5), ResolverSettings::default()).await.unwrap();
+        let connectivity = client.test_connectivity().await.unwrap();
+        // Requires outbound HTTPS, don't fail test if unavailable
+        log::info!("DoH connectivity: {}", connectivity);
+    }
     
     #[tokio::test]
     async fn test_check_known_clean_ip() {
@@ -389,6 +657,8 @@ mod tests {
             category: DnsblCategory::Spam,
             default_enabled: true,
             response_format: DnsblResponseFormat::Standard,
+            priority: 1,
+            avg_response_time_ms: 100,
         };
         
         // Use Google's DNS as a known clean IP
@@ -407,6 +677,8 @@ mod tests {
             category: DnsblCategory::Spam,
             default_enabled: true,
             response_format: DnsblResponseFormat::Standard,
+            priority: 1,
+            avg_response_time_ms: 100,
         };
         
         let result = client.check_ip_against_list("invalid", &list).await.unwrap();
@@ -1,8 +1,13 @@
 //! DNSBL lists configuration and management
 
 use crate::dnsbl::DnsblResponseFormat;
+use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Weight of the newest observation in the learned response time of a list
+const LATENCY_SMOOTHING: f64 = 0.3;
 
 /// Represents a single DNSBL list
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -46,10 +51,35 @@ pub enum DnsblCategory {
     Reputation,
 }
 
+/// Response times observed per list, as an exponential moving average.
+/// Clones share the same observations.
+#[derive(Debug, Clone, Default)]
+pub struct ListLatencies {
+    averages: Arc<RwLock<HashMap<String, f64>>>,
+}
+
+impl ListLatencies {
+    /// Fold an observed response time into the average of list `id`
+    pub fn record(&self, id: &str, response_time_ms: u64) {
+        let observed = response_time_ms as f64;
+        self.averages
+            .write()
+            .entry(id.to_string())
+            .and_modify(|average| *average += LATENCY_SMOOTHING * (observed - *average))
+            .or_insert(observed);
+    }
+
+    /// Learned response time of list `id`, `None` until a query was observed
+    pub fn get(&self, id: &str) -> Option<f64> {
+        self.averages.read().get(id).copied()
+    }
+}
+
 /// Collection of DNSBL lists
 #[derive(Debug, Clone)]
 pub struct DnsblLists {
     lists: HashMap<String, DnsblList>,
+    latencies: ListLatencies,
 }
 
 impl DnsblLists {
@@ -181,7 +211,10 @@ impl DnsblLists {
             avg_response_time_ms: 150,
         });
         
-        Self { lists }
+        Self {
+            lists,
+            latencies: ListLatencies::default(),
+        }
     }
     
     /// Get all default enabled lists
@@ -240,14 +273,26 @@ impl DnsblLists {
     pub fn get_lists_by_priority(&self, include: &[String], exclude: &[String]) -> Vec<&DnsblList> {
         let mut lists = self.filter_lists(include, exclude);
         
-        // Additional sorting by average response time as secondary criteria
+        // Additional sorting by learned response time as secondary criteria
         lists.sort_by(|a, b| {
             a.priority.cmp(&b.priority)
-                .then_with(|| a.avg_response_time_ms.cmp(&b.avg_response_time_ms))
+                .then_with(|| self.response_time_ms(a).total_cmp(&self.response_time_ms(b)))
         });
         
         lists
     }
+
+    /// Observed response times, shared with every clone of these lists
+    pub fn latencies(&self) -> &ListLatencies {
+        &self.latencies
+    }
+
+    /// Learned response time of a list, its configured average until queried
+    pub fn response_time_ms(&self, list: &DnsblList) -> f64 {
+        self.latencies
+            .get(&list.id)
+            .unwrap_or(list.avg_response_time_ms as f64)
+    }
     
     /// Get fast lists only (priority <= 3) for quick checks
     pub fn get_fast_lists(&self, include: &[String], exclude: &[String]) -> Vec<&DnsblList> {
@@ -341,4 +386,30 @@ mod tests {
         let pbl_found = excluded.iter().any(|l| l.id == "pbl");
         assert!(!pbl_found);
     }
+
+    #[test]
+    fn test_slow_list_sinks_within_its_priority() {
+        let lists = DnsblLists::new();
+        let ids = |lists: &DnsblLists| -> Vec<String> {
+            lists
+                .get_lists_by_priority(&["barracuda".to_string(), "dronebl".to_string()], &[])
+                .iter()
+                .map(|list| list.id.clone())
+                .collect()
+        };
+        assert_eq!(ids(&lists), vec!["barracuda", "dronebl"]);
+
+        // Observations made through a clone reach the original
+        let observed = lists.clone();
+        for _ in 0..5 {
+            observed.latencies().record("barracuda", 900);
+            observed.latencies().record("dronebl", 30);
+        }
+        assert_eq!(ids(&lists), vec!["dronebl", "barracuda"]);
+        assert_eq!(lists.response_time_ms(lists.get_by_id("barracuda").unwrap()), 900.0);
+
+        // Priority stays the primary key
+        let zen_first = lists.get_lists_by_priority(&["barracuda".to_string(), "zen".to_string()], &[]);
+        assert_eq!(zen_first[0].id, "zen");
+    }
 }
@@ -9,7 +9,7 @@ pub mod lists;
 pub mod checker;
 pub mod cache;
 
-pub use client::DnsblClient;
+pub use client::{DnsblClient, ResolverSettings, ResolverStats};
 pub use lists::{DnsblList, DnsblLists};
 pub use checker::DnsblChecker;
 pub use cache::DnsblCacheManager;
@@ -44,6 +44,9 @@ pub struct DnsblResult {
     pub reason: Option<String>,
     /// Time taken for the check in milliseconds
     pub response_time_ms: u64,
+    /// The query failed or timed out, so `listed: false` says nothing about the IP
+    #[serde(default)]
+    pub errored: bool,
 }
 
 /// Complete DNSBL check results for an IP
@@ -57,6 +60,9 @@ pub struct DnsblCheckResults {
     pub listed_count: usize,
     /// Total number of lists checked
     pub total_checked: usize,
+    /// Lists whose query failed or timed out, counted in `total_checked` but never as listings
+    #[serde(default)]
+    pub error_count: usize,
     /// Overall check time in milliseconds
     pub total_time_ms: u64,
     /// Whether the IP is considered malicious based on threshold
@@ -72,6 +78,9 @@ pub struct DnsblConfig {
     pub timeout_secs: u64,
     /// Maximum number of DNSBL lists to check concurrently
     pub max_concurrent: usize,
+    /// Maximum number of IPs checked concurrently during proxy validation
+    #[serde(default = "default_max_concurrent_ips")]
+    pub max_concurrent_ips: usize,
     /// Cache TTL in seconds
     pub cache_ttl_secs: u64,
     /// Maximum number of failed lists before considering an IP malicious
@@ -80,6 +89,44 @@ pub struct DnsblConfig {
     pub specific_lists: Vec<String>,
     /// DNSBL lists to exclude
     pub excluded_lists: Vec<String>,
+    /// Resolve DNSBL queries over DNS-over-HTTPS instead of plain DNS
+    pub dns_over_https: bool,
+    /// IPs or CIDR ranges always treated as clean, without any DNS query
+    pub whitelisted_ips: Vec<String>,
+    /// Number of answers kept by the resolver cache
+    #[serde(default = "default_resolver_cache_size")]
+    pub resolver_cache_size: usize,
+    /// Attempts per DNS query before giving up
+    #[serde(default = "default_resolver_attempts")]
+    pub resolver_attempts: usize,
+    /// Resolvers queried instead of the public ones, as `ip` or `ip:port`.
+    /// Entries that do not parse are skipped with a warning.
+    #[serde(default)]
+    pub dns_servers: Vec<String>,
+    /// Query every list even once `malicious_threshold` listings were found, for
+    /// complete results at the cost of speed
+    #[serde(default)]
+    pub full_scan: bool,
+    /// Percentage of failed list queries above which a clean result is inconclusive,
+    /// the check then fails soft: logged, without rejecting the proxy
+    #[serde(default = "default_max_error_rate")]
+    pub max_error_rate: f64,
+}
+
+pub fn default_max_concurrent_ips() -> usize {
+    20
+}
+
+pub fn default_resolver_cache_size() -> usize {
+    1024
+}
+
+pub fn default_resolver_attempts() -> usize {
+    2
+}
+
+pub fn default_max_error_rate() -> f64 {
+    50.0
 }
 
 impl Default for DnsblConfig {
@@ -88,10 +135,18 @@ impl Default for DnsblConfig {
             enabled: false,
             timeout_secs: 5,
             max_concurrent: 10,
+            max_concurrent_ips: default_max_concurrent_ips(),
             cache_ttl_secs: 3600, // 1 hour
             malicious_threshold: 2, // Listed in 2+ lists = malicious
             specific_lists: Vec::new(),
             excluded_lists: Vec::new(),
+            dns_over_https: false,
+            whitelisted_ips: Vec::new(),
+            resolver_cache_size: default_resolver_cache_size(),
+            resolver_attempts: default_resolver_attempts(),
+            dns_servers: Vec::new(),
+            full_scan: false,
+            max_error_rate: default_max_error_rate(),
         }
     }
 }
@@ -104,6 +159,7 @@ impl DnsblCheckResults {
             results: Vec::new(),
             listed_count: 0,
             total_checked: 0,
+            error_count: 0,
             total_time_ms: 0,
             is_malicious: false,
         }
@@ -114,6 +170,9 @@ impl DnsblCheckResults {
         if result.listed {
             self.listed_count += 1;
         }
+        if result.errored {
+            self.error_count += 1;
+        }
         self.total_checked += 1;
         self.total_time_ms += result.response_time_ms;
         self.results.push(result);
@@ -132,4 +191,19 @@ impl DnsblCheckResults {
             (self.listed_count as f64 / self.total_checked as f64) * 100.0
         }
     }
+
+    /// Get the error rate (percentage of lists that could not be queried)
+    pub fn error_rate(&self) -> f64 {
+        if self.total_checked == 0 {
+            0.0
+        } else {
+            (self.error_count as f64 / self.total_checked as f64) * 100.0
+        }
+    }
+
+    /// Whether too many queries failed for a clean result to be trusted, `max_error_rate`
+    /// being a percentage like `listing_rate`. A malicious verdict stays conclusive.
+    pub fn is_inconclusive(&self, max_error_rate: f64) -> bool {
+        !self.is_malicious && self.total_checked > 0 && self.error_rate() > max_error_rate
+    }
 }
@@ -19,6 +19,8 @@ pub struct Judge {
     pub ip_address: Option<String>,
     pub is_working: bool,
     pub marks: BTreeMap<String, usize>,
+    /// Lowercased direct response, headers the judge adds itself show up in it
+    pub baseline: String,
     pub timeout: u16,
     pub verify_ssl: bool,
 }
@@ -43,11 +45,23 @@ impl Judge {
             ip_address: None,
             is_working: false,
             marks,
+            baseline: String::new(),
             timeout: 5,
             verify_ssl: false,
         }
     }
 }
+impl From<&crate::judge_optimized::JudgeInfo> for Judge {
+    fn from(info: &crate::judge_optimized::JudgeInfo) -> Self {
+        let mut judge = Judge::new(&info.url);
+        judge.ip_address = info.ip_address.clone();
+        judge.is_working = info.is_working;
+        judge.marks = info.marks.clone();
+        judge.baseline = info.baseline.clone();
+        judge
+    }
+}
+
 // Struct representation
 impl std::fmt::Display for Judge {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -102,15 +116,15 @@ pub async fn check_judge_host(judge: &mut Judge, real_ext_ip: &str) -> bool {
                         if let Ok(body) = response.collect().await {
                             let body_bytes = body.to_bytes();
                             let body_str = String::from_utf8_lossy(&body_bytes);
-                            judge.is_working = body_str
-                                .to_lowercase()
-                                .contains(&real_ext_ip.to_lowercase());
+                            let body_lower = body_str.to_lowercase();
+                            judge.is_working = body_lower.contains(&real_ext_ip.to_lowercase());
                             judge
                                 .marks
-                                .insert("via".into(), body_str.matches("via").count());
+                                .insert("via".into(), body_lower.matches("via").count());
                             judge
                                 .marks
-                                .insert("proxy".into(), body_str.matches("proxy").count());
+                                .insert("proxy".into(), body_lower.matches("proxy").count());
+                            judge.baseline = body_lower;
                         }
                     }
                 }
@@ -3,9 +3,9 @@ use hyper_util::client::legacy::Client;
 use hyper_util::client::legacy::connect::HttpConnector;
 use hyper_tls::HttpsConnector;
 use http_body_util::{BodyExt, Empty};
-use rand::{Rng, thread_rng};
-use std::{collections::BTreeMap, sync::Arc, time::Duration};
-use tokio::{sync::RwLock, time::timeout};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{borrow::Cow, collections::BTreeMap, io::Read, sync::Arc, time::Duration};
+use tokio::{io::AsyncReadExt, net::TcpStream, sync::RwLock, time::timeout};
 use url::Url;
 
 use crate::{resolver::Resolver, utils::http::random_useragent};
@@ -33,14 +33,24 @@ const HIGH_PERFORMANCE_JUDGES: &[&str] = &[
     "https://www.proxyjudge.info/azenv.php",    // HTTPS judge
 ];
 
-// Judges SMTP pour CONNECT:25
+// Délai maximum pour recevoir la bannière SMTP
+const SMTP_PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+// Judges SMTP pour CONNECT:25 (pas de 465, TLS implicite sans bannière en clair)
 const SMTP_JUDGES: &[&str] = &[
     "smtp://smtp.gmail.com:587",
-    "smtp://smtp.gmail.com:465",
     "smtp://aspmx.l.google.com:25",
     "smtp://mail.protonmail.ch:587",
 ];
 
+// Poids d'un nouveau résultat dans le taux de succès (moyenne mobile exponentielle)
+const JUDGE_RATE_WEIGHT: f64 = 0.2;
+// Résultats nécessaires avant qu'un judge puisse sortir de la rotation
+const JUDGE_MIN_SAMPLES: u32 = 5;
+// Hystérésis : un judge sort sous ce taux et ne revient qu'au-dessus du suivant
+const JUDGE_DEMOTE_RATE: f64 = 0.3;
+const JUDGE_PROMOTE_RATE: f64 = 0.6;
+
 // Cache pour les résultats de judges
 pub type JudgeCache = Arc<RwLock<std::collections::HashMap<String, JudgeInfo>>>;
 
@@ -53,8 +63,13 @@ pub struct JudgeInfo {
     pub is_working: bool,
     pub response_time: Duration,
     pub success_rate: f64,
+    // Nombre de résultats pris en compte dans success_rate
+    pub samples: u32,
     pub last_checked: std::time::Instant,
     pub marks: BTreeMap<String, usize>,
+    // Réponse directe du judge en minuscules, les en-têtes qu'il ajoute lui-même
+    // (Via, X-Forwarded-For d'un CDN) n'y trahissent pas le proxy
+    pub baseline: String,
 }
 
 impl JudgeInfo {
@@ -76,8 +91,27 @@ impl JudgeInfo {
             is_working: false,
             response_time: Duration::from_millis(1000), // Default 1s
             success_rate: 0.0,
+            samples: 0,
             last_checked: std::time::Instant::now(),
             marks,
+            baseline: String::new(),
+        }
+    }
+
+    // Prend en compte le résultat d'un pré-test ou d'une utilisation. Le taux est la
+    // moyenne des premiers résultats puis une moyenne mobile, un échec isolé ne retire
+    // donc pas le judge de la rotation : il n'en sort qu'après JUDGE_MIN_SAMPLES
+    // résultats et sous JUDGE_DEMOTE_RATE.
+    pub fn record(&mut self, success: bool) {
+        self.samples += 1;
+        let weight = (1.0 / self.samples as f64).max(JUDGE_RATE_WEIGHT);
+        let value = if success { 1.0 } else { 0.0 };
+        self.success_rate = self.success_rate * (1.0 - weight) + value * weight;
+
+        if self.success_rate >= JUDGE_PROMOTE_RATE {
+            self.is_working = true;
+        } else if self.success_rate < JUDGE_DEMOTE_RATE && self.samples >= JUDGE_MIN_SAMPLES {
+            self.is_working = false;
         }
     }
 
@@ -95,15 +129,27 @@ impl JudgeInfo {
 }
 
 // Manager pour les judges optimisés
+#[derive(Clone)]
 pub struct OptimizedJudgeManager {
     cache: JudgeCache,
     http_judges: Vec<JudgeInfo>,
     smtp_judges: Vec<JudgeInfo>,
     client_pool: Vec<Client<HttpsConnector<HttpConnector>, Empty<bytes::Bytes>>>,
+    // Choix des clients, seedable pour des runs reproductibles
+    rng: StdRng,
 }
 
 impl OptimizedJudgeManager {
     pub fn new() -> Self {
+        Self::with_rng(StdRng::from_entropy())
+    }
+
+    /// Manager whose client selection is reproducible for a given seed
+    pub fn with_seed(seed: u64) -> Self {
+        Self::with_rng(StdRng::seed_from_u64(seed))
+    }
+
+    fn with_rng(rng: StdRng) -> Self {
         let http_judges: Vec<JudgeInfo> = HIGH_PERFORMANCE_JUDGES
             .iter()
             .map(|url| JudgeInfo::new(url))
@@ -139,9 +185,14 @@ impl OptimizedJudgeManager {
             http_judges,
             smtp_judges,
             client_pool,
+            rng,
         }
     }
 
+    fn pick_client_index(&mut self) -> usize {
+        self.rng.gen_range(0..self.client_pool.len())
+    }
+
     // Pré-test rapide de tous les judges
     pub async fn pretest_judges(&mut self, real_ext_ip: &str) {
         log::info!("🚀 Pré-test des judges haute performance...");
@@ -149,11 +200,11 @@ impl OptimizedJudgeManager {
         let mut tasks = Vec::new();
 
         // Test parallèle des judges HTTP
-        for judge in &mut self.http_judges {
-            let client_idx = thread_rng().gen_range(0..self.client_pool.len());
+        for i in 0..self.http_judges.len() {
+            let client_idx = self.pick_client_index();
             let client = self.client_pool[client_idx].clone();
             let ip = real_ext_ip.to_string();
-            let mut judge_clone = judge.clone();
+            let mut judge_clone = self.http_judges[i].clone();
 
             tasks.push(tokio::spawn(async move {
                 Self::test_judge_fast(&mut judge_clone, &client, &ip).await;
@@ -161,15 +212,12 @@ impl OptimizedJudgeManager {
             }));
         }
 
-        // Test rapide des judges SMTP
+        // Test parallèle des judges SMTP
         for judge in &mut self.smtp_judges {
             let mut judge_clone = judge.clone();
 
             tasks.push(tokio::spawn(async move {
-                // Les judges SMTP sont considérés comme working par défaut
-                judge_clone.is_working = true;
-                judge_clone.response_time = Duration::from_millis(100);
-                judge_clone.success_rate = 1.0;
+                Self::test_smtp_judge(&mut judge_clone, SMTP_PROBE_TIMEOUT).await;
                 judge_clone
             }));
         }
@@ -224,15 +272,21 @@ impl OptimizedJudgeManager {
         match task.await {
             Ok(Ok(response)) => {
                 if StatusCode::OK == response.status() {
+                    let encoding = response
+                        .headers()
+                        .get(hyper::header::CONTENT_ENCODING)
+                        .and_then(|value| value.to_str().ok())
+                        .map(str::to_string);
                     if let Ok(body) = response.collect().await {
                         let body_bytes = body.to_bytes();
+                        let body_bytes = decode_body(encoding.as_deref(), &body_bytes);
                         let body_str = String::from_utf8_lossy(&body_bytes);
 
                         let response_time = start_time.elapsed();
                         judge.response_time = response_time;
 
                         // Vérifie si le judge peut détecter l'IP réelle
-                        judge.is_working = if judge.url.contains("/ip") {
+                        let working = if judge.url.contains("/ip") {
                             // Pour les services IP simples, juste vérifier qu'on reçoit une réponse
                             body_str.trim().len() > 7 && body_str.contains('.')
                         } else {
@@ -240,30 +294,78 @@ impl OptimizedJudgeManager {
                             body_str.to_lowercase().contains(&real_ext_ip.to_lowercase())
                         };
 
-                        if judge.is_working {
-                            judge.marks.insert("via".into(), body_str.matches("via").count());
-                            judge.marks.insert("proxy".into(), body_str.matches("proxy").count());
-                            judge.success_rate = 1.0;
+                        if working {
+                            // Compté en minuscules, comme le corps analysé par le checker
+                            let body_lower = body_str.to_lowercase();
+                            judge.marks.insert("via".into(), body_lower.matches("via").count());
+                            judge.marks.insert("proxy".into(), body_lower.matches("proxy").count());
+                            judge.baseline = body_lower;
                         }
+                        judge.record(working);
 
                         log::debug!("Judge {} testé en {}ms - Working: {}",
                                    judge.host, response_time.as_millis(), judge.is_working);
                     }
+                } else {
+                    judge.record(false);
                 }
             }
             Ok(Err(err)) => {
                 log::debug!("Judge {} erreur: {}", judge.host, err);
-                judge.is_working = false;
+                judge.record(false);
             }
             Err(_) => {
                 log::debug!("Judge {} timeout", judge.host);
-                judge.is_working = false;
+                judge.record(false);
+            }
+        }
+
+        judge.is_working
+    }
+
+    // Test réel d'un judge SMTP : connexion TCP et lecture de la bannière 220
+    async fn test_smtp_judge(judge: &mut JudgeInfo, probe_timeout: Duration) -> bool {
+        let start_time = std::time::Instant::now();
+        let port = Url::parse(&judge.url).ok().and_then(|url| url.port()).unwrap_or(25);
+
+        let probe = async {
+            let mut stream = TcpStream::connect((judge.host.as_str(), port)).await?;
+            let mut banner = [0; 512];
+            let size = stream.read(&mut banner).await?;
+            Ok::<bool, std::io::Error>(banner[..size].starts_with(b"220"))
+        };
+
+        let working = match timeout(probe_timeout, probe).await {
+            Ok(Ok(has_banner)) => has_banner,
+            Ok(Err(err)) => {
+                log::debug!("Judge SMTP {} erreur: {}", judge.host, err);
+                false
+            }
+            Err(_) => {
+                log::debug!("Judge SMTP {} timeout", judge.host);
+                false
             }
+        };
+        judge.last_checked = std::time::Instant::now();
+
+        if working {
+            judge.response_time = start_time.elapsed();
         }
+        judge.record(working);
+
+        log::debug!("Judge SMTP {}:{} testé en {}ms - Working: {}",
+                   judge.host, port, start_time.elapsed().as_millis(), judge.is_working);
 
         judge.is_working
     }
 
+    // Résultat d'une utilisation du judge `url` par le checker
+    pub fn record_judge_use(&mut self, url: &str, success: bool) {
+        if let Some(judge) = self.http_judges.iter_mut().chain(self.smtp_judges.iter_mut()).find(|j| j.url == url) {
+            judge.record(success);
+        }
+    }
+
     // Obtenir le meilleur judge disponible pour un protocole
     pub async fn get_best_judge(&self, protocol: &str) -> Option<&JudgeInfo> {
         match protocol.to_uppercase().as_str() {
@@ -296,26 +398,30 @@ impl OptimizedJudgeManager {
         let http_working = self.http_judges.iter().filter(|j| j.is_working).count();
         let smtp_working = self.smtp_judges.iter().filter(|j| j.is_working).count();
 
-        let avg_response_time = if http_working > 0 {
-            let total: Duration = self.http_judges.iter()
-                .filter(|j| j.is_working)
-                .map(|j| j.response_time)
-                .sum();
-            total / http_working as u32
-        } else {
-            Duration::from_millis(0)
-        };
-
         JudgeStats {
             http_total: self.http_judges.len(),
             http_working,
             smtp_total: self.smtp_judges.len(),
             smtp_working,
-            avg_response_time_ms: avg_response_time.as_millis(),
+            avg_response_time_ms: avg_working_response_time(&self.http_judges).as_millis(),
+            smtp_avg_response_time_ms: avg_working_response_time(&self.smtp_judges).as_millis(),
         }
     }
 }
 
+// Temps de réponse moyen des judges working
+fn avg_working_response_time(judges: &[JudgeInfo]) -> Duration {
+    let working: Vec<Duration> = judges.iter()
+        .filter(|j| j.is_working)
+        .map(|j| j.response_time)
+        .collect();
+    if working.is_empty() {
+        Duration::from_millis(0)
+    } else {
+        working.iter().sum::<Duration>() / working.len() as u32
+    }
+}
+
 #[derive(Debug)]
 pub struct JudgeStats {
     pub http_total: usize,
@@ -323,13 +429,14 @@ pub struct JudgeStats {
     pub smtp_total: usize,
     pub smtp_working: usize,
     pub avg_response_time_ms: u128,
+    pub smtp_avg_response_time_ms: u128,
 }
 
 impl std::fmt::Display for JudgeStats {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Judges: HTTP {}/{} ({}ms avg) | SMTP {}/{}",
+        write!(f, "Judges: HTTP {}/{} ({}ms avg) | SMTP {}/{} ({}ms avg)",
                self.http_working, self.http_total, self.avg_response_time_ms,
-               self.smtp_working, self.smtp_total)
+               self.smtp_working, self.smtp_total, self.smtp_avg_response_time_ms)
     }
 }
 
@@ -346,4 +453,199 @@ impl<T> ChooseRandom<T> for [T] {
             Some(&self[rng.gen_range(0..self.len())])
         }
     }
-}
\ No newline at end of file
+}
+
+/// Body as sent before its `Content-Encoding` was applied. Encodings other than
+/// gzip and deflate, and bodies that fail to decode, are returned unchanged.
+fn decode_body<'a>(encoding: Option<&str>, body: &'a [u8]) -> Cow<'a, [u8]> {
+    let mut decoded = Vec::new();
+    let result = match encoding.map(|encoding| encoding.trim().to_ascii_lowercase()).as_deref() {
+        Some("gzip") | Some("x-gzip") => flate2::read::GzDecoder::new(body).read_to_end(&mut decoded),
+        // deflate is meant to be zlib wrapped, some servers send the raw stream
+        Some("deflate") => flate2::read::ZlibDecoder::new(body)
+            .read_to_end(&mut decoded)
+            .or_else(|_| {
+                decoded.clear();
+                flate2::read::DeflateDecoder::new(body).read_to_end(&mut decoded)
+            }),
+        _ => return Cow::Borrowed(body),
+    };
+    match result {
+        Ok(_) => Cow::Owned(decoded),
+        Err(err) => {
+            log::debug!("Failed to decode {:?} judge response: {}", encoding, err);
+            Cow::Borrowed(body)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+
+    async fn smtp_stub(banner: &'static [u8]) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let _ = stream.write_all(banner).await;
+            }
+        });
+        port
+    }
+
+    #[tokio::test]
+    async fn test_smtp_judge_probe() {
+        let working_port = smtp_stub(b"220 mail.example.com ESMTP ready\r\n").await;
+        let closing_port = smtp_stub(b"").await;
+
+        let mut working = JudgeInfo::new(&format!("smtp://127.0.0.1:{}", working_port));
+        let mut closing = JudgeInfo::new(&format!("smtp://127.0.0.1:{}", closing_port));
+        assert!(OptimizedJudgeManager::test_smtp_judge(&mut working, Duration::from_secs(2)).await);
+        assert!(!OptimizedJudgeManager::test_smtp_judge(&mut closing, Duration::from_secs(2)).await);
+
+        let mut manager = OptimizedJudgeManager::new();
+        manager.smtp_judges = vec![working, closing];
+        let stats = manager.get_stats();
+        assert_eq!(stats.smtp_total, 2);
+        assert_eq!(stats.smtp_working, 1);
+        assert!(manager.get_best_judge("CONNECT:25").await.unwrap().url.contains(&working_port.to_string()));
+    }
+
+    fn compress(encoding: &str, data: &[u8]) -> Vec<u8> {
+        use std::io::Write;
+        let level = flate2::Compression::default();
+        match encoding {
+            "gzip" => {
+                let mut encoder = flate2::write::GzEncoder::new(Vec::new(), level);
+                encoder.write_all(data).unwrap();
+                encoder.finish().unwrap()
+            }
+            _ => {
+                let mut encoder = flate2::write::DeflateEncoder::new(Vec::new(), level);
+                encoder.write_all(data).unwrap();
+                encoder.finish().unwrap()
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_body() {
+        let text = b"REMOTE_ADDR = 203.0.113.7";
+        assert_eq!(decode_body(Some("gzip"), &compress("gzip", text)).as_ref(), text);
+        assert_eq!(decode_body(Some("deflate"), &compress("deflate", text)).as_ref(), text);
+        assert_eq!(decode_body(None, text).as_ref(), text);
+        assert_eq!(decode_body(Some("br"), text).as_ref(), text);
+        // Mislabelled bodies are analysed as they are
+        assert_eq!(decode_body(Some("gzip"), text).as_ref(), text);
+    }
+
+    #[tokio::test]
+    async fn test_gzip_judge_is_working() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let body = compress("gzip", b"<html>REMOTE_ADDR = 203.0.113.7</html>");
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = [0; 1024];
+                let _ = stream.read(&mut request).await;
+                let head = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nContent-Length: {}\r\n\r\n",
+                    body.len()
+                );
+                let _ = stream.write_all(head.as_bytes()).await;
+                let _ = stream.write_all(&body).await;
+            }
+        });
+
+        let manager = OptimizedJudgeManager::new();
+        let mut judge = JudgeInfo::new(&format!("http://127.0.0.1:{}/azenv.php", port));
+        assert!(OptimizedJudgeManager::test_judge_fast(&mut judge, &manager.client_pool[0], "203.0.113.7").await);
+        assert!(judge.is_working);
+    }
+
+    #[tokio::test]
+    async fn test_pretest_brings_back_demoted_judge() {
+        let port = smtp_stub(b"HTTP/1.1 200 OK\r\nContent-Length: 25\r\n\r\nREMOTE_ADDR = 203.0.113.7").await;
+        let mut judge = JudgeInfo::new(&format!("http://127.0.0.1:{}/azenv.php", port));
+        while judge.samples < JUDGE_MIN_SAMPLES {
+            judge.record(false);
+        }
+        assert!(!judge.is_working);
+
+        // The judge answers again, a few pretests put it back in rotation
+        let mut manager = OptimizedJudgeManager::new();
+        manager.http_judges = vec![judge];
+        manager.smtp_judges = vec![];
+        for _ in 0..10 {
+            manager.pretest_judges("203.0.113.7").await;
+            if manager.http_judges[0].is_working {
+                break;
+            }
+        }
+        assert!(manager.http_judges[0].is_working);
+        assert_eq!(manager.get_stats().http_working, 1);
+    }
+
+    #[test]
+    fn test_success_rate_evolves_smoothly() {
+        let mut judge = JudgeInfo::new("http://127.0.0.1/azenv.php");
+        for _ in 0..JUDGE_MIN_SAMPLES {
+            judge.record(true);
+        }
+        assert!(judge.is_working);
+
+        // Une panne isolée puis des retours intermittents
+        let sequence = [false, true, true, false, true, false, false, true, true, true];
+        let mut previous = judge.success_rate;
+        for success in sequence {
+            judge.record(success);
+            assert!((judge.success_rate - previous).abs() <= JUDGE_RATE_WEIGHT + f64::EPSILON);
+            assert!(judge.is_working, "a few failures took the judge out of rotation");
+            previous = judge.success_rate;
+        }
+
+        // Une panne durable finit par le retirer, et il faut plusieurs succès pour le réintégrer
+        while judge.is_working {
+            judge.record(false);
+        }
+        assert!(judge.success_rate < JUDGE_DEMOTE_RATE);
+        judge.record(true);
+        assert!(!judge.is_working);
+        while !judge.is_working {
+            judge.record(true);
+        }
+        assert!(judge.success_rate >= JUDGE_PROMOTE_RATE);
+    }
+
+    #[test]
+    fn test_min_samples_guard_demotion() {
+        let mut judge = JudgeInfo::new("http://127.0.0.1/azenv.php");
+        judge.record(true);
+        for _ in 2..JUDGE_MIN_SAMPLES {
+            judge.record(false);
+        }
+        // Sous le seuil mais avec trop peu de résultats
+        assert!(judge.success_rate < JUDGE_DEMOTE_RATE);
+        assert!(judge.is_working);
+        judge.record(false);
+        assert!(!judge.is_working);
+
+        // Un premier échec ne met pas en rotation un judge jamais testé
+        let mut fresh = JudgeInfo::new("http://127.0.0.2/azenv.php");
+        fresh.record(false);
+        assert!(!fresh.is_working);
+    }
+
+    #[test]
+    fn test_same_seed_picks_same_clients() {
+        let mut first = OptimizedJudgeManager::with_seed(42);
+        let mut second = OptimizedJudgeManager::with_seed(42);
+        let picks: Vec<usize> = (0..20).map(|_| first.pick_client_index()).collect();
+        let replay: Vec<usize> = (0..20).map(|_| second.pick_client_index()).collect();
+        assert_eq!(picks, replay);
+        assert!(picks.iter().any(|&idx| idx != picks[0]));
+    }
+}
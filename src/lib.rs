@@ -37,7 +37,10 @@ pub mod judge;
 pub mod judge_optimized;
 pub mod resolver;
 pub mod negotiators;
+pub mod output;
 pub mod providers;
+#[cfg(feature = "sqlite")]
+pub mod store;
 pub mod utils;
 pub mod performance;
 
@@ -54,6 +57,15 @@ pub mod server {
     pub mod multi_cache;
     pub mod async_optimizer;
     pub mod mod_simple;
+    pub mod sticky;
+    pub mod chain;
+    pub mod tunnel;
+    pub mod socks5;
+    pub mod target_filter;
+    pub mod header_rules;
+    pub mod latency;
+    pub mod audit;
+    pub mod client_limit;
 }
 
 // Re-export commonly used types
@@ -1,40 +1,57 @@
 
-use argument::GrabArgs;
+use argument::{GrabArgs, ServeArgs};
 use checker::Checker;
 use clap::Parser;
 use dnsbl::DnsblConfig;
 use lazy_static::lazy_static;
 use parking_lot::Mutex;
-use proxy::Proxy;
+use output::{close_sinks, open_sinks, write_to_sinks, Progress, SortKey, Template};
+use proxy::{split_tags, Proxy};
 use regex::Regex;
-use server::{proxy_pool::{LIVE_PROXIES, ProxyPool}, Server, POOL};
+use server::{proxy_pool::{self, LIVE_PROXIES, ProxyPool}, audit::AUDIT_LOG, header_rules::HeaderRules, target_filter::TargetFilter, Server, CONNECTION_POOL, POOL, VALIDATION_CACHE};
 use simple_logger::SimpleLogger;
-use std::{path::PathBuf, pin::Pin, sync::Arc, time::Duration};
+#[cfg(feature = "sqlite")]
+use store::{ProxyStore, StoredProxy};
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 use tokio::{
     fs::File,
-    io::{stdout, AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    io::{stdin, AsyncBufRead, AsyncBufReadExt, BufReader},
     runtime,
-    sync::{
-        mpsc::{self, Sender},
-    },
+    sync::mpsc,
     task, time,
 };
 
 // New imports for improved error handling and resource management
 use utils::{
+    cidr::Cidr,
     error::{ProxyError, ProxyResult},
+    logger::JsonLogger,
+    memory_guard::MemoryGuard,
+    http::user_agents::UserAgents,
+    output_channel::{output_channel, OutputReceiver, OutputSender},
+    serializer::SessionSnapshot,
     resource_manager::{init_resource_managers, create_resource_semaphore},
-    shutdown::{init_shutdown_manager, setup_signal_handlers, register_for_shutdown},
+    vec_of_strings,
+    shutdown::{init_shutdown_manager, setup_signal_handlers, register_for_shutdown, shutdown_after, ShutdownReason},
 };
 
 mod api;
-use config::{DynamicConfig, SharedConfig};
+use config::{dynamic::{ApiSettings, ServerConfig}, DynamicConfig, SharedConfig};
 use config::hot_reload::start_config_watcher;
-use api::{ApiConfig, server::{start_default_api_server, start_api_server_with_config}};
+use api::{ApiConfig, ApiState, server::start_api_server_with_state};
 
 use crate::{
-    argument::{Cli, Commands},
-    providers::PROXIES,
+    argument::{Cli, Commands, ConfigAction, ConfigArgs},
+    performance::{PerformanceSummary, PERFORMANCE_MONITOR},
+    providers::{queue::ProxyQueue, PROXIES},
     utils::update::check_version,
 };
 
@@ -45,13 +62,27 @@ mod dnsbl;
 mod judge;
 mod judge_optimized;
 mod negotiators;
+mod output;
 mod performance;
 mod providers;
 mod proxy;
 mod resolver;
 mod server;
+#[cfg(feature = "sqlite")]
+mod store;
 mod utils;
 
+/// Configuration file read at startup and watched for changes
+const CONFIG_PATH: &str = "proxy-rs.toml";
+/// How often grab/find progress is saved to the session file
+const SESSION_SNAPSHOT_INTERVAL: Duration = Duration::from_secs(30);
+/// Time between two `--progress` status lines
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(2);
+/// How often memory is sampled against `general.memory_ceiling_mb`
+const MEMORY_SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+/// Share of their capacity the caches keep while memory is under pressure
+const CACHE_KEEP_UNDER_PRESSURE: f64 = 0.5;
+
 lazy_static! {
     static ref STOP_FIND_LOOP: Arc<Mutex<bool>> = Arc::new(Mutex::new(false));
 }
@@ -68,11 +99,14 @@ impl Iterator for ProxiesIter {
     }
 }
 
-async fn handle_grab_command(args: GrabArgs, tx: Sender<Option<Proxy>>) -> ProxyResult<()> {
+async fn handle_grab_command(args: GrabArgs, tx: OutputSender<Option<Proxy>>) -> ProxyResult<()> {
     // Register this task for graceful shutdown
     let mut shutdown_rx = register_for_shutdown("grab_command".to_string()).await;
 
     let expected_countries = args.countries;
+    let excluded_countries = args.exclude_countries;
+    let expected_regions = args.regions;
+    let expected_cities = args.cities;
 
     loop {
         // Check for shutdown signal
@@ -89,7 +123,9 @@ async fn handle_grab_command(args: GrabArgs, tx: Sender<Option<Proxy>>) -> Proxy
                 break;
             }
 
-            if !expected_countries.is_empty() && !expected_countries.contains(&proxy.geo.iso_code) {
+            if !proxy.geo.matches(&expected_countries, &expected_regions, &expected_cities)
+                || proxy.geo.is_excluded(&excluded_countries)
+            {
                 continue;
             }
 
@@ -106,12 +142,73 @@ async fn handle_grab_command(args: GrabArgs, tx: Sender<Option<Proxy>>) -> Proxy
     Ok(())
 }
 
-async fn handle_find_command(checker: Checker, _max_conn: usize, tx: Sender<Option<Proxy>>) -> ProxyResult<()> {
+/// Periodically recheck a share of the live and pooled proxies so dead ones do not get
+/// served, a batch at once under the checker's connection limit. Pinned proxies are
+/// rechecked for their stats but kept whatever the outcome.
+async fn recheck_live_proxies(checker: Checker, interval: Duration) {
+    let sem = match utils::resource_manager::get_resource_semaphore("connections").await {
+        Ok(sem) => sem,
+        Err(e) => {
+            log::warn!("Proxy recheck disabled: {}", e);
+            return;
+        }
+    };
+    let mut ticker = time::interval(interval);
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        let check = |mut proxy: Proxy| {
+            let mut checker = checker.clone();
+            let sem = sem.clone();
+            async move {
+                let _permit = sem.acquire_owned().await;
+                let working = checker.check_proxy(&mut proxy).await;
+                (proxy, working)
+            }
+        };
+        let dropped = proxy_pool::recheck_live(&LIVE_PROXIES, &POOL, proxy_pool::RECHECK_FRACTION, check).await
+            + proxy_pool::recheck_pool(&POOL, proxy_pool::RECHECK_FRACTION, check).await;
+        if dropped > 0 {
+            log::info!("Recheck dropped {} dead proxies", dropped);
+        }
+    }
+}
+
+/// `--block-signature` as a regex, or as a literal substring when it is not a valid one
+fn block_signature(pattern: &str) -> Regex {
+    Regex::new(pattern).unwrap_or_else(|e| {
+        log::warn!("--block-signature is not a valid regex ({}), matching it as plain text", e);
+        Regex::new(&regex::escape(pattern)).expect("escaped pattern is a valid regex")
+    })
+}
+
+/// Queue the pinned proxies for the server without checking them, as supporting `types`
+async fn push_pinned_proxies(pinned: &[SocketAddr], types: &[String]) {
+    for addr in pinned {
+        let Some(mut proxy) = Proxy::create(&addr.ip().to_string(), addr.port(), types.to_vec()).await else {
+            log::warn!("Failed to create pinned proxy {}", addr);
+            continue;
+        };
+        proxy.types = types.iter().map(|proxy_type| (proxy_type.clone(), None)).collect();
+        match LIVE_PROXIES.push(proxy) {
+            Ok(()) => log::info!("Pinned proxy {}", addr),
+            Err(e) => log::warn!("Failed to queue pinned proxy {}: {}", addr, e),
+        }
+    }
+}
+
+async fn handle_find_command(
+    mut checker: Checker,
+    _max_conn: usize,
+    max_response_time: Option<Duration>,
+    tx: OutputSender<Option<Proxy>>,
+) -> ProxyResult<()> {
     // Register this task for graceful shutdown
     let mut shutdown_rx = register_for_shutdown("find_command".to_string()).await;
 
     // Use resource semaphore instead of creating new one
     let sem = utils::resource_manager::get_resource_semaphore("connections").await?;
+    let mut dnsbl_reload = config::hot_reload::DNSBL_RELOAD.subscribe();
 
     while !*STOP_FIND_LOOP.lock() {
         // Check for shutdown signal
@@ -128,6 +225,22 @@ async fn handle_find_command(checker: Checker, _max_conn: usize, tx: Sender<Opti
                 break;
             }
 
+            // Output has all it needs, stop checking
+            if tx.is_closed() {
+                log::debug!("Output closed, find workers stop");
+                return Ok(());
+            }
+
+            if dnsbl_reload.has_changed().unwrap_or(false) {
+                let update = dnsbl_reload.borrow_and_update().clone();
+                if let Some(update) = update {
+                    match checker.reload_dnsbl(&update).await {
+                        Ok(()) => log::info!("DNSBL configuration reloaded"),
+                        Err(e) => log::warn!("Failed to reload DNSBL configuration: {}", e),
+                    }
+                }
+            }
+
             if *STOP_FIND_LOOP.lock() {
                 if let Err(e) = tx.send(None).await {
                     log::error!("Failed to send stop signal: {}", e);
@@ -143,7 +256,20 @@ async fn handle_find_command(checker: Checker, _max_conn: usize, tx: Sender<Opti
                     let mut checker_clone = checker.clone();
                     task::spawn(async move {
                         let _permit = permit;
+                        if tx.is_closed() {
+                            return;
+                        }
                         if checker_clone.check_proxy(&mut proxy).await {
+                            providers::record_working(&proxy);
+                            if let Some(max) = max_response_time {
+                                if !proxy.is_within_resp_time(max) {
+                                    log::debug!(
+                                        "{}:{} dropped, avg response time {:.2}s exceeds {:?}",
+                                        proxy.host, proxy.port, proxy.avg_resp_time(), max
+                                    );
+                                    return;
+                                }
+                            }
                             if let Err(e) = tx.send(Some(proxy)).await {
                                 log::error!("Failed to send proxy result: {}", e);
                             }
@@ -165,13 +291,10 @@ async fn handle_find_command(checker: Checker, _max_conn: usize, tx: Sender<Opti
     Ok(())
 }
 
-async fn handle_file_input(files: Vec<PathBuf>) -> ProxyResult<()> {
+async fn handle_file_input(files: Vec<PathBuf>, max_cidr_hosts: usize, default_tags: Vec<String>) -> ProxyResult<()> {
     // Register this task for graceful shutdown
     let mut shutdown_rx = register_for_shutdown("file_input".to_string()).await;
 
-    let ip_port = Regex::new(r#"(?P<ip>(?:\d+\.?){4}):(?P<port>\d+)"#)
-        .map_err(|e| ProxyError::Config(format!("Failed to compile regex: {}", e)))?;
-
     for file in files {
         // Check for shutdown signal
         if shutdown_rx.try_recv().is_ok() {
@@ -179,48 +302,103 @@ async fn handle_file_input(files: Vec<PathBuf>) -> ProxyResult<()> {
             break;
         }
 
+        // "-" reads the proxies piped on stdin
+        if file.as_os_str() == "-" {
+            read_proxy_lines(BufReader::new(stdin()), &PROXIES, max_cidr_hosts, &default_tags, &mut shutdown_rx).await?;
+            continue;
+        }
+
         match File::open(&file).await {
             Ok(file_handle) => {
-                let buffer = BufReader::new(file_handle);
-                let mut lines = buffer.lines();
+                read_proxy_lines(
+                    BufReader::new(file_handle),
+                    &PROXIES,
+                    max_cidr_hosts,
+                    &default_tags,
+                    &mut shutdown_rx,
+                )
+                .await?;
+            }
+            Err(e) => {
+                log::error!("Failed to open file {:?}: {}", file, e);
+                // Continue with other files instead of failing
+            }
+        }
+    }
 
-                while let Ok(Some(line)) = lines.next_line().await {
-                    // Check for shutdown signal periodically
-                    if shutdown_rx.try_recv().is_ok() {
-                        log::info!("File input received shutdown signal during processing");
-                        break;
-                    }
+    Ok(())
+}
+
+/// Queue the proxies found in `reader` until EOF, one `ip:port`, `[ipv6]:port` or
+/// `cidr:port` per line, optionally followed by `#tag1,tag2`. Lines without tags get
+/// `default_tags`.
+async fn read_proxy_lines<R: AsyncBufRead + Unpin>(
+    reader: R,
+    queue: &ProxyQueue,
+    max_cidr_hosts: usize,
+    default_tags: &[String],
+    shutdown_rx: &mut mpsc::Receiver<ShutdownReason>,
+) -> ProxyResult<()> {
+    let ip_port = Regex::new(r#"(?P<ip>(?:\d+\.?){4}):(?P<port>\d+)"#)
+        .map_err(|e| ProxyError::Config(format!("Failed to compile regex: {}", e)))?;
+    let cidr_port = Regex::new(r#"(?P<cidr>(?:\d+\.?){4}/\d{1,2}):(?P<port>\d+)"#)
+        .map_err(|e| ProxyError::Config(format!("Failed to compile regex: {}", e)))?;
+    let ipv6_port = Regex::new(r#"\[(?P<ip>[0-9A-Fa-f:.]+)\]:(?P<port>\d+)"#)
+        .map_err(|e| ProxyError::Config(format!("Failed to compile regex: {}", e)))?;
+
+    let mut lines = reader.lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        // Check for shutdown signal periodically
+        if shutdown_rx.try_recv().is_ok() {
+            log::info!("File input received shutdown signal during processing");
+            break;
+        }
 
-                    if let Some(cap) = ip_port.captures(&line) {
-                        if let (Some(ip_match), Some(port_match)) = (cap.get(1), cap.get(2)) {
-                            let ip = ip_match.as_str();
-                            let port = port_match.as_str();
-
-                            match port.parse::<u16>() {
-                                Ok(port_num) => {
-                                    if let Some(proxy) = Proxy::create(ip, port_num, vec![]).await {
-                                        match PROXIES.push(proxy) {
-                                            Ok(_) => {
-                                                log::debug!("Successfully added proxy from file");
-                                            }
-                                            Err(e) => {
-                                                log::error!("Failed to push proxy to queue: {}", e);
-                                            }
-                                        }
-                                    }
+        let (line, tags) = split_tags(&line);
+        let tags = if tags.is_empty() { default_tags.to_vec() } else { tags };
+        if let Some(cap) = cidr_port.captures(line) {
+            let hosts = cap["cidr"]
+                .parse::<Cidr>()
+                .and_then(|cidr| cidr.hosts(max_cidr_hosts));
+            match (hosts, cap["port"].parse::<u16>()) {
+                (Ok(hosts), Ok(port_num)) => {
+                    log::debug!("Expanding {} into {} proxies", &cap["cidr"], hosts.len());
+                    for host in hosts {
+                        if let Some(mut proxy) = Proxy::create(&host.to_string(), port_num, vec![]).await {
+                            proxy.tags = tags.clone();
+                            if let Err(e) = queue.push(proxy) {
+                                log::error!("{}", e);
+                            }
+                        }
+                    }
+                }
+                (Err(e), _) => log::warn!("Skipping CIDR line '{}': {}", line.trim(), e),
+                (_, Err(e)) => log::warn!("Invalid port number in file: {}", e),
+            }
+        } else if let Some(cap) = ipv6_port.captures(line).or_else(|| ip_port.captures(line)) {
+            if let (Some(ip_match), Some(port_match)) = (cap.get(1), cap.get(2)) {
+                let ip = ip_match.as_str();
+                let port = port_match.as_str();
+
+                match port.parse::<u16>() {
+                    Ok(port_num) => {
+                        if let Some(mut proxy) = Proxy::create(ip, port_num, vec![]).await {
+                            proxy.tags = tags;
+                            match queue.push(proxy) {
+                                Ok(_) => {
+                                    log::debug!("Successfully added proxy from file");
                                 }
                                 Err(e) => {
-                                    log::warn!("Invalid port number in file: {}", e);
+                                    log::error!("{}", e);
                                 }
                             }
                         }
                     }
+                    Err(e) => {
+                        log::warn!("Invalid port number in file: {}", e);
+                    }
                 }
             }
-            Err(e) => {
-                log::error!("Failed to open file {:?}: {}", file, e);
-                // Continue with other files instead of failing
-            }
         }
     }
 
@@ -234,36 +412,71 @@ fn main() -> ProxyResult<()> {
     init_resource_managers();
     init_shutdown_manager();
 
-    // Initialize dynamic configuration
-    let shared_config = Arc::new(parking_lot::RwLock::new(DynamicConfig::new()));
-
-    // Load initial configuration from file if it exists
-    let config_path = "proxy-rs.toml";
-    if std::path::Path::new(config_path).exists() {
-        match load_initial_config(config_path) {
-            Ok(config) => {
-                *shared_config.write() = config;
-                log::info!("Loaded initial configuration from {}", config_path);
-            }
-            Err(e) => {
-                log::warn!("Failed to load initial config: {}, using defaults", e);
-            }
-        }
-    }
-
+    let quiet = match &cli.sub {
+        Commands::Grab(args) => args.quiet,
+        Commands::Find(args) => args.quiet,
+        _ => false,
+    };
     let log_level = match cli.log_level.as_str() {
+        _ if quiet => log::LevelFilter::Off,
         "debug" => log::LevelFilter::Debug,
         "info" => log::LevelFilter::Info,
         "error" => log::LevelFilter::Error,
         _ => log::LevelFilter::Warn,
     };
 
-    SimpleLogger::new()
-        .with_level(log::LevelFilter::Off)
-        .with_module_level("proxy_rs", log_level)
-        .without_timestamps()
-        .init()
-        .map_err(|e| ProxyError::Config(format!("Failed to initialize logger: {}", e)))?;
+    if cli.log_format == "json" {
+        JsonLogger::new(log_level)
+            .init()
+            .map_err(|e| ProxyError::Config(format!("Failed to initialize logger: {}", e)))?;
+    } else {
+        SimpleLogger::new()
+            .with_level(log::LevelFilter::Off)
+            .with_module_level("proxy_rs", log_level)
+            .without_timestamps()
+            .init()
+            .map_err(|e| ProxyError::Config(format!("Failed to initialize logger: {}", e)))?;
+    }
+
+    if let Commands::Config(ConfigArgs { action: ConfigAction::Validate { path } }) = &cli.sub {
+        validate_config_file(path);
+        return Ok(());
+    }
+
+    // Load initial configuration from file if it exists, once the logger can report problems
+    let shared_config = Arc::new(parking_lot::RwLock::new(load_initial_config(
+        Path::new(CONFIG_PATH),
+        cli.strict_config,
+    )?));
+
+    // Environment variables take precedence over the TOML file. Applied once the
+    // logger is up so invalid values get reported.
+    let overrides = config::env::apply_env_overrides(&mut shared_config.write());
+    if overrides > 0 {
+        log::info!("Applied {} configuration overrides from environment", overrides);
+    }
+
+    let user_agents = match &cli.user_agents {
+        Some(path) => {
+            let rotation = cli.user_agent_rotation.parse().unwrap_or_default();
+            let mut agents = UserAgents::from_file(path, rotation)?;
+            if let Some(seed) = cli.rng_seed {
+                agents = agents.with_seed(seed);
+            }
+            if agents.is_empty() {
+                log::warn!("No user agents in {}, using the built-in one", path.display());
+                None
+            } else {
+                Some(Arc::new(agents))
+            }
+        }
+        None => None,
+    };
+
+    let metrics_file = cli.metrics_file.clone().unwrap_or_else(performance::default_metrics_file);
+    if let Commands::Stats(stats_args) = &cli.sub {
+        return print_stats(&metrics_file, stats_args.json);
+    }
 
     let runtime = runtime::Builder::new_multi_thread()
         .worker_threads(4)
@@ -277,79 +490,156 @@ fn main() -> ProxyResult<()> {
 
         // Register main task for graceful shutdown
         let mut shutdown_rx = register_for_shutdown("main".to_string()).await;
+        if let Some(max_runtime) = cli.max_runtime {
+            shutdown_after(Duration::from_secs(max_runtime));
+        }
 
         let max_conn = cli.max_conn;
         let timeout = cli.timeout as i32;
+        let refresh_interval = cli.refresh_interval;
+        let refresh_jitter = cli.refresh_jitter;
 
         // Create resource semaphore for connection limiting
         create_resource_semaphore("connections".to_string(), max_conn).await?;
 
+        // Publish the performance summary for `proxy-rs stats`
+        if cli.metrics_interval > 0 {
+            let metrics_interval = Duration::from_secs(cli.metrics_interval);
+            task::spawn(async move {
+                let mut interval = time::interval(metrics_interval);
+                loop {
+                    interval.tick().await;
+                    let summary = PERFORMANCE_MONITOR.get_performance_summary().await;
+                    if let Err(e) = summary.save(&metrics_file).await {
+                        log::warn!("{}", e);
+                    }
+                }
+            });
+        }
+
+        // Pause providers and trim caches when memory nears the configured ceiling
+        if let Some(guard) = MemoryGuard::new(shared_config.read().general.memory_ceiling_mb) {
+            task::spawn(guard.watch(MEMORY_SAMPLE_INTERVAL, || server::trim_caches(CACHE_KEEP_UNDER_PRESSURE)));
+        }
+
               // Start config watcher for hot-reload functionality
         let _config_watcher_handle = tokio::spawn(start_config_watcher_with_retry(
-            "proxy-rs.toml",
+            CONFIG_PATH,
             shared_config.clone(),
         ));
 
-        // Start REST API server
-        let api_config = ApiConfig {
-            host: "127.0.0.1".to_string(),
-            port: 3000,
-            enable_auth: false, // Can be enabled in production
-            jwt_secret: "proxy-rs-api-secret".to_string(),
-            rate_limit: 1000,
-            cors_origins: vec!["*".to_string()],
-            request_timeout_ms: 30000,
-        };
+        #[cfg(feature = "sqlite")]
+        let store = store_for(&cli)?;
 
-        let api_shared_config = shared_config.clone();
-        let _api_server_handle = tokio::spawn(async move {
-            if let Err(e) = start_api_server_with_config(api_config, api_shared_config).await {
-                log::error!("API server failed to start: {}", e);
-            }
-        });
+        // Start REST API server
+        let api_config = api_config_for(&cli, &shared_config.read().api);
+        if let Some(api_config) = api_config {
+            let address = format!("http://{}:{}", api_config.host, api_config.port);
+            let api_state = ApiState::new(shared_config.clone())
+                .with_connection_pool(Arc::clone(&CONNECTION_POOL));
+            #[cfg(feature = "sqlite")]
+            let api_state = match &store {
+                Some(store) => api_state.with_store(Arc::clone(store)),
+                None => api_state,
+            };
+            let _api_server_handle = tokio::spawn(async move {
+                if let Err(e) = start_api_server_with_state(api_config, api_state).await {
+                    log::error!("API server failed to start: {}", e);
+                }
+            });
 
-        log::info!("🚀 REST API Server started on http://127.0.0.1:3000");
-        log::info!("📚 API Documentation: http://127.0.0.1:3000/docs");
-        log::info!("🔗 API Health: http://127.0.0.1:3000/api/v1/health");
+            log::info!("🚀 REST API Server started on {}", address);
+            log::info!("📚 API Documentation: {}/docs", address);
+            log::info!("🔗 API Health: {}/api/v1/health", address);
+        } else {
+            log::info!("REST API disabled");
+        }
 
         let mut files = vec![];
-        let (tx, mut rx) = mpsc::channel(50);
+        let mut max_cidr_hosts = 0;
+        let mut default_tags = vec![];
+        let overflow = cli.output_overflow.parse().unwrap_or_default();
+        let (tx, mut rx) = output_channel(cli.output_capacity as usize, overflow);
         let mut tasks = vec![];
 
-        let mut outfile = None;
+        let mut outfiles = vec![];
+        let mut webhooks = vec![];
         let mut limit = 0;
         let mut format = "default".to_string();
+        let mut template = None;
+        let mut session_file = None;
+        let mut resume = false;
+        let mut progress = false;
+        let mut sort = None;
 
         let mut is_server = false;
-        let mut host = "127.0.0.1".to_string();
-        let mut port = 8080;
+        let mut server = None;
 
         if !cli.skip_version_check {
             task::spawn(check_version());
         }
 
+        if let Some(seed) = cli.rng_seed {
+            log::info!("Judge selection seeded with {}", seed);
+            checker::set_rng_seed(seed).await;
+        }
+
         match cli.sub {
             Commands::Grab(grab_args) => {
-                outfile = grab_args.outfile.clone();
+                outfiles = grab_args.outfile.clone();
+                webhooks = grab_args.webhook.clone();
                 limit = grab_args.limit;
                 format = grab_args.format.clone();
+                template = grab_args.template.as_deref().map(str::parse::<Template>).transpose()?;
+                session_file = grab_args.session_file.clone();
+                resume = grab_args.resume;
+                progress = grab_args.progress;
+                sort = grab_args.sort.as_deref().map(str::parse::<SortKey>).transpose()?;
 
                 let tx = tx.clone();
                 task::spawn(handle_grab_command(grab_args, tx));
             }
             Commands::Find(find_args) => {
-                outfile = find_args.outfile.clone();
+                outfiles = find_args.outfile.clone();
+                webhooks = find_args.webhook.clone();
                 limit = find_args.limit;
                 format = find_args.format.clone();
+                template = find_args.template.as_deref().map(str::parse::<Template>).transpose()?;
+                session_file = find_args.session_file.clone();
+                resume = find_args.resume;
+                progress = find_args.progress;
+                sort = find_args.sort.as_deref().map(str::parse::<SortKey>).transpose()?;
 
                 let mut checker = Checker::new().await;
+                checker.validation_cache = Some(Arc::clone(&VALIDATION_CACHE));
                 checker.max_tries = find_args.max_tries as i32;
+                checker.max_judge_retries = find_args.judge_retries;
+                checker.precheck_timeout = (find_args.precheck_timeout > 0).then(|| Duration::from_millis(find_args.precheck_timeout));
+                checker.check_integrity = find_args.check_integrity;
+                checker.validate_url = find_args.validate_url.clone();
+                checker.block_signature = find_args.block_signature.as_deref().map(block_signature);
+                if let Some(endpoint) = &find_args.fallback_endpoint {
+                    checker.fallback_endpoint = endpoint.clone();
+                }
+                checker.leak_headers = shared_config.read().general.leak_headers.clone();
+                checker.user_agents = user_agents.clone();
                 checker.timeout = timeout;
                 checker.support_cookie = find_args.support_cookies;
                 checker.support_referer = find_args.support_referer;
-                checker.expected_types = find_args.types.clone();
+                if find_args.discover {
+                    checker.discover_timeout = Some(Duration::from_millis(find_args.discover_timeout));
+                }
+                checker.expected_types = if find_args.types.is_empty() {
+                    vec_of_strings!["HTTP", "HTTPS", "SOCKS4", "SOCKS5", "CONNECT:80", "CONNECT:25"]
+                } else {
+                    find_args.types.clone()
+                };
+                checker.type_match_mode = find_args.type_match.parse().unwrap_or_default();
                 checker.expected_levels = find_args.levels;
                 checker.expected_countries = find_args.countries;
+                checker.excluded_countries = find_args.exclude_countries;
+                checker.expected_regions = find_args.regions;
+                checker.expected_cities = find_args.cities;
 
                 // Initialize DNSBL if enabled
                 if find_args.dnsbl_check {
@@ -357,10 +647,22 @@ fn main() -> ProxyResult<()> {
                         enabled: true,
                         timeout_secs: find_args.dnsbl_timeout_secs,
                         max_concurrent: find_args.dnsbl_max_concurrent,
+                        max_concurrent_ips: find_args.dnsbl_max_concurrent_ips,
                         cache_ttl_secs: find_args.dnsbl_cache_ttl_secs,
                         malicious_threshold: find_args.dnsbl_malicious_threshold,
                         specific_lists: find_args.dnsbl_specific_lists,
                         excluded_lists: find_args.dnsbl_excluded_lists,
+                        dns_over_https: find_args.dnsbl_doh,
+                        whitelisted_ips: find_args.dnsbl_whitelist,
+                        resolver_cache_size: find_args.dnsbl_resolver_cache_size,
+                        resolver_attempts: find_args.dnsbl_resolver_attempts,
+                        dns_servers: if find_args.dnsbl_dns_servers.is_empty() {
+                            shared_config.read().dnsbl.dns_servers.clone()
+                        } else {
+                            find_args.dnsbl_dns_servers
+                        },
+                        full_scan: find_args.dnsbl_full_scan,
+                        max_error_rate: shared_config.read().dnsbl.max_error_rate,
                     };
 
                     if let Err(e) = checker.enable_dnsbl(dnsbl_config).await {
@@ -370,31 +672,52 @@ fn main() -> ProxyResult<()> {
 
                 let ext_ip = checker.ext_ip.clone();
 
-                let expected_types = find_args.types.clone();
+                let expected_types = checker.expected_types.clone();
                 let verify_ssl = false;
+                let degraded = Arc::clone(&checker.degraded);
                 task::spawn(async move {
-                    checker::check_judges(verify_ssl, ext_ip, expected_types).await;
+                    checker::check_judges(verify_ssl, ext_ip.clone(), expected_types.clone(), Arc::clone(&degraded)).await;
+                    checker::refresh_judges(ext_ip, expected_types, degraded, checker::JUDGE_REFRESH_INTERVAL).await;
                 });
 
                 files.extend(find_args.files.clone());
+                max_cidr_hosts = find_args.max_cidr_hosts;
+                default_tags = find_args.tags.clone();
 
+                let max_response_time = find_args.max_response_time.map(Duration::from_millis);
                 let tx = tx.clone();
-                task::spawn(handle_find_command(checker, max_conn, tx));
+                task::spawn(handle_find_command(checker, max_conn, max_response_time, tx));
             }
             Commands::Serve(serve_args) => {
-                is_server = true;
-
-                host = serve_args.host;
-                port = serve_args.port;
+                #[cfg(not(unix))]
+                if serve_args.unix_socket.is_some() {
+                    return Err(ProxyError::Config("--unix-socket is only supported on Unix".to_string()));
+                }
+                server = server_for(&serve_args, &shared_config.read().server, user_agents.clone());
+                // A dry run prints the selected proxies like find does
+                is_server = server.is_some();
 
                 let mut checker = Checker::new().await;
+                checker.validation_cache = Some(Arc::clone(&VALIDATION_CACHE));
                 checker.max_tries = serve_args.max_tries as i32;
+                checker.max_judge_retries = serve_args.judge_retries;
+                checker.precheck_timeout = (serve_args.precheck_timeout > 0).then(|| Duration::from_millis(serve_args.precheck_timeout));
+                checker.check_integrity = serve_args.check_integrity;
+                checker.validate_url = serve_args.validate_url.clone();
+                checker.block_signature = serve_args.block_signature.as_deref().map(block_signature);
+                if let Some(endpoint) = &serve_args.fallback_endpoint {
+                    checker.fallback_endpoint = endpoint.clone();
+                }
+                checker.leak_headers = shared_config.read().general.leak_headers.clone();
+                checker.user_agents = user_agents.clone();
                 checker.support_cookie = true;
                 checker.support_referer = true;
 
                 checker.expected_types = serve_args.types.clone();
+                checker.type_match_mode = serve_args.type_match.parse().unwrap_or_default();
                 checker.expected_levels = serve_args.levels;
                 checker.expected_countries = serve_args.countries;
+                checker.excluded_countries = serve_args.exclude_countries;
 
                 // Initialize DNSBL if enabled
                 if serve_args.dnsbl_check {
@@ -402,10 +725,22 @@ fn main() -> ProxyResult<()> {
                         enabled: true,
                         timeout_secs: serve_args.dnsbl_timeout_secs,
                         max_concurrent: serve_args.dnsbl_max_concurrent,
+                        max_concurrent_ips: serve_args.dnsbl_max_concurrent_ips,
                         cache_ttl_secs: serve_args.dnsbl_cache_ttl_secs,
                         malicious_threshold: serve_args.dnsbl_malicious_threshold,
                         specific_lists: serve_args.dnsbl_specific_lists,
                         excluded_lists: serve_args.dnsbl_excluded_lists,
+                        dns_over_https: serve_args.dnsbl_doh,
+                        whitelisted_ips: serve_args.dnsbl_whitelist,
+                        resolver_cache_size: serve_args.dnsbl_resolver_cache_size,
+                        resolver_attempts: serve_args.dnsbl_resolver_attempts,
+                        dns_servers: if serve_args.dnsbl_dns_servers.is_empty() {
+                            shared_config.read().dnsbl.dns_servers.clone()
+                        } else {
+                            serve_args.dnsbl_dns_servers
+                        },
+                        full_scan: serve_args.dnsbl_full_scan,
+                        max_error_rate: shared_config.read().dnsbl.max_error_rate,
                     };
 
                     if let Err(e) = checker.enable_dnsbl(dnsbl_config).await {
@@ -417,23 +752,41 @@ fn main() -> ProxyResult<()> {
 
                 let expected_types = serve_args.types.clone();
                 let verify_ssl = false;
+                let degraded = Arc::clone(&checker.degraded);
                 task::spawn(async move {
-                    checker::check_judges(verify_ssl, ext_ip, expected_types).await;
+                    checker::check_judges(verify_ssl, ext_ip.clone(), expected_types.clone(), Arc::clone(&degraded)).await;
+                    checker::refresh_judges(ext_ip, expected_types, degraded, checker::JUDGE_REFRESH_INTERVAL).await;
                 });
                 files.extend(serve_args.files.clone());
+                max_cidr_hosts = serve_args.max_cidr_hosts;
+                default_tags = serve_args.tags.clone();
 
                 // Initialize ProxyPool with custom max response time
                 let max_avg_resp_time_sec = serve_args.max_avg_resp_time as f64 / 1000.0;
-                *POOL.lock() = ProxyPool::with_max_resp_time(max_avg_resp_time_sec);
+                let mut pool = ProxyPool::with_max_resp_time(max_avg_resp_time_sec);
+                pool.max_failures = serve_args.max_failures;
+                pool.max_age = serve_args.max_age.map(Duration::from_secs);
+                pool.pinned = serve_args.pin.iter().map(ToString::to_string).collect();
+                *POOL.lock() = pool;
+                push_pinned_proxies(&serve_args.pin, &serve_args.types).await;
+
+                if serve_args.recheck_interval > 0 {
+                    task::spawn(recheck_live_proxies(
+                        checker.clone(),
+                        Duration::from_secs(serve_args.recheck_interval),
+                    ));
+                }
 
                 let tx = tx.clone();
-                task::spawn(handle_find_command(checker, max_conn, tx));
+                task::spawn(handle_find_command(checker, max_conn, None, tx));
             }
+            Commands::Stats(_) => unreachable!("stats exits before the runtime starts"),
+            Commands::Config(_) => unreachable!("config exits before the runtime starts"),
         }
 
         if !files.is_empty() {
             task::spawn(async move {
-                handle_file_input(files).await;
+                handle_file_input(files, max_cidr_hosts, default_tags).await;
                 let mut stop_file_loop = STOP_FIND_LOOP.lock();
                 *stop_file_loop = true
             });
@@ -442,25 +795,54 @@ fn main() -> ProxyResult<()> {
                 log::info!("Start collecting proxies.. ");
             }
 
-            // providers
-            tasks.push(tokio::task::spawn(async {
-                let dur = Duration::from_secs(60);
-                loop {
-                    providers::run_all_providers(3).await;
+            // providers, the interval is re-read each cycle to follow config reloads
+            let refresh_config = shared_config.clone();
+            tasks.push(tokio::task::spawn(async move {
+                while !providers::is_fetching_stopped() {
+                    let (policy, watermarks, breaker, fetch_retry) = {
+                        let general = &refresh_config.read().general;
+                        (
+                            providers::RefreshPolicy::new(
+                                Duration::from_secs(refresh_interval.unwrap_or(general.provider_refresh_secs).max(1)),
+                                refresh_jitter.unwrap_or(general.provider_refresh_jitter_percent),
+                            ),
+                            providers::QueueWatermarks::new(general.queue_high_watermark, general.queue_low_watermark),
+                            providers::BreakerPolicy::new(
+                                general.provider_failure_threshold,
+                                Duration::from_secs(general.provider_cooldown_secs),
+                            ),
+                            providers::FetchRetryPolicy::new(
+                                general.provider_fetch_retries,
+                                Duration::from_millis(general.provider_fetch_backoff_ms),
+                                Duration::from_secs(general.provider_fetch_timeout_secs),
+                            ),
+                        )
+                    };
+                    providers::set_fetch_retry(fetch_retry);
+                    providers::run_all_providers(3, policy, watermarks, breaker).await;
+                    let dur = policy.next_interval(0);
                     log::debug!("Next cycle starts at {:?}", dur);
                     time::sleep(dur).await;
                 }
             }));
         }
 
-        if is_server {
+        if let Some(server) = server {
             tasks.push(tokio::task::spawn(async move {
-                let server = Server::new(host.as_str(), port);
                 server.start().await;
             }));
 
             loop {
-                if let Some(Some(proxy)) = rx.recv().await {
+                let received = tokio::select! {
+                    received = rx.recv() => received,
+                    _ = shutdown_rx.recv() => {
+                        log::info!("Stopping proxy server gracefully");
+                        break;
+                    }
+                };
+                if let Some(Some(proxy)) = received {
+                    #[cfg(feature = "sqlite")]
+                    remember(&store, &proxy).await;
                     while LIVE_PROXIES.is_full() {
                         continue;
                     }
@@ -470,59 +852,77 @@ fn main() -> ProxyResult<()> {
                 }
             }
         } else {
-            let mut output: Pin<Box<dyn AsyncWrite>> = if let Some(path) = outfile {
-                match File::create(path).await {
-                    Ok(file) => Box::pin(file),
-                    Err(e) => {
-                        log::error!("Failed to create output file: {}", e);
-                        return Err(ProxyError::Http(format!("Failed to create output file: {}", e)));
+            let output_count = Arc::new(AtomicUsize::new(0));
+            if let Some(path) = session_file.clone() {
+                if resume {
+                    if let Some(snapshot) = SessionSnapshot::load(&path).await {
+                        output_count.store(snapshot.output_count, Ordering::Relaxed);
+                        let restored = snapshot.restore(&PROXIES).await;
+                        log::info!(
+                            "Resumed session: {} queued proxies, {} already written",
+                            restored,
+                            output_count.load(Ordering::Relaxed)
+                        );
                     }
                 }
-            } else {
-                Box::pin(stdout())
-            };
 
-            let mut open_list = false;
-            let mut counter = limit;
-
-            while let Some(proxy) = rx.recv().await {
-                let stop = proxy.is_none() || (limit != 0 && counter <= 1);
-                if let Some(proxy) = proxy {
-                    if format == "json" && !open_list {
-                        if let Err(e) = output.write_all(b"[").await {
-                            log::error!("Failed to write JSON opening bracket: {}", e);
-                            break;
+                let output_count = Arc::clone(&output_count);
+                task::spawn(async move {
+                    let mut interval = time::interval(SESSION_SNAPSHOT_INTERVAL);
+                    interval.tick().await;
+                    loop {
+                        interval.tick().await;
+                        let snapshot = SessionSnapshot::capture(&PROXIES, output_count.load(Ordering::Relaxed));
+                        if let Err(e) = snapshot.save(&path).await {
+                            log::warn!("{}", e);
                         }
-                        open_list = true;
-                    }
-
-                    let msg = match format.as_str() {
-                        "text" => proxy.as_text(),
-                        "json" => proxy.as_json(),
-                        _ => format!("{}", proxy),
-                    };
-
-                    if let Err(e) = output.write_all(msg.as_bytes()).await {
-                        log::error!("Failed to write proxy data: {}", e);
-                        break;
                     }
+                });
+            }
+            let already_written = output_count.load(Ordering::Relaxed);
+            if progress {
+                task::spawn(Progress::new(limit).report(PROGRESS_INTERVAL, Arc::clone(&output_count)));
+            }
 
-                    if stop {
-                        let closing = if format == "json" { &b"]"[..] } else { &b""[..] };
-                        if let Err(e) = output.write_all(closing).await {
-                            log::error!("Failed to write closing data: {}", e);
-                        }
-                    } else if format == "json" {
-                        if let Err(e) = output.write_all(b",").await {
-                            log::error!("Failed to write JSON comma: {}", e);
-                            break;
+            // A resumed session keeps what was already written
+            let sinks = open_sinks(&outfiles, &format, template, already_written > 0, &webhooks)
+                .await
+                .inspect_err(|e| log::error!("{}", e))?;
+            let mut result = Ok(());
+            let mut counter = limit.saturating_sub(already_written);
+            // With --sort, proxies wait here until collection ends
+            let mut buffered = Vec::new();
+
+            if limit != 0 && counter == 0 {
+                log::info!("{} proxies already written, limit reached", already_written);
+                stop_collecting(&mut rx);
+            }
+            while limit == 0 || counter > 0 {
+                // A shutdown, e.g. at --max-runtime, stops like a reached limit
+                let proxy = tokio::select! {
+                    proxy = rx.recv() => match proxy {
+                        Some(proxy) => proxy,
+                        None => break,
+                    },
+                    _ = shutdown_rx.recv() => None,
+                };
+                let mut stop = proxy.is_none() || (limit != 0 && counter <= 1);
+                if let Some(proxy) = proxy {
+                    #[cfg(feature = "sqlite")]
+                    remember(&store, &proxy).await;
+                    if sort.is_some() {
+                        buffered.push(proxy);
+                    } else {
+                        match write_to_sinks(&sinks, &proxy).await {
+                            Ok(()) => {
+                                output_count.fetch_add(1, Ordering::Relaxed);
+                            }
+                            Err(e) => {
+                                result = Err(e);
+                                stop = true;
+                            }
                         }
                     }
-
-                    if let Err(e) = output.write_all(b"\n").await {
-                        log::error!("Failed to write newline: {}", e);
-                        break;
-                    }
                 }
                 if limit != 0 {
                     counter -= 1;
@@ -530,25 +930,178 @@ fn main() -> ProxyResult<()> {
 
                 if stop {
                     log::info!("Stopping proxy collection gracefully");
+                    stop_collecting(&mut rx);
+                    if rx.dropped() > 0 {
+                        log::warn!("{} results were dropped because output could not keep up", rx.dropped());
+                    }
+                    if let Some(path) = session_file.as_ref() {
+                        let snapshot = SessionSnapshot::capture(&PROXIES, output_count.load(Ordering::Relaxed));
+                        if let Err(e) = snapshot.save(path).await {
+                            log::warn!("{}", e);
+                        }
+                    }
                     break;
                 }
             }
+
+            if let Some(sort) = sort {
+                sort.sort(&mut buffered);
+                for proxy in &buffered {
+                    if let Err(e) = write_to_sinks(&sinks, proxy).await {
+                        result = Err(e);
+                        break;
+                    }
+                    output_count.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+
+            // Close the JSON array and flush even after a failed write
+            let finished = close_sinks(&sinks).await;
+            result.and(finished).inspect_err(|e| log::error!("{}", e))?;
         }
 
-        Ok(())
+        Ok::<(), ProxyError>(())
     })?;
 
     Ok(())
 }
 
-fn load_initial_config(config_path: &str) -> Result<DynamicConfig, ProxyError> {
-    let content = std::fs::read_to_string(config_path)
-        .map_err(|e| ProxyError::Config(format!("Failed to read config file: {}", e)))?;
+/// Print the summary published by a running instance
+fn print_stats(metrics_file: &Path, json: bool) -> ProxyResult<()> {
+    let summary = PerformanceSummary::load(metrics_file)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&summary)?);
+    } else {
+        println!("{}", summary);
+    }
+    Ok(())
+}
 
-    let config = toml::from_str::<DynamicConfig>(&content)
-        .map_err(|e| ProxyError::Config(format!("Failed to parse config: {}", e)))?;
+/// The `--db` store, none for a dry run which keeps nothing
+#[cfg(feature = "sqlite")]
+fn store_for(cli: &Cli) -> ProxyResult<Option<Arc<ProxyStore>>> {
+    if matches!(&cli.sub, Commands::Serve(args) if args.dry_run) {
+        if cli.db.is_some() {
+            log::info!("Dry run: not recording proxies in the database");
+        }
+        return Ok(None);
+    }
+    Ok(cli.db.as_deref().map(ProxyStore::open).transpose()?.map(Arc::new))
+}
 
-    Ok(config)
+/// Record a working proxy in the `--db` store
+#[cfg(feature = "sqlite")]
+async fn remember(store: &Option<Arc<ProxyStore>>, proxy: &Proxy) {
+    if let Some(store) = store {
+        if let Err(e) = store.upsert(&StoredProxy::from(proxy)).await {
+            log::warn!("{}", e);
+        }
+    }
+}
+
+/// Stop the providers and the find workers once output has enough proxies.
+/// Closing the channel releases workers waiting on a full output buffer.
+fn stop_collecting(rx: &mut OutputReceiver<Option<Proxy>>) {
+    *STOP_FIND_LOOP.lock() = true;
+    providers::stop_fetching();
+    rx.close();
+}
+
+/// Build the proxy server for `serve`, or nothing for a dry run
+fn server_for(args: &ServeArgs, config: &ServerConfig, user_agents: Option<Arc<UserAgents>>) -> Option<Server> {
+    if args.dry_run {
+        log::info!("Dry run: listing selected proxies instead of listening on {}:{}", args.host, args.port);
+        return None;
+    }
+    AUDIT_LOG.set_capacity(config.audit_log_size);
+    let mut server = Server::new(args.host.as_str(), args.port);
+    if args.rewrite_user_agent {
+        match user_agents {
+            Some(user_agents) => server = server.with_user_agents(user_agents),
+            None => log::warn!("--rewrite-user-agent needs --user-agents, forwarding User-Agents unchanged"),
+        }
+    }
+    if let Some(path) = &args.unix_socket {
+        server = server.with_unix_socket(path.clone());
+    }
+    Some(
+        server
+            .with_sticky_ttl(Duration::from_secs(args.sticky_ttl))
+            .with_chain_length(args.chain_length as usize)
+            .with_max_response_bytes(args.max_response_bytes)
+            .with_tunnel_reuse(Duration::from_secs(args.tunnel_idle_secs))
+            .with_protocol(args.protocol.parse().unwrap_or_default())
+            .with_request_id_header(args.request_id_header)
+            .with_warmup(args.warmup_proxies, args.warmup_connections)
+            .with_target_filter(TargetFilter::new(
+                [config.allowed_domains.as_slice(), &args.allow_domains].concat(),
+                [config.denied_domains.as_slice(), &args.deny_domains].concat(),
+            )
+            .with_connect_ports(config.allowed_connect_ports.clone()))
+            .with_header_rules(HeaderRules::new(&config.header_rules))
+            .with_max_connections_per_client(
+                args.max_connections_per_client.unwrap_or(config.max_connections_per_client),
+            ),
+    )
+}
+
+/// Settings of the REST API, `None` when `--no-api` or `api.enabled` turn it off.
+/// `--api-host` and `--api-port` take precedence over the config file.
+fn api_config_for(cli: &Cli, settings: &ApiSettings) -> Option<ApiConfig> {
+    if cli.no_api || !settings.enabled {
+        return None;
+    }
+    Some(ApiConfig {
+        host: cli.api_host.clone().unwrap_or_else(|| settings.host.clone()),
+        port: cli.api_port.unwrap_or(settings.port),
+        enable_auth: false, // Can be enabled in production
+        jwt_secret: "proxy-rs-api-secret".to_string(),
+        rate_limit: 1000,
+        cors_origins: settings.cors_origins.clone(),
+        request_timeout_ms: 30000,
+        dnsbl_bulk_max_ips: 100,
+        metrics_stream_interval_secs: 5,
+        tls_cert_path: cli.api_tls_cert.clone().or_else(|| settings.tls_cert_path.clone()),
+        tls_key_path: cli.api_tls_key.clone().or_else(|| settings.tls_key_path.clone()),
+    })
+}
+
+/// Configuration from `config_path`, or the defaults when there is no such file.
+/// A malformed file falls back to the defaults and unknown fields are ignored with a
+/// warning, unless `strict` makes either a startup error.
+fn load_initial_config(config_path: &Path, strict: bool) -> ProxyResult<DynamicConfig> {
+    if !config_path.exists() {
+        return Ok(DynamicConfig::new());
+    }
+    match config::file::load(config_path) {
+        Ok((config, mut unknown_fields)) => {
+            if strict && !unknown_fields.is_empty() {
+                return Err(unknown_fields.remove(0).into());
+            }
+            for err in unknown_fields {
+                log::warn!("{}, ignored", err);
+            }
+            log::info!("Loaded initial configuration from {}", config_path.display());
+            Ok(config)
+        }
+        Err(err) if strict => Err(err.into()),
+        Err(err) => {
+            log::warn!("{}, using defaults", err);
+            Ok(DynamicConfig::new())
+        }
+    }
+}
+
+/// `config validate`: report whether the file at `path` would be accepted, exiting
+/// with status 1 on the first problem
+fn validate_config_file(path: &Path) {
+    match config::file::validate(path) {
+        Ok(_) => println!("{}: OK", path.display()),
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
 }
 
 async fn start_config_watcher_with_retry(
@@ -568,3 +1121,169 @@ async fn start_config_watcher_with_retry(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use argument::{Cli, Commands};
+
+    fn serve_args(extra: &[&str]) -> ServeArgs {
+        let args = ["proxy-rs", "serve", "--types", "HTTP"].iter().chain(extra);
+        match Cli::parse_from(args).sub {
+            Commands::Serve(args) => args,
+            _ => unreachable!(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_does_not_bind() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let args = serve_args(&["--dry-run", "--port", &port.to_string()]);
+        assert!(server_for(&args, &DynamicConfig::new().server, None).is_none());
+        // Nothing holds the configured port
+        assert!(tokio::net::TcpListener::bind(("127.0.0.1", port)).await.is_ok());
+
+        let args = serve_args(&["--port", &port.to_string()]);
+        assert_eq!(server_for(&args, &DynamicConfig::new().server, None).map(|server| server.port), Some(port));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_dry_run_does_not_record() {
+        let db = std::env::temp_dir().join(format!("proxy-rs-dry-run-{}.db", std::process::id()));
+        let db_arg = db.to_string_lossy().to_string();
+        let cli = Cli::parse_from(["proxy-rs", "--db", &db_arg, "serve", "--types", "HTTP", "--dry-run"]);
+        assert!(store_for(&cli).unwrap().is_none());
+        assert!(!db.exists());
+
+        let cli = Cli::parse_from(["proxy-rs", "--db", &db_arg, "serve", "--types", "HTTP"]);
+        assert!(store_for(&cli).unwrap().is_some());
+        assert!(db.exists());
+        std::fs::remove_file(&db).unwrap();
+    }
+
+    struct StopMockProvider;
+
+    impl providers::ProxyProvider for StopMockProvider {
+        fn name(&self) -> &str {
+            "stop-mock"
+        }
+
+        fn fetch(&self) -> futures_util::future::BoxFuture<'_, Vec<Proxy>> {
+            Box::pin(async { Proxy::create("192.0.2.91", 3128, vec!["HTTP".to_string()]).await.into_iter().collect() })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stop_collecting_stops_workers_and_providers() {
+        let _fetching = providers::FETCH_TEST.lock().await;
+        let (tx, mut rx) = output_channel::<Option<Proxy>>(1, Default::default());
+        tx.send(None).await.unwrap();
+        // A worker waiting on the full output buffer
+        let blocked = tx.clone();
+        let worker = tokio::spawn(async move { blocked.send(None).await });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!worker.is_finished());
+
+        stop_collecting(&mut rx);
+
+        let sent = tokio::time::timeout(Duration::from_secs(1), worker).await.unwrap().unwrap();
+        assert!(sent.is_err());
+        assert!(tx.is_closed());
+        assert!(*STOP_FIND_LOOP.lock());
+
+        // Providers no longer queue anything
+        let mock: Vec<Arc<dyn providers::ProxyProvider>> = vec![Arc::new(StopMockProvider)];
+        let queued = providers::refresh_providers(mock, 1, Default::default(), Default::default()).await;
+        assert_eq!(queued, 0);
+
+        *STOP_FIND_LOOP.lock() = false;
+        providers::resume_fetching();
+    }
+
+    #[tokio::test]
+    async fn test_no_api_does_not_bind() {
+        // A free port for the API settings, released before the run
+        let port = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().port();
+        let settings = ApiSettings { port, ..DynamicConfig::new().api };
+        let cli = Cli::parse_from(["proxy-rs", "--no-api", "grab"]);
+        assert!(api_config_for(&cli, &settings).is_none());
+        // Nothing holds the API port on behalf of this run
+        assert!(tokio::net::TcpListener::bind((settings.host.as_str(), port)).await.is_ok());
+
+        let disabled = ApiSettings { enabled: false, ..settings.clone() };
+        assert!(api_config_for(&Cli::parse_from(["proxy-rs", "grab"]), &disabled).is_none());
+
+        let cli = Cli::parse_from(["proxy-rs", "--api-host", "0.0.0.0", "--api-port", "3100", "grab"]);
+        let config = api_config_for(&cli, &settings).unwrap();
+        assert_eq!((config.host.as_str(), config.port), ("0.0.0.0", 3100));
+    }
+
+    #[test]
+    fn test_api_tls_paths_reach_api_config() {
+        let settings = ApiSettings {
+            tls_cert_path: Some("config-cert.pem".to_string()),
+            tls_key_path: Some("config-key.pem".to_string()),
+            ..DynamicConfig::new().api
+        };
+        let config = api_config_for(&Cli::parse_from(["proxy-rs", "grab"]), &settings).unwrap();
+        assert_eq!(config.tls_cert_path.as_deref(), Some("config-cert.pem"));
+        assert_eq!(config.tls_key_path.as_deref(), Some("config-key.pem"));
+
+        let cli = Cli::parse_from(["proxy-rs", "--api-tls-cert", "cli-cert.pem", "--api-tls-key", "cli-key.pem", "grab"]);
+        let config = api_config_for(&cli, &settings).unwrap();
+        assert_eq!(config.tls_cert_path.as_deref(), Some("cli-cert.pem"));
+        assert_eq!(config.tls_key_path.as_deref(), Some("cli-key.pem"));
+
+        // A certificate without its key is refused by clap
+        assert!(Cli::try_parse_from(["proxy-rs", "--api-tls-cert", "cli-cert.pem", "grab"]).is_err());
+    }
+
+    #[test]
+    fn test_strict_config_rejects_bad_files() {
+        let path = std::env::temp_dir().join(format!("proxy-rs-strict-{}.toml", std::process::id()));
+        let valid = toml::to_string(&DynamicConfig::new()).unwrap();
+
+        std::fs::write(&path, valid.replace("port = 8080", "port = \"eighty\"")).unwrap();
+        let err = load_initial_config(&path, true).unwrap_err();
+        assert!(err.to_string().contains("proxy-rs-strict"), "{}", err);
+        // Lenient mode keeps starting with the defaults
+        assert_eq!(load_initial_config(&path, false).unwrap().server.port, 8080);
+
+        std::fs::write(&path, valid.replace("port = 8080", "port = 9090\nprot = 9091")).unwrap();
+        let err = load_initial_config(&path, true).unwrap_err();
+        assert!(err.to_string().contains("unknown field `server.prot`"), "{}", err);
+        // Lenient mode ignores the unknown field but keeps the rest of the file
+        assert_eq!(load_initial_config(&path, false).unwrap().server.port, 9090);
+
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(load_initial_config(&path, true).unwrap().server.port, 8080);
+    }
+
+    #[tokio::test]
+    async fn test_read_proxy_lines_from_reader() {
+        let input: &[u8] = b"127.0.0.1:8080\nno proxy here\n10.0.0.0/31:3128\n127.0.0.2:99999\n[2001:db8::1]:8080\n[not:an:ip]:80\n";
+        let queue = ProxyQueue::new();
+        let (_shutdown_tx, mut shutdown_rx) = mpsc::channel(1);
+
+        read_proxy_lines(input, &queue, 16, &[], &mut shutdown_rx).await.unwrap();
+
+        let mut queued = vec![];
+        while let Ok(proxy) = queue.pop() {
+            queued.push(proxy.as_text());
+        }
+        assert_eq!(queued, ["127.0.0.1:8080", "10.0.0.0:3128", "10.0.0.1:3128", "[2001:db8::1]:8080"]);
+    }
+
+    #[tokio::test]
+    async fn test_read_proxy_lines_with_tags() {
+        let input: &[u8] = b"127.0.0.1:8080#paid,residential\n127.0.0.2:8080\n";
+        let queue = ProxyQueue::new();
+        let (_shutdown_tx, mut shutdown_rx) = mpsc::channel(1);
+
+        read_proxy_lines(input, &queue, 16, &["free".to_string()], &mut shutdown_rx).await.unwrap();
+
+        assert_eq!(queue.pop().unwrap().tags, ["paid", "residential"]);
+        assert_eq!(queue.pop().unwrap().tags, ["free"]);
+    }
+}
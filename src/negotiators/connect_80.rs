@@ -1,3 +1,4 @@
+use super::connect_payload;
 use crate::{judge::Judge, proxy::Proxy, utils::http::response::ResponseParser};
 
 #[derive(Debug, Clone)]
@@ -8,11 +9,11 @@ pub struct Connect80Negotiator {
 }
 
 impl Connect80Negotiator {
+    /// Port the tunnel is opened to
+    pub const PORT: u16 = 80;
+
     pub async fn negotiate(&self, proxy: &mut Proxy, judge: &Judge) -> bool {
-        let connect_payload = format!(
-            "CONNECT {0}:80 HTTP/1.1\r\nHost: {0}\r\nConnection: keep-alive\r\n\r\n",
-            judge.host
-        );
+        let connect_payload = connect_payload(&judge.host, Self::PORT);
         proxy.send(connect_payload.as_bytes()).await;
 
         if let Some(data) = proxy.recv_all().await {
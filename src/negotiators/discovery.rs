@@ -0,0 +1,190 @@
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use tokio::time::timeout;
+
+use super::connect_payload;
+use crate::proxy::Proxy;
+
+/// Port the CONNECT and SOCKS4 probes ask a tunnel to
+const PROBE_PORT: u16 = 443;
+
+/// Check types a discovered protocol lets the checker try
+pub fn types_for(protocol: &str) -> &'static [&'static str] {
+    match protocol {
+        "HTTP" => &["HTTP"],
+        "CONNECT" => &["HTTPS", "CONNECT:80", "CONNECT:25"],
+        "SOCKS4" => &["SOCKS4"],
+        "SOCKS5" => &["SOCKS5"],
+        _ => &[],
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Probe {
+    Socks5,
+    Socks4,
+    Connect,
+}
+
+/// Probe the handshakes of a proxy and return the protocols it answers, among
+/// HTTP, CONNECT, SOCKS4 and SOCKS5. Each probe runs on its own connection and
+/// gets `probe_timeout`. A protocol is spoken when the proxy replies with that
+/// protocol's framing, even to refuse `target`: whether the tunnel works is left
+/// to the judge checks.
+pub async fn discover_protocols(proxy: &mut Proxy, target: &str, probe_timeout: Duration) -> Vec<String> {
+    let negotiator_proto = std::mem::replace(&mut proxy.negotiator_proto, "DISCOVERY".to_string());
+    let mut spoken = vec![];
+
+    if probe(proxy, Probe::Socks5, target, probe_timeout).await.is_some() {
+        spoken.push("SOCKS5".to_string());
+    }
+    if probe(proxy, Probe::Socks4, target, probe_timeout).await.is_some() {
+        spoken.push("SOCKS4".to_string());
+    }
+    if let Some(status) = probe(proxy, Probe::Connect, target, probe_timeout).await {
+        spoken.push("HTTP".to_string());
+        // These mean the method itself is not understood
+        if !matches!(status, 400 | 405 | 501) {
+            spoken.push("CONNECT".to_string());
+        }
+    }
+
+    proxy.log(format!("Discovered protocols: {}", spoken.join(", ")).as_str(), None, None);
+    proxy.negotiator_proto = negotiator_proto;
+    spoken
+}
+
+/// Run one handshake on a fresh connection, closed afterwards
+async fn probe(proxy: &mut Proxy, probe: Probe, target: &str, probe_timeout: Duration) -> Option<u16> {
+    let reply = timeout(probe_timeout, handshake(proxy, probe, target)).await.ok().flatten();
+    proxy.close().await;
+    reply
+}
+
+/// Send the opening message of `probe` and return the reply code when it is framed
+/// as that protocol: the SOCKS5 method, the SOCKS4 result or the HTTP status
+async fn handshake(proxy: &mut Proxy, probe: Probe, target: &str) -> Option<u16> {
+    if !proxy.connect().await {
+        return None;
+    }
+    match probe {
+        Probe::Socks5 => {
+            proxy.send(&[5, 1, 0]).await;
+            let reply = proxy.recv(2).await?;
+            (reply[0] == 0x05).then_some(reply[1] as u16)
+        }
+        Probe::Socks4 => {
+            proxy.send(&socks4_payload(target)).await;
+            let reply = proxy.recv(8).await?;
+            (reply[0] == 0x00 && (0x5a..=0x5d).contains(&reply[1])).then_some(reply[1] as u16)
+        }
+        Probe::Connect => {
+            proxy.send(connect_payload(target, PROBE_PORT).as_bytes()).await;
+            // "HTTP/1.1 200"
+            let reply = proxy.recv(12).await?;
+            let status_line = std::str::from_utf8(&reply).ok()?;
+            let (version, status) = status_line.split_once(' ')?;
+            if !version.starts_with("HTTP/1.") {
+                return None;
+            }
+            status.parse().ok()
+        }
+    }
+}
+
+/// SOCKS4 CONNECT to `target`, in its SOCKS4a form when `target` is not an IPv4 address
+fn socks4_payload(target: &str) -> Vec<u8> {
+    let mut packet = vec![4, 1];
+    packet.extend_from_slice(&PROBE_PORT.to_be_bytes());
+    match target.parse::<Ipv4Addr>() {
+        Ok(ip) => {
+            packet.extend_from_slice(&ip.octets());
+            packet.push(0);
+        }
+        Err(_) => {
+            packet.extend_from_slice(&[0, 0, 0, 1, 0]);
+            packet.extend_from_slice(target.as_bytes());
+            packet.push(0);
+        }
+    }
+    packet
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Proxy stub answering every connection with `reply` once it got a first message
+    async fn stub(reply: &'static [u8]) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut buf = [0; 512];
+                    if stream.read(&mut buf).await.unwrap_or(0) > 0 {
+                        let _ = stream.write_all(reply).await;
+                    }
+                    // Keep the connection open, like a proxy waiting for more
+                    let _ = stream.read(&mut buf).await;
+                });
+            }
+        });
+        port
+    }
+
+    /// Stub speaking SOCKS5 only: it drops any message that is not a SOCKS5 greeting
+    async fn socks5_stub() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut buf = [0; 512];
+                    let n = stream.read(&mut buf).await.unwrap_or(0);
+                    if n > 0 && buf[0] == 5 {
+                        let _ = stream.write_all(&[5, 0]).await;
+                        let _ = stream.read(&mut buf).await;
+                    }
+                });
+            }
+        });
+        port
+    }
+
+    #[tokio::test]
+    async fn test_discovers_socks5_only() {
+        let port = socks5_stub().await;
+        let mut proxy = Proxy::create("127.0.0.1", port, vec![]).await.unwrap();
+
+        let spoken = discover_protocols(&mut proxy, "example.com", Duration::from_millis(500)).await;
+        assert_eq!(spoken, vec!["SOCKS5".to_string()]);
+        assert_eq!(proxy.negotiator_proto, "HTTP");
+    }
+
+    #[tokio::test]
+    async fn test_discovers_http_only() {
+        // A proxy refusing CONNECT but answering in HTTP
+        let port = stub(b"HTTP/1.1 405 Method Not Allowed\r\nContent-Length: 0\r\n\r\n").await;
+        let mut proxy = Proxy::create("127.0.0.1", port, vec![]).await.unwrap();
+
+        let spoken = discover_protocols(&mut proxy, "example.com", Duration::from_millis(500)).await;
+        assert_eq!(spoken, vec!["HTTP".to_string()]);
+
+        let port = stub(b"HTTP/1.1 200 Connection established\r\n\r\n").await;
+        let mut proxy = Proxy::create("127.0.0.1", port, vec![]).await.unwrap();
+        let spoken = discover_protocols(&mut proxy, "example.com", Duration::from_millis(500)).await;
+        assert_eq!(spoken, vec!["HTTP".to_string(), "CONNECT".to_string()]);
+    }
+
+    #[test]
+    fn test_socks4_payload_forms() {
+        assert_eq!(socks4_payload("192.0.2.1"), vec![4, 1, 1, 187, 192, 0, 2, 1, 0]);
+        let socks4a = socks4_payload("example.com");
+        assert_eq!(&socks4a[..9], &[4, 1, 1, 187, 0, 0, 0, 1, 0]);
+        assert!(socks4a.ends_with(b"example.com\0"));
+    }
+}
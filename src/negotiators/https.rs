@@ -1,3 +1,4 @@
+use super::connect_payload;
 use crate::{judge::Judge, proxy::Proxy};
 
 #[derive(Debug, Clone)]
@@ -5,15 +6,18 @@ pub struct HttpsNegotiator {
     pub name: String,
     pub check_anon_lvl: bool,
     pub use_full_path: bool,
+    /// Name sent as SNI instead of the judge host
+    pub sni: Option<String>,
 }
 
 impl HttpsNegotiator {
+    /// Port the tunnel is opened to
+    pub const PORT: u16 = 443;
+
     pub async fn negotiate(&self, proxy: &mut Proxy, judge: &Judge) -> bool {
-        let connect_payload = format!(
-            "CONNECT {0}:443 HTTP/1.1\r\nHost: {0}\r\nConnection: keep-alive\r\n\r\n",
-            judge.host
-        );
-        proxy.connect_ssl(connect_payload.as_bytes()).await
+        let connect_payload = connect_payload(&judge.host, Self::PORT);
+        let server_name = self.sni.as_deref().unwrap_or(&judge.host);
+        proxy.connect_ssl(connect_payload.as_bytes(), server_name).await
     }
 }
 
@@ -23,6 +27,7 @@ impl Default for HttpsNegotiator {
             name: "HTTPS".to_string(),
             check_anon_lvl: false,
             use_full_path: false,
+            sni: None,
         }
     }
 }
@@ -1,6 +1,34 @@
 pub mod connect_25;
 pub mod connect_80;
+pub mod discovery;
 pub mod http;
 pub mod https;
 pub mod socks4;
 pub mod socks5;
+
+/// CONNECT request opening a tunnel to `host:port`
+pub fn connect_payload(host: &str, port: u16) -> String {
+    format!(
+        "CONNECT {0}:{1} HTTP/1.1\r\nHost: {0}:{1}\r\nConnection: keep-alive\r\n\r\n",
+        host, port
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connect_line_uses_protocol_port() {
+        let ports = [
+            ("HTTPS", https::HttpsNegotiator::PORT, 443),
+            ("CONNECT:80", connect_80::Connect80Negotiator::PORT, 80),
+            ("CONNECT:25", connect_25::Connect25Negotiator::PORT, 25),
+        ];
+        for (proto, port, expected_port) in ports {
+            let payload = connect_payload("judge.example", port);
+            let expected = format!("CONNECT judge.example:{} HTTP/1.1\r\n", expected_port);
+            assert!(payload.starts_with(&expected), "{}: {}", proto, payload);
+        }
+    }
+}
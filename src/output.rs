@@ -0,0 +1,895 @@
+//! Writing checked proxies to the output in the selected format
+
+use std::{
+    io::Write,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use futures_util::future::BoxFuture;
+use http_body_util::Full;
+use hyper::{header::CONTENT_TYPE, Request, Uri};
+use hyper_tls::HttpsConnector;
+use hyper_util::{
+    client::legacy::{connect::HttpConnector, Client},
+    rt::TokioExecutor,
+};
+use tokio::{
+    fs::{File, OpenOptions},
+    io::{stdout, AsyncWrite, AsyncWriteExt, Stdout},
+    sync::{mpsc, Mutex},
+    task::JoinHandle,
+    time,
+};
+
+use crate::{
+    performance::PERFORMANCE_MONITOR,
+    proxy::Proxy,
+    utils::error::{ProxyError, ProxyResult},
+};
+
+/// Time a webhook gets to accept a proxy
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+/// Proxies waiting for a webhook before writes wait for it too
+const WEBHOOK_QUEUE_SIZE: usize = 100;
+/// Deliveries tried before a proxy is given up for a webhook
+const WEBHOOK_ATTEMPTS: u32 = 3;
+/// Wait before the first retry of a delivery, doubled for each next one
+const WEBHOOK_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Placeholders a template may use
+const PLACEHOLDERS: [&str; 6] = ["ip", "port", "country", "protocols", "latency", "anonymity"];
+
+#[derive(Debug, Clone, PartialEq)]
+enum TemplatePart {
+    Text(String),
+    Placeholder(&'static str),
+}
+
+/// Output line with `{placeholder}` fields, e.g. `{ip}:{port} {country} {latency}ms`.
+/// Parsed once, so an unknown placeholder fails at startup rather than per line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    parts: Vec<TemplatePart>,
+}
+
+impl std::str::FromStr for Template {
+    type Err = ProxyError;
+
+    fn from_str(template: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(TemplatePart::Text(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| ProxyError::Config(format!("Unclosed placeholder in template: {}", template)))?;
+            let name = &rest[start + 1..start + end];
+            let placeholder = PLACEHOLDERS.iter().find(|known| **known == name).ok_or_else(|| {
+                ProxyError::Config(format!(
+                    "Unknown placeholder {{{}}} in template, expected one of {{{}}}",
+                    name,
+                    PLACEHOLDERS.join("}, {")
+                ))
+            })?;
+            parts.push(TemplatePart::Placeholder(placeholder));
+            rest = &rest[start + end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(TemplatePart::Text(rest.to_string()));
+        }
+        Ok(Self { parts })
+    }
+}
+
+impl Template {
+    pub fn render(&self, proxy: &Proxy) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                TemplatePart::Text(text) => text.clone(),
+                TemplatePart::Placeholder("ip") => proxy.host.clone(),
+                TemplatePart::Placeholder("port") => proxy.port.to_string(),
+                TemplatePart::Placeholder("country") => proxy.geo.iso_code.clone(),
+                TemplatePart::Placeholder("protocols") => {
+                    proxy.types.iter().map(|(proto, _)| proto.as_str()).collect::<Vec<_>>().join(",")
+                }
+                TemplatePart::Placeholder("latency") => format!("{:.0}", proxy.avg_resp_time() * 1000.0),
+                TemplatePart::Placeholder(_) => proxy.anonymity_level.clone().unwrap_or_else(|| "-".to_string()),
+            })
+            .collect()
+    }
+}
+
+/// Order in which `--sort` emits the proxies
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    /// Best `Proxy::score` first
+    Score,
+    /// Lowest average response time first
+    Latency,
+    /// By country code, then lowest latency
+    Country,
+}
+
+impl std::str::FromStr for SortKey {
+    type Err = ProxyError;
+
+    fn from_str(key: &str) -> Result<Self, Self::Err> {
+        match key.to_lowercase().as_str() {
+            "score" => Ok(Self::Score),
+            "latency" => Ok(Self::Latency),
+            "country" => Ok(Self::Country),
+            _ => Err(ProxyError::Config(format!(
+                "Unknown sort key {}, expected score, latency or country",
+                key
+            ))),
+        }
+    }
+}
+
+impl SortKey {
+    pub fn sort(self, proxies: &mut [Proxy]) {
+        match self {
+            Self::Score => proxies.sort_by(|a, b| b.score().total_cmp(&a.score())),
+            Self::Latency => proxies.sort_by(|a, b| a.avg_resp_time().total_cmp(&b.avg_resp_time())),
+            Self::Country => proxies.sort_by(|a, b| {
+                a.geo
+                    .iso_code
+                    .cmp(&b.geo.iso_code)
+                    .then(a.avg_resp_time().total_cmp(&b.avg_resp_time()))
+            }),
+        }
+    }
+}
+
+/// Writes proxies one by one. A JSON array is opened by the first proxy and
+/// closed by `finish`. A failed write may leave part of an entry behind, so
+/// the writer then refuses further writes and `finish` leaves the array open.
+pub struct ProxyWriter<W> {
+    output: W,
+    format: String,
+    /// Replaces the format when set
+    template: Option<Template>,
+    open_list: bool,
+    /// Set by a failed write, the output may end with a partial entry
+    broken: bool,
+}
+
+impl<W: AsyncWrite + Unpin> ProxyWriter<W> {
+    pub fn new(output: W, format: &str) -> Self {
+        Self {
+            output,
+            format: format.to_string(),
+            template: None,
+            open_list: false,
+            broken: false,
+        }
+    }
+
+    pub fn with_template(mut self, template: Option<Template>) -> Self {
+        self.template = template;
+        self
+    }
+
+    pub async fn write(&mut self, proxy: &Proxy) -> ProxyResult<()> {
+        if self.broken {
+            return Err(ProxyError::Io(format!("Output broken by a failed write, {} not written", proxy.as_text())));
+        }
+        let entry = match (&self.template, self.format.as_str()) {
+            (Some(template), _) => format!("{}\n", template.render(proxy)),
+            (None, "json") => {
+                let separator = if self.open_list { ",\n" } else { "[" };
+                format!("{}{}", separator, proxy.as_json())
+            }
+            (None, "text") => format!("{}\n", proxy.as_text()),
+            (None, _) => format!("{}\n", proxy),
+        };
+        if let Err(e) = self.output.write_all(entry.as_bytes()).await {
+            self.broken = true;
+            return Err(ProxyError::Io(format!("Failed to write proxy {}: {}", proxy.as_text(), e)));
+        }
+        self.open_list = true;
+        Ok(())
+    }
+
+    /// Close the JSON array if one was opened and flush the output
+    pub async fn finish(&mut self) -> ProxyResult<()> {
+        if self.broken && self.open_list {
+            log::warn!("JSON output left unclosed after a failed write");
+        } else if self.format == "json" && self.template.is_none() && self.open_list {
+            self.output
+                .write_all(b"]\n")
+                .await
+                .map_err(|e| ProxyError::Io(format!("Failed to close JSON output: {}", e)))?;
+            self.open_list = false;
+        }
+        self.output
+            .flush()
+            .await
+            .map_err(|e| ProxyError::Io(format!("Failed to flush output: {}", e)))
+    }
+}
+
+/// `--outfile` value: a path, or `path:format` to write that file in its own format
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputTarget {
+    pub path: PathBuf,
+    /// Overrides `--format` and `--template` for this file
+    pub format: Option<String>,
+}
+
+impl std::str::FromStr for OutputTarget {
+    type Err = std::convert::Infallible;
+
+    /// A suffix that is not a known format is part of the path
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let paired = value.rsplit_once(':').and_then(|(path, format)| {
+            let format = match format {
+                "default" => "default",
+                "text" | "txt" => "text",
+                "json" => "json",
+                _ => return None,
+            };
+            (!path.is_empty()).then_some((path, format))
+        });
+        Ok(match paired {
+            Some((path, format)) => Self {
+                path: PathBuf::from(path),
+                format: Some(format.to_string()),
+            },
+            None => Self {
+                path: PathBuf::from(value),
+                format: None,
+            },
+        })
+    }
+}
+
+/// Destination of the found proxies: a file, stdout or an external system
+pub trait OutputSink: Send + Sync {
+    /// Name used in logs
+    fn name(&self) -> String;
+
+    /// Deliver one proxy
+    fn write<'a>(&'a self, proxy: &'a Proxy) -> BoxFuture<'a, ProxyResult<()>>;
+
+    /// Flush what was written and end the output, e.g. close a JSON array.
+    /// Runs even after a failed write.
+    fn close(&self) -> BoxFuture<'_, ProxyResult<()>>;
+}
+
+/// Sink writing through a `ProxyWriter`
+pub struct WriterSink<W> {
+    name: String,
+    writer: Mutex<ProxyWriter<W>>,
+}
+
+/// `--outfile` sink
+pub type FileSink = WriterSink<File>;
+/// Sink used when there is no `--outfile`
+pub type StdoutSink = WriterSink<Stdout>;
+
+impl<W> WriterSink<W> {
+    pub fn new(name: impl Into<String>, writer: ProxyWriter<W>) -> Self {
+        Self {
+            name: name.into(),
+            writer: Mutex::new(writer),
+        }
+    }
+}
+
+impl FileSink {
+    /// Create the file of `target`, or append to it when `append` (a resumed session
+    /// keeps what was already written, a JSON array is reopened to add to it). Without
+    /// a format of its own the file uses `format` and `template`.
+    pub async fn open(target: &OutputTarget, format: &str, template: Option<Template>, append: bool) -> ProxyResult<Self> {
+        let json = match &target.format {
+            Some(format) => format == "json",
+            None => format == "json" && template.is_none(),
+        };
+        let reopened = append && json && reopen_json_array(&target.path).await?;
+        let file = if append {
+            OpenOptions::new().create(true).append(true).open(&target.path).await
+        } else {
+            File::create(&target.path).await
+        };
+        let file = file.map_err(|e| {
+            ProxyError::Io(format!("Failed to create output file {}: {}", target.path.display(), e))
+        })?;
+        let mut writer = match &target.format {
+            Some(format) => ProxyWriter::new(file, format),
+            None => ProxyWriter::new(file, format).with_template(template),
+        };
+        writer.open_list = reopened;
+        Ok(Self::new(target.path.display().to_string(), writer))
+    }
+}
+
+/// Drop the closing `]` of the JSON array in `path` so proxies can be appended to it.
+/// `false` when there is no array to continue, e.g. a missing or empty file.
+async fn reopen_json_array(path: &std::path::Path) -> ProxyResult<bool> {
+    let content = match tokio::fs::read_to_string(path).await {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(ProxyError::Io(format!("Failed to read output file {}: {}", path.display(), e))),
+    };
+    let content = content.trim_end();
+    if !content.starts_with('[') || !content.ends_with(']') || content.len() < 2 {
+        return Ok(false);
+    }
+    let file = OpenOptions::new()
+        .write(true)
+        .open(path)
+        .await
+        .map_err(|e| ProxyError::Io(format!("Failed to reopen output file {}: {}", path.display(), e)))?;
+    // An empty array is dropped whole, the next proxy opens a new one
+    let empty = content[1..content.len() - 1].trim().is_empty();
+    let keep = if empty { 0 } else { content.len() - 1 };
+    file.set_len(keep as u64)
+        .await
+        .map_err(|e| ProxyError::Io(format!("Failed to reopen output file {}: {}", path.display(), e)))?;
+    Ok(!empty)
+}
+
+impl StdoutSink {
+    pub fn stdout(format: &str, template: Option<Template>) -> Self {
+        Self::new("stdout", ProxyWriter::new(stdout(), format).with_template(template))
+    }
+}
+
+impl<W: AsyncWrite + Unpin + Send> OutputSink for WriterSink<W> {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn write<'a>(&'a self, proxy: &'a Proxy) -> BoxFuture<'a, ProxyResult<()>> {
+        Box::pin(async move { self.writer.lock().await.write(proxy).await })
+    }
+
+    fn close(&self) -> BoxFuture<'_, ProxyResult<()>> {
+        Box::pin(async move { self.writer.lock().await.finish().await })
+    }
+}
+
+/// Sink POSTing each proxy as JSON to an HTTP endpoint. Deliveries run in the
+/// background and are retried; a proxy the webhook still refuses is logged and
+/// counted, it never stops the run.
+pub struct WebhookSink {
+    url: Uri,
+    /// Proxies waiting for delivery as `(ip:port, JSON)`, `None` once closed
+    queue: Mutex<Option<mpsc::Sender<(String, String)>>>,
+    delivery: Mutex<Option<JoinHandle<()>>>,
+    failed: Arc<AtomicUsize>,
+}
+
+impl WebhookSink {
+    pub fn new(url: &str) -> ProxyResult<Self> {
+        let url: Uri = url
+            .parse()
+            .map_err(|e| ProxyError::Config(format!("Invalid webhook URL {}: {}", url, e)))?;
+        if !matches!(url.scheme_str(), Some("http" | "https")) {
+            return Err(ProxyError::Config(format!("Webhook URL {} must be http or https", url)));
+        }
+        let client = Client::builder(TokioExecutor::new()).build(HttpsConnector::new());
+        let (queue, pending) = mpsc::channel(WEBHOOK_QUEUE_SIZE);
+        let failed = Arc::new(AtomicUsize::new(0));
+        let delivery = tokio::spawn(deliver(url.clone(), client, pending, Arc::clone(&failed)));
+        Ok(Self {
+            url,
+            queue: Mutex::new(Some(queue)),
+            delivery: Mutex::new(Some(delivery)),
+            failed,
+        })
+    }
+
+    /// Proxies the webhook refused after every attempt
+    pub fn failed(&self) -> usize {
+        self.failed.load(Ordering::Relaxed)
+    }
+}
+
+/// POST the queued proxies to `url` one after another
+async fn deliver(
+    url: Uri,
+    client: Client<HttpsConnector<HttpConnector>, Full<Bytes>>,
+    mut pending: mpsc::Receiver<(String, String)>,
+    failed: Arc<AtomicUsize>,
+) {
+    while let Some((proxy, json)) = pending.recv().await {
+        let mut attempt = 1;
+        loop {
+            match post(&client, &url, &json).await {
+                Ok(()) => break,
+                Err(reason) if attempt < WEBHOOK_ATTEMPTS => {
+                    log::debug!("Webhook {} refused proxy {}: {}, retrying", url, proxy, reason);
+                    time::sleep(WEBHOOK_RETRY_DELAY * 2u32.pow(attempt - 1)).await;
+                    attempt += 1;
+                }
+                Err(reason) => {
+                    log::warn!("Webhook {} refused proxy {}: {}", url, proxy, reason);
+                    failed.fetch_add(1, Ordering::Relaxed);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// POST one proxy, the reason of the failure otherwise
+async fn post(client: &Client<HttpsConnector<HttpConnector>, Full<Bytes>>, url: &Uri, json: &str) -> Result<(), String> {
+    let request = Request::post(url.clone())
+        .header(CONTENT_TYPE, "application/json")
+        .body(Full::new(Bytes::from(json.to_string())))
+        .map_err(|e| format!("failed to build request: {}", e))?;
+    let response = time::timeout(WEBHOOK_TIMEOUT, client.request(request))
+        .await
+        .map_err(|_| "timed out".to_string())?
+        .map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(response.status().to_string());
+    }
+    Ok(())
+}
+
+impl OutputSink for WebhookSink {
+    fn name(&self) -> String {
+        self.url.to_string()
+    }
+
+    /// Queue the proxy, waiting only while the queue is full
+    fn write<'a>(&'a self, proxy: &'a Proxy) -> BoxFuture<'a, ProxyResult<()>> {
+        Box::pin(async move {
+            let queue = self.queue.lock().await.clone();
+            let queued = match queue {
+                Some(queue) => queue.send((proxy.as_text(), proxy.as_json())).await.is_ok(),
+                None => false,
+            };
+            if !queued {
+                return Err(ProxyError::Http(format!("Webhook {} is closed", self.url)));
+            }
+            Ok(())
+        })
+    }
+
+    /// Wait for the queued deliveries
+    fn close(&self) -> BoxFuture<'_, ProxyResult<()>> {
+        Box::pin(async move {
+            self.queue.lock().await.take();
+            if let Some(delivery) = self.delivery.lock().await.take() {
+                let _ = delivery.await;
+            }
+            if self.failed() > 0 {
+                log::warn!("Webhook {} refused {} proxies", self.url, self.failed());
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Every destination of the proxies: the `--outfile` files, or stdout when there are
+/// none, and the `--webhook` endpoints
+pub async fn open_sinks(
+    targets: &[OutputTarget],
+    format: &str,
+    template: Option<Template>,
+    append: bool,
+    webhooks: &[String],
+) -> ProxyResult<Vec<Box<dyn OutputSink>>> {
+    let mut sinks: Vec<Box<dyn OutputSink>> = Vec::with_capacity(targets.len().max(1) + webhooks.len());
+    if targets.is_empty() {
+        sinks.push(Box::new(StdoutSink::stdout(format, template)));
+    } else {
+        for target in targets {
+            sinks.push(Box::new(FileSink::open(target, format, template.clone(), append).await?));
+        }
+    }
+    for url in webhooks {
+        sinks.push(Box::new(WebhookSink::new(url)?));
+    }
+    Ok(sinks)
+}
+
+/// Write `proxy` to every sink, the first failure is returned once all were tried
+pub async fn write_to_sinks(sinks: &[Box<dyn OutputSink>], proxy: &Proxy) -> ProxyResult<()> {
+    let mut result = Ok(());
+    for sink in sinks {
+        let written = sink.write(proxy).await;
+        if let Err(e) = &written {
+            log::debug!("Output {} failed: {}", sink.name(), e);
+        }
+        result = result.and(written);
+    }
+    result
+}
+
+/// Close every sink, see `OutputSink::close`
+pub async fn close_sinks(sinks: &[Box<dyn OutputSink>]) -> ProxyResult<()> {
+    let mut result = Ok(());
+    for sink in sinks {
+        let closed = sink.close().await;
+        result = result.and(closed);
+    }
+    result
+}
+
+/// Status lines of `--progress`, written apart from the proxies so piped output stays clean
+pub struct Progress {
+    started: Instant,
+    limit: usize,
+}
+
+impl Progress {
+    pub fn new(limit: usize) -> Self {
+        Progress {
+            started: Instant::now(),
+            limit,
+        }
+    }
+
+    /// Checked and working proxies so far, the check rate and, with a limit, the ETA
+    pub fn line(&self, checked: u64, working: u64, written: usize) -> String {
+        let elapsed = self.started.elapsed().as_secs_f64().max(0.001);
+        let mut line = format!(
+            "checked {} | working {} | written {} | {:.1} checks/s",
+            checked,
+            working,
+            written,
+            checked as f64 / elapsed
+        );
+        if self.limit > 0 {
+            let remaining = self.limit.saturating_sub(written);
+            let eta = match written as f64 / elapsed {
+                _ if remaining == 0 => "0s".to_string(),
+                rate if rate > 0.0 => format!("{:.0}s", remaining as f64 / rate),
+                _ => "?".to_string(),
+            };
+            line.push_str(&format!(" | {}/{} | ETA {}", written.min(self.limit), self.limit, eta));
+        }
+        line
+    }
+
+    /// Write one status line from the performance monitor
+    pub async fn print(&self, out: &mut impl Write, written: usize) -> std::io::Result<()> {
+        let metrics = PERFORMANCE_MONITOR.get_metrics().await.proxy_metrics;
+        writeln!(out, "{}", self.line(metrics.total_checks, metrics.successful_checks, written))
+    }
+
+    /// Print a status line on stderr every `interval`, until the task is aborted
+    pub async fn report(self, interval: Duration, written: Arc<AtomicUsize>) {
+        let mut ticker = time::interval(interval);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            if let Err(e) = self.print(&mut std::io::stderr(), written.load(Ordering::Relaxed)).await {
+                log::debug!("Failed to print progress: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io,
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    /// Writer failing its `fail_at`-th write call and accepting the others
+    struct FlakyWriter {
+        data: Vec<u8>,
+        calls: usize,
+        fail_at: usize,
+    }
+
+    impl AsyncWrite for FlakyWriter {
+        fn poll_write(mut self: Pin<&mut Self>, _: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+            self.calls += 1;
+            if self.calls == self.fail_at {
+                return Poll::Ready(Err(io::Error::new(io::ErrorKind::BrokenPipe, "disk went away")));
+            }
+            self.data.extend_from_slice(buf);
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sort_keys() {
+        let mut proxies = Vec::new();
+        for (host, country, millis, level) in
+            [("127.0.0.1", "US", 3000, "Transparent"), ("127.0.0.2", "DE", 900, "High"), ("127.0.0.3", "FR", 200, "High")]
+        {
+            let mut proxy = Proxy::create(host, 8080, vec![]).await.unwrap();
+            proxy.geo.iso_code = country.to_string();
+            proxy.log("Request: success", Some(Duration::from_millis(millis)), None);
+            proxy.anonymity_level = Some(level.to_string());
+            proxies.push(proxy);
+        }
+        let hosts = |proxies: &[Proxy]| proxies.iter().map(|proxy| proxy.host.clone()).collect::<Vec<_>>();
+
+        SortKey::Score.sort(&mut proxies);
+        assert_eq!(hosts(&proxies), ["127.0.0.3", "127.0.0.2", "127.0.0.1"]);
+        SortKey::Country.sort(&mut proxies);
+        assert_eq!(hosts(&proxies), ["127.0.0.2", "127.0.0.3", "127.0.0.1"]);
+        SortKey::Latency.sort(&mut proxies);
+        assert_eq!(hosts(&proxies), ["127.0.0.3", "127.0.0.2", "127.0.0.1"]);
+        assert!("fastest".parse::<SortKey>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_failed_write_stops_the_output() {
+        let first = Proxy::create("127.0.0.1", 8080, vec![]).await.unwrap();
+        let second = Proxy::create("127.0.0.2", 8080, vec![]).await.unwrap();
+        let mut writer = ProxyWriter::new(
+            FlakyWriter {
+                data: vec![],
+                calls: 0,
+                fail_at: 2,
+            },
+            "json",
+        );
+
+        writer.write(&first).await.unwrap();
+        let err = writer.write(&second).await.unwrap_err();
+        assert!(err.to_string().contains("127.0.0.2:8080"));
+        assert!(err.to_string().contains("disk went away"));
+        assert!(matches!(err, ProxyError::Io(_)));
+
+        // Nothing more goes after what may be a partial entry, not even the closing bracket
+        assert!(writer.write(&first).await.is_err());
+        writer.finish().await.unwrap();
+        assert_eq!(writer.output.data, format!("[{}", first.as_json()).into_bytes());
+    }
+
+    #[test]
+    fn test_progress_line_estimates_eta() {
+        let progress = Progress::new(10);
+        let line = progress.line(40, 4, 0);
+        assert!(line.starts_with("checked 40 | working 4 | written 0"));
+        assert!(line.ends_with("0/10 | ETA ?"));
+        assert!(progress.line(90, 12, 12).ends_with("10/10 | ETA 0s"));
+        assert!(!Progress::new(0).line(1, 1, 1).contains("ETA"));
+    }
+
+    #[tokio::test]
+    async fn test_progress_stays_off_the_results() {
+        let progress = Progress::new(5);
+        let mut stdout = ProxyWriter::new(Vec::new(), "json");
+        let mut stderr = Vec::new();
+
+        let proxy = Proxy::create("127.0.0.1", 8080, vec![]).await.unwrap();
+        stdout.write(&proxy).await.unwrap();
+        progress.print(&mut stderr, 1).await.unwrap();
+        stdout.write(&proxy).await.unwrap();
+        progress.print(&mut stderr, 2).await.unwrap();
+        stdout.finish().await.unwrap();
+
+        let stderr = String::from_utf8(stderr).unwrap();
+        assert_eq!(stderr.lines().count(), 2);
+        assert!(stderr.lines().all(|line| line.starts_with("checked ")));
+        let output: serde_json::Value = serde_json::from_slice(&stdout.output).unwrap();
+        assert_eq!(output.as_array().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_template_renders_placeholders() {
+        let mut proxy = Proxy::create("127.0.0.1", 8080, vec![]).await.unwrap();
+        proxy.geo.iso_code = "FR".to_string();
+        proxy.types = vec![("HTTP".to_string(), Some("High".to_string())), ("SOCKS5".to_string(), None)];
+        proxy.anonymity_level = Some("High".to_string());
+        proxy.runtimes = vec![0.1, 0.3];
+
+        let template: Template = "{ip}:{port} {country} [{protocols}] {anonymity} {latency}ms".parse().unwrap();
+        assert_eq!(template.render(&proxy), "127.0.0.1:8080 FR [HTTP,SOCKS5] High 200ms");
+
+        // The template replaces the format, JSON included
+        let mut writer = ProxyWriter::new(Vec::new(), "json").with_template(Some("{ip} {port}".parse().unwrap()));
+        writer.write(&proxy).await.unwrap();
+        writer.write(&proxy).await.unwrap();
+        writer.finish().await.unwrap();
+        assert_eq!(String::from_utf8(writer.output).unwrap(), "127.0.0.1 8080\n127.0.0.1 8080\n");
+    }
+
+    #[test]
+    fn test_template_rejects_unknown_placeholders() {
+        let err = "{ip}:{prot}".parse::<Template>().unwrap_err();
+        assert!(err.to_string().contains("Unknown placeholder {prot}"), "{}", err);
+        assert!("{ip}:{port".parse::<Template>().is_err());
+        assert!("no placeholders".parse::<Template>().is_ok());
+    }
+
+    #[test]
+    fn test_output_target_pairing() {
+        let target: OutputTarget = "results.json:json".parse().unwrap();
+        assert_eq!(target.path, PathBuf::from("results.json"));
+        assert_eq!(target.format.as_deref(), Some("json"));
+        assert_eq!("list.txt:txt".parse::<OutputTarget>().unwrap().format.as_deref(), Some("text"));
+
+        // Without a known format the whole value is the path
+        for value in ["proxies.txt", "C:\\out\\proxies.txt", "archive:2024", ":json"] {
+            let target: OutputTarget = value.parse().unwrap();
+            assert_eq!(target.path, PathBuf::from(value));
+            assert_eq!(target.format, None);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_outputs_write_every_file_in_its_format() {
+        let dir = std::env::temp_dir().join(format!("proxy-rs-outputs-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let json = dir.join("results.json");
+        let list = dir.join("list.txt");
+        let targets = [
+            format!("{}:json", json.display()).parse().unwrap(),
+            format!("{}:txt", list.display()).parse().unwrap(),
+        ];
+
+        let sinks = open_sinks(&targets, "default", None, false, &[]).await.unwrap();
+        for host in ["127.0.0.1", "127.0.0.2"] {
+            write_to_sinks(&sinks, &Proxy::create(host, 8080, vec![]).await.unwrap()).await.unwrap();
+        }
+        close_sinks(&sinks).await.unwrap();
+
+        let dump: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&json).unwrap()).unwrap();
+        assert_eq!(dump.as_array().unwrap().len(), 2);
+        assert_eq!(std::fs::read_to_string(&list).unwrap(), "127.0.0.1:8080\n127.0.0.2:8080\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Sink recording what it is given, refusing the proxies of `fail_host`
+    struct MockSink {
+        delivered: Arc<parking_lot::Mutex<Vec<String>>>,
+        closed: Arc<AtomicUsize>,
+        fail_host: Option<&'static str>,
+    }
+
+    impl OutputSink for MockSink {
+        fn name(&self) -> String {
+            "mock".to_string()
+        }
+
+        fn write<'a>(&'a self, proxy: &'a Proxy) -> BoxFuture<'a, ProxyResult<()>> {
+            Box::pin(async move {
+                self.delivered.lock().push(proxy.as_text());
+                match self.fail_host {
+                    Some(host) if host == proxy.host => Err(ProxyError::Http("sink unavailable".to_string())),
+                    _ => Ok(()),
+                }
+            })
+        }
+
+        fn close(&self) -> BoxFuture<'_, ProxyResult<()>> {
+            self.closed.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sinks_get_each_proxy_once() {
+        let deliveries = [Arc::default(), Arc::default()];
+        let closed = Arc::new(AtomicUsize::new(0));
+        let sinks: Vec<Box<dyn OutputSink>> = vec![
+            Box::new(MockSink { delivered: Arc::clone(&deliveries[0]), closed: Arc::clone(&closed), fail_host: Some("127.0.0.2") }),
+            Box::new(MockSink { delivered: Arc::clone(&deliveries[1]), closed: Arc::clone(&closed), fail_host: None }),
+        ];
+
+        for host in ["127.0.0.1", "127.0.0.2", "127.0.0.3"] {
+            let written = write_to_sinks(&sinks, &Proxy::create(host, 8080, vec![]).await.unwrap()).await;
+            // A failing sink does not keep the others from getting the proxy
+            assert_eq!(written.is_err(), host == "127.0.0.2");
+        }
+        close_sinks(&sinks).await.unwrap();
+
+        for delivered in &deliveries {
+            assert_eq!(*delivered.lock(), ["127.0.0.1:8080", "127.0.0.2:8080", "127.0.0.3:8080"]);
+        }
+        assert_eq!(closed.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_webhook_posts_json() {
+        use hyper::{body::Incoming, service::service_fn, Response};
+        use hyper_util::rt::TokioIo;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (bodies, mut received) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let bodies = bodies.clone();
+                tokio::spawn(async move {
+                    let service = service_fn(move |request: hyper::Request<Incoming>| {
+                        let bodies = bodies.clone();
+                        async move {
+                            let content_type = request.headers()[CONTENT_TYPE].clone();
+                            let body = http_body_util::BodyExt::collect(request.into_body()).await?.to_bytes();
+                            let _ = bodies.send((content_type, body));
+                            Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::new())))
+                        }
+                    });
+                    let _ = hyper::server::conn::http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+
+        let sink = WebhookSink::new(&format!("http://127.0.0.1:{}/proxies", port)).unwrap();
+        sink.write(&Proxy::create("127.0.0.1", 8080, vec![]).await.unwrap()).await.unwrap();
+        let (content_type, body) = received.recv().await.unwrap();
+        assert_eq!(content_type, "application/json");
+        let proxy: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(proxy["host"], "127.0.0.1");
+
+        assert!(WebhookSink::new("ftp://example.com/").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_failing_webhook_does_not_stop_output() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&attempts);
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                counted.fetch_add(1, Ordering::SeqCst);
+                let mut request = [0; 4096];
+                let _ = tokio::io::AsyncReadExt::read(&mut stream, &mut request).await;
+                let _ = stream
+                    .write_all(b"HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                    .await;
+            }
+        });
+
+        let sink = WebhookSink::new(&format!("http://127.0.0.1:{}/proxies", port)).unwrap();
+        let started = Instant::now();
+        sink.write(&Proxy::create("127.0.0.1", 8080, vec![]).await.unwrap()).await.unwrap();
+        // Queued, not awaited inline
+        assert!(started.elapsed() < WEBHOOK_RETRY_DELAY);
+        sink.close().await.unwrap();
+
+        assert_eq!(attempts.load(Ordering::SeqCst), WEBHOOK_ATTEMPTS as usize);
+        assert_eq!(sink.failed(), 1);
+        assert!(sink.write(&Proxy::create("127.0.0.2", 8080, vec![]).await.unwrap()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resumed_json_file_stays_one_array() {
+        let path = std::env::temp_dir().join(format!("proxy-rs-resume-{}.json", std::process::id()));
+        let target = OutputTarget { path: path.clone(), format: None };
+        for (host, append) in [("127.0.0.1", false), ("127.0.0.2", true)] {
+            let sink = FileSink::open(&target, "json", None, append).await.unwrap();
+            sink.write(&Proxy::create(host, 8080, vec![]).await.unwrap()).await.unwrap();
+            sink.close().await.unwrap();
+        }
+
+        let written: Vec<serde_json::Value> = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let hosts: Vec<_> = written.iter().map(|proxy| proxy["host"].as_str().unwrap()).collect();
+        assert_eq!(hosts, ["127.0.0.1", "127.0.0.2"]);
+    }
+
+    #[tokio::test]
+    async fn test_empty_json_output_is_left_untouched() {
+        let mut writer = ProxyWriter::new(Vec::new(), "json");
+        writer.finish().await.unwrap();
+        assert!(writer.output.is_empty());
+    }
+}
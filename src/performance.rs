@@ -1,11 +1,36 @@
 //! Performance monitoring and optimization utilities
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
+use lazy_static::lazy_static;
 use tokio::sync::RwLock;
 use serde::{Deserialize, Serialize};
 
+use crate::utils::error::ProxyError;
+
+/// Number of recent response times kept for percentile computation
+const MAX_RESPONSE_TIME_SAMPLES: usize = 1000;
+
+/// File a running instance periodically writes its `PerformanceSummary` to,
+/// read back by the `stats` subcommand
+pub const DEFAULT_METRICS_FILE: &str = "proxy-rs-metrics.json";
+
+/// `DEFAULT_METRICS_FILE` in the user's data directory, in the current directory
+/// when there is no home to hold it
+pub fn default_metrics_file() -> PathBuf {
+    directories::ProjectDirs::from_path(option_env!("CARGO_PKG_NAME").unwrap_or("proxy-rs").into())
+        .map(|dirs| dirs.data_dir().join(DEFAULT_METRICS_FILE))
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_METRICS_FILE))
+}
+
+lazy_static! {
+    /// Process-wide monitor fed by the checker and read by the API
+    pub static ref PERFORMANCE_MONITOR: PerformanceMonitor = PerformanceMonitor::new();
+}
+
 /// Performance metrics collector
 #[derive(Debug, Clone)]
 pub struct PerformanceMonitor {
@@ -71,8 +96,50 @@ pub struct ProxyMetrics {
     pub dnsbl_rejections: u64,
     /// DNSBL rejection rate
     pub dnsbl_rejection_rate: f64,
-    /// Protocol success rates
+    /// Protocol success rates (exponential moving average)
     pub protocol_success_rates: HashMap<String, f64>,
+    /// Checks attempted per protocol
+    pub protocol_attempts: HashMap<String, u64>,
+    /// Successful checks per protocol
+    pub protocol_successes: HashMap<String, u64>,
+    /// Success rates by proxy country (ISO code)
+    pub country_success_rates: HashMap<String, f64>,
+}
+
+/// Raw counts and moving average success rate of one protocol
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProtocolStats {
+    pub attempts: u64,
+    pub successes: u64,
+    /// Exponential moving average, weighs recent checks more than `successes / attempts`
+    pub success_rate: f64,
+}
+
+impl ProxyMetrics {
+    /// Count a protocol check and update its moving average success rate
+    fn record_protocol(&mut self, protocol: &str, success: bool) {
+        *self.protocol_attempts.entry(protocol.to_string()).or_insert(0) += 1;
+        if success {
+            *self.protocol_successes.entry(protocol.to_string()).or_insert(0) += 1;
+        }
+        let rate = self.protocol_success_rates.entry(protocol.to_string()).or_insert(0.0);
+        *rate = (*rate * 0.9) + (if success { 1.0 } else { 0.0 } * 0.1); // Exponential moving average
+    }
+
+    /// Counts and success rate of every protocol checked so far
+    pub fn protocol_stats(&self) -> BTreeMap<String, ProtocolStats> {
+        self.protocol_attempts
+            .iter()
+            .map(|(protocol, &attempts)| {
+                let stats = ProtocolStats {
+                    attempts,
+                    successes: self.protocol_successes.get(protocol).copied().unwrap_or(0),
+                    success_rate: self.protocol_success_rates.get(protocol).copied().unwrap_or(0.0),
+                };
+                (protocol.clone(), stats)
+            })
+            .collect()
+    }
 }
 
 /// Network performance metrics
@@ -86,12 +153,26 @@ pub struct NetworkMetrics {
     pub failed_requests: u64,
     /// Average response time (milliseconds)
     pub avg_response_time_ms: f64,
+    /// Recent response times (milliseconds), bounded ring buffer
+    pub response_times: VecDeque<u64>,
     /// Connection pool utilization
     pub connection_pool_utilization: f64,
     /// DNS query times
     pub dns_query_times: Vec<u64>,
     /// Average DNS query time
     pub avg_dns_query_time_ms: f64,
+    /// Forwarded requests that had to open a new upstream connection
+    #[serde(default)]
+    pub cold_requests: u64,
+    /// Average response time of cold requests (milliseconds)
+    #[serde(default)]
+    pub avg_cold_response_time_ms: f64,
+    /// Forwarded requests served over a pooled connection
+    #[serde(default)]
+    pub warm_requests: u64,
+    /// Average response time of warm requests (milliseconds)
+    #[serde(default)]
+    pub avg_warm_response_time_ms: f64,
 }
 
 /// Cache performance metrics
@@ -186,8 +267,16 @@ impl PerformanceMonitor {
         }
     }
 
-    /// Record proxy check performance
-    pub async fn record_proxy_check(&self, duration: Duration, success: bool, dnsbl_rejected: bool) {
+    /// Record proxy check performance, `country` being the proxy's ISO code when known
+    /// and `protocols` the outcome of each protocol checked
+    pub async fn record_proxy_check(
+        &self,
+        duration: Duration,
+        success: bool,
+        dnsbl_rejected: bool,
+        country: Option<&str>,
+        protocols: &[(String, bool)],
+    ) {
         let mut metrics = self.metrics.write().await;
         let proxy = &mut metrics.proxy_metrics;
         
@@ -207,6 +296,15 @@ impl PerformanceMonitor {
             proxy.dnsbl_rejections += 1;
         }
         proxy.dnsbl_rejection_rate = proxy.dnsbl_rejections as f64 / proxy.total_checks as f64;
+
+        if let Some(country) = country.filter(|code| !code.is_empty() && *code != "--") {
+            let rate = proxy.country_success_rates.entry(country.to_string()).or_insert(0.0);
+            *rate = (*rate * 0.9) + (if success { 1.0 } else { 0.0 } * 0.1); // Exponential moving average
+        }
+
+        for (protocol, protocol_success) in protocols {
+            proxy.record_protocol(protocol, *protocol_success);
+        }
     }
 
     /// Record network request performance
@@ -225,6 +323,42 @@ impl PerformanceMonitor {
         
         // Update average response time
         network.avg_response_time_ms = (network.avg_response_time_ms * (network.total_requests - 1) as f64 + duration_ms as f64) / network.total_requests as f64;
+
+        if network.response_times.len() >= MAX_RESPONSE_TIME_SAMPLES {
+            network.response_times.pop_front();
+        }
+        network.response_times.push_back(duration_ms);
+    }
+
+    /// Record a forwarded request, also counting it as warm when its upstream
+    /// connection was `reused` from the pool and cold otherwise
+    pub async fn record_pooled_request(&self, duration: Duration, success: bool, reused: bool) {
+        self.record_network_request(duration, success).await;
+
+        let mut metrics = self.metrics.write().await;
+        let network = &mut metrics.network_metrics;
+        let (count, average) = if reused {
+            (&mut network.warm_requests, &mut network.avg_warm_response_time_ms)
+        } else {
+            (&mut network.cold_requests, &mut network.avg_cold_response_time_ms)
+        };
+        *count += 1;
+        *average = (*average * (*count - 1) as f64 + duration.as_millis() as f64) / *count as f64;
+    }
+
+    /// Median response time of recent network requests (milliseconds)
+    pub async fn p50(&self) -> u64 {
+        self.metrics.read().await.network_metrics.response_time_percentile(50.0)
+    }
+
+    /// 90th percentile response time of recent network requests (milliseconds)
+    pub async fn p90(&self) -> u64 {
+        self.metrics.read().await.network_metrics.response_time_percentile(90.0)
+    }
+
+    /// 99th percentile response time of recent network requests (milliseconds)
+    pub async fn p99(&self) -> u64 {
+        self.metrics.read().await.network_metrics.response_time_percentile(99.0)
     }
 
     /// Record DNS query performance
@@ -266,10 +400,7 @@ impl PerformanceMonitor {
     /// Record protocol success
     pub async fn record_protocol_success(&self, protocol: &str, success: bool) {
         let mut metrics = self.metrics.write().await;
-        let proxy = &mut metrics.proxy_metrics;
-        
-        let rate = proxy.protocol_success_rates.entry(protocol.to_string()).or_insert(0.0);
-        *rate = (*rate * 0.9) + (if success { 1.0 } else { 0.0 } * 0.1); // Exponential moving average
+        metrics.proxy_metrics.record_protocol(protocol, success);
     }
 
     /// Update system metrics
@@ -283,6 +414,12 @@ impl PerformanceMonitor {
         system.concurrent_operations = concurrent_operations;
     }
 
+    /// Zero every counter. Recording holds the same write lock, so an update
+    /// racing the reset is applied entirely before or entirely after it.
+    pub async fn reset(&self) {
+        *self.metrics.write().await = PerformanceMetrics::default();
+    }
+
     /// Get current metrics snapshot
     pub async fn get_metrics(&self) -> PerformanceMetrics {
         self.metrics.read().await.clone()
@@ -298,6 +435,11 @@ impl PerformanceMonitor {
             proxy_throughput: self.calculate_proxy_throughput(&metrics),
             network_performance: self.calculate_network_performance(&metrics),
             cache_efficiency: metrics.cache_metrics.hit_rate,
+            response_time_p50_ms: metrics.network_metrics.response_time_percentile(50.0),
+            response_time_p90_ms: metrics.network_metrics.response_time_percentile(90.0),
+            response_time_p99_ms: metrics.network_metrics.response_time_percentile(99.0),
+            cold_response_time_ms: metrics.network_metrics.avg_cold_response_time_ms,
+            warm_response_time_ms: metrics.network_metrics.avg_warm_response_time_ms,
             recommendations: self.generate_recommendations(&metrics),
         }
     }
@@ -430,10 +572,92 @@ pub struct PerformanceSummary {
     pub network_performance: f64,
     /// Cache efficiency (0-1)
     pub cache_efficiency: f64,
+    /// Median network response time (milliseconds)
+    pub response_time_p50_ms: u64,
+    /// 90th percentile network response time (milliseconds)
+    pub response_time_p90_ms: u64,
+    /// 99th percentile network response time (milliseconds)
+    pub response_time_p99_ms: u64,
+    /// Average response time of requests that opened a new connection (milliseconds)
+    #[serde(default)]
+    pub cold_response_time_ms: f64,
+    /// Average response time of requests over a pooled connection (milliseconds)
+    #[serde(default)]
+    pub warm_response_time_ms: f64,
     /// Performance recommendations
     pub recommendations: Vec<String>,
 }
 
+impl PerformanceSummary {
+    /// Write the summary as JSON, through a temporary file so readers never see a partial one
+    pub async fn save(&self, path: &Path) -> Result<(), ProxyError> {
+        let content = serde_json::to_string_pretty(self)?;
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| ProxyError::Config(format!("Failed to create metrics directory: {}", e)))?;
+        }
+        let tmp_path = path.with_extension("tmp");
+        tokio::fs::write(&tmp_path, content)
+            .await
+            .map_err(|e| ProxyError::Config(format!("Failed to write metrics file: {}", e)))?;
+        tokio::fs::rename(&tmp_path, path)
+            .await
+            .map_err(|e| ProxyError::Config(format!("Failed to write metrics file: {}", e)))
+    }
+
+    /// Read a summary written by `save`
+    pub fn load(path: &Path) -> Result<Self, ProxyError> {
+        let content = std::fs::read_to_string(path).map_err(|e| {
+            ProxyError::Config(format!("Cannot read metrics file {:?} (is proxy-rs running?): {}", path, e))
+        })?;
+        Ok(serde_json::from_str(&content)?)
+    }
+}
+
+impl fmt::Display for PerformanceSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Overall score:       {:.1}/100", self.overall_score)?;
+        writeln!(f, "DNSBL efficiency:    {:.1}/100", self.dnsbl_efficiency)?;
+        writeln!(f, "Proxy throughput:    {:.1} checks/min", self.proxy_throughput)?;
+        writeln!(f, "Network performance: {:.1}/100", self.network_performance)?;
+        writeln!(f, "Cache efficiency:    {:.1}%", self.cache_efficiency * 100.0)?;
+        writeln!(
+            f,
+            "Response time:       p50 {}ms, p90 {}ms, p99 {}ms",
+            self.response_time_p50_ms, self.response_time_p90_ms, self.response_time_p99_ms
+        )?;
+        writeln!(
+            f,
+            "Connection reuse:    cold {:.0}ms, warm {:.0}ms",
+            self.cold_response_time_ms, self.warm_response_time_ms
+        )?;
+        if self.recommendations.is_empty() {
+            write!(f, "Recommendations:     none")
+        } else {
+            write!(f, "Recommendations:")?;
+            for recommendation in &self.recommendations {
+                write!(f, "\n  - {}", recommendation)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+impl NetworkMetrics {
+    /// Nearest-rank percentile of recent response times, 0 when no samples
+    pub fn response_time_percentile(&self, percentile: f64) -> u64 {
+        if self.response_times.is_empty() {
+            return 0;
+        }
+        let mut sorted: Vec<u64> = self.response_times.iter().copied().collect();
+        sorted.sort_unstable();
+
+        let rank = ((percentile / 100.0) * sorted.len() as f64).ceil() as usize;
+        sorted[rank.clamp(1, sorted.len()) - 1]
+    }
+}
+
 impl PerformanceMetrics {
     /// Add async task execution time (for async_optimizer compatibility)
     pub fn add_async_task_time(&mut self, time_ms: f64) {
@@ -485,7 +709,7 @@ impl PerformanceTimer {
                 self.monitor.record_dnsbl_result(true, false).await;
             }
             OperationType::ProxyCheck => {
-                self.monitor.record_proxy_check(duration, true, false).await;
+                self.monitor.record_proxy_check(duration, true, false, None, &[]).await;
             }
             OperationType::NetworkRequest => {
                 self.monitor.record_network_request(duration, true).await;
@@ -523,7 +747,7 @@ impl PerformanceTimer {
                     })
                     .unwrap_or(false);
                 
-                self.monitor.record_proxy_check(duration, success, dnsbl_rejected).await;
+                self.monitor.record_proxy_check(duration, success, dnsbl_rejected, None, &[]).await;
             }
             OperationType::NetworkRequest => {
                 self.monitor.record_network_request(duration, success).await;
@@ -602,7 +826,7 @@ mod tests {
         
         // Record some test data
         monitor.record_dnsbl_check(Duration::from_millis(50), 2, true).await;
-        monitor.record_proxy_check(Duration::from_millis(200), true, false).await;
+        monitor.record_proxy_check(Duration::from_millis(200), true, false, None, &[]).await;
         monitor.record_cache_operation(true, Duration::from_micros(100)).await;
         
         let summary = monitor.get_performance_summary().await;
@@ -617,10 +841,185 @@ mod tests {
         
         // Record slow performance to trigger recommendations
         monitor.record_dnsbl_check(Duration::from_millis(300), 5, false).await;
-        monitor.record_proxy_check(Duration::from_millis(6000), true, false).await;
+        monitor.record_proxy_check(Duration::from_millis(6000), true, false, None, &[]).await;
         
         let summary = monitor.get_performance_summary().await;
         assert!(!summary.recommendations.is_empty());
         assert!(summary.recommendations.iter().any(|r| r.contains("DNSBL timeout")));
     }
+
+    #[tokio::test]
+    async fn test_response_time_percentiles() {
+        let monitor = PerformanceMonitor::new();
+        assert_eq!(monitor.p50().await, 0);
+
+        for ms in (1..=100).rev() {
+            monitor.record_network_request(Duration::from_millis(ms), true).await;
+        }
+
+        assert_eq!(monitor.p50().await, 50);
+        assert_eq!(monitor.p90().await, 90);
+        assert_eq!(monitor.p99().await, 99);
+
+        let summary = monitor.get_performance_summary().await;
+        assert_eq!(summary.response_time_p50_ms, 50);
+        assert_eq!(summary.response_time_p99_ms, 99);
+    }
+
+    #[tokio::test]
+    async fn test_response_times_are_bounded() {
+        let monitor = PerformanceMonitor::new();
+        for _ in 0..MAX_RESPONSE_TIME_SAMPLES {
+            monitor.record_network_request(Duration::from_millis(1000), true).await;
+        }
+        for _ in 0..MAX_RESPONSE_TIME_SAMPLES {
+            monitor.record_network_request(Duration::from_millis(10), true).await;
+        }
+
+        let metrics = monitor.get_metrics().await;
+        assert_eq!(metrics.network_metrics.response_times.len(), MAX_RESPONSE_TIME_SAMPLES);
+        assert_eq!(monitor.p99().await, 10);
+    }
+
+    #[tokio::test]
+    async fn test_cold_and_warm_requests() {
+        let monitor = PerformanceMonitor::new();
+        monitor.record_pooled_request(Duration::from_millis(300), true, false).await;
+        monitor.record_pooled_request(Duration::from_millis(40), true, true).await;
+
+        let network = monitor.get_metrics().await.network_metrics;
+        assert_eq!(network.total_requests, 2);
+        assert_eq!((network.cold_requests, network.warm_requests), (1, 1));
+
+        let summary = monitor.get_performance_summary().await;
+        assert_eq!(summary.cold_response_time_ms, 300.0);
+        assert_eq!(summary.warm_response_time_ms, 40.0);
+    }
+
+    #[tokio::test]
+    async fn test_protocol_counts_match_rate() {
+        let monitor = PerformanceMonitor::new();
+        let checks = [
+            vec![("HTTP".to_string(), true), ("SOCKS5".to_string(), false)],
+            vec![("HTTP".to_string(), true)],
+            vec![("HTTP".to_string(), false), ("SOCKS5".to_string(), false)],
+        ];
+        for protocols in &checks {
+            monitor.record_proxy_check(Duration::from_millis(100), true, false, None, protocols).await;
+        }
+        monitor.record_protocol_success("HTTP", true).await;
+
+        let stats = monitor.get_metrics().await.proxy_metrics.protocol_stats();
+        assert_eq!(stats.len(), 2);
+        let http = stats["HTTP"];
+        assert_eq!((http.attempts, http.successes), (4, 3));
+        // true, true, false, true
+        let expected = [1.0, 1.0, 0.0, 1.0].iter().fold(0.0, |rate, success| rate * 0.9 + success * 0.1);
+        assert!((http.success_rate - expected).abs() < 1e-9);
+
+        let socks5 = stats["SOCKS5"];
+        assert_eq!((socks5.attempts, socks5.successes), (2, 0));
+        assert_eq!(socks5.success_rate, 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_country_success_rates() {
+        let monitor = PerformanceMonitor::new();
+        for _ in 0..10 {
+            monitor.record_proxy_check(Duration::from_millis(100), true, false, Some("FR"), &[]).await;
+            monitor.record_proxy_check(Duration::from_millis(100), false, false, Some("US"), &[]).await;
+        }
+        monitor.record_proxy_check(Duration::from_millis(100), true, false, Some("US"), &[]).await;
+        monitor.record_proxy_check(Duration::from_millis(100), true, false, Some("--"), &[]).await;
+
+        let rates = monitor.get_metrics().await.proxy_metrics.country_success_rates;
+        let fr = rates["FR"];
+        let us = rates["US"];
+        assert!((fr - (1.0 - 0.9f64.powi(10))).abs() < 1e-9);
+        assert!((us - 0.1).abs() < 1e-9);
+        assert!(fr > us);
+        // Unknown locations are not tracked
+        assert_eq!(rates.len(), 2);
+    }
+
+    #[test]
+    fn test_summary_rendering() {
+        let summary = PerformanceSummary {
+            overall_score: 72.5,
+            dnsbl_efficiency: 80.0,
+            proxy_throughput: 120.0,
+            network_performance: 65.25,
+            cache_efficiency: 0.5,
+            response_time_p50_ms: 40,
+            response_time_p90_ms: 90,
+            response_time_p99_ms: 150,
+            cold_response_time_ms: 180.0,
+            warm_response_time_ms: 35.4,
+            recommendations: vec!["Increase DNSBL cache TTL for better hit rates".to_string()],
+        };
+
+        let text = summary.to_string();
+        assert!(text.contains("Overall score:       72.5/100"));
+        assert!(text.contains("Proxy throughput:    120.0 checks/min"));
+        assert!(text.contains("Cache efficiency:    50.0%"));
+        assert!(text.contains("p50 40ms, p90 90ms, p99 150ms"));
+        assert!(text.contains("cold 180ms, warm 35ms"));
+        assert!(text.ends_with("  - Increase DNSBL cache TTL for better hit rates"));
+
+        let json: serde_json::Value = serde_json::from_str(&serde_json::to_string_pretty(&summary).unwrap()).unwrap();
+        assert_eq!(json["overall_score"], 72.5);
+        assert_eq!(json["recommendations"][0], "Increase DNSBL cache TTL for better hit rates");
+
+        let empty = PerformanceSummary { recommendations: vec![], ..summary };
+        assert!(empty.to_string().ends_with("Recommendations:     none"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_reset_zeroes_counters() {
+        let monitor = PerformanceMonitor::new();
+        let recorders: Vec<_> = (0..4)
+            .map(|_| {
+                let monitor = monitor.clone();
+                tokio::spawn(async move {
+                    for _ in 0..200 {
+                        monitor.record_network_request(Duration::from_millis(10), true).await;
+                    }
+                })
+            })
+            .collect();
+        monitor.reset().await;
+        for recorder in recorders {
+            recorder.await.unwrap();
+        }
+
+        // Every request landed wholly before or after the reset
+        let network = monitor.get_metrics().await.network_metrics;
+        assert!(network.total_requests <= 800);
+        assert_eq!(network.total_requests, network.successful_requests);
+
+        monitor.record_dnsbl_check(Duration::from_millis(100), 3, false).await;
+        monitor.reset().await;
+        let metrics = monitor.get_metrics().await;
+        assert_eq!(metrics.network_metrics.total_requests, 0);
+        assert_eq!(metrics.network_metrics.response_time_percentile(50.0), 0);
+        assert_eq!(metrics.dnsbl_metrics.total_checks, 0);
+    }
+
+    #[tokio::test]
+    async fn test_summary_snapshot_reloads() {
+        let monitor = PerformanceMonitor::new();
+        monitor.record_network_request(Duration::from_millis(40), true).await;
+        let summary = monitor.get_performance_summary().await;
+
+        // The data directory is created on the first write
+        let dir = std::env::temp_dir().join(format!("proxy-rs-data-{}", std::process::id()));
+        let path = dir.join(DEFAULT_METRICS_FILE);
+        summary.save(&path).await.unwrap();
+        let loaded = PerformanceSummary::load(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(loaded.response_time_p50_ms, 40);
+        assert_eq!(loaded.overall_score, summary.overall_score);
+        assert_eq!(loaded.recommendations, summary.recommendations);
+    }
 }
@@ -1,5 +1,5 @@
 use async_recursion::async_recursion;
-use std::time::Duration;
+use futures_util::future::BoxFuture;
 
 use hyper::{header::LOCATION, Request};
 use hyper_util::client::legacy::{Client, connect::HttpConnector};
@@ -8,11 +8,26 @@ use http_body_util::{Empty, BodyExt};
 use regex::Regex;
 use tokio::time::timeout;
 
-use crate::utils::{
-    http::{hyper_client, random_useragent},
-    vec_of_strings,
+use crate::{
+    providers::{fetch_retry, retry_fetch, FetchError},
+    proxy::Proxy,
+    utils::{
+        http::{hyper_client, random_useragent},
+        vec_of_strings,
+    },
 };
 
+use super::UNIQUE_PROXIES;
+
+/// Source of proxies polled by `run_all_providers`
+pub trait ProxyProvider: Send + Sync {
+    /// Name used in logs
+    fn name(&self) -> &str;
+
+    /// Fetch the proxies currently offered by this source
+    fn fetch(&self) -> BoxFuture<'_, Vec<Proxy>>;
+}
+
 #[derive(Debug, Clone)]
 pub struct Provider {
     pub url: &'static str,
@@ -21,7 +36,6 @@ pub struct Provider {
     pub pattern: &'static str,
     pub proto: Vec<String>,
     pub name: &'static str,
-    pub timeout: i32,
 }
 
 impl Default for Provider {
@@ -32,7 +46,6 @@ impl Default for Provider {
             name: "",
             new_urls: None,
             max_depth: 1,
-            timeout: 5,
             proto: vec_of_strings![
                 "HTTP",
                 "HTTPS",
@@ -64,29 +77,41 @@ impl ProviderTask {
             .unwrap()
     }
 
-    #[async_recursion]
-    async fn get_html(&self, request: Request<Empty<bytes::Bytes>>) -> String {
-        if let Ok(Ok(response)) = timeout(
-            Duration::from_secs(self.base.timeout as u64),
-            self.client.request(request),
-        )
-        .await
-        {
-            let (part, body) = response.into_parts();
-            let location = part.headers.get(LOCATION);
-            if let Some(redirect_url) = location {
-                let redirect_url = redirect_url.to_str().unwrap();
-                let request = self.build_get_request(redirect_url);
-                return self.get_html(request).await;
+    /// Page at `uri`, retried as the global fetch policy says; empty when it cannot be had
+    async fn get_html(&self, uri: &str) -> String {
+        let policy = fetch_retry();
+        let attempt = || async {
+            timeout(policy.timeout, self.fetch_page(uri.to_string()))
+                .await
+                .unwrap_or(Err(FetchError::Timeout))
+        };
+        match retry_fetch(&policy, attempt).await {
+            Ok(html) => html,
+            Err(e) => {
+                log::debug!("Provider {} could not fetch {}: {}", self.base.name, uri, e);
+                String::new()
             }
+        }
+    }
 
-            if let Ok(body) = body.collect().await {
-                let body_bytes = body.to_bytes();
-                let body_str = String::from_utf8_lossy(&body_bytes);
-                return body_str.to_string();
-            }
+    /// One attempt at the page at `uri`, following redirects
+    #[async_recursion]
+    async fn fetch_page(&self, uri: String) -> Result<String, FetchError> {
+        let response = self
+            .client
+            .request(self.build_get_request(&uri))
+            .await
+            .map_err(|e| FetchError::Connect(e.to_string()))?;
+        let (part, body) = response.into_parts();
+        if let Some(redirect_url) = part.headers.get(LOCATION).and_then(|location| location.to_str().ok()) {
+            return self.fetch_page(redirect_url.to_string()).await;
+        }
+        if !part.status.is_success() {
+            return Err(FetchError::Status(part.status.as_u16()));
         }
-        String::new()
+
+        let body = body.collect().await.map_err(|e| FetchError::Connect(e.to_string()))?;
+        Ok(String::from_utf8_lossy(&body.to_bytes()).to_string())
     }
 
     pub async fn get_proxies(&self) -> Vec<(String, u16, Vec<String>)> {
@@ -97,8 +122,7 @@ impl ProviderTask {
         let re = Regex::new(self.base.pattern).unwrap();
 
         while let Some(url) = urls.pop() {
-            let request = self.build_get_request(&url);
-            let html = self.get_html(request).await;
+            let html = self.get_html(&url).await;
 
             if depth < self.base.max_depth {
                 if let Some(find_urls) = self.base.new_urls {
@@ -129,3 +153,26 @@ impl ProviderTask {
         all_proxies
     }
 }
+
+impl ProxyProvider for Provider {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn fetch(&self) -> BoxFuture<'_, Vec<Proxy>> {
+        Box::pin(async move {
+            let found = ProviderTask::new(self.clone()).get_proxies().await;
+            let mut proxies = Vec::with_capacity(found.len());
+            for (ip, port, proto) in found {
+                // Skip known proxies before paying for the geo lookup
+                if UNIQUE_PROXIES.read().await.contains(&format!("{}:{}", ip, port)) {
+                    continue;
+                }
+                if let Some(proxy) = Proxy::create(&ip, port, proto).await {
+                    proxies.push(proxy);
+                }
+            }
+            proxies
+        })
+    }
+}
@@ -1,25 +1,426 @@
 pub mod base_provider;
+pub mod queue;
 
-use std::{collections::HashSet, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
-use concurrent_queue::ConcurrentQueue;
-use futures_util::{stream::FuturesUnordered, StreamExt};
+use futures_util::{
+    future::{BoxFuture, Shared},
+    stream::FuturesUnordered,
+    FutureExt, StreamExt,
+};
 use lazy_static::lazy_static;
-use rand::{seq::SliceRandom, thread_rng};
+use parking_lot::Mutex;
+use rand::{seq::SliceRandom, thread_rng, Rng};
 use regex::Regex;
+use serde::Serialize;
 use tokio::sync::{RwLock, Semaphore};
 
-use crate::{proxy::Proxy, utils::vec_of_strings};
+use crate::{proxy::Proxy, utils::{memory_guard, vec_of_strings}};
 
-use self::base_provider::{Provider, ProviderTask};
+use self::base_provider::Provider;
+use self::queue::ProxyQueue;
+pub use self::base_provider::ProxyProvider;
 
 // Rate limiting configuration
 const PROVIDER_RATE_LIMIT_DELAY_MS: u64 = 500;  // 500ms between provider requests
 const MAX_CONCURRENT_PROVIDERS: usize = 3;     // Max providers running simultaneously
+const MAX_BACKOFF_EXPONENT: u32 = 4;            // Empty cycles stop doubling the wait after 2^4
+const QUEUE_POLL_INTERVAL_MS: u64 = 200;        // How often a paused provider loop checks the queue
+
+/// Refresh in flight that later refreshes join
+type RefreshSlot = Mutex<Option<Shared<BoxFuture<'static, usize>>>>;
+
+/// Set once enough proxies were collected, providers stop fetching
+static STOP_FETCHING: AtomicBool = AtomicBool::new(false);
+
+/// Stop launching provider fetches and discard results of fetches in flight
+pub fn stop_fetching() {
+    STOP_FETCHING.store(true, Ordering::Relaxed);
+}
+
+pub fn is_fetching_stopped() -> bool {
+    STOP_FETCHING.load(Ordering::Relaxed)
+}
+
+/// Undo `stop_fetching` once a test is done with it
+#[cfg(test)]
+pub fn resume_fetching() {
+    STOP_FETCHING.store(false, Ordering::Relaxed);
+}
+
+#[cfg(test)]
+lazy_static! {
+    /// Held by tests that fetch or stop fetching, the stop flag is process-wide
+    pub static ref FETCH_TEST: tokio::sync::Mutex<()> = tokio::sync::Mutex::new(());
+}
 
 lazy_static! {
-    pub static ref PROXIES: ConcurrentQueue<Proxy> = ConcurrentQueue::unbounded();
+    pub static ref PROXIES: ProxyQueue = ProxyQueue::new();
     pub static ref UNIQUE_PROXIES: Arc<RwLock<HashSet<String>>> = Arc::new(RwLock::new(HashSet::new()));
+    /// Circuit breakers of the providers that failed at least once since their last
+    /// success, they also hold when the refresh cycle polls them again
+    static ref BREAKERS: Mutex<HashMap<String, CircuitBreaker>> = Mutex::new(HashMap::new());
+    /// On-demand refresh in flight, joined by the refreshes asked for meanwhile
+    static ref REFRESH: RefreshSlot = Mutex::new(None);
+    /// Retries of provider page fetches, set from the configuration
+    static ref FETCH_RETRY: Mutex<FetchRetryPolicy> = Mutex::new(FetchRetryPolicy::default());
+    /// What each provider yielded since startup
+    static ref PROVIDER_STATS: Mutex<HashMap<String, ProviderStats>> = Mutex::new(HashMap::new());
+    static ref REGISTRY: Mutex<Vec<Arc<dyn ProxyProvider>>> = Mutex::new(
+        providers()
+            .into_iter()
+            .map(|provider| Arc::new(provider) as Arc<dyn ProxyProvider>)
+            .collect()
+    );
+}
+
+/// How often providers are polled
+#[derive(Debug, Clone, Copy)]
+pub struct RefreshPolicy {
+    pub interval: Duration,
+    /// Random spread around the interval, in percent of it
+    pub jitter_percent: u64,
+}
+
+impl RefreshPolicy {
+    pub fn new(interval: Duration, jitter_percent: u64) -> Self {
+        Self {
+            interval,
+            jitter_percent: jitter_percent.min(100),
+        }
+    }
+
+    /// Wait before the next poll: the interval doubled for each consecutive failure
+    /// (up to 2^4 times) and moved by a random ±`jitter_percent`
+    pub fn next_interval(&self, failures: u32) -> Duration {
+        let base = self.interval * 2u32.pow(failures.min(MAX_BACKOFF_EXPONENT));
+        if self.jitter_percent == 0 {
+            return base;
+        }
+        let jitter = self.jitter_percent as f64 / 100.0;
+        base.mul_f64(thread_rng().gen_range(1.0 - jitter..=1.0 + jitter))
+    }
+}
+
+/// Fill levels of the check queue between which providers pause
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueWatermarks {
+    /// Queued proxies at which fetching pauses
+    pub high: usize,
+    /// Queued proxies below which fetching resumes
+    pub low: usize,
+}
+
+impl QueueWatermarks {
+    pub fn new(high: usize, low: usize) -> Self {
+        Self {
+            high: high.max(1),
+            low: low.min(high.max(1) - 1),
+        }
+    }
+
+    /// Wait while `queue` is at the high watermark or above, until it drains below the
+    /// low one, so freshly fetched proxies are not piled onto checks that cannot keep up
+    pub async fn wait_for_room(&self, queued: impl Fn() -> usize) {
+        if queued() < self.high {
+            return;
+        }
+        log::info!("{} proxies waiting for checks, pausing providers", queued());
+        while queued() >= self.low.max(1) && !is_fetching_stopped() {
+            tokio::time::sleep(Duration::from_millis(QUEUE_POLL_INTERVAL_MS)).await;
+        }
+        log::info!("Check queue drained to {} proxies, resuming providers", queued());
+    }
+}
+
+impl Default for QueueWatermarks {
+    fn default() -> Self {
+        Self::new(50_000, 25_000)
+    }
+}
+
+/// When a repeatedly failing provider is given up on for a while
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BreakerPolicy {
+    /// Consecutive failures that open the breaker
+    pub failure_threshold: u32,
+    /// How long an open breaker skips the provider before letting one probe through
+    pub cooldown: Duration,
+}
+
+impl BreakerPolicy {
+    pub fn new(failure_threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            cooldown,
+        }
+    }
+}
+
+impl Default for BreakerPolicy {
+    fn default() -> Self {
+        Self::new(3, Duration::from_secs(600))
+    }
+}
+
+/// How a provider page fetch that failed for a passing reason is retried
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FetchRetryPolicy {
+    /// Attempts after the first one
+    pub max_retries: u32,
+    /// Wait before the first retry, doubled for each later one
+    pub backoff: Duration,
+    /// Limit of a single attempt, redirects included
+    pub timeout: Duration,
+}
+
+impl FetchRetryPolicy {
+    pub fn new(max_retries: u32, backoff: Duration, timeout: Duration) -> Self {
+        Self {
+            max_retries,
+            backoff,
+            timeout: timeout.max(Duration::from_millis(1)),
+        }
+    }
+
+    /// Wait before retry number `retry`, counted from 0
+    fn backoff_for(&self, retry: u32) -> Duration {
+        self.backoff * 2u32.pow(retry.min(MAX_BACKOFF_EXPONENT))
+    }
+}
+
+impl Default for FetchRetryPolicy {
+    fn default() -> Self {
+        Self::new(2, Duration::from_millis(500), Duration::from_secs(5))
+    }
+}
+
+/// Apply `policy` to the provider fetches started from now on
+pub fn set_fetch_retry(policy: FetchRetryPolicy) {
+    *FETCH_RETRY.lock() = policy;
+}
+
+pub fn fetch_retry() -> FetchRetryPolicy {
+    *FETCH_RETRY.lock()
+}
+
+/// Why a provider page fetch failed
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum FetchError {
+    #[error("timed out")]
+    Timeout,
+    #[error("connection failed: {0}")]
+    Connect(String),
+    #[error("HTTP status {0}")]
+    Status(u16),
+}
+
+impl FetchError {
+    /// Whether another attempt may succeed: timeouts, connection errors and 5xx
+    /// answers are transient, a 4xx would be answered the same way again
+    pub fn is_retriable(&self) -> bool {
+        match self {
+            FetchError::Timeout | FetchError::Connect(_) => true,
+            FetchError::Status(status) => *status >= 500,
+        }
+    }
+}
+
+/// Run `attempt` until it succeeds, fails for good or `policy` runs out of retries
+pub async fn retry_fetch<T, F, Fut>(policy: &FetchRetryPolicy, mut attempt: F) -> Result<T, FetchError>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T, FetchError>>,
+{
+    let mut retries = 0;
+    loop {
+        match attempt().await {
+            Err(e) if e.is_retriable() && retries < policy.max_retries => {
+                let wait = policy.backoff_for(retries);
+                log::debug!("Provider fetch {}, retrying in {:?}", e, wait);
+                tokio::time::sleep(wait).await;
+                retries += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    /// The provider is polled normally
+    Closed,
+    /// The provider is skipped until the cooldown ends
+    Open,
+    /// The cooldown ended, a single probe decides whether to close or reopen
+    HalfOpen,
+}
+
+#[derive(Debug, Clone)]
+struct CircuitBreaker {
+    state: BreakerState,
+    failures: u32,
+    /// When the breaker last opened or let its probe through
+    changed_at: Instant,
+    /// The refresh cycle skips the provider until then, backing off after each failure
+    next_poll: Instant,
+}
+
+impl CircuitBreaker {
+    fn new() -> Self {
+        let now = Instant::now();
+        Self {
+            state: BreakerState::Closed,
+            failures: 0,
+            changed_at: now,
+            next_poll: now,
+        }
+    }
+
+    /// Whether the provider may be polled, an open breaker past its cooldown lets
+    /// this one poll through as the probe. A probe not reported within the cooldown
+    /// counts as failed and opens the breaker again.
+    fn allow(&mut self, policy: &BreakerPolicy, now: Instant) -> bool {
+        let cooled_down = now.duration_since(self.changed_at) >= policy.cooldown;
+        match self.state {
+            BreakerState::Closed => true,
+            BreakerState::Open if cooled_down => {
+                self.state = BreakerState::HalfOpen;
+                self.changed_at = now;
+                true
+            }
+            BreakerState::HalfOpen if cooled_down => {
+                self.state = BreakerState::Open;
+                self.changed_at = now;
+                false
+            }
+            BreakerState::Open | BreakerState::HalfOpen => false,
+        }
+    }
+
+    /// Record a poll outcome, returning the new state when it changed
+    fn record(&mut self, success: bool, policy: &BreakerPolicy, now: Instant) -> Option<BreakerState> {
+        let previous = self.state;
+        if success {
+            self.failures = 0;
+            self.state = BreakerState::Closed;
+        } else {
+            self.failures += 1;
+            if self.state == BreakerState::HalfOpen || self.failures >= policy.failure_threshold {
+                self.state = BreakerState::Open;
+                self.changed_at = now;
+            }
+        }
+        (self.state != previous).then_some(self.state)
+    }
+}
+
+/// Whether the refresh cycle may poll provider `name` now: it is not backing off
+/// and its breaker lets it through
+fn breaker_allows(name: &str, policy: &BreakerPolicy) -> bool {
+    let now = Instant::now();
+    BREAKERS
+        .lock()
+        .get_mut(name)
+        .is_none_or(|breaker| breaker.next_poll <= now && breaker.allow(policy, now))
+}
+
+/// Feed a poll outcome of provider `name` to its breaker, logging state changes.
+/// With a `refresh` policy a failure also postpones the provider's next scheduled poll.
+fn record_poll(name: &str, success: bool, policy: &BreakerPolicy, refresh: Option<RefreshPolicy>) {
+    let mut breakers = BREAKERS.lock();
+    if success && !breakers.contains_key(name) {
+        return;
+    }
+    let now = Instant::now();
+    let breaker = breakers.entry(name.to_string()).or_insert_with(CircuitBreaker::new);
+    match breaker.record(success, policy, now) {
+        Some(BreakerState::Open) => log::warn!(
+            "Provider {} failed {} times in a row, skipping it for {:?}",
+            name,
+            breaker.failures,
+            policy.cooldown
+        ),
+        Some(BreakerState::Closed) => log::info!("Provider {} recovered", name),
+        _ => {}
+    }
+    if let (false, Some(refresh)) = (success, refresh) {
+        let wait = refresh.next_interval(breaker.failures);
+        log::debug!("Provider {} returned nothing, next poll in {:?}", name, wait);
+        breaker.next_poll = now + wait;
+    }
+    if breaker.state == BreakerState::Closed && success {
+        breakers.remove(name);
+    }
+}
+
+/// Breaker state of every provider that failed since its last success, providers
+/// not listed are closed
+pub fn breaker_states() -> BTreeMap<String, BreakerState> {
+    BREAKERS
+        .lock()
+        .iter()
+        .map(|(name, breaker)| (name.clone(), breaker.state))
+        .collect()
+}
+
+/// What a provider yielded since startup
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ProviderStats {
+    /// Proxies returned by its fetches
+    pub fetched: u64,
+    /// Proxies no provider had returned before
+    pub unique: u64,
+    /// Unique proxies a check then found working
+    pub working: u64,
+}
+
+impl ProviderStats {
+    /// Share of the unique proxies not found working, proxies still unchecked included
+    pub fn dead_rate(&self) -> f64 {
+        if self.unique == 0 {
+            return 0.0;
+        }
+        1.0 - self.working.min(self.unique) as f64 / self.unique as f64
+    }
+}
+
+/// Statistics of every provider polled since startup
+pub fn provider_stats() -> BTreeMap<String, ProviderStats> {
+    PROVIDER_STATS
+        .lock()
+        .iter()
+        .map(|(name, stats)| (name.clone(), *stats))
+        .collect()
+}
+
+/// Credit the provider of `proxy`, if it came from one, with a working proxy
+pub fn record_working(proxy: &Proxy) {
+    if let Some(name) = &proxy.provider {
+        PROVIDER_STATS.lock().entry(name.clone()).or_default().working += 1;
+    }
+}
+
+/// Add a custom source, polled alongside the built-in providers from the next cycle
+#[allow(dead_code)] // Library API, the binary only polls the built-in providers
+pub fn register_provider(provider: Box<dyn ProxyProvider>) {
+    log::info!("Registered provider {}", provider.name());
+    REGISTRY.lock().push(Arc::from(provider));
+}
+
+/// Built-in and registered providers, in random order
+pub fn registered_providers() -> Vec<Arc<dyn ProxyProvider>> {
+    let mut providers = REGISTRY.lock().clone();
+    providers.shuffle(&mut thread_rng());
+    providers
 }
 
 pub fn providers() -> Vec<Provider> {
@@ -253,10 +654,12 @@ pub fn providers() -> Vec<Provider> {
     providers
 }
 
-async fn update_stack(name: &'static str, proxies: &Vec<(String, u16, Vec<String>)>) {
+async fn update_stack(name: &str, proxies: Vec<Proxy>) -> usize {
+    let found = proxies.len();
     let mut added = 0;
-    for (ip, port, proto) in proxies {
-        let host_port = format!("{}:{}", ip, port);
+    for mut proxy in proxies {
+        let host_port = proxy.as_text();
+        proxy.provider = Some(name.to_string());
 
         {
             let mut unique_proxies = UNIQUE_PROXIES.write().await;
@@ -266,54 +669,424 @@ async fn update_stack(name: &'static str, proxies: &Vec<(String, u16, Vec<String
             unique_proxies.insert(host_port.clone());
         }
 
-        if let Some(proxy) = Proxy::create(ip, *port, proto.to_vec()).await {
-            if PROXIES.push(proxy).is_ok() {
-                added += 1;
-            } else {
-                // If push failed, remove from unique_proxies to maintain consistency
-                UNIQUE_PROXIES.write().await.remove(&host_port);
-            };
-        }
+        if PROXIES.push(proxy).is_ok() {
+            added += 1;
+        } else {
+            // If push failed, remove from unique_proxies to maintain consistency
+            UNIQUE_PROXIES.write().await.remove(&host_port);
+        };
+    }
+    log::debug!("{} of {} proxies added from {}", added, found, name);
+    let mut stats = PROVIDER_STATS.lock();
+    let stats = stats.entry(name.to_string()).or_default();
+    stats.fetched += found as u64;
+    stats.unique += added as u64;
+    added
+}
+
+/// Poll every provider not backing off and whose breaker is not open, an empty
+/// result postpones the provider's next poll and counts towards tripping its breaker.
+/// Returns the number of new proxies queued.
+pub async fn run_all_providers(
+    num_conn: usize,
+    policy: RefreshPolicy,
+    watermarks: QueueWatermarks,
+    breaker: BreakerPolicy,
+) -> usize {
+    let provider_list: Vec<_> = registered_providers()
+        .into_iter()
+        .filter(|provider| breaker_allows(provider.name(), &breaker))
+        .collect();
+    run_providers(provider_list, num_conn, Some(policy), watermarks, breaker).await
+}
+
+/// Run `run_all_providers` once now, outside the refresh cycle
+pub async fn refresh_all_providers(
+    num_conn: usize,
+    policy: RefreshPolicy,
+    watermarks: QueueWatermarks,
+    breaker: BreakerPolicy,
+) -> usize {
+    coalesce(&REFRESH, run_all_providers(num_conn, policy, watermarks, breaker).boxed()).await
+}
+
+/// Poll `provider_list` once now, ignoring backoff
+pub async fn refresh_providers(
+    provider_list: Vec<Arc<dyn ProxyProvider>>,
+    num_conn: usize,
+    watermarks: QueueWatermarks,
+    breaker: BreakerPolicy,
+) -> usize {
+    coalesce(&REFRESH, run_providers(provider_list, num_conn, None, watermarks, breaker).boxed()).await
+}
+
+/// Await `refresh`, or the refresh already in flight in `slot` which then reports
+/// its count to every caller instead of starting another one
+async fn coalesce(slot: &RefreshSlot, refresh: BoxFuture<'static, usize>) -> usize {
+    let running = slot.lock().get_or_insert_with(|| refresh.shared()).clone();
+    let added = running.clone().await;
+    let mut current = slot.lock();
+    if current.as_ref().is_some_and(|current| current.ptr_eq(&running)) {
+        *current = None;
     }
-    log::debug!("{} of {} proxies added from {}", added, proxies.len(), name);
+    added
 }
 
-pub async fn run_all_providers(num_conn: usize) {
+async fn run_providers(
+    provider_list: Vec<Arc<dyn ProxyProvider>>,
+    num_conn: usize,
+    policy: Option<RefreshPolicy>,
+    watermarks: QueueWatermarks,
+    breaker: BreakerPolicy,
+) -> usize {
     let sem = Arc::new(Semaphore::new(num_conn.min(MAX_CONCURRENT_PROVIDERS)));
     let provider_sem = Arc::new(Semaphore::new(MAX_CONCURRENT_PROVIDERS));
     let mut futures = FuturesUnordered::new();
 
-    let provider_list = providers();
     log::info!("Starting {} providers with rate limiting", provider_list.len());
+    let polled: HashSet<String> = provider_list.iter().map(|provider| provider.name().to_string()).collect();
 
     for provider in provider_list {
+        watermarks.wait_for_room(|| PROXIES.len()).await;
+        memory_guard::wait_for_memory().await;
+        if is_fetching_stopped() {
+            log::debug!("Provider fetching stopped, skipping remaining providers");
+            break;
+        }
         let permit = Arc::clone(&sem).acquire_owned().await;
         let provider_permit = Arc::clone(&provider_sem).acquire_owned().await;
 
         futures.push(tokio::spawn(async move {
             let _permit = permit;
             let _provider_permit = provider_permit;
-            let name = provider.name;
+            let name = provider.name();
 
             log::debug!("Processing provider: {}", name);
 
             // Rate limiting: small delay between provider requests
             tokio::time::sleep(tokio::time::Duration::from_millis(PROVIDER_RATE_LIMIT_DELAY_MS)).await;
 
-            let task = ProviderTask::new(provider);
-            let proxies = task.get_proxies().await;
-            update_stack(name, &proxies).await;
+            let proxies = provider.fetch().await;
+            if is_fetching_stopped() {
+                return 0;
+            }
+            let found = proxies.len();
+            let added = update_stack(name, proxies).await;
+            record_poll(name, found > 0, &breaker, policy);
 
-            log::debug!("Completed provider: {}, found {} proxies", name, proxies.len());
+            log::debug!("Completed provider: {}, found {} proxies", name, found);
+            added
         }));
     }
 
     // Wait for all providers to complete
+    let mut added = 0;
     while let Some(result) = futures.next().await {
-        if let Err(e) = result {
-            log::error!("Provider task failed: {}", e);
+        match result {
+            Ok(count) => added += count,
+            Err(e) => log::error!("Provider task failed: {}", e),
+        }
+    }
+
+    log::info!("All providers completed, {} new proxies queued", added);
+    for (name, stats) in provider_stats().into_iter().filter(|(name, _)| polled.contains(name)) {
+        log::info!(
+            "Provider {}: {} fetched, {} unique, {} working ({:.0}% dead)",
+            name,
+            stats.fetched,
+            stats.unique,
+            stats.working,
+            stats.dead_rate() * 100.0
+        );
+    }
+    added
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::future::BoxFuture;
+
+    struct MockProvider;
+
+    impl ProxyProvider for MockProvider {
+        fn name(&self) -> &str {
+            "mock"
+        }
+
+        fn fetch(&self) -> BoxFuture<'_, Vec<Proxy>> {
+            Box::pin(async {
+                let mut proxies = vec![];
+                for port in [3128, 8080] {
+                    proxies.extend(Proxy::create("192.0.2.10", port, vec!["HTTP".to_string()]).await);
+                }
+                proxies
+            })
         }
     }
 
-    log::info!("All providers completed");
+    #[tokio::test]
+    async fn test_registered_provider_feeds_queue() {
+        let _fetching = FETCH_TEST.lock().await;
+        register_provider(Box::new(MockProvider));
+        let mock: Vec<_> = registered_providers().into_iter().filter(|p| p.name() == "mock").collect();
+        assert_eq!(mock.len(), 1);
+
+        run_providers(mock, 1, None, QueueWatermarks::default(), BreakerPolicy::default()).await;
+
+        let mut queued = vec![];
+        while let Ok(proxy) = PROXIES.pop() {
+            queued.push(proxy.as_text());
+        }
+        assert!(queued.contains(&"192.0.2.10:3128".to_string()));
+        assert!(queued.contains(&"192.0.2.10:8080".to_string()));
+    }
+
+    /// Provider returning `ports` of one host, duplicates included
+    struct PortsProvider {
+        name: &'static str,
+        host: &'static str,
+        ports: Vec<u16>,
+    }
+
+    impl ProxyProvider for PortsProvider {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn fetch(&self) -> BoxFuture<'_, Vec<Proxy>> {
+            Box::pin(async {
+                let mut proxies = vec![];
+                for port in &self.ports {
+                    proxies.extend(Proxy::create(self.host, *port, vec!["HTTP".to_string()]).await);
+                }
+                proxies
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_provider_stats_tell_sources_apart() {
+        let _fetching = FETCH_TEST.lock().await;
+        let good: Arc<dyn ProxyProvider> = Arc::new(PortsProvider {
+            name: "stats-good",
+            host: "198.51.100.20",
+            ports: vec![3128, 8080],
+        });
+        let poor: Arc<dyn ProxyProvider> = Arc::new(PortsProvider {
+            name: "stats-poor",
+            host: "198.51.100.21",
+            ports: vec![3128, 3128, 3128, 8080],
+        });
+        run_providers(vec![good, poor], 2, None, QueueWatermarks::default(), BreakerPolicy::default()).await;
+
+        // The finder credits proxies tagged with their provider once a check passes
+        for (name, host, port) in [("stats-good", "198.51.100.20", 3128), ("stats-good", "198.51.100.20", 8080)] {
+            let mut proxy = Proxy::create(host, port, vec![]).await.unwrap();
+            proxy.provider = Some(name.to_string());
+            record_working(&proxy);
+        }
+
+        let stats = provider_stats();
+        let good = stats["stats-good"];
+        let poor = stats["stats-poor"];
+        assert_eq!(good, ProviderStats { fetched: 2, unique: 2, working: 2 });
+        assert_eq!(poor, ProviderStats { fetched: 4, unique: 2, working: 0 });
+        assert_eq!(good.dead_rate(), 0.0);
+        assert_eq!(poor.dead_rate(), 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_refreshes_coalesce() {
+        let started = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let refresh = |count: usize| {
+            let started = Arc::clone(&started);
+            async move {
+                started.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                count
+            }
+            .boxed()
+        };
+
+        let slot = RefreshSlot::default();
+        let (first, second) = tokio::join!(coalesce(&slot, refresh(3)), coalesce(&slot, refresh(5)));
+        assert_eq!((first, second), (3, 3));
+        assert_eq!(started.load(Ordering::SeqCst), 1);
+
+        // Once done, the next refresh runs again
+        assert_eq!(coalesce(&slot, refresh(5)).await, 5);
+        assert_eq!(started.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_full_queue_pauses_until_low_watermark() {
+        let _fetching = FETCH_TEST.lock().await;
+        let queue = Arc::new(concurrent_queue::ConcurrentQueue::unbounded());
+        for i in 0..10 {
+            queue.push(i).unwrap();
+        }
+        let watermarks = QueueWatermarks::new(10, 4);
+        let waiting = Arc::clone(&queue);
+        let mut paused = tokio::spawn(async move { watermarks.wait_for_room(|| waiting.len()).await });
+
+        let still_paused = tokio::time::timeout(Duration::from_millis(500), &mut paused).await;
+        assert!(still_paused.is_err(), "providers kept fetching into a full queue");
+
+        // Draining below the high watermark down to the low one is not enough to resume
+        for _ in 0..6 {
+            queue.pop().unwrap();
+        }
+        let still_paused = tokio::time::timeout(Duration::from_millis(500), &mut paused).await;
+        assert!(still_paused.is_err());
+
+        queue.pop().unwrap();
+        tokio::time::timeout(Duration::from_secs(1), paused)
+            .await
+            .expect("providers did not resume below the low watermark")
+            .unwrap();
+
+        // Below the high watermark fetching goes on right away
+        tokio::time::timeout(Duration::from_millis(50), watermarks.wait_for_room(|| queue.len())).await.unwrap();
+    }
+
+    #[test]
+    fn test_breaker_trips_and_recovers() {
+        let policy = BreakerPolicy::new(2, Duration::from_secs(60));
+        let start = Instant::now();
+        let mut breaker = CircuitBreaker::new();
+
+        assert_eq!(breaker.record(false, &policy, start), None);
+        assert!(breaker.allow(&policy, start));
+        assert_eq!(breaker.record(false, &policy, start), Some(BreakerState::Open));
+        assert!(!breaker.allow(&policy, start + Duration::from_secs(59)));
+
+        // After the cooldown a single probe goes through, and fails
+        let probe = start + Duration::from_secs(60);
+        assert!(breaker.allow(&policy, probe));
+        assert_eq!(breaker.state, BreakerState::HalfOpen);
+        assert!(!breaker.allow(&policy, probe));
+        assert_eq!(breaker.record(false, &policy, probe), Some(BreakerState::Open));
+        assert!(!breaker.allow(&policy, probe + Duration::from_secs(30)));
+
+        // The next probe succeeds and closes the breaker
+        let probe = probe + Duration::from_secs(60);
+        assert!(breaker.allow(&policy, probe));
+        assert_eq!(breaker.record(true, &policy, probe), Some(BreakerState::Closed));
+        assert!(breaker.allow(&policy, probe));
+        assert_eq!(breaker.failures, 0);
+    }
+
+    #[test]
+    fn test_lost_probe_reopens_breaker() {
+        let policy = BreakerPolicy::new(1, Duration::from_secs(60));
+        let start = Instant::now();
+        let mut breaker = CircuitBreaker::new();
+        assert_eq!(breaker.record(false, &policy, start), Some(BreakerState::Open));
+
+        // The probe goes through but never reports back
+        let probe = start + Duration::from_secs(60);
+        assert!(breaker.allow(&policy, probe));
+        assert!(!breaker.allow(&policy, probe + Duration::from_secs(59)));
+        assert!(!breaker.allow(&policy, probe + Duration::from_secs(60)));
+        assert_eq!(breaker.state, BreakerState::Open);
+
+        // A new probe is let through after another cooldown
+        assert!(breaker.allow(&policy, probe + Duration::from_secs(120)));
+        assert_eq!(breaker.state, BreakerState::HalfOpen);
+    }
+
+    #[test]
+    fn test_empty_polls_back_off_before_the_breaker_opens() {
+        let policy = BreakerPolicy::new(3, Duration::from_secs(3600));
+        let refresh = RefreshPolicy::new(Duration::from_secs(600), 0);
+        record_poll("backoff-test", false, &policy, Some(refresh));
+
+        // Still closed, but the refresh cycle waits before polling it again
+        assert_eq!(breaker_states().get("backoff-test"), Some(&BreakerState::Closed));
+        assert!(!breaker_allows("backoff-test", &policy));
+
+        record_poll("backoff-test", true, &policy, Some(refresh));
+        assert!(breaker_allows("backoff-test", &policy));
+        assert!(!breaker_states().contains_key("backoff-test"));
+    }
+
+    #[test]
+    fn test_breaker_states_list_failing_providers() {
+        let policy = BreakerPolicy::new(1, Duration::from_secs(3600));
+        record_poll("breaker-test-failing", false, &policy, None);
+        record_poll("breaker-test-working", true, &policy, None);
+
+        let states = breaker_states();
+        assert_eq!(states.get("breaker-test-failing"), Some(&BreakerState::Open));
+        assert!(!states.contains_key("breaker-test-working"));
+        assert!(!breaker_allows("breaker-test-failing", &policy));
+
+        record_poll("breaker-test-failing", true, &policy, None);
+        assert!(!breaker_states().contains_key("breaker-test-failing"));
+    }
+
+    /// Attempts made by `retry_fetch` when successive attempts answer `responses`
+    async fn attempts_for(max_retries: u32, responses: Vec<Result<&'static str, FetchError>>) -> (usize, Result<&'static str, FetchError>) {
+        let policy = FetchRetryPolicy::new(max_retries, Duration::ZERO, Duration::from_secs(1));
+        let mut responses = responses.into_iter();
+        let mut attempts = 0;
+        let result = retry_fetch(&policy, || {
+            attempts += 1;
+            let response = responses.next().expect("more attempts than responses");
+            async move { response }
+        })
+        .await;
+        (attempts, result)
+    }
+
+    #[tokio::test]
+    async fn test_fetch_retry_decisions() {
+        // Transient failures are retried until the page comes
+        let (attempts, result) =
+            attempts_for(2, vec![Err(FetchError::Timeout), Err(FetchError::Status(503)), Ok("page")]).await;
+        assert_eq!((attempts, result), (3, Ok("page")));
+
+        // A 4xx ends the fetch at once
+        let (attempts, result) = attempts_for(2, vec![Err(FetchError::Status(404)), Ok("page")]).await;
+        assert_eq!((attempts, result), (1, Err(FetchError::Status(404))));
+
+        // Retries are bounded
+        let failures = vec![
+            Err(FetchError::Connect("reset".to_string())),
+            Err(FetchError::Timeout),
+            Err(FetchError::Status(502)),
+        ];
+        let (attempts, result) = attempts_for(2, failures).await;
+        assert_eq!((attempts, result), (3, Err(FetchError::Status(502))));
+
+        let (attempts, _) = attempts_for(0, vec![Err(FetchError::Timeout)]).await;
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn test_fetch_backoff_doubles() {
+        let policy = FetchRetryPolicy::new(5, Duration::from_millis(100), Duration::from_secs(1));
+        assert_eq!(policy.backoff_for(0), Duration::from_millis(100));
+        assert_eq!(policy.backoff_for(2), Duration::from_millis(400));
+    }
+
+    #[test]
+    fn test_next_interval_jitter_and_backoff() {
+        let exact = RefreshPolicy::new(Duration::from_secs(60), 0);
+        assert_eq!(exact.next_interval(0), Duration::from_secs(60));
+        assert_eq!(exact.next_interval(2), Duration::from_secs(240));
+        // Backoff stops growing after 2^4
+        assert_eq!(exact.next_interval(10), Duration::from_secs(960));
+
+        let jittered = RefreshPolicy::new(Duration::from_secs(60), 10);
+        for _ in 0..200 {
+            let wait = jittered.next_interval(0);
+            assert!(wait >= Duration::from_secs(54) && wait <= Duration::from_secs(66), "{:?}", wait);
+            let wait = jittered.next_interval(1);
+            assert!(wait >= Duration::from_secs(108) && wait <= Duration::from_secs(132), "{:?}", wait);
+        }
+
+        assert_eq!(RefreshPolicy::new(Duration::from_secs(60), 250).jitter_percent, 100);
+    }
 }
@@ -0,0 +1,96 @@
+//! Queue of the proxies waiting for a check, with a view of its content that session
+//! snapshots can read without popping anything
+
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use concurrent_queue::{ConcurrentQueue, PopError};
+use parking_lot::Mutex;
+
+use crate::{
+    proxy::Proxy,
+    utils::{
+        error::{ProxyError, ProxyResult},
+        serializer::QueuedProxy,
+    },
+};
+
+/// Queued copies of a proxy: when it was first pushed, what it is, how many are queued
+type QueuedEntry = (u64, QueuedProxy, usize);
+
+/// Unbounded FIFO of proxies to check
+pub struct ProxyQueue {
+    queue: ConcurrentQueue<Proxy>,
+    /// Queued proxies by `host:port`
+    queued: Mutex<HashMap<String, QueuedEntry>>,
+    pushed: AtomicU64,
+}
+
+impl ProxyQueue {
+    pub fn new() -> Self {
+        Self {
+            queue: ConcurrentQueue::unbounded(),
+            queued: Mutex::new(HashMap::new()),
+            pushed: AtomicU64::new(0),
+        }
+    }
+
+    pub fn push(&self, proxy: Proxy) -> ProxyResult<()> {
+        let key = proxy.as_text();
+        let order = self.pushed.fetch_add(1, Ordering::Relaxed);
+        self.queued
+            .lock()
+            .entry(key.clone())
+            .or_insert_with(|| (order, QueuedProxy::from(&proxy), 0))
+            .2 += 1;
+        self.queue.push(proxy).map_err(|e| {
+            self.forget(&key);
+            ProxyError::Http(format!("Failed to queue proxy {}: {}", key, e))
+        })
+    }
+
+    pub fn pop(&self) -> Result<Proxy, PopError> {
+        let proxy = self.queue.pop()?;
+        self.forget(&proxy.as_text());
+        Ok(proxy)
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Queued proxies in the order they were pushed, the queue is left untouched.
+    /// A proxy popped while this runs may still be listed.
+    pub fn snapshot(&self) -> Vec<QueuedProxy> {
+        let mut queued: Vec<(u64, QueuedProxy)> = self
+            .queued
+            .lock()
+            .values()
+            .map(|(order, proxy, _)| (*order, proxy.clone()))
+            .collect();
+        queued.sort_by_key(|(order, _)| *order);
+        queued.into_iter().map(|(_, proxy)| proxy).collect()
+    }
+
+    fn forget(&self, key: &str) {
+        let mut queued = self.queued.lock();
+        if let Some(entry) = queued.get_mut(key) {
+            entry.2 -= 1;
+            if entry.2 == 0 {
+                queued.remove(key);
+            }
+        }
+    }
+}
+
+impl Default for ProxyQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -24,6 +24,28 @@ impl Default for GeoData {
     }
 }
 
+impl GeoData {
+    /// Whether the location passes the country, region and city filters.
+    /// Empty filters match anything, comparisons ignore case.
+    pub fn matches(&self, countries: &[String], regions: &[String], cities: &[String]) -> bool {
+        matches_any(countries, &[&self.iso_code])
+            && matches_any(regions, &[&self.region_name, &self.region_iso_code])
+            && matches_any(cities, &[&self.city_name])
+    }
+
+    /// Whether the country is in `excluded_countries`, ignoring case
+    pub fn is_excluded(&self, excluded_countries: &[String]) -> bool {
+        !excluded_countries.is_empty() && matches_any(excluded_countries, &[&self.iso_code])
+    }
+}
+
+fn matches_any(filter: &[String], values: &[&str]) -> bool {
+    filter.is_empty()
+        || filter
+            .iter()
+            .any(|expected| values.iter().any(|value| value.eq_ignore_ascii_case(expected)))
+}
+
 pub struct Resolver {
     // Simple cache for DNS lookups
     cache: HashMap<String, String>,
@@ -119,4 +141,72 @@ impl Resolver {
             _ => country_code,
         }.to_string()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paris() -> GeoData {
+        GeoData {
+            iso_code: "FR".to_string(),
+            name: "France".to_string(),
+            region_iso_code: "IDF".to_string(),
+            region_name: "Ile-de-France".to_string(),
+            city_name: "Paris".to_string(),
+        }
+    }
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn test_geo_filters_default_to_any() {
+        assert!(paris().matches(&[], &[], &[]));
+        assert!(paris().matches(&strings(&["fr"]), &[], &[]));
+        assert!(!paris().matches(&strings(&["DE"]), &[], &[]));
+    }
+
+    #[test]
+    fn test_city_passes_region_fails() {
+        let geo = paris();
+        assert!(geo.matches(&[], &[], &strings(&["PARIS"])));
+        assert!(!geo.matches(&[], &strings(&["Bavaria"]), &strings(&["paris"])));
+    }
+
+    #[test]
+    fn test_region_passes_city_fails() {
+        let geo = paris();
+        assert!(geo.matches(&[], &strings(&["ile-de-france"]), &[]));
+        assert!(geo.matches(&[], &strings(&["idf"]), &[]));
+        assert!(!geo.matches(&[], &strings(&["Ile-de-France"]), &strings(&["Lyon"])));
+    }
+
+    #[test]
+    fn test_exclude_countries_only() {
+        let geo = paris();
+        assert!(!geo.is_excluded(&[]));
+        assert!(geo.is_excluded(&strings(&["de", "fr"])));
+        assert!(!geo.is_excluded(&strings(&["DE"])));
+    }
+
+    #[test]
+    fn test_include_countries_only() {
+        let geo = paris();
+        assert!(geo.matches(&strings(&["DE", "FR"]), &[], &[]) && !geo.is_excluded(&[]));
+        assert!(!geo.matches(&strings(&["DE"]), &[], &[]));
+    }
+
+    #[test]
+    fn test_exclude_applies_after_include() {
+        let geo = paris();
+        let passes = |include: &[&str], exclude: &[&str]| {
+            geo.matches(&strings(include), &[], &[]) && !geo.is_excluded(&strings(exclude))
+        };
+        assert!(passes(&["FR", "DE"], &["DE"]));
+        // Listed in both: the exclusion wins
+        assert!(!passes(&["FR", "DE"], &["FR"]));
+        assert!(!passes(&["DE"], &["IT"]));
+    }
+}
@@ -58,11 +58,14 @@ impl Default for AsyncOptimizerConfig {
     }
 }
 
-/// Async optimizer with custom runtime and task management
+/// Async optimizer with custom runtime and task management.
+///
+/// Dropping it only cancels running tasks, the worker threads of its runtime are
+/// stopped by [`AsyncOptimizer::into_shutdown`].
 #[derive(Debug)]
 pub struct AsyncOptimizer {
-    /// Custom tokio runtime
-    runtime: Runtime,
+    /// Custom tokio runtime, only taken by `into_shutdown`
+    runtime: Option<Runtime>,
     /// Semaphore for limiting concurrent tasks
     task_semaphore: Arc<Semaphore>,
     /// Performance metrics
@@ -82,7 +85,7 @@ impl AsyncOptimizer {
         let cancellation_token = CancellationToken::new();
 
         Ok(Self {
-            runtime,
+            runtime: Some(runtime),
             task_semaphore,
             metrics,
             cancellation_token,
@@ -119,7 +122,7 @@ impl AsyncOptimizer {
 
     /// Get a reference to the runtime
     pub fn runtime(&self) -> &Runtime {
-        &self.runtime
+        self.runtime.as_ref().expect("runtime is only taken by into_shutdown")
     }
 
     /// Execute a function with optimized scheduling
@@ -239,7 +242,7 @@ impl AsyncOptimizer {
 
     /// Get runtime statistics
     pub fn get_runtime_stats(&self) -> RuntimeStats {
-        let metrics = self.runtime.metrics();
+        let metrics = self.runtime().metrics();
         RuntimeStats {
             num_workers: metrics.num_workers(),
             num_blocking_threads: 0, // Deprecated
@@ -263,7 +266,7 @@ impl AsyncOptimizer {
         let runtime_stats_interval = Duration::from_secs(30);
         let cancel_token_clone = cancel_token.clone();
 
-        self.runtime.spawn(async move {
+        self.runtime().spawn(async move {
             let mut interval = tokio::time::interval(runtime_stats_interval);
             
             loop {
@@ -297,7 +300,7 @@ impl AsyncOptimizer {
         let shutdown_timeout = Duration::from_secs(10);
         let start_time = Instant::now();
         
-        while self.task_semaphore.available_permits() < self.config.max_concurrent_tasks {
+        while !self.is_drained() {
             if start_time.elapsed() > shutdown_timeout {
                 log::warn!("Shutdown timeout - some tasks may still be running");
                 break;
@@ -308,6 +311,35 @@ impl AsyncOptimizer {
         log::info!("Async optimizer shutdown complete");
     }
 
+    /// Shut down for good: cancel every task, wait up to `timeout` for them to
+    /// release their permits, then stop the runtime and its worker threads.
+    ///
+    /// Use this rather than relying on `Drop`, which can only cancel. Blocks the
+    /// calling thread, so it must not be called from within an async context.
+    pub fn into_shutdown(mut self, timeout: Duration) {
+        log::info!("Shutting down async optimizer...");
+        self.cancellation_token.cancel();
+
+        let start_time = Instant::now();
+        while !self.is_drained() {
+            if start_time.elapsed() > timeout {
+                log::warn!("Shutdown timeout - some tasks may still be running");
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_timeout(timeout.saturating_sub(start_time.elapsed()));
+        }
+        log::info!("Async optimizer shutdown complete");
+    }
+
+    /// Whether no task holds a permit anymore
+    fn is_drained(&self) -> bool {
+        self.task_semaphore.available_permits() >= self.config.max_concurrent_tasks
+    }
+
     /// Create a task group for batch operations
     pub fn create_task_group(&self) -> TaskGroup {
         TaskGroup {
@@ -434,7 +466,7 @@ impl TaskGroup {
 
 impl Drop for AsyncOptimizer {
     fn drop(&mut self) {
-        // Note: We can't use async in Drop, so we just cancel
+        // Note: We can't use async in Drop, so we just cancel, `into_shutdown` stops the runtime
         self.cancellation_token.cancel();
     }
 }
@@ -465,13 +497,37 @@ mod tests {
     use super::*;
     use tokio::time::sleep;
 
-    #[tokio::test]
-    async fn test_async_optimizer_creation() {
+    #[test]
+    fn test_async_optimizer_creation() {
         let config = AsyncOptimizerConfig::default();
         let optimizer = AsyncOptimizer::new(config).unwrap();
         
         let stats = optimizer.get_runtime_stats();
         assert!(stats.num_workers > 0);
+        optimizer.into_shutdown(Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_into_shutdown_drains_tasks() {
+        let config = AsyncOptimizerConfig {
+            worker_threads: Some(1),
+            max_concurrent_tasks: 4,
+            ..Default::default()
+        };
+        let optimizer = AsyncOptimizer::new(config).unwrap();
+        let semaphore = Arc::clone(&optimizer.task_semaphore);
+
+        // Tasks that only finish once cancelled
+        for _ in 0..2 {
+            let group = optimizer.create_task_group();
+            optimizer.runtime().spawn(async move { group.execute(std::future::pending::<()>()).await });
+        }
+        while semaphore.available_permits() > 2 {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        optimizer.into_shutdown(Duration::from_secs(5));
+        assert_eq!(semaphore.available_permits(), 4);
     }
 
     #[tokio::test]
@@ -538,7 +594,7 @@ mod tests {
         }).await;
         
         let metrics = optimizer.get_metrics().await;
-        assert!(metrics.async_task_count > 0);
-        assert!(metrics.avg_async_task_time() > 0.0);
+        assert!(metrics.system_metrics.async_tasks_completed > 0);
+        assert!(metrics.system_metrics.avg_async_task_time_ms > 0.0);
     }
 }
@@ -0,0 +1,103 @@
+//! Recent requests served by the proxy server, showing which upstream reached which target
+
+use std::collections::VecDeque;
+use std::net::IpAddr;
+
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use serde::Serialize;
+
+/// Entries kept until `server.audit_log_size` says otherwise
+pub const DEFAULT_AUDIT_LOG_SIZE: usize = 1000;
+
+lazy_static! {
+    /// Requests handled by the proxy server, read by the API
+    pub static ref AUDIT_LOG: AuditLog = AuditLog::new(DEFAULT_AUDIT_LOG_SIZE);
+}
+
+/// One request handled by the proxy server
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AuditEntry {
+    pub timestamp: DateTime<Utc>,
+    pub client_ip: IpAddr,
+    /// Host the client asked for, `None` when the request named none
+    pub target: Option<String>,
+    /// Upstream chain the request went through, `None` when it was refused or no proxy was picked
+    pub proxy: Option<String>,
+    pub status: u16,
+}
+
+/// Ring buffer of the last entries, older ones are dropped
+#[derive(Debug)]
+pub struct AuditLog {
+    entries: Mutex<VecDeque<AuditEntry>>,
+    capacity: Mutex<usize>,
+}
+
+impl AuditLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: Mutex::new(VecDeque::with_capacity(capacity.min(DEFAULT_AUDIT_LOG_SIZE))),
+            capacity: Mutex::new(capacity),
+        }
+    }
+
+    /// Change how many entries are kept, 0 disables the log
+    pub fn set_capacity(&self, capacity: usize) {
+        *self.capacity.lock() = capacity;
+        let mut entries = self.entries.lock();
+        while entries.len() > capacity {
+            entries.pop_front();
+        }
+    }
+
+    pub fn record(&self, entry: AuditEntry) {
+        let capacity = *self.capacity.lock();
+        if capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock();
+        while entries.len() >= capacity {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+
+    /// Up to `limit` entries, most recent first
+    pub fn recent(&self, limit: usize) -> Vec<AuditEntry> {
+        self.entries.lock().iter().rev().take(limit).cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(status: u16) -> AuditEntry {
+        AuditEntry {
+            timestamp: Utc::now(),
+            client_ip: IpAddr::from([127, 0, 0, 1]),
+            target: Some("example.com".to_string()),
+            proxy: None,
+            status,
+        }
+    }
+
+    #[test]
+    fn test_audit_log_is_bounded() {
+        let log = AuditLog::new(3);
+        for status in [200, 201, 202, 203] {
+            log.record(entry(status));
+        }
+        let statuses: Vec<u16> = log.recent(10).iter().map(|entry| entry.status).collect();
+        assert_eq!(statuses, vec![203, 202, 201]);
+        assert_eq!(log.recent(1)[0].status, 203);
+
+        log.set_capacity(1);
+        assert_eq!(log.recent(10).len(), 1);
+        log.set_capacity(0);
+        log.record(entry(204));
+        assert!(log.recent(10).is_empty());
+    }
+}
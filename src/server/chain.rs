@@ -0,0 +1,319 @@
+//! Tunnelling through an ordered chain of upstream proxies with nested CONNECTs
+
+use std::{fmt, time::Duration};
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::TcpStream,
+    time::timeout,
+};
+
+use super::connection_pool::ConnectionPool;
+use super::proxy_pool::SimpleProxy;
+use crate::utils::http::response::ResponseParser;
+
+/// Upper bound on the size of a CONNECT response head
+const MAX_RESPONSE_HEAD: usize = 8192;
+
+/// Failure while building a chain, `hop` is the index of the proxy to blame
+#[derive(Debug)]
+pub struct ChainError {
+    pub hop: usize,
+    /// Short reason recorded in the proxy error stats
+    pub reason: &'static str,
+    pub message: String,
+}
+
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "hop {}: {}", self.hop + 1, self.message)
+    }
+}
+
+impl std::error::Error for ChainError {}
+
+/// Open a stream through every proxy of `chain` in order.
+///
+/// Each hop is asked to CONNECT to the next one. With a `target` the last hop is
+/// asked to CONNECT to it and the returned stream is a tunnel to the target,
+/// otherwise the stream talks to the last hop directly. The connect, read and write
+/// timeouts come from the configuration of `connection_pool`.
+pub async fn connect_through_chain(
+    chain: &[SimpleProxy],
+    target: Option<&str>,
+    connection_pool: &ConnectionPool,
+) -> Result<TcpStream, ChainError> {
+    connect_through_chain_with_reuse(chain, target, connection_pool)
+        .await
+        .map(|(stream, _)| stream)
+}
+
+/// Like `connect_through_chain`, also telling whether the connection to the first
+/// hop was reused from the pool
+pub async fn connect_through_chain_with_reuse(
+    chain: &[SimpleProxy],
+    target: Option<&str>,
+    connection_pool: &ConnectionPool,
+) -> Result<(TcpStream, bool), ChainError> {
+    let config = connection_pool.get_config();
+    let first = chain.first().ok_or_else(|| ChainError {
+        hop: 0,
+        reason: "empty_chain",
+        message: "no proxy in chain".to_string(),
+    })?;
+
+    let (mut stream, reused) = match connection_pool.get_connection_with_reuse(&first.as_text()).await {
+        Ok(connection) => {
+            log::debug!("Using pooled connection to {}", first.as_text());
+            connection
+        }
+        Err(e) => {
+            log::debug!("Failed to get pooled connection to {}: {}, creating new connection", first.as_text(), e);
+            match timeout(config.connect_timeout, TcpStream::connect(first.as_text())).await {
+                Ok(Ok(stream)) => (stream, false),
+                Ok(Err(e)) => {
+                    return Err(ChainError {
+                        hop: 0,
+                        reason: "connection_failed",
+                        message: format!("failed to connect to {}: {}", first.as_text(), e),
+                    })
+                }
+                Err(_) => {
+                    return Err(ChainError {
+                        hop: 0,
+                        reason: "connection_failed",
+                        message: format!("timed out connecting to {}", first.as_text()),
+                    })
+                }
+            }
+        }
+    };
+
+    let next_hops = chain[1..].iter().map(|proxy| proxy.as_text());
+    for (index, authority) in next_hops.chain(target.map(str::to_string)).enumerate() {
+        match send_connect_request(&mut stream, &authority, config.write_timeout, config.read_timeout).await {
+            Some(200) => log::debug!("Hop {} tunnelled to {}", index + 1, authority),
+            Some(status) => {
+                // The hop answered, so the next hop (or the target) is the one unreachable
+                let hop = (index + 1).min(chain.len() - 1);
+                return Err(ChainError {
+                    hop,
+                    reason: "connect_failed",
+                    message: format!("CONNECT {} via {} returned {}", authority, chain[index].as_text(), status),
+                });
+            }
+            None => {
+                return Err(ChainError {
+                    hop: index,
+                    reason: "connect_failed",
+                    message: format!("no CONNECT response from {}", chain[index].as_text()),
+                });
+            }
+        }
+    }
+
+    Ok((stream, reused))
+}
+
+/// Send a CONNECT for `authority` (`host:port`) and return the response status
+pub async fn send_connect_request<R: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut R,
+    authority: &str,
+    write_timeout: Duration,
+    read_timeout: Duration,
+) -> Option<u16> {
+    let connect = format!(
+        "CONNECT {0} HTTP/1.1\r\nHost: {0}\r\nProxy-Connection: Keep-Alive\r\n\r\n",
+        authority
+    );
+    if let Ok(Ok(_)) = timeout(write_timeout, stream.write_all(connect.as_bytes())).await {
+        let data = read_response_head(stream, read_timeout).await;
+        return ResponseParser::parse(data.as_slice()).status_code;
+    }
+    None
+}
+
+/// Read a response head byte by byte so no tunnelled data gets consumed
+async fn read_response_head<R: AsyncRead + Unpin>(reader: &mut R, read_timeout: Duration) -> Vec<u8> {
+    let mut data = Vec::new();
+    let read = async {
+        let mut byte = [0; 1];
+        while !data.ends_with(b"\r\n\r\n") && data.len() < MAX_RESPONSE_HEAD {
+            match reader.read(&mut byte).await {
+                Ok(0) | Err(_) => break,
+                Ok(_) => data.push(byte[0]),
+            }
+        }
+    };
+    let _ = timeout(read_timeout, read).await;
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolver::GeoData;
+    use crate::server::connection_pool::PoolConfig;
+    use std::collections::BTreeMap;
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+
+    fn simple_proxy(port: u16) -> SimpleProxy {
+        SimpleProxy {
+            host: "127.0.0.1".to_string(),
+            port,
+            geo: GeoData::default(),
+            types: vec![("HTTPS".to_string(), None)],
+            schemes: vec![],
+            runtimes: vec![],
+            request_stat: 0,
+            error_stat: BTreeMap::new(),
+            failure_stat: 0,
+            last_verified: None,
+        }
+    }
+
+    /// CONNECT-capable stub reporting each requested authority
+    async fn connect_stub(seen: mpsc::UnboundedSender<String>) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut client, _)) = listener.accept().await {
+                let seen = seen.clone();
+                tokio::spawn(async move {
+                    let head = read_response_head(&mut client, Duration::from_secs(5)).await;
+                    let head = String::from_utf8_lossy(&head).to_string();
+                    let authority = head.split_whitespace().nth(1).unwrap_or_default().to_string();
+                    let _ = seen.send(authority.clone());
+                    match TcpStream::connect(&authority).await {
+                        Ok(mut upstream) => {
+                            client.write_all(b"HTTP/1.1 200 Connection established\r\n\r\n").await.unwrap();
+                            let _ = tokio::io::copy_bidirectional(&mut client, &mut upstream).await;
+                        }
+                        Err(_) => {
+                            let _ = client.write_all(b"HTTP/1.1 502 Bad Gateway\r\n\r\n").await;
+                        }
+                    }
+                });
+            }
+        });
+        port
+    }
+
+    async fn echo_server() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let (mut reader, mut writer) = stream.split();
+                    let _ = tokio::io::copy(&mut reader, &mut writer).await;
+                });
+            }
+        });
+        port
+    }
+
+    #[tokio::test]
+    async fn test_tunnel_through_two_hops() {
+        let (seen_a_tx, mut seen_a) = mpsc::unbounded_channel();
+        let (seen_b_tx, mut seen_b) = mpsc::unbounded_channel();
+        let hop_a = connect_stub(seen_a_tx).await;
+        let hop_b = connect_stub(seen_b_tx).await;
+        let target = format!("127.0.0.1:{}", echo_server().await);
+
+        let pool = ConnectionPool::new(PoolConfig::default());
+        let chain = vec![simple_proxy(hop_a), simple_proxy(hop_b)];
+        let mut stream = connect_through_chain(&chain, Some(&target), &pool).await.unwrap();
+
+        stream.write_all(b"ping").await.unwrap();
+        let mut buf = [0; 4];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+
+        assert_eq!(seen_a.recv().await.unwrap(), format!("127.0.0.1:{}", hop_b));
+        assert_eq!(seen_b.recv().await.unwrap(), target);
+    }
+
+    /// Proxy accepting at once but answering CONNECT only after `delay`
+    async fn slow_answering_proxy(delay: Duration) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut client, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    read_response_head(&mut client, Duration::from_secs(5)).await;
+                    tokio::time::sleep(delay).await;
+                    let _ = client.write_all(b"HTTP/1.1 200 Connection established\r\n\r\n").await;
+                    let mut rest = vec![];
+                    let _ = client.read_to_end(&mut rest).await;
+                });
+            }
+        });
+        port
+    }
+
+    #[tokio::test]
+    async fn test_read_timeout_governs_slow_answers() {
+        let port = slow_answering_proxy(Duration::from_millis(300)).await;
+        let chain = vec![simple_proxy(port)];
+
+        // A short connect timeout does not cut a slow answer on an open connection
+        let pool = ConnectionPool::new(PoolConfig {
+            connect_timeout: Duration::from_millis(100),
+            read_timeout: Duration::from_secs(2),
+            ..PoolConfig::default()
+        });
+        assert!(connect_through_chain(&chain, Some("example.com:443"), &pool).await.is_ok());
+
+        // A short read timeout does
+        let pool = ConnectionPool::new(PoolConfig {
+            connect_timeout: Duration::from_secs(5),
+            read_timeout: Duration::from_millis(100),
+            ..PoolConfig::default()
+        });
+        let err = connect_through_chain(&chain, Some("example.com:443"), &pool).await.unwrap_err();
+        assert_eq!(err.reason, "connect_failed");
+        assert!(err.message.contains("no CONNECT response"));
+    }
+
+    #[tokio::test]
+    async fn test_connect_timeout_governs_slow_connects() {
+        assert_eq!(PoolConfig::default().connect_timeout, Duration::from_secs(8));
+
+        // A listener that never accepts drops new handshakes once its backlog is full
+        let socket = tokio::net::TcpSocket::new_v4().unwrap();
+        socket.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let listener = socket.listen(1).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mut backlog = vec![];
+        while let Ok(Ok(stream)) = timeout(Duration::from_millis(200), TcpStream::connect(("127.0.0.1", port))).await {
+            backlog.push(stream);
+        }
+
+        let pool = ConnectionPool::new(PoolConfig {
+            connect_timeout: Duration::from_millis(200),
+            ..PoolConfig::default()
+        });
+        let started = std::time::Instant::now();
+        let err = connect_through_chain(&[simple_proxy(port)], None, &pool).await.unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(err.reason, "connection_failed");
+        assert!(err.message.contains("timed out"));
+    }
+
+    #[tokio::test]
+    async fn test_failing_hop_is_reported() {
+        let (seen_tx, _seen) = mpsc::unbounded_channel();
+        let hop_a = connect_stub(seen_tx).await;
+        let closed = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let closed_port = closed.local_addr().unwrap().port();
+        drop(closed);
+
+        let pool = ConnectionPool::new(PoolConfig::default());
+        let chain = vec![simple_proxy(hop_a), simple_proxy(closed_port)];
+        let err = connect_through_chain(&chain, Some("127.0.0.1:1"), &pool).await.unwrap_err();
+        assert_eq!(err.hop, 1);
+        assert_eq!(err.reason, "connect_failed");
+    }
+}
@@ -0,0 +1,106 @@
+//! Cap on the connections a single client keeps open to the proxy server
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Slot held by a client connection, released when dropped
+#[derive(Debug)]
+pub struct ClientPermit {
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+/// Per source IP semaphores bounding concurrent connections
+#[derive(Debug, Default)]
+pub struct ClientLimiter {
+    clients: Mutex<HashMap<IpAddr, Arc<Semaphore>>>,
+    limit: usize,
+}
+
+impl ClientLimiter {
+    /// Allow `limit` concurrent connections per client, 0 disables the cap
+    pub fn new(limit: usize) -> Self {
+        Self {
+            clients: Mutex::new(HashMap::new()),
+            limit,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.limit > 0
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Take a slot for a connection from `client_ip`, `None` when it already has `limit` open
+    pub fn try_acquire(&self, client_ip: IpAddr) -> Option<ClientPermit> {
+        if !self.is_enabled() {
+            return Some(ClientPermit { _permit: None });
+        }
+        // Acquired under the lock, so `cleanup_idle` cannot forget the semaphore in between
+        let mut clients = self.clients.lock();
+        let semaphore = clients
+            .entry(client_ip)
+            .or_insert_with(|| Arc::new(Semaphore::new(self.limit)));
+        Arc::clone(semaphore)
+            .try_acquire_owned()
+            .ok()
+            .map(|permit| ClientPermit { _permit: Some(permit) })
+    }
+
+    /// Forget clients without an open connection
+    pub fn cleanup_idle(&self) {
+        let limit = self.limit;
+        self.clients
+            .lock()
+            .retain(|_, semaphore| semaphore.available_permits() < limit);
+    }
+
+    /// Number of clients tracked
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.clients.lock().len()
+    }
+
+    #[cfg(test)]
+    pub fn is_empty(&self) -> bool {
+        self.clients.lock().is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_idle_clients_are_forgotten() {
+        let limiter = ClientLimiter::new(2);
+        let client = IpAddr::from([192, 0, 2, 1]);
+        let first = limiter.try_acquire(client).unwrap();
+        let second = limiter.try_acquire(client).unwrap();
+        assert!(limiter.try_acquire(client).is_none());
+
+        drop(first);
+        limiter.cleanup_idle();
+        assert_eq!(limiter.len(), 1);
+        assert!(limiter.try_acquire(client).is_some());
+
+        drop(second);
+        limiter.cleanup_idle();
+        assert!(limiter.is_empty());
+    }
+
+    #[test]
+    fn test_zero_limit_never_refuses() {
+        let limiter = ClientLimiter::new(0);
+        let client = IpAddr::from([192, 0, 2, 1]);
+        let permits: Vec<_> = (0..100).map(|_| limiter.try_acquire(client)).collect();
+        assert!(permits.iter().all(Option::is_some));
+        assert!(limiter.is_empty());
+    }
+}
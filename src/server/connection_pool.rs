@@ -1,18 +1,19 @@
 //! High-performance TCP connection pooling for proxy connections
 
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     time::{Duration, Instant},
 };
 
+use futures_util::future::join_all;
+
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
     net::TcpStream,
     sync::{Mutex, RwLock},
     time::timeout,
 };
 
-use crate::utils::http::response::ResponseParser;
+use serde::Serialize;
 
 /// Configuration for connection pool
 #[derive(Debug, Clone)]
@@ -23,8 +24,12 @@ pub struct PoolConfig {
     pub max_idle_time: Duration,
     /// Maximum total connections in pool
     pub max_total_connections: usize,
-    /// Connection timeout
-    pub connection_timeout: Duration,
+    /// Time allowed to open a TCP connection to a proxy
+    pub connect_timeout: Duration,
+    /// Time allowed for each read of a proxy response
+    pub read_timeout: Duration,
+    /// Time allowed to send a request to a proxy
+    pub write_timeout: Duration,
     /// Health check interval
     pub health_check_interval: Duration,
 }
@@ -35,7 +40,9 @@ impl Default for PoolConfig {
             max_connections_per_proxy: 5,
             max_idle_time: Duration::from_secs(30),
             max_total_connections: 1000,
-            connection_timeout: Duration::from_secs(5),
+            connect_timeout: Duration::from_secs(8),
+            read_timeout: Duration::from_secs(8),
+            write_timeout: Duration::from_secs(8),
             health_check_interval: Duration::from_secs(60),
         }
     }
@@ -169,7 +176,7 @@ pub struct ConnectionPool {
 }
 
 /// Statistics for a specific proxy pool
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct PoolStats {
     pub total_connections: usize,
     pub available_connections: usize,
@@ -178,7 +185,7 @@ pub struct PoolStats {
 }
 
 /// Global pool statistics
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct PoolGlobalStats {
     pub total_pools: usize,
     pub total_connections: usize,
@@ -201,6 +208,14 @@ impl ConnectionPool {
 
     /// Get a connection from the pool or create a new one
     pub async fn get_connection(&self, proxy_addr: &str) -> Result<TcpStream, Box<dyn std::error::Error + Send + Sync>> {
+        self.get_connection_with_reuse(proxy_addr).await.map(|(stream, _)| stream)
+    }
+
+    /// Like `get_connection`, also telling whether the connection was reused from the pool
+    pub async fn get_connection_with_reuse(
+        &self,
+        proxy_addr: &str,
+    ) -> Result<(TcpStream, bool), Box<dyn std::error::Error + Send + Sync>> {
         // Periodic cleanup
         self.maybe_cleanup().await;
 
@@ -225,7 +240,7 @@ impl ConnectionPool {
                 // Since we need to return the stream but also keep the pooled connection,
                 // we need to handle this differently. We'll return the stream directly
                 // and let the caller create a new connection if needed.
-                return Ok(pooled_conn.stream);
+                return Ok((pooled_conn.stream, true));
             } else {
                 log::debug!("Connection to {} is dead, creating new one", proxy_addr);
                 // Connection is dead, remove it and continue to create new one
@@ -235,7 +250,7 @@ impl ConnectionPool {
         // Create new connection
         log::debug!("Creating new connection to {}", proxy_addr);
         let stream = timeout(
-            self.config.connection_timeout,
+            self.config.connect_timeout,
             TcpStream::connect(proxy_addr)
         ).await??;
 
@@ -252,7 +267,57 @@ impl ConnectionPool {
         // For now, we'll not pool the connection to avoid clone issues
         log::debug!("Connection established for {} (not pooled due to Tokio limitations)", proxy_addr);
 
-        Ok(stream)
+        Ok((stream, false))
+    }
+
+    /// Open idle connections to `proxies` ahead of the first requests, up to `per_proxy`
+    /// each within `max_connections_per_proxy` and `max_total_connections`.
+    /// Returns the number of connections opened.
+    pub async fn warmup(&self, proxies: &[String], per_proxy: usize) -> usize {
+        let per_proxy = per_proxy.min(self.config.max_connections_per_proxy);
+        let planned = {
+            let pools = self.proxy_pools.read().await;
+            let held: usize = pools.values().map(|pool| pool.connections.len()).sum();
+            let mut room = self.config.max_total_connections.saturating_sub(held);
+            let mut seen = HashSet::new();
+            let mut planned = Vec::new();
+            for proxy in proxies.iter().filter(|proxy| seen.insert(proxy.as_str())) {
+                let held = pools.get(proxy).map_or(0, |pool| pool.connections.len());
+                let count = per_proxy.saturating_sub(held).min(room);
+                room -= count;
+                planned.extend(std::iter::repeat_n(proxy.clone(), count));
+            }
+            planned
+        };
+
+        let connections = join_all(planned.into_iter().map(|proxy| async move {
+            let stream = timeout(self.config.connect_timeout, TcpStream::connect(&proxy)).await;
+            (proxy, stream)
+        }))
+        .await;
+
+        let mut pools = self.proxy_pools.write().await;
+        let mut stats = self.stats.write().await;
+        let mut opened = 0;
+        for (proxy, stream) in connections {
+            match stream {
+                Ok(Ok(stream)) => {
+                    let pool = pools
+                        .entry(proxy.clone())
+                        .or_insert_with(|| ProxyConnectionPool::new(proxy));
+                    // Connections may have been returned while these were opening
+                    if pool.connections.len() < self.config.max_connections_per_proxy {
+                        pool.total_connections += 1;
+                        pool.return_connection(PooledConnection::new(stream));
+                        stats.connections_created += 1;
+                        opened += 1;
+                    }
+                }
+                Ok(Err(e)) => log::debug!("Warmup connection to {} failed: {}", proxy, e),
+                Err(_) => log::debug!("Warmup connection to {} timed out", proxy),
+            }
+        }
+        opened
     }
 
     /// Return a connection to the pool
@@ -272,10 +337,8 @@ impl ConnectionPool {
         match stream.try_read(&mut [0u8; 1]) {
             Ok(0) => false, // Connection closed
             Ok(_) => true,  // Data available (connection alive)
-            Err(_) => {
-                // Would block or error - try peek
-                stream.peek(&mut [0u8; 1]).await.is_ok()
-            }
+            // Nothing to read on an idle connection, waiting for data would hang
+            Err(e) => e.kind() == std::io::ErrorKind::WouldBlock,
         }
     }
 
@@ -359,55 +422,10 @@ impl ConnectionPool {
     }
 }
 
-/// Helper function to send CONNECT request through pooled connection
-pub async fn send_connect_request_pooled(
-    stream: &mut TcpStream,
-    host: &str,
-    timeout_in_seconds: u64,
-) -> bool {
-    let connect = format!(
-        "CONNECT {0}:443 HTTP/1.1\r\nHost: {0}:443\r\nProxy-Connection: Keep-Alive\r\n\r\n",
-        host
-    );
-    
-    // Send data
-    if let Ok(Ok(_)) = timeout(
-        Duration::from_secs(timeout_in_seconds),
-        stream.write_all(connect.as_bytes()),
-    ).await {
-        // Read Response
-        let data = read_timeout(stream, timeout_in_seconds).await;
-        let response = ResponseParser::parse(data.as_slice());
-
-        if let Some(status_code) = response.status_code {
-            return status_code == 200;
-        }
-    }
-    false
-}
-
-async fn read_timeout<R: tokio::io::AsyncRead + Unpin>(reader: &mut R, timeout_in_seconds: u64) -> Vec<u8> {
-    let mut data = vec![];
-    loop {
-        let mut buf = [0; 512];
-        if let Ok(Ok(buf_size)) = timeout(
-            Duration::from_secs(timeout_in_seconds),
-            reader.read(&mut buf),
-        ).await {
-            if buf_size == 0 {
-                break;
-            }
-            data.extend(&buf[..buf_size]);
-            continue;
-        }
-        break;
-    }
-    data
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
     use tokio::time::sleep;
     use std::sync::Arc;
 
@@ -452,4 +470,47 @@ mod tests {
         let stats = pool.get_global_stats().await;
         assert_eq!(stats.total_connections, 0);
     }
+
+    /// Local server echoing back whatever its clients send
+    async fn echo_server() -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let (mut reader, mut writer) = stream.split();
+                    let _ = tokio::io::copy(&mut reader, &mut writer).await;
+                });
+            }
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_warmup_populates_pool() {
+        let config = PoolConfig {
+            max_connections_per_proxy: 2,
+            max_total_connections: 3,
+            ..Default::default()
+        };
+        let pool = ConnectionPool::new(config);
+        let (first, second) = (echo_server().await, echo_server().await);
+
+        let opened = pool.warmup(&[first.clone(), second.clone(), first.clone()], 5).await;
+        assert_eq!(opened, 3);
+        let stats = pool.get_global_stats().await;
+        assert_eq!(stats.total_pools, 2);
+        assert_eq!(stats.total_connections, 3);
+        assert_eq!(stats.connections_created, 3);
+        assert_eq!(pool.get_proxy_stats(&first).await.unwrap().available_connections, 2);
+        assert_eq!(pool.get_proxy_stats(&second).await.unwrap().available_connections, 1);
+
+        // The first request goes through a warm connection
+        let mut stream = pool.get_connection(&first).await.unwrap();
+        assert_eq!(pool.get_global_stats().await.cache_hits, 1);
+        stream.write_all(b"ping").await.unwrap();
+        let mut echoed = [0; 4];
+        stream.read_exact(&mut echoed).await.unwrap();
+        assert_eq!(&echoed, b"ping");
+    }
 }
@@ -0,0 +1,92 @@
+//! Header rewriting applied to the requests the local server forwards
+
+use hyper::header::{HeaderMap, HeaderName, HeaderValue};
+
+use crate::config::dynamic::HeaderRule;
+
+/// Parsed `server.header_rules`, applied in order to each forwarded request.
+///
+/// CONNECT tunnels only see their CONNECT request rewritten: the tunnelled bytes,
+/// e.g. a TLS session, are passed through untouched.
+#[derive(Debug, Clone, Default)]
+pub struct HeaderRules {
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone)]
+enum Rule {
+    Set(HeaderName, HeaderValue),
+    Remove(HeaderName),
+}
+
+impl HeaderRules {
+    /// Parse `rules`, skipping the ones whose name or value is not a valid header
+    pub fn new(rules: &[HeaderRule]) -> Self {
+        let rules = rules
+            .iter()
+            .filter_map(|rule| match to_rule(rule) {
+                Ok(rule) => Some(rule),
+                Err(e) => {
+                    log::warn!("Ignoring header rule {:?}: {}", rule, e);
+                    None
+                }
+            })
+            .collect();
+        Self { rules }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.rules.is_empty()
+    }
+
+    pub fn apply(&self, headers: &mut HeaderMap) {
+        for rule in &self.rules {
+            match rule {
+                Rule::Set(name, value) => {
+                    headers.insert(name.clone(), value.clone());
+                }
+                Rule::Remove(name) => {
+                    headers.remove(name);
+                }
+            }
+        }
+    }
+}
+
+/// Check a configured rule, the error says what is wrong with it
+pub fn validate(rule: &HeaderRule) -> Result<(), String> {
+    to_rule(rule).map(|_| ())
+}
+
+fn to_rule(rule: &HeaderRule) -> Result<Rule, String> {
+    let name = |name: &str| HeaderName::from_bytes(name.as_bytes()).map_err(|_| format!("invalid header name {:?}", name));
+    match rule {
+        HeaderRule::Set { name: header, value } => {
+            let value = HeaderValue::from_str(value).map_err(|_| format!("invalid value for {}", header))?;
+            Ok(Rule::Set(name(header)?, value))
+        }
+        HeaderRule::Remove { name: header } => Ok(Rule::Remove(name(header)?)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rules_apply_in_order() {
+        let rules = HeaderRules::new(&[
+            HeaderRule::Remove { name: "Via".to_string() },
+            HeaderRule::Set { name: "Accept-Language".to_string(), value: "en-US".to_string() },
+            HeaderRule::Set { name: "bad header".to_string(), value: "x".to_string() },
+        ]);
+        assert_eq!(rules.rules.len(), 2);
+
+        let mut headers = HeaderMap::new();
+        headers.insert("via", HeaderValue::from_static("1.1 corp-proxy"));
+        headers.insert("accept-language", HeaderValue::from_static("fr-FR"));
+        rules.apply(&mut headers);
+        assert!(!headers.contains_key("via"));
+        assert_eq!(headers["accept-language"], "en-US");
+    }
+}
@@ -0,0 +1,176 @@
+//! Recent latencies of each proxy, showing how steady it is beyond its average
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use dashmap::DashMap;
+use lazy_static::lazy_static;
+use serde::Serialize;
+
+use crate::proxy::host_port;
+
+/// Latencies kept per proxy, older ones are dropped
+pub const LATENCY_SAMPLES: usize = 32;
+/// Proxies tracked at most, the least recently measured ones are dropped beyond
+pub const LATENCY_PROXIES: usize = 10_000;
+
+lazy_static! {
+    /// Latencies of successful checks and forwards, read by the API
+    pub static ref LATENCIES: LatencyRegistry = LatencyRegistry::default();
+}
+
+/// Summary of the latencies kept for a proxy
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct LatencyStats {
+    pub samples: usize,
+    pub min_ms: u64,
+    pub avg_ms: f64,
+    pub p95_ms: u64,
+    pub max_ms: u64,
+}
+
+/// Ring buffer of the last `LATENCY_SAMPLES` latencies of one proxy
+#[derive(Debug, Clone, Default)]
+pub struct LatencyRing {
+    samples: VecDeque<u64>,
+    /// Registry tick of the last sample, lower means measured longer ago
+    last_tick: u64,
+}
+
+impl LatencyRing {
+    pub fn push(&mut self, latency: Duration) {
+        if self.samples.len() == LATENCY_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(latency.as_millis() as u64);
+    }
+
+    /// Min, average, nearest-rank 95th percentile and max, `None` before any sample
+    pub fn stats(&self) -> Option<LatencyStats> {
+        let mut sorted: Vec<u64> = self.samples.iter().copied().collect();
+        sorted.sort_unstable();
+        let (&min_ms, &max_ms) = (sorted.first()?, sorted.last()?);
+        let rank = (sorted.len() as f64 * 0.95).ceil() as usize;
+        Some(LatencyStats {
+            samples: sorted.len(),
+            min_ms,
+            avg_ms: sorted.iter().sum::<u64>() as f64 / sorted.len() as f64,
+            p95_ms: sorted[rank.saturating_sub(1)],
+            max_ms,
+        })
+    }
+}
+
+/// Latency rings keyed by `host:port`, at most `capacity` of them
+#[derive(Debug)]
+pub struct LatencyRegistry {
+    proxies: DashMap<String, LatencyRing>,
+    capacity: usize,
+    ticks: AtomicU64,
+}
+
+impl Default for LatencyRegistry {
+    fn default() -> Self {
+        Self::with_capacity(LATENCY_PROXIES)
+    }
+}
+
+impl LatencyRegistry {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            proxies: DashMap::new(),
+            capacity: capacity.max(1),
+            ticks: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record(&self, host: &str, port: u16, latency: Duration) {
+        {
+            let mut ring = self.proxies.entry(host_port(host, port)).or_default();
+            ring.push(latency);
+            ring.last_tick = self.ticks.fetch_add(1, Ordering::Relaxed);
+        }
+        if self.proxies.len() > self.capacity {
+            // Make room for a tenth more at once rather than evicting on every record
+            self.evict_to(self.capacity - self.capacity / 10);
+        }
+    }
+
+    pub fn stats(&self, host: &str, port: u16) -> Option<LatencyStats> {
+        self.proxies.get(&host_port(host, port))?.stats()
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.proxies.len()
+    }
+
+    /// Drop the least recently measured proxies down to `keep` of the capacity,
+    /// returns the number dropped
+    pub fn trim(&self, keep: f64) -> usize {
+        self.evict_to((self.capacity as f64 * keep.clamp(0.0, 1.0)) as usize)
+    }
+
+    fn evict_to(&self, target: usize) -> usize {
+        let excess = self.proxies.len().saturating_sub(target);
+        if excess == 0 {
+            return 0;
+        }
+        let mut ticks: Vec<(u64, String)> =
+            self.proxies.iter().map(|ring| (ring.last_tick, ring.key().clone())).collect();
+        ticks.sort_unstable();
+        for (_, key) in ticks.into_iter().take(excess) {
+            self.proxies.remove(&key);
+        }
+        excess
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latency_stats() {
+        let registry = LatencyRegistry::default();
+        assert_eq!(registry.stats("192.0.2.1", 8080), None);
+
+        for ms in [120, 80, 100, 400, 90, 110, 95, 105, 85, 115] {
+            registry.record("192.0.2.1", 8080, Duration::from_millis(ms));
+        }
+        let stats = registry.stats("192.0.2.1", 8080).unwrap();
+        assert_eq!(
+            stats,
+            LatencyStats { samples: 10, min_ms: 80, avg_ms: 130.0, p95_ms: 400, max_ms: 400 }
+        );
+
+        // Only the most recent samples are kept
+        for _ in 0..LATENCY_SAMPLES {
+            registry.record("192.0.2.1", 8080, Duration::from_millis(50));
+        }
+        let stats = registry.stats("192.0.2.1", 8080).unwrap();
+        assert_eq!((stats.samples, stats.min_ms, stats.max_ms), (LATENCY_SAMPLES, 50, 50));
+    }
+
+    #[test]
+    fn test_least_recently_measured_proxies_are_dropped() {
+        let registry = LatencyRegistry::with_capacity(10);
+        for port in 0..10 {
+            registry.record("192.0.2.1", port, Duration::from_millis(100));
+        }
+        // Measured again, so newer than the other early ones
+        registry.record("192.0.2.1", 0, Duration::from_millis(100));
+
+        registry.record("192.0.2.1", 10, Duration::from_millis(100));
+        assert_eq!(registry.len(), 9);
+        assert!(registry.stats("192.0.2.1", 0).is_some());
+        assert!(registry.stats("192.0.2.1", 1).is_none());
+        assert!(registry.stats("192.0.2.1", 10).is_some());
+
+        // Memory pressure keeps the most recent half
+        assert_eq!(registry.trim(0.5), 4);
+        assert!(registry.stats("192.0.2.1", 0).is_some());
+        assert!(registry.stats("192.0.2.1", 5).is_none());
+    }
+}
@@ -2,32 +2,53 @@ pub mod proxy_pool;
 pub mod connection_pool;
 pub mod multi_cache;
 pub mod async_optimizer;
+pub mod sticky;
+pub mod chain;
+pub mod tunnel;
+pub mod socks5;
+pub mod target_filter;
+pub mod header_rules;
+pub mod latency;
+pub mod audit;
+pub mod client_limit;
 
+use std::net::IpAddr;
+use std::path::PathBuf;
+#[cfg(unix)]
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
-use hyper::{service::service_fn, Method, Request, Response, StatusCode};
+use hyper::{header::HeaderValue, service::service_fn, Method, Request, Response, StatusCode};
 use hyper::body::Bytes;
-use hyper_util::rt::TokioIo;
+use hyper_util::rt::{TokioExecutor, TokioIo};
 use hyper_util::server::conn::auto;
-use http_body_util::{BodyExt, Full};
+use http_body_util::{BodyExt, Full, LengthLimitError, Limited};
 use lazy_static::lazy_static;
 use parking_lot::Mutex;
-use tokio::{
-    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
-    net::{TcpListener, TcpStream},
-    time::timeout,
-};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
 
 use self::proxy_pool::{ProxyPool, SimpleProxy, LIVE_PROXIES};
 use self::connection_pool::{ConnectionPool, PoolConfig};
 use self::multi_cache::{MultiCache, MultiCacheConfig, ProxyValidationCache, ConnectionMetadataCache};
-use crate::utils::http::response::ResponseParser;
+use self::sticky::{SessionKey, StickyMap};
+use self::chain::{connect_through_chain, connect_through_chain_with_reuse, ChainError};
+use self::tunnel::TunnelCache;
+use self::target_filter::TargetFilter;
+use self::header_rules::HeaderRules;
+use self::latency::LATENCIES;
+use self::audit::{AuditEntry, AUDIT_LOG};
+use self::client_limit::ClientLimiter;
+use crate::performance::PERFORMANCE_MONITOR;
+use crate::utils::http::user_agents::UserAgents;
 
 lazy_static! {
     pub static ref POOL: Mutex<ProxyPool> = Mutex::new(ProxyPool::new());
     pub static ref CONNECTION_POOL: Arc<ConnectionPool> = Arc::new(ConnectionPool::new(PoolConfig::default()));
-    static ref VALIDATION_CACHE: Arc<ProxyValidationCache> = Arc::new(MultiCache::new(MultiCacheConfig::default()));
+    /// Recent proxy verdicts, shared by the checkers of find and serve
+    pub static ref VALIDATION_CACHE: Arc<ProxyValidationCache> = Arc::new(MultiCache::new(MultiCacheConfig::default()));
     static ref CONNECTION_METADATA_CACHE: Arc<ConnectionMetadataCache> = Arc::new(MultiCache::new(MultiCacheConfig {
         l1_size: 500,
         l2_size: 2000,
@@ -38,15 +59,129 @@ lazy_static! {
         l1_promotion_threshold: 0.05,
         cleanup_interval: Duration::from_secs(120),
     }));
+    /// Source of request ids, seeded randomly so ids differ across restarts
+    static ref REQUEST_COUNTER: AtomicU32 = AtomicU32::new(rand::random());
 }
 
-const TIMEOUT_IN_SECONDS: u64 = 8;
+/// Default cap on a proxied response body
+pub const DEFAULT_MAX_RESPONSE_BYTES: usize = 16 * 1024 * 1024;
+
+/// Response header carrying the id of a forwarded request
+pub const REQUEST_ID_HEADER: &str = "X-Proxy-Rs-Request-Id";
+
+/// Longest wait for the pool to reach `min_queue` proxies before warming up anyway
+const WARMUP_MAX_WAIT: Duration = Duration::from_secs(30);
+
+/// Answer to an HTTP client over its connection cap, sent before any request is read
+const TOO_MANY_CONNECTIONS_RESPONSE: &[u8] =
+    b"HTTP/1.1 429 Too Many Requests\r\nContent-Length: 20\r\nConnection: close\r\n\r\nToo many connections";
+
+/// The `n` healthiest proxies once the pool holds `min_queue` of them, or what it
+/// holds after `max_wait`, so warmup is not done on the first proxy found
+async fn warmup_targets(n: usize, max_wait: Duration) -> Vec<String> {
+    let deadline = tokio::time::Instant::now() + max_wait;
+    loop {
+        {
+            let mut pool = POOL.lock();
+            pool.import_live();
+            if pool.available() >= pool.min_queue.max(0) as usize || tokio::time::Instant::now() >= deadline {
+                return pool.healthiest(n);
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
+
+/// Trim the server caches to `keep` of their capacity, e.g. under memory pressure
+pub async fn trim_caches(keep: f64) {
+    let evicted = VALIDATION_CACHE.trim(keep).await
+        + CONNECTION_METADATA_CACHE.trim(keep).await
+        + LATENCIES.trim(keep);
+    if evicted > 0 {
+        log::debug!("Trimmed {} cache entries", evicted);
+    }
+}
+
+/// Short id correlating the log lines of one forwarded request
+fn next_request_id() -> String {
+    format!("{:08x}", REQUEST_COUNTER.fetch_add(1, Ordering::Relaxed))
+}
+
+/// Per-request forwarding settings, copied from the `Server` fields of the same name
+#[derive(Debug, Clone, Copy)]
+struct ForwardOptions {
+    chain_length: usize,
+    max_response_bytes: usize,
+    request_id_header: bool,
+}
+
+impl From<&Server> for ForwardOptions {
+    fn from(server: &Server) -> Self {
+        Self {
+            chain_length: server.chain_length,
+            max_response_bytes: server.max_response_bytes,
+            request_id_header: server.request_id_header,
+        }
+    }
+}
+
+impl Default for ForwardOptions {
+    /// The settings of a server left as built by `Server::new`
+    fn default() -> Self {
+        Self::from(&Server::new("127.0.0.1", 0))
+    }
+}
+
+/// Protocol spoken by clients of the local listener
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ListenProtocol {
+    /// HTTP forward proxy with CONNECT support
+    #[default]
+    Http,
+    /// SOCKS5 with the CONNECT command
+    Socks5,
+}
+
+impl std::str::FromStr for ListenProtocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "http" => Ok(Self::Http),
+            "socks5" => Ok(Self::Socks5),
+            other => Err(format!("unknown listen protocol: {}", other)),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct Server {
     pub host: String,
     pub port: u16,
     pub connection_pool: Arc<ConnectionPool>,
+    pub sticky_sessions: Arc<StickyMap>,
+    /// Number of upstream proxies each request is tunnelled through
+    pub chain_length: usize,
+    /// Upstream response bodies above this size are answered with a 502
+    pub max_response_bytes: usize,
+    pub tunnel_cache: Arc<TunnelCache>,
+    pub protocol: ListenProtocol,
+    /// Echo each request id back in the `X-Proxy-Rs-Request-Id` response header
+    pub request_id_header: bool,
+    /// Destinations clients may reach, others get a 403
+    pub target_filter: Arc<TargetFilter>,
+    /// Number of healthiest proxies connected to ahead of the first requests
+    pub warmup_proxies: usize,
+    /// Idle connections opened to each of those proxies
+    pub warmup_connections: usize,
+    /// Replace the User-Agent of forwarded requests with these when set
+    pub user_agents: Option<Arc<UserAgents>>,
+    /// Header changes applied to forwarded requests
+    pub header_rules: Arc<HeaderRules>,
+    /// Listen on this Unix domain socket instead of `host:port`
+    pub unix_socket: Option<PathBuf>,
+    /// Concurrent connections allowed per client IP, extra ones get a 429
+    pub client_limiter: Arc<ClientLimiter>,
 }
 
 impl Server {
@@ -55,6 +190,19 @@ impl Server {
             host: host.to_string(),
             port,
             connection_pool: Arc::clone(&CONNECTION_POOL),
+            sticky_sessions: Arc::new(StickyMap::default()),
+            chain_length: 1,
+            max_response_bytes: DEFAULT_MAX_RESPONSE_BYTES,
+            tunnel_cache: Arc::new(TunnelCache::default()),
+            protocol: ListenProtocol::Http,
+            request_id_header: false,
+            target_filter: Arc::new(TargetFilter::default()),
+            warmup_proxies: 0,
+            warmup_connections: 0,
+            user_agents: None,
+            header_rules: Arc::new(HeaderRules::default()),
+            unix_socket: None,
+            client_limiter: Arc::new(ClientLimiter::default()),
         }
     }
 
@@ -63,9 +211,97 @@ impl Server {
             host: host.to_string(),
             port,
             connection_pool: Arc::new(ConnectionPool::new(pool_config)),
+            sticky_sessions: Arc::new(StickyMap::default()),
+            chain_length: 1,
+            max_response_bytes: DEFAULT_MAX_RESPONSE_BYTES,
+            tunnel_cache: Arc::new(TunnelCache::default()),
+            protocol: ListenProtocol::Http,
+            request_id_header: false,
+            target_filter: Arc::new(TargetFilter::default()),
+            warmup_proxies: 0,
+            warmup_connections: 0,
+            user_agents: None,
+            header_rules: Arc::new(HeaderRules::default()),
+            unix_socket: None,
+            client_limiter: Arc::new(ClientLimiter::default()),
         }
     }
 
+    /// Keep routing a client to the same upstream proxy for `ttl` (zero disables)
+    pub fn with_sticky_ttl(mut self, ttl: Duration) -> Self {
+        self.sticky_sessions = Arc::new(StickyMap::new(ttl));
+        self
+    }
+
+    /// Route requests through `chain_length` proxies in a row (1 means a single proxy)
+    pub fn with_chain_length(mut self, chain_length: usize) -> Self {
+        self.chain_length = chain_length.max(1);
+        self
+    }
+
+    /// Keep CONNECT tunnels a client left unused for `idle_timeout` and hand them
+    /// to later CONNECTs to the same target through the same proxy (zero disables)
+    pub fn with_tunnel_reuse(mut self, idle_timeout: Duration) -> Self {
+        self.tunnel_cache = Arc::new(TunnelCache::new(idle_timeout));
+        self
+    }
+
+    /// Refuse upstream responses whose body exceeds `max_response_bytes`
+    pub fn with_max_response_bytes(mut self, max_response_bytes: usize) -> Self {
+        self.max_response_bytes = max_response_bytes;
+        self
+    }
+
+    /// Speak `protocol` to local clients
+    pub fn with_protocol(mut self, protocol: ListenProtocol) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    /// Echo each request id back in the `X-Proxy-Rs-Request-Id` response header
+    pub fn with_request_id_header(mut self, enabled: bool) -> Self {
+        self.request_id_header = enabled;
+        self
+    }
+
+    /// Open `per_proxy` connections to each of the `proxies` healthiest proxies at start
+    pub fn with_warmup(mut self, proxies: usize, per_proxy: usize) -> Self {
+        self.warmup_proxies = proxies;
+        self.warmup_connections = per_proxy;
+        self
+    }
+
+    /// Send forwarded requests with a User-Agent from `user_agents` instead of the client's
+    pub fn with_user_agents(mut self, user_agents: Arc<UserAgents>) -> Self {
+        self.user_agents = Some(user_agents);
+        self
+    }
+
+    /// Refuse requests whose target host is denied by `target_filter`
+    pub fn with_target_filter(mut self, target_filter: TargetFilter) -> Self {
+        self.target_filter = Arc::new(target_filter);
+        self
+    }
+
+    /// Rewrite the headers of forwarded requests with `header_rules`
+    pub fn with_header_rules(mut self, header_rules: HeaderRules) -> Self {
+        self.header_rules = Arc::new(header_rules);
+        self
+    }
+
+    /// Listen on the Unix domain socket at `path` instead of `host:port`, Unix only
+    pub fn with_unix_socket(mut self, path: PathBuf) -> Self {
+        self.unix_socket = Some(path);
+        self
+    }
+
+    /// Allow each client IP `limit` concurrent connections, refusing extra ones with a 429
+    /// (0 disables). Unix socket clients all share the loopback address and its cap.
+    pub fn with_max_connections_per_client(mut self, limit: usize) -> Self {
+        self.client_limiter = Arc::new(ClientLimiter::new(limit));
+        self
+    }
+
     pub async fn start(&self) {
         log::info!("Starting proxy server with connection pooling enabled");
         log::info!("Pool config: max_connections_per_proxy={}, max_idle_time={:?}s", 
@@ -76,12 +312,43 @@ impl Server {
         let stats = self.connection_pool.get_global_stats().await;
         log::info!("Initial pool stats: {} pools, {} connections", stats.total_pools, stats.total_connections);
 
+        if self.sticky_sessions.is_enabled() {
+            log::info!("Sticky sessions enabled with ttl={}s", self.sticky_sessions.ttl().as_secs());
+        }
+        if self.chain_length > 1 {
+            log::info!("Proxy chaining enabled with {} hops", self.chain_length);
+        }
+        if self.tunnel_cache.is_enabled() {
+            log::info!("CONNECT tunnel reuse enabled with idle timeout={}s", self.tunnel_cache.idle_timeout().as_secs());
+        }
+        if self.target_filter.is_enabled() {
+            log::info!("Target domain filtering enabled");
+        }
+        if self.header_rules.is_enabled() {
+            log::info!("Request header rules enabled");
+        }
+        if self.client_limiter.is_enabled() {
+            log::info!("Connections limited to {} per client", self.client_limiter.limit());
+        }
+
         // Start periodic stats logging
         let connection_pool_clone = Arc::clone(&self.connection_pool);
+        let sticky_sessions_clone = Arc::clone(&self.sticky_sessions);
+        let tunnel_cache_clone = Arc::clone(&self.tunnel_cache);
+        let client_limiter_clone = Arc::clone(&self.client_limiter);
         tokio::spawn(async move {
             let mut interval = tokio::time::interval(Duration::from_secs(60));
             loop {
                 interval.tick().await;
+                sticky_sessions_clone.cleanup_expired();
+                tunnel_cache_clone.cleanup_expired();
+                client_limiter_clone.cleanup_idle();
+                if !tunnel_cache_clone.is_empty() {
+                    log::info!("Idle CONNECT tunnels: {}", tunnel_cache_clone.len());
+                }
+                if !sticky_sessions_clone.is_empty() {
+                    log::info!("Sticky sessions: {} active", sticky_sessions_clone.len());
+                }
                 let stats = connection_pool_clone.get_global_stats().await;
                 if stats.cache_hits + stats.cache_misses > 0 {
                     let hit_rate = stats.cache_hits as f64 / (stats.cache_hits + stats.cache_misses) as f64 * 100.0;
@@ -95,72 +362,309 @@ impl Server {
             continue;
         }
 
+        if self.warmup_proxies > 0 && self.warmup_connections > 0 {
+            let targets = warmup_targets(self.warmup_proxies, WARMUP_MAX_WAIT).await;
+            let opened = self.connection_pool.warmup(&targets, self.warmup_connections).await;
+            log::info!("Warmed up {} connections to {} proxies", opened, targets.len());
+        }
+
+        #[cfg(unix)]
+        if let Some(path) = &self.unix_socket {
+            return self.listen_unix(path).await;
+        }
+        #[cfg(not(unix))]
+        if self.unix_socket.is_some() {
+            log::error!("Unix domain sockets are not supported on this platform");
+            return;
+        }
+
         let addr = format!("{}:{}", self.host, self.port);
         if let Ok(listener) = TcpListener::bind(&addr).await {
-            log::info!("Listening on http://{}", addr);
+            log::info!("Listening on {}://{}", self.scheme(), addr);
 
             loop {
                 if let Ok((stream, addr)) = listener.accept().await {
                     log::info!("Accepted connection from {}", addr);
-                    let connection_pool = Arc::clone(&self.connection_pool);
-                    tokio::task::spawn(async move {
-                        let io = TokioIo::new(stream);
-                        // Simplified connection handling for compilation
-                        log::info!("Connection accepted - simplified handling");
-                        // Full hyper implementation would be added here for production
-                    });
+                    self.serve_stream(stream, addr.ip(), addr.to_string());
                 }
             }
         }
     }
+
+    /// Accept clients on the Unix domain socket at `path`, removed again when the
+    /// server stops. Clients are seen as coming from the loopback address.
+    #[cfg(unix)]
+    async fn listen_unix(&self, path: &Path) {
+        use std::os::unix::fs::FileTypeExt;
+
+        // A socket left by a previous run would make the bind fail, other files are kept
+        if std::fs::metadata(path).is_ok_and(|meta| meta.file_type().is_socket()) {
+            let _ = std::fs::remove_file(path);
+        }
+        let listener = match tokio::net::UnixListener::bind(path) {
+            Ok(listener) => listener,
+            Err(err) => {
+                log::error!("Failed to listen on {}: {}", path.display(), err);
+                return;
+            }
+        };
+        let _socket_file = SocketFile(path.to_path_buf());
+        log::info!("Listening on {}+unix://{}", self.scheme(), path.display());
+
+        loop {
+            if let Ok((stream, _)) = listener.accept().await {
+                log::info!("Accepted connection on {}", path.display());
+                self.serve_stream(stream, IpAddr::from([127, 0, 0, 1]), path.display().to_string());
+            }
+        }
+    }
+
+    fn scheme(&self) -> &'static str {
+        match self.protocol {
+            ListenProtocol::Http => "http",
+            ListenProtocol::Socks5 => "socks5",
+        }
+    }
+
+    /// Serve one client connection in its own task. A client already holding its
+    /// share of connections gets a 429 (HTTP) or is disconnected (SOCKS5).
+    fn serve_stream<S>(&self, mut stream: S, client_ip: IpAddr, peer: String)
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let Some(permit) = self.client_limiter.try_acquire(client_ip) else {
+            log::warn!("Refusing connection from {}: {} connections already open", peer, self.client_limiter.limit());
+            if self.protocol == ListenProtocol::Http {
+                tokio::task::spawn(async move {
+                    let _ = stream.write_all(TOO_MANY_CONNECTIONS_RESPONSE).await;
+                    let _ = stream.shutdown().await;
+                });
+            }
+            return;
+        };
+        let connection_pool = Arc::clone(&self.connection_pool);
+        let sticky_sessions = Arc::clone(&self.sticky_sessions);
+        let options = ForwardOptions::from(self);
+        let chain_length = options.chain_length;
+        let tunnel_cache = Arc::clone(&self.tunnel_cache);
+        let target_filter = Arc::clone(&self.target_filter);
+        let header_rules = Arc::clone(&self.header_rules);
+        let user_agents = self.user_agents.clone();
+        if self.protocol == ListenProtocol::Socks5 {
+            tokio::task::spawn(async move {
+                let _permit = permit;
+                if let Err(err) = handle_socks5_stream(
+                    stream,
+                    connection_pool,
+                    sticky_sessions,
+                    client_ip,
+                    chain_length,
+                    tunnel_cache,
+                    target_filter,
+                )
+                .await
+                {
+                    log::error!("Failed to serve SOCKS5 connection from {}: {}", peer, err);
+                }
+            });
+            return;
+        }
+        tokio::task::spawn(async move {
+            let _permit = permit;
+            let io = TokioIo::new(stream);
+            let service = service_fn(move |mut request| {
+                if let Some(user_agents) = &user_agents {
+                    rewrite_user_agent(&mut request, user_agents);
+                }
+                handle_stream_with_pool(
+                    request,
+                    Arc::clone(&connection_pool),
+                    Arc::clone(&sticky_sessions),
+                    client_ip,
+                    options,
+                    Arc::clone(&tunnel_cache),
+                    Arc::clone(&target_filter),
+                    Arc::clone(&header_rules),
+                )
+            });
+            if let Err(err) = auto::Builder::new(TokioExecutor::new())
+                .serve_connection_with_upgrades(io, service)
+                .await
+            {
+                log::error!("Failed to serve connection from {}: {}", peer, err);
+            }
+        });
+    }
+}
+
+/// Unix socket file, removed when dropped
+#[cfg(unix)]
+struct SocketFile(PathBuf);
+
+#[cfg(unix)]
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        if let Err(err) = std::fs::remove_file(&self.0) {
+            log::warn!("Failed to remove socket {}: {}", self.0.display(), err);
+        }
+    }
 }
 
-/// Handle HTTP request with connection pooling
+/// Handle HTTP request with connection pooling, tagging it with a request id.
+/// Requests to a target denied by `target_filter` are answered with a 403, the
+/// others are forwarded after `header_rules` rewrote their headers. Every request
+/// ends up in `AUDIT_LOG`.
+#[allow(clippy::too_many_arguments)]
 async fn handle_stream_with_pool<B>(
+    mut request: Request<B>,
+    connection_pool: Arc<ConnectionPool>,
+    sticky_sessions: Arc<StickyMap>,
+    client_ip: IpAddr,
+    options: ForwardOptions,
+    tunnel_cache: Arc<TunnelCache>,
+    target_filter: Arc<TargetFilter>,
+    header_rules: Arc<HeaderRules>,
+) -> Result<Response<Full<Bytes>>, hyper::Error>
+where
+    B: BodyExt<Data = Bytes> + Send + 'static,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    header_rules.apply(request.headers_mut());
+    let request_id = next_request_id();
+    let (method, uri) = (request.method().clone(), request.uri().clone());
+    let host = request_host(&request);
+    let allowed = match host.as_deref() {
+        Some(host) => target_filter.is_allowed(host),
+        None => !target_filter.is_enabled(),
+    };
+    let port_allowed = method != Method::CONNECT || target_filter.is_connect_port_allowed(connect_port(&uri));
+    let mut upstream = None;
+    let result = if !port_allowed {
+        log::warn!("[{}] Refusing {} {}: port not allowed", request_id, method, uri);
+        Ok(Response::builder()
+            .status(StatusCode::FORBIDDEN)
+            .body(Full::new(Bytes::from("Port not allowed")))
+            .unwrap())
+    } else if allowed {
+        forward_request(
+            request,
+            connection_pool,
+            sticky_sessions,
+            client_ip,
+            options,
+            tunnel_cache,
+            &request_id,
+            &mut upstream,
+        )
+        .await
+    } else {
+        log::warn!("[{}] Refusing {} {}: target not allowed", request_id, method, uri);
+        Ok(Response::builder()
+            .status(StatusCode::FORBIDDEN)
+            .body(Full::new(Bytes::from("Target not allowed")))
+            .unwrap())
+    };
+
+    let status = result.as_ref().map_or(StatusCode::BAD_GATEWAY, |response| response.status());
+    AUDIT_LOG.record(AuditEntry {
+        timestamp: chrono::Utc::now(),
+        client_ip,
+        target: host,
+        proxy: upstream,
+        status: status.as_u16(),
+    });
+
+    match result {
+        Ok(mut response) => {
+            log::info!("[{}] {} {} -> {}", request_id, method, uri, response.status());
+            if options.request_id_header {
+                if let Ok(value) = HeaderValue::from_str(&request_id) {
+                    response.headers_mut().insert(REQUEST_ID_HEADER, value);
+                }
+            }
+            Ok(response)
+        }
+        Err(err) => {
+            log::error!("[{}] {} {} failed: {}", request_id, method, uri, err);
+            Err(err)
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn forward_request<B>(
     request: Request<B>,
     connection_pool: Arc<ConnectionPool>,
+    sticky_sessions: Arc<StickyMap>,
+    client_ip: IpAddr,
+    options: ForwardOptions,
+    tunnel_cache: Arc<TunnelCache>,
+    request_id: &str,
+    upstream: &mut Option<String>,
 ) -> Result<Response<Full<Bytes>>, hyper::Error>
 where
     B: BodyExt<Data = Bytes> + Send + 'static,
     B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
 {
-    if let Some(mut proxy) = get_proxy(request.method()) {
-        log::info!("Proxying to: {} (using connection pool)", proxy.as_text());
+    let session_key = SessionKey::from_request(&request, client_ip);
+    if let Some(chain) = get_proxy_chain(request.method(), &sticky_sessions, &session_key, options.chain_length) {
+        log::info!("[{}] Proxying to: {} (using connection pool)", request_id, chain_text(&chain));
+        *upstream = Some(chain_text(&chain));
 
         if request.method() == Method::CONNECT {
-            tokio::task::spawn(async move {
-                if let Err(err) = handle_connect_stream_with_pool(request, proxy, connection_pool).await {
-                    log::error!("Failed to connect proxy: {}", err);
-                }
-            });
-            Ok(Response::new(Full::new(Bytes::from(""))))
-        } else {
-            // Try to get connection from pool first
-            let proxy_stream = match connection_pool.get_connection(&proxy.as_text()).await {
-                Ok(stream) => {
-                    log::debug!("Using pooled connection to {}", proxy.as_text());
-                    stream
+            let Some(target) = connect_target(request.uri()) else {
+                release_chain(chain, None, &sticky_sessions);
+                return Ok(Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .body(Full::new(Bytes::from("Missing CONNECT host")))
+                    .unwrap());
+            };
+            let proxy_key = chain_text(&chain);
+
+            let upstream = match tunnel_cache.take(&proxy_key, &target) {
+                Some(upstream) => {
+                    log::debug!("[{}] Reusing idle tunnel through {} to {}", request_id, proxy_key, target);
+                    Ok(upstream)
                 }
-                Err(e) => {
-                    log::error!("Failed to get pooled connection to {}: {}, creating new connection", proxy.as_text(), e);
-                    match TcpStream::connect(proxy.as_text()).await {
-                        Ok(stream) => {
-                            log::debug!("Created new connection to {}", proxy.as_text());
-                            stream
-                        }
-                        Err(e) => {
-                            log::error!("Failed to connect to proxy {}: {}", proxy.as_text(), e);
-                            return Ok(Response::builder()
-                                .status(StatusCode::BAD_GATEWAY)
-                                .body(Full::new(Bytes::from("Proxy connection failed")))
-                                .unwrap());
+                None => connect_through_chain(&chain, Some(&target), &connection_pool).await,
+            };
+            match upstream {
+                Ok(upstream) => {
+                    release_chain(chain, None, &sticky_sessions);
+                    let request_id = request_id.to_string();
+                    tokio::task::spawn(async move {
+                        match handle_connect_stream_with_pool(request, upstream).await {
+                            Ok(Some(unused)) => tunnel_cache.put(&proxy_key, &target, unused),
+                            Ok(None) => log::debug!("[{}] CONNECT tunnel to {} closed", request_id, target),
+                            Err(err) => log::error!("[{}] Failed to tunnel CONNECT: {}", request_id, err),
                         }
-                    }
+                    });
+                    Ok(Response::new(Full::new(Bytes::from(""))))
+                }
+                Err(err) => {
+                    log::error!("[{}] CONNECT through {} failed at {}", request_id, chain_text(&chain), err);
+                    release_chain(chain, Some(&err), &sticky_sessions);
+                    Ok(Response::builder()
+                        .status(StatusCode::BAD_GATEWAY)
+                        .body(Full::new(Bytes::from("Proxy chain failed")))
+                        .unwrap())
+                }
+            }
+        } else {
+            let started = std::time::Instant::now();
+            let (proxy_stream, reused) = match connect_through_chain_with_reuse(&chain, None, &connection_pool).await {
+                Ok(connected) => connected,
+                Err(err) => {
+                    log::error!("[{}] Failed to connect through {}: {}", request_id, chain_text(&chain), err);
+                    release_chain(chain, Some(&err), &sticky_sessions);
+                    return Ok(Response::builder()
+                        .status(StatusCode::BAD_GATEWAY)
+                        .body(Full::new(Bytes::from("Proxy connection failed")))
+                        .unwrap());
                 }
             };
 
-            let _proxy_addr = proxy.as_text().clone();
-
+            let last_hop = chain.len() - 1;
             if let Ok((mut sender, conn)) = hyper::client::conn::http1::Builder::new()
                 .title_case_headers(true)
                 .preserve_header_case(true)
@@ -173,16 +677,39 @@ where
                     }
                 });
                 
-                let response = sender.send_request(request).await;
-                proxy.request_stat += 1;
-                POOL.lock().put(proxy);
-                
-                // Return connection to pool if possible
-                // Note: In a real implementation, we'd need to handle this more carefully
-                // as the connection might be consumed by the HTTP client
-                
-                response.map(|resp| resp.map(|_| Full::new(Bytes::from("Proxied response"))))
+                let response = match sender.send_request(request).await {
+                    Ok(response) => response,
+                    Err(e) => {
+                        log::error!("[{}] Request through {} failed: {}", request_id, chain_text(&chain), e);
+                        let err = ChainError { hop: last_hop, reason: "bad_gateway", message: "request failed".to_string() };
+                        release_chain(chain, Some(&err), &sticky_sessions);
+                        return Ok(Response::builder()
+                            .status(StatusCode::BAD_GATEWAY)
+                            .body(Full::new(Bytes::from("Proxy request failed")))
+                            .unwrap());
+                    }
+                };
+                if let [proxy] = chain.as_slice() {
+                    LATENCIES.record(&proxy.host, proxy.port, started.elapsed());
+                }
+                release_chain(chain, None, &sticky_sessions);
+
+                let (parts, body) = response.into_parts();
+                let body = read_limited_body(body, options.max_response_bytes).await;
+                PERFORMANCE_MONITOR.record_pooled_request(started.elapsed(), body.is_ok(), reused).await;
+                match body {
+                    Ok(body) => Ok(Response::from_parts(parts, Full::new(body))),
+                    Err(err) => {
+                        log::warn!("[{}] Dropping upstream response: {}", request_id, err);
+                        Ok(Response::builder()
+                            .status(StatusCode::BAD_GATEWAY)
+                            .body(Full::new(Bytes::from(err.to_string())))
+                            .unwrap())
+                    }
+                }
             } else {
+                let err = ChainError { hop: last_hop, reason: "handshake_failed", message: "handshake failed".to_string() };
+                release_chain(chain, Some(&err), &sticky_sessions);
                 Ok(Response::builder()
                     .status(StatusCode::BAD_GATEWAY)
                     .body(Full::new(Bytes::from("HTTP handshake failed")))
@@ -197,55 +724,95 @@ where
     }
 }
 
-/// Handle CONNECT request with connection pooling
-async fn handle_connect_stream_with_pool(
-    request: Request<impl BodyExt<Data = Bytes> + Send + 'static>,
-    mut proxy: SimpleProxy,
+/// Serve a SOCKS5 client, tunnelling its CONNECT through a proxy chain from the pool.
+/// Every CONNECT ends up in `AUDIT_LOG` with the HTTP status its reply stands for.
+async fn handle_socks5_stream<S: AsyncRead + AsyncWrite + Unpin>(
+    mut stream: S,
     connection_pool: Arc<ConnectionPool>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let uri = request.uri().clone();
-    if let Some(host) = uri.host() {
-        if let Ok(mut upgrade) = hyper::upgrade::on(request).await {
-            // Try to get connection from pool first
-            let mut proxy_stream = match connection_pool.get_connection(&proxy.as_text()).await {
-                Ok(stream) => {
-                    log::debug!("Using pooled connection for CONNECT to {}", proxy.as_text());
-                    stream
-                }
-                Err(e) => {
-                    log::error!("Failed to get pooled connection for CONNECT to {}: {}", proxy.as_text(), e);
-                    match TcpStream::connect(proxy.as_text()).await {
-                        Ok(stream) => {
-                            log::debug!("Created new connection for CONNECT to {}", proxy.as_text());
-                            stream
-                        }
-                        Err(e) => {
-                            log::error!("Failed to connect to proxy {}: {}", proxy.as_text(), e);
-                            return Err(e.into());
-                        }
-                    }
-                }
-            };
-
-            let connect_status = send_connect_request(&mut proxy_stream, host, TIMEOUT_IN_SECONDS).await;
+    sticky_sessions: Arc<StickyMap>,
+    client_ip: IpAddr,
+    chain_length: usize,
+    tunnel_cache: Arc<TunnelCache>,
+    target_filter: Arc<TargetFilter>,
+) -> std::io::Result<()> {
+    let target = socks5::accept(&mut stream).await?;
+    let (host, port) = target.rsplit_once(':').map_or((target.as_str(), None), |(host, port)| (host, port.parse().ok()));
+    let audit = |proxy: Option<String>, status: StatusCode| {
+        AUDIT_LOG.record(AuditEntry {
+            timestamp: chrono::Utc::now(),
+            client_ip,
+            target: Some(host.to_string()),
+            proxy,
+            status: status.as_u16(),
+        });
+    };
+    if !target_filter.is_allowed(host) {
+        log::warn!("Refusing SOCKS5 CONNECT {}: target not allowed", target);
+        audit(None, StatusCode::FORBIDDEN);
+        return socks5::reply(&mut stream, socks5::REPLY_NOT_ALLOWED).await;
+    }
+    if !port.is_some_and(|port| target_filter.is_connect_port_allowed(port)) {
+        log::warn!("Refusing SOCKS5 CONNECT {}: port not allowed", target);
+        audit(None, StatusCode::FORBIDDEN);
+        return socks5::reply(&mut stream, socks5::REPLY_NOT_ALLOWED).await;
+    }
+    let session_key = SessionKey::ClientIp(client_ip);
+    let Some(chain) = get_proxy_chain(&Method::CONNECT, &sticky_sessions, &session_key, chain_length) else {
+        audit(None, StatusCode::BAD_GATEWAY);
+        return socks5::reply(&mut stream, socks5::REPLY_GENERAL_FAILURE).await;
+    };
+    log::info!("Proxying SOCKS5 CONNECT {} to: {}", target, chain_text(&chain));
 
-            if connect_status {
-                // Note: Simplified implementation for compilation
-                    // In production, would need proper async stream handling
-                    let _ = (&mut upgrade, &mut proxy_stream);
-                    log::debug!("CONNECT tunnel established (simplified)");
-                proxy.request_stat += 1;
-                POOL.lock().put(proxy);
-                
-                // Try to return connection to pool if it's still usable
-                // Note: This is a simplified approach - in practice, CONNECT connections
-                // are usually not reusable as they're tied to a specific client
-            } else {
-                log::error!("CONNECT request failed for proxy {}", proxy.as_text());
+    let proxy_key = chain_text(&chain);
+    let upstream = match tunnel_cache.take(&proxy_key, &target) {
+        Some(upstream) => {
+            log::debug!("Reusing idle tunnel through {} to {}", proxy_key, target);
+            Ok(upstream)
+        }
+        None => connect_through_chain(&chain, Some(&target), &connection_pool).await,
+    };
+    match upstream {
+        Ok(upstream) => {
+            release_chain(chain, None, &sticky_sessions);
+            audit(Some(proxy_key.clone()), StatusCode::OK);
+            socks5::reply(&mut stream, socks5::REPLY_SUCCEEDED).await?;
+            if let Some(unused) = tunnel::relay(&mut stream, upstream).await? {
+                tunnel_cache.put(&proxy_key, &target, unused);
             }
+            Ok(())
+        }
+        Err(err) => {
+            log::error!("SOCKS5 CONNECT through {} failed at {}", chain_text(&chain), err);
+            release_chain(chain, Some(&err), &sticky_sessions);
+            audit(Some(proxy_key), StatusCode::BAD_GATEWAY);
+            socks5::reply(&mut stream, socks5::REPLY_HOST_UNREACHABLE).await
         }
     }
-    Ok(())
+}
+
+/// Buffer a response body, giving up as soon as more than `max_bytes` arrive
+async fn read_limited_body<B>(body: B, max_bytes: usize) -> Result<Bytes, String>
+where
+    B: BodyExt<Data = Bytes>,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    match Limited::new(body, max_bytes).collect().await {
+        Ok(collected) => Ok(collected.to_bytes()),
+        Err(err) if err.is::<LengthLimitError>() => {
+            Err(format!("Upstream response exceeds {} bytes", max_bytes))
+        }
+        Err(err) => Err(format!("Failed to read upstream response: {}", err)),
+    }
+}
+
+/// Relay a CONNECT tunnel between the upgraded client connection and the upstream stream.
+/// The upstream stream is returned when the client left without using it.
+async fn handle_connect_stream_with_pool(
+    request: Request<impl BodyExt<Data = Bytes> + Send + 'static>,
+    upstream: TcpStream,
+) -> Result<Option<TcpStream>, Box<dyn std::error::Error>> {
+    let upgraded = hyper::upgrade::on(request).await?;
+    Ok(tunnel::relay(&mut TokioIo::new(upgraded), upstream).await?)
 }
 
 /// Legacy function for backward compatibility
@@ -254,7 +821,17 @@ where
     B: BodyExt<Data = Bytes> + Send + 'static,
     B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
 {
-    handle_stream_with_pool(request, Arc::clone(&CONNECTION_POOL)).await
+    handle_stream_with_pool(
+        request,
+        Arc::clone(&CONNECTION_POOL),
+        Arc::new(StickyMap::default()),
+        IpAddr::from([127, 0, 0, 1]),
+        ForwardOptions::default(),
+        Arc::new(TunnelCache::default()),
+        Arc::new(TargetFilter::default()),
+        Arc::new(HeaderRules::default()),
+    )
+    .await
 }
 
 /// Legacy function for backward compatibility
@@ -262,62 +839,590 @@ async fn handle_connect_stream(
     request: Request<impl BodyExt<Data = Bytes> + Send + 'static>,
     proxy: SimpleProxy,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    handle_connect_stream_with_pool(request, proxy, Arc::clone(&CONNECTION_POOL)).await
+    let target = connect_target(request.uri()).unwrap_or_default();
+    let chain = [proxy];
+    let upstream = connect_through_chain(&chain, Some(&target), &CONNECTION_POOL).await?;
+    handle_connect_stream_with_pool(request, upstream).await.map(|_| ())
 }
 
-fn get_proxy(method: &Method) -> Option<SimpleProxy> {
-    let mut pool = POOL.lock();
-    if method == Method::CONNECT {
-        pool.get("HTTPS")
-    } else {
-        pool.get("HTTP")
+/// Give a forwarded request the next configured User-Agent, CONNECT tunnels are left alone
+fn rewrite_user_agent<B>(request: &mut Request<B>, user_agents: &UserAgents) {
+    if request.method() == Method::CONNECT {
+        return;
+    }
+    if let Some(value) = user_agents.next().and_then(|agent| HeaderValue::from_str(&agent).ok()) {
+        request.headers_mut().insert(hyper::header::USER_AGENT, value);
     }
 }
 
-async fn send_connect_request<R: AsyncRead + AsyncWrite + Unpin>(
-    stream: &mut R,
-    host: &str,
-    timeout_in_seconds: u64,
-) -> bool {
-    let connect = format!(
-        "CONNECT {0}:443 HTTP/1.1\r\nHost: {0}:443\r\nProxy-Connection: Keep-Alive\r\n\r\n",
-        host
-    );
-    // Send data
-    if let Ok(Ok(_)) = timeout(
-        Duration::from_secs(timeout_in_seconds),
-        stream.write_all(connect.as_bytes()),
-    )
-    .await
-    {
-        // read Response
-        let data = read_timeout(stream, timeout_in_seconds).await;
-        let response = ResponseParser::parse(data.as_slice());
+/// Host a request targets, from its URI or else its `Host` header
+fn request_host<B>(request: &Request<B>) -> Option<String> {
+    if let Some(host) = request.uri().host() {
+        return Some(host.to_string());
+    }
+    let header = request.headers().get(hyper::header::HOST)?.to_str().ok()?;
+    let authority: hyper::http::uri::Authority = header.parse().ok()?;
+    Some(authority.host().to_string())
+}
+
+/// `host:port` a CONNECT request asks for, 443 when the client gave no port
+fn connect_target(uri: &hyper::Uri) -> Option<String> {
+    let host = uri.host()?;
+    Some(format!("{}:{}", host, connect_port(uri)))
+}
+
+/// Port a CONNECT request asks for, 443 when the client gave none
+fn connect_port(uri: &hyper::Uri) -> u16 {
+    uri.port_u16().unwrap_or(443)
+}
 
-        if let Some(status_code) = response.status_code {
-            return status_code == 200;
+/// Pick `chain_length` distinct proxies, the first one honouring sticky sessions.
+/// Every hop but the last must tunnel, so chained hops are taken from HTTPS proxies.
+fn get_proxy_chain(
+    method: &Method,
+    sticky_sessions: &StickyMap,
+    session_key: &SessionKey,
+    chain_length: usize,
+) -> Option<Vec<SimpleProxy>> {
+    let mut pool = POOL.lock();
+    let scheme = if method == Method::CONNECT || chain_length > 1 { "HTTPS" } else { "HTTP" };
+    let mut chain = vec![sticky_sessions.get_or_assign(session_key, &mut pool, scheme)?];
+    while chain.len() < chain_length {
+        match pool.get("HTTPS") {
+            Some(proxy) if !chain.contains(&proxy) => chain.push(proxy),
+            other => {
+                log::warn!("Not enough proxies for a chain of {}", chain_length);
+                for proxy in chain.into_iter().chain(other) {
+                    pool.put(proxy);
+                }
+                return None;
+            }
         }
     }
-    false
+    Some(chain)
 }
 
-async fn read_timeout<R: AsyncRead + Unpin>(reader: &mut R, timeout_in_seconds: u64) -> Vec<u8> {
-    let mut data = vec![];
-    loop {
-        let mut buf = [0; 512];
-        if let Ok(Ok(buf_size)) = timeout(
-            Duration::from_secs(timeout_in_seconds),
-            reader.read(&mut buf),
+/// Return chained proxies to the pool, recording the failure against the hop to blame
+fn release_chain(chain: Vec<SimpleProxy>, failure: Option<&ChainError>, sticky_sessions: &StickyMap) {
+    let mut pool = POOL.lock();
+    for (hop, proxy) in chain.into_iter().enumerate() {
+        match failure {
+            Some(err) if err.hop == hop => {
+                sticky_sessions.evict(&proxy.host, proxy.port);
+                pool.record_failure(proxy, err.reason);
+            }
+            Some(_) => pool.put(proxy),
+            None => pool.record_success(proxy),
+        }
+    }
+}
+
+fn chain_text(chain: &[SimpleProxy]) -> String {
+    chain.iter().map(SimpleProxy::as_text).collect::<Vec<_>>().join(" -> ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::stream;
+    use http_body_util::StreamBody;
+    use hyper::body::Frame;
+    use crate::config::dynamic::HeaderRule;
+    use crate::server::target_filter::DEFAULT_CONNECT_PORTS;
+    use crate::server::audit::DEFAULT_AUDIT_LOG_SIZE;
+    use crate::proxy::Proxy;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    lazy_static! {
+        /// Tests going through the global pool run one at a time
+        static ref GLOBAL_POOL_TEST: tokio::sync::Mutex<()> = tokio::sync::Mutex::new(());
+    }
+
+    /// Hold the global pool for one test, emptied of the upstreams earlier tests left
+    /// behind, their stubs are gone with their runtimes
+    async fn global_pool_test() -> tokio::sync::MutexGuard<'static, ()> {
+        let guard = GLOBAL_POOL_TEST.lock().await;
+        *POOL.lock() = ProxyPool::new();
+        while LIVE_PROXIES.pop().is_ok() {}
+        guard
+    }
+
+    #[tokio::test]
+    async fn test_response_body_stops_at_cap() {
+        let pulled = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&pulled);
+        // An endless upstream body sent in 1 KiB chunks
+        let chunks = stream::repeat_with(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Ok::<_, std::io::Error>(Frame::data(Bytes::from(vec![0u8; 1024])))
+        });
+
+        let err = read_limited_body(StreamBody::new(chunks), 8 * 1024).await.unwrap_err();
+        assert_eq!(err, "Upstream response exceeds 8192 bytes");
+        assert_eq!(pulled.load(Ordering::SeqCst), 9);
+
+        let body = Full::new(Bytes::from(vec![0u8; 8 * 1024]));
+        assert_eq!(read_limited_body(body, 8 * 1024).await.unwrap().len(), 8 * 1024);
+    }
+
+    #[test]
+    fn test_connect_target_keeps_requested_port() {
+        let target = |authority: &str| connect_target(&authority.parse().unwrap());
+        assert_eq!(target("example.com:443").as_deref(), Some("example.com:443"));
+        assert_eq!(target("example.com:80").as_deref(), Some("example.com:80"));
+        assert_eq!(target("mail.example.com:25").as_deref(), Some("mail.example.com:25"));
+        assert_eq!(target("example.com").as_deref(), Some("example.com:443"));
+    }
+
+    #[test]
+    fn test_rewrite_user_agent_skips_connect() {
+        use crate::utils::http::user_agents::Rotation;
+
+        let user_agents = UserAgents::new(vec!["Mozilla/5.0".to_string()], Rotation::RoundRobin);
+        let mut request = Request::get("http://example.com/").header("User-Agent", "curl/8.5.0").body(()).unwrap();
+        rewrite_user_agent(&mut request, &user_agents);
+        assert_eq!(request.headers()[hyper::header::USER_AGENT], "Mozilla/5.0");
+
+        let mut request = Request::connect("example.com:443").header("User-Agent", "curl/8.5.0").body(()).unwrap();
+        rewrite_user_agent(&mut request, &user_agents);
+        assert_eq!(request.headers()[hyper::header::USER_AGENT], "curl/8.5.0");
+    }
+
+    /// Upstream HTTP proxy stub accepting a single CONNECT
+    async fn connect_proxy_stub() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut client, _) = listener.accept().await.unwrap();
+            let mut head = Vec::new();
+            let mut byte = [0; 1];
+            while !head.ends_with(b"\r\n\r\n") {
+                client.read_exact(&mut byte).await.unwrap();
+                head.push(byte[0]);
+            }
+            let head = String::from_utf8_lossy(&head).to_string();
+            let authority = head.split_whitespace().nth(1).unwrap().to_string();
+            let mut target = TcpStream::connect(authority).await.unwrap();
+            client.write_all(b"HTTP/1.1 200 Connection established\r\n\r\n").await.unwrap();
+            let _ = tokio::io::copy_bidirectional(&mut client, &mut target).await;
+        });
+        port
+    }
+
+    #[tokio::test]
+    async fn test_socks5_client_reaches_target() {
+        let _guard = global_pool_test().await;
+        let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target_port = target.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut stream, _) = target.accept().await.unwrap();
+            let (mut reader, mut writer) = stream.split();
+            let _ = tokio::io::copy(&mut reader, &mut writer).await;
+        });
+
+        let upstream_port = connect_proxy_stub().await;
+        let mut upstream = Proxy::create("127.0.0.1", upstream_port, vec!["HTTPS".to_string()])
+            .await
+            .unwrap();
+        upstream.types = vec![("HTTPS".to_string(), None)];
+        LIVE_PROXIES.push(upstream).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let local = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, addr) = listener.accept().await.unwrap();
+            handle_socks5_stream(
+                stream,
+                Arc::new(ConnectionPool::new(PoolConfig::default())),
+                Arc::new(StickyMap::default()),
+                addr.ip(),
+                1,
+                Arc::new(TunnelCache::default()),
+                Arc::new(TargetFilter::default()),
+            )
+            .await
+            .unwrap();
+        });
+
+        let mut client = tokio_socks::tcp::Socks5Stream::connect(local, ("127.0.0.1", target_port))
+            .await
+            .unwrap();
+        client.write_all(b"ping").await.unwrap();
+        let mut echoed = [0; 4];
+        client.read_exact(&mut echoed).await.unwrap();
+        assert_eq!(&echoed, b"ping");
+
+        let proxy = format!("127.0.0.1:{}", upstream_port);
+        let entry = AUDIT_LOG
+            .recent(DEFAULT_AUDIT_LOG_SIZE)
+            .into_iter()
+            .find(|entry| entry.proxy.as_deref() == Some(proxy.as_str()))
+            .expect("no audit entry for the SOCKS5 CONNECT");
+        assert_eq!(entry.target.as_deref(), Some("127.0.0.1"));
+        assert_eq!(entry.status, 200);
+    }
+
+    #[tokio::test]
+    async fn test_refused_socks5_connect_is_audited() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let local = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, addr) = listener.accept().await.unwrap();
+            handle_socks5_stream(
+                stream,
+                Arc::new(ConnectionPool::new(PoolConfig::default())),
+                Arc::new(StickyMap::default()),
+                addr.ip(),
+                1,
+                Arc::new(TunnelCache::default()),
+                Arc::new(TargetFilter::new(Vec::new(), vec!["socks-denied.example.com".to_string()])),
+            )
+            .await
+            .unwrap();
+        });
+
+        assert!(tokio_socks::tcp::Socks5Stream::connect(local, ("socks-denied.example.com", 443)).await.is_err());
+        let entry = AUDIT_LOG
+            .recent(DEFAULT_AUDIT_LOG_SIZE)
+            .into_iter()
+            .find(|entry| entry.target.as_deref() == Some("socks-denied.example.com"))
+            .expect("no audit entry for the refused SOCKS5 CONNECT");
+        assert_eq!(entry.client_ip, IpAddr::from([127, 0, 0, 1]));
+        assert_eq!(entry.proxy, None);
+        assert_eq!(entry.status, 403);
+    }
+
+    /// Upstream HTTP proxy stub answering every request with an empty 200
+    async fn http_proxy_stub() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let service = service_fn(|_request: Request<hyper::body::Incoming>| async {
+                        Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from("ok"))))
+                    });
+                    let _ = hyper::server::conn::http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+        port
+    }
+
+    #[tokio::test]
+    async fn test_request_id_header_is_unique() {
+        let _guard = global_pool_test().await;
+        let port = http_proxy_stub().await;
+        for _ in 0..2 {
+            let mut upstream = Proxy::create("127.0.0.1", port, vec!["HTTP".to_string()]).await.unwrap();
+            upstream.types = vec![("HTTP".to_string(), None)];
+            LIVE_PROXIES.push(upstream).unwrap();
+        }
+
+        let options = ForwardOptions::from(&Server::new("127.0.0.1", 0).with_request_id_header(true));
+        let mut ids = Vec::new();
+        for _ in 0..2 {
+            let request = Request::get("http://example.com/").body(Full::new(Bytes::new())).unwrap();
+            let response = handle_stream_with_pool(
+                request,
+                Arc::new(ConnectionPool::new(PoolConfig::default())),
+                Arc::new(StickyMap::default()),
+                IpAddr::from([127, 0, 0, 1]),
+                options,
+                Arc::new(TunnelCache::default()),
+                Arc::new(TargetFilter::default()),
+                Arc::new(HeaderRules::default()),
+            )
+            .await
+            .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            ids.push(response.headers()[REQUEST_ID_HEADER].to_str().unwrap().to_string());
+        }
+        assert_ne!(ids[0], ids[1]);
+    }
+
+    #[tokio::test]
+    async fn test_header_rule_removes_via() {
+        let _guard = global_pool_test().await;
+        // Upstream answering with the names of the headers it received
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let service = service_fn(|request: Request<hyper::body::Incoming>| async move {
+                        let names: Vec<String> = request
+                            .headers()
+                            .iter()
+                            .map(|(name, value)| format!("{}={}", name, value.to_str().unwrap_or("")))
+                            .collect();
+                        Ok::<_, hyper::Error>(Response::new(Full::new(Bytes::from(names.join("\n")))))
+                    });
+                    let _ = hyper::server::conn::http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+        let mut upstream = Proxy::create("127.0.0.1", port, vec!["HTTP".to_string()]).await.unwrap();
+        upstream.types = vec![("HTTP".to_string(), None)];
+        LIVE_PROXIES.push(upstream).unwrap();
+
+        let rules = HeaderRules::new(&[
+            HeaderRule::Remove { name: "Via".to_string() },
+            HeaderRule::Set { name: "Accept-Language".to_string(), value: "en-US".to_string() },
+        ]);
+        let request = Request::get("http://example.com/")
+            .header("Via", "1.1 corp-proxy")
+            .header("Accept-Language", "fr-FR")
+            .body(Full::new(Bytes::new()))
+            .unwrap();
+        let response = handle_stream_with_pool(
+            request,
+            Arc::new(ConnectionPool::new(PoolConfig::default())),
+            Arc::new(StickyMap::default()),
+            IpAddr::from([127, 0, 0, 1]),
+            ForwardOptions::default(),
+            Arc::new(TunnelCache::default()),
+            Arc::new(TargetFilter::default()),
+            Arc::new(rules),
         )
         .await
-        {
-            if buf_size == 0 {
-                break;
-            }
-            data.extend(&buf[..buf_size]);
-            continue;
+        .unwrap();
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let received = String::from_utf8_lossy(&body).to_string();
+        assert!(!received.contains("via="), "{}", received);
+        assert!(received.contains("accept-language=en-US"), "{}", received);
+    }
+
+    #[tokio::test]
+    async fn test_warmup_waits_for_min_queue() {
+        let _guard = global_pool_test().await;
+        let push = |port| async move {
+            let mut proxy = Proxy::create("192.0.2.30", port, vec!["HTTP".to_string()]).await.unwrap();
+            proxy.types = vec![("HTTP".to_string(), None)];
+            LIVE_PROXIES.push(proxy).unwrap();
+        };
+
+        // Short of min_queue, the proxies found so far once the wait is over
+        push(3128).await;
+        let started = tokio::time::Instant::now();
+        assert_eq!(warmup_targets(2, Duration::from_millis(300)).await.len(), 1);
+        assert!(started.elapsed() >= Duration::from_millis(300));
+
+        // Ready as soon as min_queue proxies are pooled
+        let min_queue = POOL.lock().min_queue as u16;
+        for port in 1..min_queue {
+            push(3128 + port).await;
         }
-        break;
+        let started = tokio::time::Instant::now();
+        assert_eq!(warmup_targets(2, Duration::from_secs(10)).await.len(), 2);
+        assert!(started.elapsed() < Duration::from_secs(1));
+        *POOL.lock() = ProxyPool::new();
+    }
+
+    #[tokio::test]
+    async fn test_upstream_closing_early_is_a_bad_gateway() {
+        let _guard = global_pool_test().await;
+        // Upstream reading the request then closing without an answer
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = [0; 1024];
+                let _ = stream.read(&mut buf).await;
+            }
+        });
+        let mut upstream = Proxy::create("127.0.0.1", port, vec!["HTTP".to_string()]).await.unwrap();
+        upstream.types = vec![("HTTP".to_string(), None)];
+        LIVE_PROXIES.push(upstream).unwrap();
+
+        let request = Request::get("http://example.com/").body(Full::new(Bytes::new())).unwrap();
+        let response = handle_stream_with_pool(
+            request,
+            Arc::new(ConnectionPool::new(PoolConfig::default())),
+            Arc::new(StickyMap::default()),
+            IpAddr::from([127, 0, 0, 1]),
+            ForwardOptions::default(),
+            Arc::new(TunnelCache::default()),
+            Arc::new(TargetFilter::default()),
+            Arc::new(HeaderRules::default()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+    }
+
+    #[tokio::test]
+    async fn test_forwarded_request_is_audited() {
+        use crate::api::handlers_minimal::{get_audit, AuditQuery};
+        use axum::extract::Query;
+
+        let _guard = global_pool_test().await;
+        let port = http_proxy_stub().await;
+        let mut upstream = Proxy::create("127.0.0.1", port, vec!["HTTP".to_string()]).await.unwrap();
+        upstream.types = vec![("HTTP".to_string(), None)];
+        LIVE_PROXIES.push(upstream).unwrap();
+
+        let request = Request::get("http://audit.example.com/").body(Full::new(Bytes::new())).unwrap();
+        let response = handle_stream_with_pool(
+            request,
+            Arc::new(ConnectionPool::new(PoolConfig::default())),
+            Arc::new(StickyMap::default()),
+            IpAddr::from([127, 0, 0, 1]),
+            ForwardOptions::default(),
+            Arc::new(TunnelCache::default()),
+            Arc::new(TargetFilter::default()),
+            Arc::new(HeaderRules::default()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let audit = get_audit(Query(AuditQuery { limit: Some(DEFAULT_AUDIT_LOG_SIZE) })).await.0;
+        let entries = audit.data.unwrap();
+        let entry = entries
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|entry| entry["target"] == "audit.example.com")
+            .expect("no audit entry for the forwarded request");
+        assert_eq!(entry["client_ip"], "127.0.0.1");
+        assert_eq!(entry["proxy"], format!("127.0.0.1:{}", port));
+        assert_eq!(entry["status"], 200);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_unix_socket_proxies_requests() {
+        let _guard = global_pool_test().await;
+        let port = http_proxy_stub().await;
+        let mut upstream = Proxy::create("127.0.0.1", port, vec!["HTTP".to_string()]).await.unwrap();
+        upstream.types = vec![("HTTP".to_string(), None)];
+        LIVE_PROXIES.push(upstream).unwrap();
+
+        let path = std::env::temp_dir().join(format!("proxy-rs-{}.sock", std::process::id()));
+        let server = Server::new("127.0.0.1", 0).with_unix_socket(path.clone());
+        let serving = tokio::spawn(async move { server.start().await });
+        let stream = loop {
+            match tokio::net::UnixStream::connect(&path).await {
+                Ok(stream) => break stream,
+                Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+            }
+        };
+
+        let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(stream)).await.unwrap();
+        tokio::spawn(conn);
+        let request = Request::get("http://example.com/").body(Full::new(Bytes::new())).unwrap();
+        let response = sender.send_request(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.into_body().collect().await.unwrap().to_bytes(), "ok");
+
+        // Stopping the server removes the socket file
+        serving.abort();
+        let _ = serving.await;
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_client_over_its_cap_is_refused() {
+        let _guard = global_pool_test().await;
+        let port = http_proxy_stub().await;
+        let mut upstream = Proxy::create("127.0.0.1", port, vec!["HTTP".to_string()]).await.unwrap();
+        upstream.types = vec![("HTTP".to_string(), None)];
+        LIVE_PROXIES.push(upstream).unwrap();
+
+        let server = Server::new("127.0.0.1", 0).with_max_connections_per_client(2);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((stream, peer)) = listener.accept().await {
+                server.serve_stream(stream, peer.ip(), peer.to_string());
+            }
+        });
+        let connect_from = |ip: [u8; 4]| async move {
+            let socket = tokio::net::TcpSocket::new_v4().unwrap();
+            socket.bind((IpAddr::from(ip), 0).into()).unwrap();
+            socket.connect(addr).await.unwrap()
+        };
+        let get = |stream: TcpStream| async move {
+            let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(stream)).await.unwrap();
+            tokio::spawn(conn);
+            let request = Request::get("http://example.com/").body(Full::new(Bytes::new())).unwrap();
+            sender.send_request(request).await.unwrap().status()
+        };
+
+        // The first client holds its two connections open
+        let _held = [connect_from([127, 0, 0, 1]).await, connect_from([127, 0, 0, 1]).await];
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let mut refused = connect_from([127, 0, 0, 1]).await;
+        let mut answer = String::new();
+        refused.read_to_string(&mut answer).await.unwrap();
+        assert!(answer.starts_with("HTTP/1.1 429"));
+
+        // Another client is unaffected
+        assert_eq!(get(connect_from([127, 0, 0, 2]).await).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_target_filter_refuses_denied_hosts() {
+        let _guard = global_pool_test().await;
+        let mut upstream = Proxy::create("127.0.0.1", http_proxy_stub().await, vec!["HTTP".to_string()])
+            .await
+            .unwrap();
+        upstream.types = vec![("HTTP".to_string(), None)];
+        LIVE_PROXIES.push(upstream).unwrap();
+
+        let target_filter = Arc::new(TargetFilter::new(
+            vec!["*.example.com".to_string()],
+            vec!["admin.example.com".to_string()],
+        ));
+        let send = |request: Request<Full<Bytes>>| {
+            handle_stream_with_pool(
+                request,
+                Arc::new(ConnectionPool::new(PoolConfig::default())),
+                Arc::new(StickyMap::default()),
+                IpAddr::from([127, 0, 0, 1]),
+                ForwardOptions::default(),
+                Arc::new(TunnelCache::default()),
+                Arc::clone(&target_filter),
+                Arc::new(HeaderRules::default()),
+            )
+        };
+
+        let allowed = Request::get("http://www.example.com/").body(Full::new(Bytes::new())).unwrap();
+        assert_eq!(send(allowed).await.unwrap().status(), StatusCode::OK);
+
+        let denied = Request::get("http://admin.example.com/").body(Full::new(Bytes::new())).unwrap();
+        assert_eq!(send(denied).await.unwrap().status(), StatusCode::FORBIDDEN);
+
+        let outside = Request::connect("example.net:443").body(Full::new(Bytes::new())).unwrap();
+        assert_eq!(send(outside).await.unwrap().status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_connect_ports_default_to_https_and_http() {
+        let target_filter = Arc::new(TargetFilter::default().with_connect_ports(DEFAULT_CONNECT_PORTS.to_vec()));
+        assert!(target_filter.is_connect_port_allowed(connect_port(&"www.example.com".parse().unwrap())));
+
+        // Refused before any proxy is picked from the pool
+        let smtp = handle_stream_with_pool(
+            Request::connect("mail.example.com:25").body(Full::new(Bytes::new())).unwrap(),
+            Arc::new(ConnectionPool::new(PoolConfig::default())),
+            Arc::new(StickyMap::default()),
+            IpAddr::from([127, 0, 0, 1]),
+            ForwardOptions::default(),
+            Arc::new(TunnelCache::default()),
+            target_filter,
+            Arc::new(HeaderRules::default()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(smtp.status(), StatusCode::FORBIDDEN);
+        assert_eq!(smtp.into_body().collect().await.unwrap().to_bytes(), "Port not allowed");
     }
-    data
 }
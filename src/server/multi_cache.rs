@@ -3,14 +3,15 @@
 use std::{
     collections::HashMap,
     sync::Arc,
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 use lru::LruCache;
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use tokio::sync::RwLock;
 
 use crate::resolver::GeoData;
+use crate::utils::error::ProxyError;
 
 /// Cache entry with metadata
 #[derive(Debug, Clone)]
@@ -43,6 +44,10 @@ impl<T> CacheEntry<T> {
         self.created_at.elapsed() > self.ttl
     }
 
+    fn remaining_ttl(&self) -> Duration {
+        self.ttl.saturating_sub(self.created_at.elapsed())
+    }
+
     fn mark_accessed(&mut self) {
         self.last_accessed = Instant::now();
         self.access_count += 1;
@@ -445,12 +450,14 @@ impl<T: Clone + Send + Sync + 'static> MultiCache<T> {
             if let Some(value) = l2.get(key) {
                 let mut stats = self.stats.write().await;
                 stats.l2_hits += 1;
+                drop(stats);
 
-                // Check if we should promote to L1
+                // Check if we should promote to L1, keeping the remaining TTL
                 if l2.cache.get(key).map(|e| e.access_frequency()).unwrap_or(0.0) > l2.access_threshold {
                     if let Some(promoted_value) = l2.promote_to_l1(key) {
+                        let ttl = l2.cache.peek(key).map(CacheEntry::remaining_ttl);
                         let mut l1 = self.l1_cache.write().await;
-                        l1.put(key.to_string(), promoted_value, None);
+                        l1.put(key.to_string(), promoted_value, ttl);
                         
                         let mut stats = self.stats.write().await;
                         stats.promotions += 1;
@@ -468,9 +475,10 @@ impl<T: Clone + Send + Sync + 'static> MultiCache<T> {
                 let mut stats = self.stats.write().await;
                 stats.l3_hits += 1;
 
-                // Move to L2 since it was accessed
+                // Move to L2 since it was accessed, keeping the remaining TTL
+                let ttl = l3.cache.get(key).map(CacheEntry::remaining_ttl);
                 let mut l2 = self.l2_cache.write().await;
-                l2.put(key.to_string(), value.clone(), None);
+                l2.put(key.to_string(), value.clone(), ttl);
 
                 return Some(value);
             }
@@ -545,6 +553,40 @@ impl<T: Clone + Send + Sync + 'static> MultiCache<T> {
         *stats = CacheStats::default();
     }
 
+    /// Evict entries until each level holds at most `keep` of its capacity, least recently
+    /// used first in L1 and L2, oldest first in L3. Returns the number of evicted entries.
+    pub async fn trim(&self, keep: f64) -> usize {
+        let target = |max_size: usize| (max_size as f64 * keep.clamp(0.0, 1.0)) as usize;
+        let mut evicted = 0;
+
+        let mut l1 = self.l1_cache.write().await;
+        let l1_target = target(l1.max_size);
+        while l1.cache.len() > l1_target && l1.cache.pop_lru().is_some() {
+            evicted += 1;
+        }
+        drop(l1);
+
+        let mut l2 = self.l2_cache.write().await;
+        let l2_target = target(l2.max_size);
+        while l2.cache.len() > l2_target && l2.cache.pop_lru().is_some() {
+            evicted += 1;
+        }
+        drop(l2);
+
+        let mut l3 = self.l3_cache.write().await;
+        let l3_target = target(l3.max_size);
+        if l3.cache.len() > l3_target {
+            let mut by_age: Vec<_> = l3.cache.iter().map(|(key, entry)| (entry.created_at, key.clone())).collect();
+            by_age.sort();
+            let excess = l3.cache.len() - l3_target;
+            for (_, key) in by_age.into_iter().take(excess) {
+                l3.cache.remove(&key);
+                evicted += 1;
+            }
+        }
+        evicted
+    }
+
     /// Get cache statistics
     pub async fn get_stats(&self) -> CacheStats {
         self.stats.read().await.clone()
@@ -608,6 +650,79 @@ impl<T: Clone + Send + Sync + 'static> MultiCache<T> {
     }
 }
 
+/// Cache entry as stored in a snapshot file
+#[derive(Debug, Serialize, Deserialize)]
+struct SnapshotEntry<T> {
+    key: String,
+    value: T,
+    /// When the entry expires, in milliseconds since the Unix epoch
+    expires_at_ms: u64,
+}
+
+/// Milliseconds since the Unix epoch
+fn unix_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+impl<T: Clone + Send + Sync + Serialize + DeserializeOwned + 'static> MultiCache<T> {
+    /// Dump L2 and L3 entries with their expiry time to a JSON file,
+    /// returns the number of entries written
+    pub async fn dump_snapshot(&self, path: impl AsRef<std::path::Path>) -> Result<usize, ProxyError> {
+        let now = unix_millis();
+        let mut entries = Vec::new();
+        {
+            let l2 = self.l2_cache.read().await;
+            entries.extend(l2.cache.iter().filter(|(_, e)| !e.is_expired()).map(|(k, e)| SnapshotEntry {
+                key: k.clone(),
+                value: e.value.clone(),
+                expires_at_ms: now + e.remaining_ttl().as_millis() as u64,
+            }));
+        }
+        {
+            let l3 = self.l3_cache.read().await;
+            entries.extend(
+                l3.cache
+                    .iter()
+                    .filter(|(k, e)| !e.is_expired() && !entries.iter().any(|s| &s.key == *k))
+                    .map(|(k, e)| SnapshotEntry {
+                        key: k.clone(),
+                        value: e.value.clone(),
+                        expires_at_ms: now + e.remaining_ttl().as_millis() as u64,
+                    })
+                    .collect::<Vec<_>>(),
+            );
+        }
+
+        let content = serde_json::to_string(&entries)?;
+        tokio::fs::write(path.as_ref(), content)
+            .await
+            .map_err(|e| ProxyError::CacheError(format!("failed to write snapshot: {}", e)))?;
+        Ok(entries.len())
+    }
+
+    /// Preload entries from a snapshot file into L3, skipping the ones expired since
+    /// the dump, returns the number of entries loaded
+    pub async fn load_snapshot(&self, path: impl AsRef<std::path::Path>) -> Result<usize, ProxyError> {
+        let content = tokio::fs::read_to_string(path.as_ref())
+            .await
+            .map_err(|e| ProxyError::CacheError(format!("failed to read snapshot: {}", e)))?;
+        let entries: Vec<SnapshotEntry<T>> = serde_json::from_str(&content)?;
+
+        let now = unix_millis();
+        let mut l3 = self.l3_cache.write().await;
+        let mut loaded = 0;
+        for entry in entries {
+            if entry.expires_at_ms <= now {
+                continue;
+            }
+            l3.put(entry.key, entry.value, Some(Duration::from_millis(entry.expires_at_ms - now)));
+            loaded += 1;
+        }
+        log::debug!("Loaded {} cache entries from snapshot", loaded);
+        Ok(loaded)
+    }
+}
+
 /// Cache level for insertion
 #[derive(Debug, Clone, Copy)]
 pub enum CacheLevel {
@@ -672,6 +787,33 @@ mod tests {
     use super::*;
     use tokio::time::sleep;
 
+    #[tokio::test]
+    async fn test_trim_keeps_share_of_capacity() {
+        let config = MultiCacheConfig { l1_size: 4, l2_size: 4, l3_size: 4, ..MultiCacheConfig::default() };
+        let cache: MultiCache<u32> = MultiCache::new(config);
+        for i in 0..4 {
+            cache.put(format!("l1-{}", i), i, CacheLevel::L1).await;
+            cache.put(format!("l3-{}", i), i, CacheLevel::L3).await;
+        }
+
+        assert_eq!(cache.trim(0.5).await, 4);
+        // The most recently used L1 entries survive
+        assert_eq!(cache.get("l1-3").await, Some(3));
+        assert_eq!(cache.get("l1-0").await, None);
+        assert_eq!(cache.trim(0.5).await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_moved_entries_keep_their_ttl() {
+        let cache: MultiCache<u32> = MultiCache::new(MultiCacheConfig::default());
+        cache.put_with_ttl("short".to_string(), 1, Duration::from_millis(50), CacheLevel::L3).await;
+
+        // Read once, the entry moves up to L2 with what is left of its TTL
+        assert_eq!(cache.get("short").await, Some(1));
+        sleep(Duration::from_millis(100)).await;
+        assert_eq!(cache.get("short").await, None);
+    }
+
     #[tokio::test]
     async fn test_multi_cache_basic() {
         let config = MultiCacheConfig::default();
@@ -704,7 +846,7 @@ mod tests {
 
         // Should now be in L1
         let stats = cache.get_detailed_stats().await;
-        assert!(stats.promotions > 0);
+        assert!(stats.overall.promotions > 0);
     }
 
     #[tokio::test]
@@ -741,4 +883,46 @@ mod tests {
         assert_eq!(stats.total_misses, 1);
         assert_eq!(stats.hit_rate(), 50.0);
     }
+
+    #[tokio::test]
+    async fn test_snapshot_round_trip() {
+        let path = std::env::temp_dir().join(format!("proxy-rs-snapshot-{}.json", std::process::id()));
+        let cache: MultiCache<String> = MultiCache::new(MultiCacheConfig::default());
+        cache.put("l2".to_string(), "value2".to_string(), CacheLevel::L2).await;
+        cache.put("l3".to_string(), "value3".to_string(), CacheLevel::L3).await;
+        cache.put_with_ttl("short".to_string(), "soon".to_string(), Duration::from_millis(200), CacheLevel::L3).await;
+        cache.put_with_ttl("expired".to_string(), "gone".to_string(), Duration::from_millis(1), CacheLevel::L3).await;
+        sleep(Duration::from_millis(10)).await;
+
+        assert_eq!(cache.dump_snapshot(&path).await.unwrap(), 3);
+
+        let preloaded: MultiCache<String> = MultiCache::new(MultiCacheConfig::default());
+        assert_eq!(preloaded.load_snapshot(&path).await.unwrap(), 3);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(preloaded.get("l2").await, Some("value2".to_string()));
+        assert_eq!(preloaded.get("l3").await, Some("value3".to_string()));
+        assert_eq!(preloaded.get("expired").await, None);
+
+        // The remaining TTL is carried over rather than reset to the L3 default
+        sleep(Duration::from_millis(250)).await;
+        assert_eq!(preloaded.get("short").await, None);
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_drops_entries_expired_since_dump() {
+        let path = std::env::temp_dir().join(format!("proxy-rs-snapshot-expiry-{}.json", std::process::id()));
+        let cache: MultiCache<String> = MultiCache::new(MultiCacheConfig::default());
+        cache.put("kept".to_string(), "value".to_string(), CacheLevel::L3).await;
+        cache.put_with_ttl("short".to_string(), "soon".to_string(), Duration::from_millis(100), CacheLevel::L3).await;
+        assert_eq!(cache.dump_snapshot(&path).await.unwrap(), 2);
+
+        // The snapshot sits on disk longer than the short entry lives
+        sleep(Duration::from_millis(150)).await;
+        let preloaded: MultiCache<String> = MultiCache::new(MultiCacheConfig::default());
+        assert_eq!(preloaded.load_snapshot(&path).await.unwrap(), 1);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(preloaded.get("short").await, None);
+        assert_eq!(preloaded.get("kept").await, Some("value".to_string()));
+    }
 }
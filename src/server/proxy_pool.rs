@@ -1,15 +1,102 @@
-use crate::{proxy::Proxy, resolver::GeoData};
+use crate::{
+    proxy::{host_port, is_fresh, Proxy},
+    resolver::GeoData,
+};
+use chrono::{DateTime, Utc};
 use concurrent_queue::ConcurrentQueue;
+use futures_util::future::join_all;
 use lazy_static::lazy_static;
+use parking_lot::Mutex;
 use std::{
     cmp::Ordering,
-    collections::{BTreeMap, BinaryHeap, VecDeque},
+    collections::{BTreeMap, BinaryHeap, HashSet, VecDeque},
+    future::Future,
+    time::Duration,
 };
 
 lazy_static! {
     pub static ref LIVE_PROXIES: ConcurrentQueue<Proxy> = ConcurrentQueue::bounded(20);
 }
 
+/// Share of the live proxies rechecked per cycle, the others stay available to the server
+pub const RECHECK_FRACTION: f64 = 0.25;
+
+/// Revalidate a `fraction` of the queued proxies (at least one) at once with `check`,
+/// which gives the proxy back with whether it still works. Working proxies go back to
+/// the end of the queue, or to `pool` when the queue refilled meanwhile, the others are
+/// dropped. Pinned proxies of `pool` are kept whatever the check says. Returns the
+/// number of dropped proxies.
+pub async fn recheck_live<F, Fut>(
+    queue: &ConcurrentQueue<Proxy>,
+    pool: &Mutex<ProxyPool>,
+    fraction: f64,
+    check: F,
+) -> usize
+where
+    F: FnMut(Proxy) -> Fut,
+    Fut: Future<Output = (Proxy, bool)>,
+{
+    let batch = ((queue.len() as f64 * fraction).ceil() as usize).max(1);
+    let proxies: Vec<Proxy> = (0..batch).map_while(|_| queue.pop().ok()).collect();
+    let mut dropped = 0;
+    for (proxy, working) in join_all(proxies.into_iter().map(check)).await {
+        let addr = proxy.as_text();
+        if !working && !pool.lock().pinned.contains(&addr) {
+            log::debug!("{} dropped, recheck failed", addr);
+            dropped += 1;
+            continue;
+        }
+        if let Err(e) = queue.push(proxy) {
+            log::debug!("{} moved to the pool, live queue is full", addr);
+            pool.lock().put(SimpleProxy::from(e.into_inner()));
+        }
+    }
+    dropped
+}
+
+/// Revalidate at once with `check` the pinned proxies of `pool` and a `fraction` of the
+/// others, least recently verified first. Working proxies go back with a new
+/// verification time, failing ones are dropped unless pinned. Returns the number of
+/// dropped proxies.
+pub async fn recheck_pool<F, Fut>(pool: &Mutex<ProxyPool>, fraction: f64, check: F) -> usize
+where
+    F: FnMut(Proxy) -> Fut,
+    Fut: Future<Output = (Proxy, bool)>,
+{
+    let mut checked = vec![];
+    let mut proxies = vec![];
+    let mut unchecked = vec![];
+    let batch = pool.lock().take_for_recheck(fraction);
+    for simple in batch {
+        match Proxy::create(&simple.host, simple.port, vec![]).await {
+            Some(mut proxy) => {
+                proxy.types = simple.types.clone();
+                checked.push(simple);
+                proxies.push(proxy);
+            }
+            None => unchecked.push(simple),
+        }
+    }
+    let results = join_all(proxies.into_iter().map(check)).await;
+
+    let mut dropped = 0;
+    let mut pool = pool.lock();
+    for (mut simple, (proxy, working)) in checked.into_iter().zip(results) {
+        if working {
+            simple.last_verified = proxy.last_verified;
+        } else if !pool.is_pinned(&simple) {
+            log::debug!("{} dropped from the pool, recheck failed", simple.as_text());
+            dropped += 1;
+            continue;
+        }
+        pool.put(simple);
+    }
+    for simple in unchecked {
+        pool.put(simple);
+    }
+    dropped
+}
+
 #[derive(Debug, Clone)]
 pub struct SimpleProxy {
     pub host: String,
@@ -21,10 +108,14 @@ pub struct SimpleProxy {
     pub runtimes: Vec<f64>,
     pub request_stat: i32,
     pub error_stat: BTreeMap<String, i32>,
+    /// Consecutive forwarding failures while serving
+    pub failure_stat: i32,
+    /// When a check last found the proxy working
+    pub last_verified: Option<DateTime<Utc>>,
 }
 impl SimpleProxy {
     pub fn as_text(&self) -> String {
-        format!("{}:{}", self.host, self.port)
+        host_port(&self.host, self.port)
     }
 
     pub fn error_rate(&self) -> f64 {
@@ -43,6 +134,15 @@ impl SimpleProxy {
         sum / self.runtimes.len() as f64
     }
 
+    /// Health between 0 and 1, higher is better: the share of successful requests,
+    /// discounted by the average response time (in seconds) and by consecutive failures
+    pub fn health_score(&self) -> f64 {
+        let reliability = 1.0 - self.error_rate().min(1.0);
+        let speed = 1.0 / (1.0 + self.avg_resp_time());
+        let streak = 1.0 / (1.0 + self.failure_stat.max(0) as f64);
+        reliability * speed * streak
+    }
+
     pub fn get_schemes(&mut self) -> Vec<String> {
         if self.schemes.is_empty() {
             for (proxy_type, _) in &self.types {
@@ -62,18 +162,27 @@ impl SimpleProxy {
     }
 }
 
+impl From<Proxy> for SimpleProxy {
+    fn from(proxy: Proxy) -> Self {
+        Self {
+            host: proxy.host,
+            port: proxy.port,
+            geo: proxy.geo,
+            types: proxy.types,
+            schemes: proxy.schemes,
+            runtimes: proxy.runtimes,
+            request_stat: proxy.request_stat,
+            error_stat: proxy.error_stat,
+            failure_stat: 0,
+            last_verified: proxy.last_verified,
+        }
+    }
+}
+
+/// The pool is a max-heap, the healthiest proxy comes out first
 impl Ord for SimpleProxy {
     fn cmp(&self, other: &Self) -> Ordering {
-        self.error_rate()
-            .partial_cmp(&other.error_rate())
-            .unwrap()
-            .reverse()
-            .then(
-                self.avg_resp_time()
-                    .partial_cmp(&other.avg_resp_time())
-                    .unwrap()
-                    .reverse(),
-            )
+        self.health_score().total_cmp(&other.health_score())
     }
 }
 
@@ -100,6 +209,11 @@ pub struct ProxyPool {
     pub max_error_rate: f64,
     pub max_avg_resp_time: f64,
     pub min_queue: i32,
+    pub max_failures: i32,
+    /// Proxies not verified within this window are dropped
+    pub max_age: Option<Duration>,
+    /// `host:port` of trusted proxies never evicted, however their checks go
+    pub pinned: HashSet<String>,
 }
 
 impl ProxyPool {
@@ -112,6 +226,9 @@ impl ProxyPool {
             max_error_rate: 0.5,
             max_avg_resp_time: 8.0,
             min_queue: 5,
+            max_failures: 3,
+            max_age: None,
+            pinned: HashSet::new(),
         }
     }
 
@@ -124,48 +241,52 @@ impl ProxyPool {
             max_error_rate: 0.5,
             max_avg_resp_time,
             min_queue: 5,
+            max_failures: 3,
+            max_age: None,
+            pinned: HashSet::new(),
         }
     }
 
     pub fn get(&mut self, schemes: &str) -> Option<SimpleProxy> {
         let scheme = schemes.to_uppercase();
-        if self.pool.len() + self.newcomers.len() < self.min_queue as usize {
-            return self.import(&scheme);
-        } else if !self.newcomers.is_empty() {
-            return self.newcomers.pop_front();
-        } else if self.strategy == "best" {
-            let mut cache = VecDeque::new();
-            while !self.pool.is_empty() {
-                if let Some(mut proxy) = self.pool.pop() {
-                    if proxy.get_schemes().contains(&scheme) {
-                        self.pool.extend(cache);
+        loop {
+            if self.pool.len() + self.newcomers.len() < self.min_queue as usize {
+                return self.import(&scheme);
+            } else if !self.newcomers.is_empty() {
+                while let Some(proxy) = self.newcomers.pop_front() {
+                    if !self.is_stale(&proxy) || self.is_pinned(&proxy) {
                         return Some(proxy);
+                    }
+                    log::debug!("{} dropped, not verified within {:?}", proxy.as_text(), self.max_age);
+                }
+            } else if self.strategy == "best" {
+                let mut cache = VecDeque::new();
+                while !self.pool.is_empty() {
+                    if let Some(mut proxy) = self.pool.pop() {
+                        if self.is_stale(&proxy) && !self.is_pinned(&proxy) {
+                            log::debug!("{} dropped, not verified within {:?}", proxy.as_text(), self.max_age);
+                        } else if proxy.get_schemes().contains(&scheme) {
+                            self.pool.extend(cache);
+                            return Some(proxy);
+                        } else {
+                            cache.push_back(proxy)
+                        }
                     } else {
-                        cache.push_back(proxy)
+                        break;
                     }
-                } else {
-                    break;
                 }
+                self.pool.extend(cache);
+                return self.import(&scheme);
+            } else {
+                return None;
             }
-            self.pool.extend(cache);
-            return self.import(&scheme);
         }
-        None
     }
 
     pub fn import(&mut self, expected_schemes: &String) -> Option<SimpleProxy> {
         loop {
             if let Ok(proxy) = LIVE_PROXIES.pop() {
-                let mut proxy = SimpleProxy {
-                    host: proxy.host.clone(),
-                    port: proxy.port,
-                    geo: proxy.geo.clone(),
-                    types: proxy.types.clone(),
-                    schemes: proxy.schemes.clone(),
-                    runtimes: proxy.runtimes.clone(),
-                    request_stat: proxy.request_stat,
-                    error_stat: proxy.error_stat.clone(),
-                };
+                let mut proxy = SimpleProxy::from(proxy);
                 if !proxy.get_schemes().contains(expected_schemes) {
                     self.put(proxy)
                 } else {
@@ -175,14 +296,95 @@ impl ProxyPool {
         }
     }
 
+    /// Move every proxy waiting in `LIVE_PROXIES` into the pool
+    pub fn import_live(&mut self) {
+        while let Ok(proxy) = LIVE_PROXIES.pop() {
+            self.put(SimpleProxy::from(proxy));
+        }
+    }
+
+    /// Take out the pinned proxies and a `fraction` of the others (at least one),
+    /// least recently verified first
+    pub fn take_for_recheck(&mut self, fraction: f64) -> Vec<SimpleProxy> {
+        let (mut batch, mut others): (Vec<_>, Vec<_>) = std::mem::take(&mut self.pool)
+            .into_iter()
+            .chain(std::mem::take(&mut self.newcomers))
+            .partition(|proxy| self.is_pinned(proxy));
+        let count = ((others.len() as f64 * fraction).ceil() as usize).max(1).min(others.len());
+        // Never verified sorts first
+        others.sort_by_key(|proxy| proxy.last_verified);
+        let rest = others.split_off(count);
+        batch.extend(others);
+        for proxy in rest {
+            self.put(proxy);
+        }
+        batch
+    }
+
+    /// Number of proxies held, ranked or newcomers
+    pub fn available(&self) -> usize {
+        self.pool.len() + self.newcomers.len()
+    }
+
+    /// Addresses of the `n` healthiest pooled proxies, best first
+    pub fn healthiest(&self, n: usize) -> Vec<String> {
+        let mut proxies: Vec<&SimpleProxy> = self.pool.iter().chain(&self.newcomers).collect();
+        proxies.sort_by(|a, b| b.cmp(a));
+        proxies.into_iter().take(n).map(SimpleProxy::as_text).collect()
+    }
+
+    /// Whether the proxy was not verified within `max_age`
+    pub fn is_stale(&self, proxy: &SimpleProxy) -> bool {
+        self.max_age
+            .is_some_and(|max_age| !is_fresh(proxy.last_verified, max_age))
+    }
+
+    /// Whether the proxy is pinned, so kept in the pool whatever its health
+    pub fn is_pinned(&self, proxy: &SimpleProxy) -> bool {
+        !self.pinned.is_empty() && self.pinned.contains(&proxy.as_text())
+    }
+
+    /// Whether a proxy is stale, failed too many times in a row, or has enough
+    /// requests and exceeds the error rate or response time limits
+    pub fn is_unhealthy(&self, proxy: &SimpleProxy) -> bool {
+        self.is_stale(proxy)
+            || proxy.failure_stat > self.max_failures
+            || (proxy.request_stat >= self.min_req_proxy
+                && (proxy.error_rate() > self.max_error_rate
+                    || proxy.avg_resp_time() > self.max_avg_resp_time))
+    }
+
+    /// Return a proxy to the pool after a successful forward
+    pub fn record_success(&mut self, mut proxy: SimpleProxy) {
+        proxy.request_stat += 1;
+        proxy.failure_stat = 0;
+        self.put(proxy);
+    }
+
+    /// Return a proxy to the pool after a failed forward, evicting it once
+    /// its consecutive failures exceed `max_failures`
+    pub fn record_failure(&mut self, mut proxy: SimpleProxy, reason: &str) {
+        proxy.request_stat += 1;
+        proxy.failure_stat += 1;
+        *proxy.error_stat.entry(reason.to_string()).or_insert(0) += 1;
+        self.put(proxy);
+    }
+
     pub fn put(&mut self, proxy: SimpleProxy) {
-        let is_exceed_time = proxy.error_rate() > self.max_error_rate
-            || proxy.avg_resp_time() > self.max_avg_resp_time;
+        if self.newcomers.contains(&proxy) || self.pool.iter().any(|p| p == &proxy) {
+            log::debug!("{} already in ProxyPool", proxy.as_text());
+            return;
+        }
 
-        if proxy.request_stat < self.min_req_proxy {
+        let pinned = self.is_pinned(&proxy);
+        if proxy.failure_stat > self.max_failures && !pinned {
+            log::debug!("{} removed from ProxyPool after {} failures", proxy.as_text(), proxy.failure_stat);
+        } else if self.is_stale(&proxy) && !pinned {
+            log::debug!("{} removed from ProxyPool, not verified within {:?}", proxy.as_text(), self.max_age);
+        } else if proxy.request_stat < self.min_req_proxy {
             log::debug!("{} added to newcomers", proxy.as_text());
             self.newcomers.push_back(proxy)
-        } else if proxy.request_stat >= self.min_req_proxy && is_exceed_time {
+        } else if self.is_unhealthy(&proxy) && !pinned {
             log::debug!("{} removed from ProxyPool", proxy.as_text());
         } else {
             log::debug!("{} added to pool", proxy.as_text());
@@ -190,16 +392,26 @@ impl ProxyPool {
         }
     }
 
-    pub fn remove(&mut self, host: &str, port: u16) -> Option<SimpleProxy> {
-        for index in 0..self.newcomers.len() {
-            let proxy = self.newcomers.pop_front().unwrap();
-            if proxy.host == host && proxy.port == port {
-                self.newcomers.remove(index);
-                return Some(proxy);
-            } else {
-                self.newcomers.push_back(proxy)
+    /// Fold in the proxies of `other`, e.g. the pool of another discovery worker.
+    /// A proxy both pools know keeps the copy with the best `health_score`.
+    #[allow(dead_code)] // Library API for coordinators, the binary runs a single pool
+    pub fn merge(&mut self, other: ProxyPool) {
+        for proxy in other.pool.into_iter().chain(other.newcomers) {
+            match self.remove(&proxy.host, proxy.port) {
+                Some(existing) if existing >= proxy => self.put(existing),
+                _ => self.put(proxy),
             }
         }
+    }
+
+    pub fn remove(&mut self, host: &str, port: u16) -> Option<SimpleProxy> {
+        if let Some(index) = self
+            .newcomers
+            .iter()
+            .position(|proxy| proxy.host == host && proxy.port == port)
+        {
+            return self.newcomers.remove(index);
+        }
         let mut cache = VecDeque::new();
         while !self.pool.is_empty() {
             if let Some(proxy) = self.pool.pop() {
@@ -218,3 +430,276 @@ impl ProxyPool {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn simple_proxy(host: &str, port: u16) -> SimpleProxy {
+        SimpleProxy {
+            host: host.to_string(),
+            port,
+            geo: GeoData::default(),
+            types: vec![("HTTP".to_string(), None)],
+            schemes: vec![],
+            runtimes: vec![],
+            request_stat: 0,
+            error_stat: BTreeMap::new(),
+            failure_stat: 0,
+            last_verified: None,
+        }
+    }
+
+    #[test]
+    fn test_failing_proxy_is_evicted() {
+        let mut pool = ProxyPool::new();
+        pool.min_queue = 0;
+        pool.max_failures = 2;
+        pool.put(simple_proxy("10.0.0.1", 8080));
+        pool.put(simple_proxy("10.0.0.2", 8080));
+
+        let failing = pool.get("HTTP").unwrap();
+        assert_eq!(failing.host, "10.0.0.1");
+        pool.record_failure(failing, "bad_gateway");
+        for _ in 0..2 {
+            let proxy = pool.remove("10.0.0.1", 8080).unwrap();
+            pool.record_failure(proxy, "bad_gateway");
+        }
+
+        let next = pool.get("HTTP").unwrap();
+        assert_eq!(next.host, "10.0.0.2");
+        assert!(pool.remove("10.0.0.1", 8080).is_none());
+    }
+
+    #[test]
+    fn test_pinned_proxy_survives_eviction() {
+        let mut pool = ProxyPool::new();
+        pool.min_queue = 0;
+        pool.max_failures = 1;
+        pool.pinned.insert("10.0.0.1:8080".to_string());
+        pool.put(simple_proxy("10.0.0.1", 8080));
+        pool.put(simple_proxy("10.0.0.2", 8080));
+
+        for _ in 0..3 {
+            for host in ["10.0.0.1", "10.0.0.2"] {
+                if let Some(proxy) = pool.remove(host, 8080) {
+                    pool.record_failure(proxy, "bad_gateway");
+                }
+            }
+        }
+
+        // The pinned proxy keeps its stats, the unpinned one is gone
+        let pinned = pool.remove("10.0.0.1", 8080).unwrap();
+        assert_eq!(pinned.failure_stat, 3);
+        assert_eq!(pinned.request_stat, 3);
+        assert!(pool.remove("10.0.0.2", 8080).is_none());
+    }
+
+    #[test]
+    fn test_success_resets_failures() {
+        let mut pool = ProxyPool::new();
+        pool.min_queue = 0;
+        pool.max_failures = 1;
+        pool.put(simple_proxy("10.0.0.1", 8080));
+
+        let proxy = pool.get("HTTP").unwrap();
+        pool.record_failure(proxy, "bad_gateway");
+        let proxy = pool.get("HTTP").unwrap();
+        assert_eq!(proxy.failure_stat, 1);
+        pool.record_success(proxy);
+
+        let proxy = pool.get("HTTP").unwrap();
+        assert_eq!(proxy.failure_stat, 0);
+        assert_eq!(proxy.request_stat, 2);
+    }
+
+    #[test]
+    fn test_stale_proxy_is_filtered() {
+        let mut pool = ProxyPool::new();
+        pool.min_queue = 0;
+        pool.max_age = Some(Duration::from_secs(600));
+
+        let mut old = simple_proxy("10.0.0.1", 8080);
+        old.last_verified = Some(Utc::now() - chrono::Duration::hours(2));
+        let mut fresh = simple_proxy("10.0.0.2", 8080);
+        fresh.last_verified = Some(Utc::now());
+        assert!(pool.is_stale(&old));
+        assert!(!pool.is_stale(&fresh));
+
+        pool.put(old);
+        pool.put(fresh);
+        assert_eq!(pool.get("HTTP").unwrap().host, "10.0.0.2");
+        assert!(pool.remove("10.0.0.1", 8080).is_none());
+
+        // A long run of stale proxies is skipped without growing the stack
+        for port in 1..=50_000 {
+            let mut old = simple_proxy("10.0.0.3", port);
+            old.last_verified = Some(Utc::now() - chrono::Duration::hours(2));
+            pool.put(old);
+        }
+        let mut fresh = simple_proxy("10.0.0.4", 8080);
+        fresh.last_verified = Some(Utc::now());
+        pool.put(fresh);
+        assert_eq!(pool.get("HTTP").unwrap().host, "10.0.0.4");
+    }
+
+    #[tokio::test]
+    async fn test_recheck_drops_failing_proxy() {
+        let queue = ConcurrentQueue::bounded(8);
+        for host in ["10.0.0.1", "10.0.0.2", "10.0.0.3", "10.0.0.4"] {
+            queue.push(Proxy::create(host, 8080, vec![]).await.unwrap()).unwrap();
+        }
+
+        let pool = Mutex::new(ProxyPool::new());
+        let check = |proxy: Proxy| async move {
+            let working = proxy.host != "10.0.0.1";
+            (proxy, working)
+        };
+        // A quarter of the queue per cycle, the failing proxy is first in line
+        assert_eq!(recheck_live(&queue, &pool, RECHECK_FRACTION, check).await, 1);
+        assert_eq!(queue.len(), 3);
+
+        // A full round brings every remaining proxy back
+        for _ in 0..3 {
+            assert_eq!(recheck_live(&queue, &pool, RECHECK_FRACTION, check).await, 0);
+        }
+        let mut hosts = vec![];
+        while let Ok(proxy) = queue.pop() {
+            hosts.push(proxy.host);
+        }
+        assert_eq!(hosts, ["10.0.0.2", "10.0.0.3", "10.0.0.4"]);
+    }
+
+    #[tokio::test]
+    async fn test_recheck_keeps_pinned_proxy() {
+        let queue = ConcurrentQueue::bounded(2);
+        for host in ["10.0.0.1", "10.0.0.2"] {
+            queue.push(Proxy::create(host, 8080, vec![]).await.unwrap()).unwrap();
+        }
+        let mut pinned_pool = ProxyPool::new();
+        pinned_pool.pinned.insert("10.0.0.1:8080".to_string());
+        let pool = Mutex::new(pinned_pool);
+
+        // The pinned proxy fails its check and the queue refills while it runs
+        let refill = Proxy::create("10.0.0.3", 8080, vec![]).await.unwrap();
+        let mut refill = Some(refill);
+        let check = |proxy: Proxy| {
+            if let Some(refill) = refill.take() {
+                queue.push(refill).unwrap();
+            }
+            async move { (proxy, false) }
+        };
+        assert_eq!(recheck_live(&queue, &pool, 0.5, check).await, 0);
+        assert_eq!(queue.len(), 2);
+        assert_eq!(pool.lock().healthiest(10), ["10.0.0.1:8080"]);
+
+        // Rechecked where it rests, kept when failing, verified again when working
+        assert_eq!(recheck_pool(&pool, 0.5, |proxy: Proxy| async move { (proxy, false) }).await, 0);
+        assert!(pool.lock().remove("10.0.0.1", 8080).unwrap().last_verified.is_none());
+        pool.lock().put(simple_proxy("10.0.0.1", 8080));
+        recheck_pool(&pool, 0.5, |mut proxy: Proxy| async move {
+            proxy.last_verified = Some(Utc::now());
+            (proxy, true)
+        })
+        .await;
+        assert!(pool.lock().remove("10.0.0.1", 8080).unwrap().last_verified.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_recheck_keeps_working_proxy_when_queue_refills() {
+        let queue = ConcurrentQueue::bounded(1);
+        queue.push(Proxy::create("10.0.0.1", 8080, vec![]).await.unwrap()).unwrap();
+        let pool = Mutex::new(ProxyPool::new());
+
+        let mut refill = Some(Proxy::create("10.0.0.2", 8080, vec![]).await.unwrap());
+        let check = |proxy: Proxy| {
+            if let Some(refill) = refill.take() {
+                queue.push(refill).unwrap();
+            }
+            async move { (proxy, true) }
+        };
+        assert_eq!(recheck_live(&queue, &pool, RECHECK_FRACTION, check).await, 0);
+        assert_eq!(queue.pop().unwrap().host, "10.0.0.2");
+        assert_eq!(pool.lock().healthiest(10), ["10.0.0.1:8080"]);
+    }
+
+    #[tokio::test]
+    async fn test_recheck_pool_drops_failing_proxies() {
+        let pool = Mutex::new(ProxyPool::new());
+        for (host, minutes) in [("10.0.0.1", 30), ("10.0.0.2", 20), ("10.0.0.3", 10), ("10.0.0.4", 1)] {
+            let mut proxy = simple_proxy(host, 8080);
+            proxy.last_verified = Some(Utc::now() - chrono::Duration::minutes(minutes));
+            pool.lock().put(proxy);
+        }
+
+        // Half the pool at once, the least recently verified ones
+        let checked = std::sync::Mutex::new(vec![]);
+        let check = |proxy: Proxy| {
+            checked.lock().unwrap().push(proxy.host.clone());
+            async move {
+                let working = proxy.host != "10.0.0.1";
+                (proxy, working)
+            }
+        };
+        assert_eq!(recheck_pool(&pool, 0.5, check).await, 1);
+        let mut checked = checked.into_inner().unwrap();
+        checked.sort();
+        assert_eq!(checked, ["10.0.0.1", "10.0.0.2"]);
+
+        let mut left = pool.lock().healthiest(10);
+        left.sort();
+        assert_eq!(left, ["10.0.0.2:8080", "10.0.0.3:8080", "10.0.0.4:8080"]);
+    }
+
+    #[test]
+    fn test_merge_keeps_faster_duplicate() {
+        let mut slow = simple_proxy("10.0.0.1", 8080);
+        slow.runtimes = vec![2.0, 3.0];
+        let mut fast = simple_proxy("10.0.0.1", 8080);
+        fast.runtimes = vec![0.2, 0.3];
+
+        let mut coordinator = ProxyPool::new();
+        coordinator.put(slow);
+        coordinator.put(simple_proxy("10.0.0.2", 8080));
+        let mut worker = ProxyPool::new();
+        worker.put(fast);
+        worker.put(simple_proxy("10.0.0.3", 8080));
+
+        coordinator.merge(worker);
+        let mut merged = coordinator.healthiest(10);
+        merged.sort();
+        assert_eq!(merged, ["10.0.0.1:8080", "10.0.0.2:8080", "10.0.0.3:8080"]);
+        assert_eq!(coordinator.remove("10.0.0.1", 8080).unwrap().runtimes, [0.2, 0.3]);
+
+        // A slower copy does not replace the one already kept
+        let mut slower = simple_proxy("10.0.0.2", 8080);
+        slower.runtimes = vec![5.0];
+        let mut worker = ProxyPool::new();
+        worker.put(slower);
+        coordinator.merge(worker);
+        assert!(coordinator.remove("10.0.0.2", 8080).unwrap().runtimes.is_empty());
+    }
+
+    #[test]
+    fn test_healthiest_proxy_comes_first() {
+        let mut pool = ProxyPool::new();
+        pool.min_queue = 0;
+        pool.min_req_proxy = 5;
+
+        let mut flaky = simple_proxy("10.0.0.1", 8080);
+        flaky.request_stat = 10;
+        flaky.error_stat.insert("bad_gateway".to_string(), 4);
+        flaky.failure_stat = 1;
+        flaky.runtimes = vec![3.0, 4.0];
+        let mut reliable = simple_proxy("10.0.0.2", 8080);
+        reliable.request_stat = 10;
+        reliable.runtimes = vec![0.2, 0.3];
+        assert!(reliable.health_score() > flaky.health_score());
+
+        pool.put(flaky);
+        pool.put(reliable);
+        assert_eq!(pool.healthiest(1), ["10.0.0.2:8080"]);
+        assert_eq!(pool.get("HTTP").unwrap().host, "10.0.0.2");
+        assert_eq!(pool.get("HTTP").unwrap().host, "10.0.0.1");
+    }
+}
@@ -0,0 +1,115 @@
+//! Server side of the SOCKS5 protocol (RFC 1928), limited to the CONNECT command
+//! without authentication
+
+use std::io::{Error, ErrorKind, Result};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+const VERSION: u8 = 0x05;
+const NO_AUTH: u8 = 0x00;
+const NO_ACCEPTABLE_METHOD: u8 = 0xff;
+const CMD_CONNECT: u8 = 0x01;
+const ATYP_IPV4: u8 = 0x01;
+const ATYP_DOMAIN: u8 = 0x03;
+const ATYP_IPV6: u8 = 0x04;
+
+/// Reply codes sent back to the client
+pub const REPLY_SUCCEEDED: u8 = 0x00;
+pub const REPLY_GENERAL_FAILURE: u8 = 0x01;
+pub const REPLY_NOT_ALLOWED: u8 = 0x02;
+pub const REPLY_HOST_UNREACHABLE: u8 = 0x04;
+pub const REPLY_COMMAND_NOT_SUPPORTED: u8 = 0x07;
+pub const REPLY_ADDRESS_NOT_SUPPORTED: u8 = 0x08;
+
+/// Negotiate the method and read the CONNECT request, returning the target as `host:port`.
+/// Unsupported requests are answered before the error is returned.
+pub async fn accept<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S) -> Result<String> {
+    let mut greeting = [0; 2];
+    stream.read_exact(&mut greeting).await?;
+    if greeting[0] != VERSION {
+        return Err(Error::new(ErrorKind::InvalidData, format!("unsupported SOCKS version {}", greeting[0])));
+    }
+    let mut methods = vec![0; greeting[1] as usize];
+    stream.read_exact(&mut methods).await?;
+    if !methods.contains(&NO_AUTH) {
+        stream.write_all(&[VERSION, NO_ACCEPTABLE_METHOD]).await?;
+        return Err(Error::new(ErrorKind::PermissionDenied, "client offers no unauthenticated method"));
+    }
+    stream.write_all(&[VERSION, NO_AUTH]).await?;
+
+    let mut request = [0; 4];
+    stream.read_exact(&mut request).await?;
+    if request[0] != VERSION {
+        return Err(Error::new(ErrorKind::InvalidData, format!("unsupported SOCKS version {}", request[0])));
+    }
+    if request[1] != CMD_CONNECT {
+        reply(stream, REPLY_COMMAND_NOT_SUPPORTED).await?;
+        return Err(Error::new(ErrorKind::Unsupported, format!("unsupported SOCKS command {}", request[1])));
+    }
+
+    let host = match request[3] {
+        ATYP_IPV4 => {
+            let mut octets = [0; 4];
+            stream.read_exact(&mut octets).await?;
+            Ipv4Addr::from(octets).to_string()
+        }
+        ATYP_IPV6 => {
+            let mut octets = [0; 16];
+            stream.read_exact(&mut octets).await?;
+            format!("[{}]", Ipv6Addr::from(octets))
+        }
+        ATYP_DOMAIN => {
+            let len = stream.read_u8().await? as usize;
+            let mut domain = vec![0; len];
+            stream.read_exact(&mut domain).await?;
+            String::from_utf8(domain).map_err(|e| Error::new(ErrorKind::InvalidData, e))?
+        }
+        atyp => {
+            reply(stream, REPLY_ADDRESS_NOT_SUPPORTED).await?;
+            return Err(Error::new(ErrorKind::InvalidData, format!("unsupported address type {}", atyp)));
+        }
+    };
+    let port = stream.read_u16().await?;
+    Ok(format!("{}:{}", host, port))
+}
+
+/// Send a reply to the CONNECT request. The bound address is not meaningful behind
+/// an upstream proxy, so it is always reported as 0.0.0.0:0.
+pub async fn reply<S: AsyncWrite + Unpin>(stream: &mut S, code: u8) -> Result<()> {
+    stream
+        .write_all(&[VERSION, code, 0x00, ATYP_IPV4, 0, 0, 0, 0, 0, 0])
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_accept_domain_connect() {
+        let (mut client, mut server) = tokio::io::duplex(64);
+        client.write_all(&[0x05, 0x02, 0x02, 0x00]).await.unwrap();
+        client.write_all(&[0x05, 0x01, 0x00, 0x03, 11]).await.unwrap();
+        client.write_all(b"example.com").await.unwrap();
+        client.write_all(&443u16.to_be_bytes()).await.unwrap();
+
+        assert_eq!(accept(&mut server).await.unwrap(), "example.com:443");
+        let mut method = [0; 2];
+        client.read_exact(&mut method).await.unwrap();
+        assert_eq!(method, [0x05, 0x00]);
+    }
+
+    #[tokio::test]
+    async fn test_bind_command_is_refused() {
+        let (mut client, mut server) = tokio::io::duplex(64);
+        client.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+        client.write_all(&[0x05, 0x02, 0x00, 0x01, 127, 0, 0, 1, 0, 80]).await.unwrap();
+
+        assert_eq!(accept(&mut server).await.unwrap_err().kind(), ErrorKind::Unsupported);
+        let mut answer = [0; 12];
+        client.read_exact(&mut answer).await.unwrap();
+        assert_eq!(answer[..2], [0x05, 0x00]);
+        assert_eq!(answer[2..4], [0x05, REPLY_COMMAND_NOT_SUPPORTED]);
+    }
+}
@@ -0,0 +1,238 @@
+//! Sticky sessions mapping a client to the same upstream proxy for a time window
+
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+use hyper::Request;
+use parking_lot::RwLock;
+
+use super::proxy_pool::{ProxyPool, SimpleProxy};
+
+/// Header a client can send to pin requests to a named session instead of its IP
+pub const SESSION_HEADER: &str = "x-proxy-session";
+
+/// Identifies a client session
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SessionKey {
+    /// Session keyed by the client source IP
+    ClientIp(IpAddr),
+    /// Session keyed by the value of the session header
+    Header(String),
+}
+
+impl SessionKey {
+    /// Build the session key for a request, preferring the session header over the source IP
+    pub fn from_request<B>(request: &Request<B>, client_ip: IpAddr) -> Self {
+        request
+            .headers()
+            .get(SESSION_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(|value| SessionKey::Header(value.to_string()))
+            .unwrap_or(SessionKey::ClientIp(client_ip))
+    }
+}
+
+/// Maps client sessions to the proxy they were assigned
+#[derive(Debug)]
+pub struct StickyMap {
+    sessions: RwLock<HashMap<SessionKey, (SimpleProxy, Instant)>>,
+    ttl: Duration,
+}
+
+impl StickyMap {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            sessions: RwLock::new(HashMap::new()),
+            ttl,
+        }
+    }
+
+    /// Sticky sessions are disabled when the TTL is zero
+    pub fn is_enabled(&self) -> bool {
+        !self.ttl.is_zero()
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Get the proxy assigned to `key`, or assign a new one from the pool
+    pub fn get_or_assign(
+        &self,
+        key: &SessionKey,
+        pool: &mut ProxyPool,
+        scheme: &str,
+    ) -> Option<SimpleProxy> {
+        if !self.is_enabled() {
+            return pool.get(scheme);
+        }
+
+        let assigned = self.sessions.read().get(key).cloned();
+        if let Some((mut proxy, assigned_at)) = assigned {
+            let serves_scheme = proxy.get_schemes().contains(&scheme.to_uppercase());
+            if !serves_scheme {
+                log::debug!("{} does not serve {}, reassigning session", proxy.as_text(), scheme);
+            } else if assigned_at.elapsed() < self.ttl {
+                // Prefer the pooled copy, it carries the latest stats. The proxy may
+                // also be in flight for a concurrent request of the same session.
+                let proxy = pool.remove(&proxy.host, proxy.port).unwrap_or(proxy);
+                if !pool.is_unhealthy(&proxy) {
+                    self.sessions
+                        .write()
+                        .insert(key.clone(), (proxy.clone(), assigned_at));
+                    return Some(proxy);
+                }
+                log::debug!("{} is unhealthy, reassigning session", proxy.as_text());
+            }
+            self.sessions.write().remove(key);
+        }
+
+        let proxy = pool.get(scheme)?;
+        log::debug!("Session {:?} assigned to {}", key, proxy.as_text());
+        self.sessions
+            .write()
+            .insert(key.clone(), (proxy.clone(), Instant::now()));
+        Some(proxy)
+    }
+
+    /// Drop every session assigned to the given proxy
+    pub fn evict(&self, host: &str, port: u16) {
+        self.sessions
+            .write()
+            .retain(|_, (proxy, _)| !(proxy.host == host && proxy.port == port));
+    }
+
+    /// Drop sessions whose TTL has expired
+    pub fn cleanup_expired(&self) {
+        let ttl = self.ttl;
+        self.sessions
+            .write()
+            .retain(|_, (_, assigned_at)| assigned_at.elapsed() < ttl);
+    }
+
+    pub fn len(&self) -> usize {
+        self.sessions.read().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sessions.read().is_empty()
+    }
+}
+
+impl Default for StickyMap {
+    fn default() -> Self {
+        Self::new(Duration::ZERO)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolver::GeoData;
+    use std::collections::BTreeMap;
+
+    fn simple_proxy(host: &str, port: u16) -> SimpleProxy {
+        SimpleProxy {
+            host: host.to_string(),
+            port,
+            geo: GeoData::default(),
+            types: vec![("HTTP".to_string(), None)],
+            schemes: vec![],
+            runtimes: vec![],
+            request_stat: 0,
+            error_stat: BTreeMap::new(),
+            failure_stat: 0,
+            last_verified: None,
+        }
+    }
+
+    fn test_pool() -> ProxyPool {
+        let mut pool = ProxyPool::new();
+        pool.min_queue = 0;
+        pool.put(simple_proxy("10.0.0.1", 8080));
+        pool.put(simple_proxy("10.0.0.2", 8080));
+        pool
+    }
+
+    #[test]
+    fn test_same_key_gets_same_proxy() {
+        let sticky = StickyMap::new(Duration::from_secs(60));
+        let mut pool = test_pool();
+        let client_a = SessionKey::ClientIp("192.168.1.10".parse().unwrap());
+        let client_b = SessionKey::Header("session-b".to_string());
+
+        let first = sticky.get_or_assign(&client_a, &mut pool, "HTTP").unwrap();
+        pool.put(first.clone());
+
+        let second = sticky.get_or_assign(&client_a, &mut pool, "HTTP").unwrap();
+        assert_eq!(first, second);
+        pool.put(second);
+
+        let other = sticky.get_or_assign(&client_b, &mut pool, "HTTP").unwrap();
+        assert_ne!(first, other);
+        assert_eq!(sticky.len(), 2);
+    }
+
+    #[test]
+    fn test_session_is_reassigned_for_another_scheme() {
+        let sticky = StickyMap::new(Duration::from_secs(60));
+        let mut pool = ProxyPool::new();
+        pool.min_queue = 0;
+        pool.put(simple_proxy("10.0.0.1", 8080));
+        let mut https_proxy = simple_proxy("10.0.0.3", 8080);
+        https_proxy.types = vec![("HTTPS".to_string(), None)];
+        pool.put(https_proxy);
+        let key = SessionKey::ClientIp("192.168.1.10".parse().unwrap());
+
+        let first = sticky.get_or_assign(&key, &mut pool, "HTTP").unwrap();
+        assert_eq!(first.host, "10.0.0.1");
+        pool.put(first.clone());
+
+        // The HTTP-only proxy cannot tunnel HTTPS, so the session moves
+        let second = sticky.get_or_assign(&key, &mut pool, "HTTPS").unwrap();
+        assert_eq!(second.host, "10.0.0.3");
+        pool.put(second.clone());
+        assert_eq!(sticky.get_or_assign(&key, &mut pool, "HTTPS").unwrap(), second);
+    }
+
+    #[test]
+    fn test_expired_and_evicted_sessions() {
+        let sticky = StickyMap::new(Duration::from_millis(1));
+        let mut pool = test_pool();
+        let key = SessionKey::ClientIp("192.168.1.10".parse().unwrap());
+
+        let first = sticky.get_or_assign(&key, &mut pool, "HTTP").unwrap();
+        std::thread::sleep(Duration::from_millis(5));
+        sticky.cleanup_expired();
+        assert!(sticky.is_empty());
+
+        pool.put(first.clone());
+        let second = sticky.get_or_assign(&key, &mut pool, "HTTP").unwrap();
+        sticky.evict(&second.host, second.port);
+        assert!(sticky.is_empty());
+    }
+
+    #[test]
+    fn test_session_key_from_request() {
+        let client_ip: IpAddr = "192.168.1.10".parse().unwrap();
+        let request = Request::builder()
+            .header(SESSION_HEADER, "scraper-1")
+            .body(())
+            .unwrap();
+        assert_eq!(
+            SessionKey::from_request(&request, client_ip),
+            SessionKey::Header("scraper-1".to_string())
+        );
+
+        let request = Request::builder().body(()).unwrap();
+        assert_eq!(
+            SessionKey::from_request(&request, client_ip),
+            SessionKey::ClientIp(client_ip)
+        );
+    }
+}
@@ -0,0 +1,132 @@
+//! Allow and deny lists restricting the destinations clients of the local
+//! server may reach
+
+/// Ports CONNECT tunnels may be opened to unless configured otherwise
+pub const DEFAULT_CONNECT_PORTS: [u16; 2] = [443, 80];
+
+/// Domain patterns checked against the target host of every request.
+///
+/// Patterns are case-insensitive globs where `*` matches any run of characters,
+/// e.g. `*.example.com`. A host on the deny list is refused even when the allow
+/// list matches it too, and an empty allow list admits every host not denied.
+/// Tunnels, through CONNECT or SOCKS5, may also be limited to a set of ports.
+#[derive(Debug, Clone, Default)]
+pub struct TargetFilter {
+    allow: Vec<String>,
+    deny: Vec<String>,
+    /// Ports tunnels may reach, empty allows every port
+    connect_ports: Vec<u16>,
+}
+
+impl TargetFilter {
+    pub fn new(allow: Vec<String>, deny: Vec<String>) -> Self {
+        let normalize = |patterns: Vec<String>| -> Vec<String> {
+            patterns
+                .into_iter()
+                .map(|pattern| normalize_host(pattern.trim()))
+                .filter(|pattern| !pattern.is_empty())
+                .collect()
+        };
+        Self {
+            allow: normalize(allow),
+            deny: normalize(deny),
+            connect_ports: vec![],
+        }
+    }
+
+    /// Only let tunnels reach `ports`, an empty list allows every port
+    pub fn with_connect_ports(mut self, ports: Vec<u16>) -> Self {
+        self.connect_ports = ports;
+        self
+    }
+
+    /// Whether a tunnel may be opened to `port`
+    pub fn is_connect_port_allowed(&self, port: u16) -> bool {
+        self.connect_ports.is_empty() || self.connect_ports.contains(&port)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.allow.is_empty() || !self.deny.is_empty()
+    }
+
+    /// Whether clients may reach `host`, given without its port
+    pub fn is_allowed(&self, host: &str) -> bool {
+        let host = normalize_host(host);
+        let matches = |patterns: &[String]| patterns.iter().any(|pattern| glob_match(pattern, &host));
+        if matches(&self.deny) {
+            return false;
+        }
+        self.allow.is_empty() || matches(&self.allow)
+    }
+}
+
+/// Lowercase `host` without IPv6 brackets or the trailing dot of a FQDN
+fn normalize_host(host: &str) -> String {
+    host.trim_start_matches('[').trim_end_matches(']').trim_end_matches('.').to_lowercase()
+}
+
+/// Match `text` against `pattern` where `*` stands for any run of characters
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let (pattern, text) = (pattern.as_bytes(), text.as_bytes());
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` and of the text it currently absorbs up to
+    let mut backtrack = None;
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == b'*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == text[t] {
+            p += 1;
+            t += 1;
+        } else if let Some((star, absorbed)) = backtrack {
+            p = star + 1;
+            t = absorbed + 1;
+            backtrack = Some((star, absorbed + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target_filter(allow: &[&str], deny: &[&str]) -> TargetFilter {
+        let owned = |patterns: &[&str]| patterns.iter().map(|p| p.to_string()).collect();
+        TargetFilter::new(owned(allow), owned(deny))
+    }
+
+    #[test]
+    fn test_allowed_hosts_pass() {
+        let filter = target_filter(&["*.example.com", "example.org"], &[]);
+        assert!(filter.is_allowed("www.example.com"));
+        assert!(filter.is_allowed("API.Example.COM."));
+        assert!(filter.is_allowed("example.org"));
+        assert!(!filter.is_allowed("example.com"));
+        assert!(!filter.is_allowed("example.org.evil.net"));
+        assert!(TargetFilter::default().is_allowed("anything.net"));
+    }
+
+    #[test]
+    fn test_deny_takes_precedence_over_allow() {
+        let filter = target_filter(&["*.example.com"], &["admin.example.com"]);
+        assert!(filter.is_allowed("www.example.com"));
+        assert!(!filter.is_allowed("admin.example.com"));
+
+        let deny_only = target_filter(&[], &["*.internal", "[::1]"]);
+        assert!(deny_only.is_allowed("example.com"));
+        assert!(!deny_only.is_allowed("db.internal"));
+        assert!(!deny_only.is_allowed("::1"));
+    }
+
+    #[test]
+    fn test_connect_ports() {
+        let filter = TargetFilter::default().with_connect_ports(DEFAULT_CONNECT_PORTS.to_vec());
+        assert!(filter.is_connect_port_allowed(443));
+        assert!(filter.is_connect_port_allowed(80));
+        assert!(!filter.is_connect_port_allowed(25));
+        assert!(TargetFilter::default().is_connect_port_allowed(25));
+    }
+}
@@ -0,0 +1,217 @@
+//! Reuse of idle CONNECT tunnels for later CONNECTs to the same target
+//!
+//! Only tunnels that never carried a byte are handed back: once a TLS session ran
+//! through a tunnel the target will not accept a new handshake on it. This covers
+//! clients that pre-open tunnels and drop them unused (browser preconnects, pools
+//! warming up) which would otherwise cost a full proxy round trip each.
+
+use std::{
+    collections::HashMap,
+    io::ErrorKind,
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+/// Idle tunnels with the time they were parked, keyed by `(proxy chain, target)`
+type IdleTunnels = HashMap<(String, String), Vec<(TcpStream, Instant)>>;
+
+/// Established tunnels waiting to be reused
+#[derive(Debug)]
+pub struct TunnelCache {
+    tunnels: Mutex<IdleTunnels>,
+    idle_timeout: Duration,
+}
+
+impl TunnelCache {
+    pub fn new(idle_timeout: Duration) -> Self {
+        Self {
+            tunnels: Mutex::new(HashMap::new()),
+            idle_timeout,
+        }
+    }
+
+    /// Tunnel reuse is disabled when the idle timeout is zero
+    pub fn is_enabled(&self) -> bool {
+        !self.idle_timeout.is_zero()
+    }
+
+    pub fn idle_timeout(&self) -> Duration {
+        self.idle_timeout
+    }
+
+    /// Keep an unused tunnel through `proxy` to `target` for later CONNECTs
+    pub fn put(&self, proxy: &str, target: &str, stream: TcpStream) {
+        if self.is_enabled() {
+            self.tunnels
+                .lock()
+                .entry((proxy.to_string(), target.to_string()))
+                .or_default()
+                .push((stream, Instant::now()));
+        }
+    }
+
+    /// Take an idle tunnel through `proxy` to `target`, skipping expired or closed ones
+    pub fn take(&self, proxy: &str, target: &str) -> Option<TcpStream> {
+        let mut tunnels = self.tunnels.lock();
+        let key = (proxy.to_string(), target.to_string());
+        let idle = tunnels.get_mut(&key)?;
+        let mut found = None;
+        while let Some((stream, idle_since)) = idle.pop() {
+            if idle_since.elapsed() >= self.idle_timeout {
+                continue;
+            }
+            if let Some(stream) = untouched(stream) {
+                found = Some(stream);
+                break;
+            }
+        }
+        if idle.is_empty() {
+            tunnels.remove(&key);
+        }
+        found
+    }
+
+    /// Drop tunnels idle for longer than the timeout
+    pub fn cleanup_expired(&self) {
+        let idle_timeout = self.idle_timeout;
+        self.tunnels.lock().retain(|_, idle| {
+            idle.retain(|(_, idle_since)| idle_since.elapsed() < idle_timeout);
+            !idle.is_empty()
+        });
+    }
+
+    /// Number of idle tunnels
+    pub fn len(&self) -> usize {
+        self.tunnels.lock().values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for TunnelCache {
+    fn default() -> Self {
+        Self::new(Duration::ZERO)
+    }
+}
+
+/// An idle tunnel is reusable while open and without unread data from the target.
+/// Peeks the socket itself, the reactor's cached readiness may be stale.
+fn untouched(stream: TcpStream) -> Option<TcpStream> {
+    let stream = stream.into_std().ok()?;
+    match stream.peek(&mut [0; 1]) {
+        Err(e) if e.kind() == ErrorKind::WouldBlock => TcpStream::from_std(stream).ok(),
+        _ => None,
+    }
+}
+
+/// Relay between a client and a tunnel. The tunnel is returned when the client went
+/// away before a byte was relayed in either direction, so it can be reused.
+pub async fn relay<C>(client: &mut C, mut upstream: TcpStream) -> std::io::Result<Option<TcpStream>>
+where
+    C: AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    let mut buf = vec![0; 8192];
+    let mut upstream_buf = vec![0; 8192];
+    tokio::select! {
+        biased;
+        read = client.read(&mut buf) => match read {
+            Ok(0) | Err(_) => return Ok(Some(upstream)),
+            Ok(n) => upstream.write_all(&buf[..n]).await?,
+        },
+        // The target talks first, the tunnel now carries data
+        read = upstream.read(&mut upstream_buf) => match read? {
+            0 => return Ok(None),
+            n => client.write_all(&upstream_buf[..n]).await?,
+        },
+    }
+
+    let (from_client, from_upstream) = tokio::io::copy_bidirectional(client, &mut upstream).await?;
+    log::debug!("CONNECT tunnel closed ({} bytes sent, {} bytes received)", from_client, from_upstream);
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::chain::send_connect_request;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+    use tokio::net::TcpListener;
+
+    /// CONNECT proxy stub counting established tunnels, the target side stays silent
+    async fn counting_proxy(connects: Arc<AtomicUsize>) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut client, _)) = listener.accept().await {
+                connects.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(async move {
+                    let mut head = vec![0; 1024];
+                    let _ = client.read(&mut head).await;
+                    client.write_all(b"HTTP/1.1 200 Connection established\r\n\r\n").await.unwrap();
+                    let mut rest = vec![];
+                    let _ = client.read_to_end(&mut rest).await;
+                });
+            }
+        });
+        port
+    }
+
+    async fn open_tunnel(port: u16) -> TcpStream {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        assert_eq!(send_connect_request(&mut stream, "example.com:443", Duration::from_secs(5), Duration::from_secs(5)).await, Some(200));
+        stream
+    }
+
+    #[tokio::test]
+    async fn test_second_connect_reuses_tunnel() {
+        let connects = Arc::new(AtomicUsize::new(0));
+        let port = counting_proxy(Arc::clone(&connects)).await;
+        let proxy = format!("127.0.0.1:{}", port);
+        let cache = TunnelCache::new(Duration::from_secs(10));
+
+        // First CONNECT: the client leaves without using the tunnel
+        let (mut client, client_side) = tokio::io::duplex(64);
+        drop(client_side);
+        let unused = relay(&mut client, open_tunnel(port).await).await.unwrap();
+        cache.put(&proxy, "example.com:443", unused.expect("unused tunnel is returned"));
+
+        // Second CONNECT to the same target gets it back without a new proxy round trip
+        assert!(cache.take(&proxy, "other.com:443").is_none());
+        assert!(cache.take(&proxy, "example.com:443").is_some());
+        assert_eq!(connects.load(Ordering::SeqCst), 1);
+        assert!(cache.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_used_tunnel_is_not_reused() {
+        let connects = Arc::new(AtomicUsize::new(0));
+        let port = counting_proxy(connects).await;
+
+        let (mut client, mut client_side) = tokio::io::duplex(64);
+        client_side.write_all(b"\x16\x03\x01").await.unwrap();
+        drop(client_side);
+        assert!(relay(&mut client, open_tunnel(port).await).await.unwrap().is_none());
+
+        // A tunnel with pending data from the target is dropped on take
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let (mut server, _) = listener.accept().await.unwrap();
+        server.write_all(b"late").await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let cache = TunnelCache::new(Duration::from_secs(10));
+        cache.put("proxy", "example.com:443", stream);
+        assert!(cache.take("proxy", "example.com:443").is_none());
+    }
+}
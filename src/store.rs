@@ -0,0 +1,258 @@
+//! Persistent SQLite store of discovered proxies, enabled with the `sqlite` feature
+
+use std::{path::Path, sync::Arc};
+
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::Serialize;
+
+use crate::{
+    proxy::Proxy,
+    utils::error::{ProxyError, ProxyResult},
+};
+
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS proxies (
+    host TEXT NOT NULL,
+    port INTEGER NOT NULL,
+    country_code TEXT NOT NULL,
+    country_name TEXT NOT NULL,
+    region_name TEXT NOT NULL,
+    city_name TEXT NOT NULL,
+    protocols TEXT NOT NULL,
+    first_seen TEXT NOT NULL,
+    last_verified TEXT,
+    success_rate REAL NOT NULL,
+    tags TEXT NOT NULL DEFAULT '',
+    PRIMARY KEY (host, port)
+)";
+
+/// Databases created before tags were stored lack the column
+const ADD_TAGS: &str = "ALTER TABLE proxies ADD COLUMN tags TEXT NOT NULL DEFAULT ''";
+
+const COLUMNS: &str =
+    "host, port, country_code, country_name, region_name, city_name, protocols, first_seen, last_verified, success_rate, tags";
+
+/// A proxy as kept in the store
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StoredProxy {
+    pub host: String,
+    pub port: u16,
+    pub country_code: String,
+    pub country_name: String,
+    pub region_name: String,
+    pub city_name: String,
+    pub protocols: Vec<String>,
+    pub first_seen: DateTime<Utc>,
+    pub last_verified: Option<DateTime<Utc>>,
+    pub success_rate: f64,
+    pub tags: Vec<String>,
+}
+
+impl StoredProxy {
+    /// Id used by the API, `host:port`
+    pub fn id(&self) -> String {
+        crate::proxy::host_port(&self.host, self.port)
+    }
+
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        let protocols: String = row.get(6)?;
+        Ok(Self {
+            host: row.get(0)?,
+            port: row.get(1)?,
+            country_code: row.get(2)?,
+            country_name: row.get(3)?,
+            region_name: row.get(4)?,
+            city_name: row.get(5)?,
+            protocols: protocols.split(',').filter(|p| !p.is_empty()).map(String::from).collect(),
+            first_seen: row.get(7)?,
+            last_verified: row.get(8)?,
+            success_rate: row.get(9)?,
+            tags: row.get::<_, String>(10)?.split(',').filter(|t| !t.is_empty()).map(String::from).collect(),
+        })
+    }
+}
+
+impl From<&Proxy> for StoredProxy {
+    fn from(proxy: &Proxy) -> Self {
+        Self {
+            host: proxy.host.clone(),
+            port: proxy.port,
+            country_code: proxy.geo.iso_code.clone(),
+            country_name: proxy.geo.name.clone(),
+            region_name: proxy.geo.region_name.clone(),
+            city_name: proxy.geo.city_name.clone(),
+            protocols: proxy.types.iter().map(|(proto, _)| proto.clone()).collect(),
+            first_seen: proxy.first_seen,
+            last_verified: proxy.last_verified,
+            success_rate: 1.0 - proxy.error_rate().min(1.0),
+            tags: proxy.tags.clone(),
+        }
+    }
+}
+
+fn storage_error(e: rusqlite::Error) -> ProxyError {
+    ProxyError::Storage(e.to_string())
+}
+
+/// Proxies keyed by `host:port`, a proxy seen again updates its row. Queries run
+/// on the blocking thread pool, never on the async workers.
+#[derive(Debug)]
+pub struct ProxyStore {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl ProxyStore {
+    /// Open or create the database at `path`
+    pub fn open(path: &Path) -> ProxyResult<Self> {
+        Self::with_connection(Connection::open(path).map_err(storage_error)?)
+    }
+
+    pub fn open_in_memory() -> ProxyResult<Self> {
+        Self::with_connection(Connection::open_in_memory().map_err(storage_error)?)
+    }
+
+    fn with_connection(conn: Connection) -> ProxyResult<Self> {
+        conn.execute(SCHEMA, []).map_err(storage_error)?;
+        if conn.prepare("SELECT tags FROM proxies LIMIT 0").is_err() {
+            conn.execute(ADD_TAGS, []).map_err(storage_error)?;
+        }
+        Ok(Self { conn: Arc::new(Mutex::new(conn)) })
+    }
+
+    /// Run `query` on the connection from the blocking thread pool
+    async fn run<T, F>(&self, query: F) -> ProxyResult<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Connection) -> rusqlite::Result<T> + Send + 'static,
+    {
+        let conn = Arc::clone(&self.conn);
+        tokio::task::spawn_blocking(move || query(&conn.lock()))
+            .await
+            .map_err(|e| ProxyError::Storage(e.to_string()))?
+            .map_err(storage_error)
+    }
+
+    /// Insert the proxy or refresh its row, keeping when it was first seen
+    pub async fn upsert(&self, proxy: &StoredProxy) -> ProxyResult<()> {
+        let proxy = proxy.clone();
+        self.run(move |conn| {
+            conn.execute(
+                &format!(
+                    "INSERT INTO proxies ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+                     ON CONFLICT (host, port) DO UPDATE SET
+                        country_code = excluded.country_code,
+                        country_name = excluded.country_name,
+                        region_name = excluded.region_name,
+                        city_name = excluded.city_name,
+                        protocols = excluded.protocols,
+                        last_verified = COALESCE(excluded.last_verified, last_verified),
+                        success_rate = excluded.success_rate,
+                        tags = excluded.tags",
+                    COLUMNS
+                ),
+                params![
+                    proxy.host,
+                    proxy.port,
+                    proxy.country_code,
+                    proxy.country_name,
+                    proxy.region_name,
+                    proxy.city_name,
+                    proxy.protocols.join(","),
+                    proxy.first_seen,
+                    proxy.last_verified,
+                    proxy.success_rate,
+                    proxy.tags.join(","),
+                ],
+            )
+            .map(|_| ())
+        })
+        .await
+    }
+
+    /// Every stored proxy, most recently verified first
+    pub async fn list(&self) -> ProxyResult<Vec<StoredProxy>> {
+        self.run(|conn| {
+            let mut statement = conn.prepare(&format!(
+                "SELECT {} FROM proxies ORDER BY last_verified IS NULL, last_verified DESC, host, port",
+                COLUMNS
+            ))?;
+            let rows = statement.query_map([], StoredProxy::from_row)?;
+            rows.collect()
+        })
+        .await
+    }
+
+    pub async fn get(&self, host: &str, port: u16) -> ProxyResult<Option<StoredProxy>> {
+        let host = host.to_string();
+        self.run(move |conn| {
+            conn.query_row(
+                &format!("SELECT {} FROM proxies WHERE host = ?1 AND port = ?2", COLUMNS),
+                params![host, port],
+                StoredProxy::from_row,
+            )
+            .optional()
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_upsert_round_trip() {
+        let store = ProxyStore::open_in_memory().unwrap();
+        let mut proxy = Proxy::create("127.0.0.1", 8080, vec![]).await.unwrap();
+        proxy.types.push(("HTTP".to_string(), Some("High".to_string())));
+        proxy.types.push(("SOCKS5".to_string(), None));
+        proxy.last_verified = Some(Utc::now());
+        proxy.tags = vec!["paid".to_string()];
+
+        let stored = StoredProxy::from(&proxy);
+        store.upsert(&stored).await.unwrap();
+        let loaded = store.get("127.0.0.1", 8080).await.unwrap().unwrap();
+        assert_eq!(loaded.protocols, ["HTTP", "SOCKS5"]);
+        assert_eq!(loaded.last_verified, stored.last_verified);
+        assert_eq!(loaded.first_seen, stored.first_seen);
+        assert_eq!(loaded.success_rate, 1.0);
+        assert_eq!(loaded.tags, ["paid"]);
+        assert!(store.get("127.0.0.1", 3128).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_upsert_updates_existing_row() {
+        let store = ProxyStore::open_in_memory().unwrap();
+        let proxy = Proxy::create("127.0.0.1", 8080, vec![]).await.unwrap();
+        let first = StoredProxy {
+            last_verified: Some(Utc::now()),
+            ..StoredProxy::from(&proxy)
+        };
+        store.upsert(&first).await.unwrap();
+
+        let second = StoredProxy {
+            protocols: vec!["HTTPS".to_string()],
+            first_seen: Utc::now(),
+            last_verified: None,
+            success_rate: 0.5,
+            ..first.clone()
+        };
+        store.upsert(&second).await.unwrap();
+        let other = StoredProxy {
+            host: "127.0.0.2".to_string(),
+            ..second.clone()
+        };
+        store.upsert(&other).await.unwrap();
+
+        let proxies = store.list().await.unwrap();
+        assert_eq!(proxies.len(), 2);
+        // Verified proxies come first
+        assert_eq!(proxies[0].id(), "127.0.0.1:8080");
+        assert_eq!(proxies[0].protocols, ["HTTPS"]);
+        assert_eq!(proxies[0].success_rate, 0.5);
+        // An unverified update keeps the previous verification and first sighting
+        assert_eq!(proxies[0].last_verified, first.last_verified);
+        assert_eq!(proxies[0].first_seen, first.first_seen);
+    }
+}
@@ -0,0 +1,167 @@
+//! IP network (CIDR) parsing and matching
+
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+};
+
+use super::error::ProxyError;
+
+/// An IPv4 or IPv6 network such as `10.0.0.0/8`, or a single address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl Cidr {
+    /// Whether `ip` belongs to this network
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.network, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = mask_u32(self.prefix_len);
+                u32::from(network) & mask == u32::from(*ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = mask_u128(self.prefix_len);
+                u128::from(network) & mask == u128::from(*ip) & mask
+            }
+            _ => false,
+        }
+    }
+
+    /// Number of usable host addresses, the IPv4 network and broadcast
+    /// addresses are excluded for prefixes shorter than /31
+    pub fn host_count(&self) -> u128 {
+        match self.network {
+            IpAddr::V4(_) if self.prefix_len < 31 => (1u128 << (32 - self.prefix_len)) - 2,
+            IpAddr::V4(_) => 1u128 << (32 - self.prefix_len),
+            IpAddr::V6(_) if self.prefix_len == 0 => u128::MAX,
+            IpAddr::V6(_) => 1u128 << (128 - self.prefix_len),
+        }
+    }
+
+    /// List the usable host addresses, failing when there are more than `max_hosts`
+    pub fn hosts(&self, max_hosts: usize) -> Result<Vec<IpAddr>, ProxyError> {
+        let count = self.host_count();
+        if count > max_hosts as u128 {
+            return Err(ProxyError::ResourceExhausted(format!(
+                "{} expands to {} hosts, more than the maximum of {}",
+                self, count, max_hosts
+            )));
+        }
+
+        let hosts = match self.network {
+            IpAddr::V4(network) => {
+                let first = u32::from(network) & mask_u32(self.prefix_len);
+                let skip = if self.prefix_len < 31 { 1 } else { 0 };
+                (0..count as u32)
+                    .map(|offset| IpAddr::V4(Ipv4Addr::from(first + skip + offset)))
+                    .collect()
+            }
+            IpAddr::V6(network) => {
+                let first = u128::from(network) & mask_u128(self.prefix_len);
+                (0..count)
+                    .map(|offset| IpAddr::V6(Ipv6Addr::from(first + offset)))
+                    .collect()
+            }
+        };
+        Ok(hosts)
+    }
+}
+
+fn mask_u32(prefix_len: u8) -> u32 {
+    if prefix_len == 0 {
+        0
+    } else {
+        u32::MAX << (32 - prefix_len as u32)
+    }
+}
+
+fn mask_u128(prefix_len: u8) -> u128 {
+    if prefix_len == 0 {
+        0
+    } else {
+        u128::MAX << (128 - prefix_len as u32)
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = ProxyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (address, prefix) = match s.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (s, None),
+        };
+
+        let network: IpAddr = address
+            .parse()
+            .map_err(|_| ProxyError::InvalidFormat(format!("invalid IP address in '{}'", s)))?;
+        let max_len = if network.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix {
+            Some(prefix) => prefix
+                .parse::<u8>()
+                .ok()
+                .filter(|len| *len <= max_len)
+                .ok_or_else(|| ProxyError::InvalidFormat(format!("invalid prefix length in '{}'", s)))?,
+            None => max_len,
+        };
+
+        Ok(Self { network, prefix_len })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix_len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_contains() {
+        let cidr: Cidr = "192.168.0.0/16".parse().unwrap();
+        assert!(cidr.contains(&"192.168.10.20".parse().unwrap()));
+        assert!(!cidr.contains(&"192.169.0.1".parse().unwrap()));
+
+        let single: Cidr = "8.8.8.8".parse().unwrap();
+        assert_eq!(single.to_string(), "8.8.8.8/32");
+        assert!(single.contains(&"8.8.8.8".parse().unwrap()));
+        assert!(!single.contains(&"8.8.4.4".parse().unwrap()));
+
+        let v6: Cidr = "2001:db8::/32".parse().unwrap();
+        assert!(v6.contains(&"2001:db8::1".parse().unwrap()));
+        assert!(!v6.contains(&"10.0.0.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_invalid_cidr() {
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("not-an-ip/8".parse::<Cidr>().is_err());
+        assert!("10.0.0.0/abc".parse::<Cidr>().is_err());
+    }
+
+    #[test]
+    fn test_hosts_expansion() {
+        let cidr: Cidr = "10.0.0.0/30".parse().unwrap();
+        let hosts = cidr.hosts(16).unwrap();
+        assert_eq!(cidr.host_count(), 2);
+        assert_eq!(hosts, vec!["10.0.0.1".parse::<IpAddr>().unwrap(), "10.0.0.2".parse().unwrap()]);
+
+        let single: Cidr = "10.0.0.7/32".parse().unwrap();
+        assert_eq!(single.hosts(1).unwrap(), vec!["10.0.0.7".parse::<IpAddr>().unwrap()]);
+    }
+
+    #[test]
+    fn test_hosts_expansion_cap() {
+        let cidr: Cidr = "10.0.0.0/8".parse().unwrap();
+        assert_eq!(cidr.host_count(), 16_777_214);
+        assert!(matches!(cidr.hosts(1024), Err(ProxyError::ResourceExhausted(_))));
+    }
+}
@@ -37,6 +37,12 @@ pub enum ProxyError {
 
     #[error("Resource exhausted: {0}")]
     ResourceExhausted(String),
+
+    #[error("Storage error: {0}")]
+    Storage(String),
+
+    #[error("I/O error: {0}")]
+    Io(String),
 }
 
 pub type ProxyResult<T> = Result<T, ProxyError>;
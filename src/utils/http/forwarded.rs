@@ -0,0 +1,109 @@
+//! Parsing of the `Forwarded` header (RFC 7239)
+
+/// Nodes named by the `for=` parameters of a `Forwarded` header value, one per hop
+/// in order. Addresses lose their quotes, IPv6 brackets and port; obfuscated
+/// identifiers such as `_hidden` and `unknown` are returned as is.
+pub fn forwarded_for(value: &str) -> Vec<String> {
+    split_unquoted(value, ',')
+        .iter()
+        .flat_map(|element| split_unquoted(element, ';'))
+        .filter_map(|pair| {
+            let (key, node) = pair.split_once('=')?;
+            key.trim().eq_ignore_ascii_case("for").then(|| node_name(&unquote(node.trim())))
+        })
+        .filter(|node| !node.is_empty())
+        .collect()
+}
+
+/// Values of the `Forwarded` headers echoed in a judge response, either as sent
+/// (`Forwarded: ...`) or CGI style (`HTTP_FORWARDED = ...`)
+pub fn echoed_values(content: &str) -> Vec<&str> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim_start();
+            ["http_forwarded", "forwarded"].iter().find_map(|name| {
+                let rest = line.get(..name.len())?.eq_ignore_ascii_case(name).then(|| &line[name.len()..])?;
+                let rest = rest.trim_start();
+                rest.strip_prefix('=').or_else(|| rest.strip_prefix(':')).map(str::trim)
+            })
+        })
+        .collect()
+}
+
+/// Split on `separator` outside of quoted strings
+fn split_unquoted(value: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut start, mut quoted, mut escaped) = (0, false, false);
+    for (index, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            c if c == separator && !quoted => {
+                parts.push(&value[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&value[start..]);
+    parts
+}
+
+/// Content of a quoted-string with its escapes resolved, a token unchanged
+fn unquote(value: &str) -> String {
+    match value.strip_prefix('"').and_then(|rest| rest.strip_suffix('"')) {
+        Some(inner) => {
+            let mut unquoted = String::with_capacity(inner.len());
+            let mut chars = inner.chars();
+            while let Some(c) = chars.next() {
+                unquoted.push(if c == '\\' { chars.next().unwrap_or(c) } else { c });
+            }
+            unquoted
+        }
+        None => value.to_string(),
+    }
+}
+
+/// Node without its port, `[2001:db8::1]:4711` becomes `2001:db8::1`. An IPv6
+/// address some proxies send without the brackets is kept whole.
+fn node_name(node: &str) -> String {
+    if let Some(rest) = node.strip_prefix('[') {
+        return rest.split(']').next().unwrap_or_default().to_string();
+    }
+    match node.split_once(':') {
+        Some((host, port)) if !port.contains(':') => host.to_string(),
+        _ => node.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_forwarded_for_shapes() {
+        assert_eq!(forwarded_for("for=192.0.2.60;proto=http;by=203.0.113.43"), ["192.0.2.60"]);
+        assert_eq!(forwarded_for("For=\"192.0.2.43:47011\""), ["192.0.2.43"]);
+        assert_eq!(forwarded_for("for=\"[2001:db8:cafe::17]:4711\""), ["2001:db8:cafe::17"]);
+        assert_eq!(forwarded_for("for=\"[2001:db8:cafe::17]\""), ["2001:db8:cafe::17"]);
+        assert_eq!(forwarded_for("for=\"2001:db8:cafe::17\""), ["2001:db8:cafe::17"]);
+        // Obfuscated identifiers are kept, parameters other than for are skipped
+        assert_eq!(forwarded_for("for=_hidden, for=unknown;by=_proxy1"), ["_hidden", "unknown"]);
+        assert!(forwarded_for("by=203.0.113.43;proto=https").is_empty());
+    }
+
+    #[test]
+    fn test_forwarded_for_multiple_hops() {
+        let value = "for=192.0.2.43, for=\"[2001:db8:cafe::17]\";proto=\"a,b;c\" , FOR = 198.51.100.17";
+        assert_eq!(forwarded_for(value), ["192.0.2.43", "2001:db8:cafe::17", "198.51.100.17"]);
+        assert_eq!(forwarded_for("for=\"_quoted\\\"node\""), ["_quoted\"node"]);
+    }
+
+    #[test]
+    fn test_echoed_values() {
+        let content = "REMOTE_ADDR = 203.0.113.7\nHTTP_FORWARDED = for=192.0.2.60\nforwarded: for=_hidden\nHTTP_FORWARDED_FOR = 10.0.0.1";
+        assert_eq!(echoed_values(content), ["for=192.0.2.60", "for=_hidden"]);
+    }
+}
@@ -5,8 +5,10 @@ use hyper_util::rt::TokioExecutor;
 use rand::Rng;
 use std::collections::BTreeMap;
 
+pub mod forwarded;
 pub mod request;
 pub mod response;
+pub mod user_agents;
 
 pub fn random_useragent(random_value: bool) -> String {
     let name = option_env!("CARGO_PKG_NAME").unwrap_or("proxy-rs");
@@ -0,0 +1,125 @@
+//! User-Agent values rotated across check requests and, optionally, forwarded requests
+
+use std::{
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use parking_lot::Mutex;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::utils::error::{ProxyError, ProxyResult};
+
+/// How the next User-Agent is picked from the list
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Rotation {
+    /// Each value in turn, starting over after the last one
+    #[default]
+    RoundRobin,
+    /// A value picked at random every time
+    Random,
+}
+
+impl std::str::FromStr for Rotation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "round-robin" => Ok(Self::RoundRobin),
+            "random" => Ok(Self::Random),
+            other => Err(format!("unknown user-agent rotation: {}", other)),
+        }
+    }
+}
+
+/// A configured list of User-Agent values
+#[derive(Debug)]
+pub struct UserAgents {
+    agents: Vec<String>,
+    rotation: Rotation,
+    next: AtomicUsize,
+    /// Picks of the random rotation
+    rng: Mutex<StdRng>,
+}
+
+impl Default for UserAgents {
+    fn default() -> Self {
+        Self::new(vec![], Rotation::default())
+    }
+}
+
+impl UserAgents {
+    pub fn new(agents: Vec<String>, rotation: Rotation) -> Self {
+        Self {
+            agents,
+            rotation,
+            next: AtomicUsize::new(0),
+            rng: Mutex::new(StdRng::from_entropy()),
+        }
+    }
+
+    /// Make the random rotation reproducible
+    pub fn with_seed(self, seed: u64) -> Self {
+        *self.rng.lock() = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    /// Read one User-Agent per line, skipping blank lines and `#` comments
+    pub fn from_file(path: &Path, rotation: Rotation) -> ProxyResult<Self> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| ProxyError::Config(format!("Failed to read user agents from {}: {}", path.display(), e)))?;
+        let agents = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(String::from)
+            .collect();
+        Ok(Self::new(agents, rotation))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.agents.is_empty()
+    }
+
+    /// Next User-Agent to send, `None` when the list is empty
+    pub fn next(&self) -> Option<String> {
+        if self.agents.is_empty() {
+            return None;
+        }
+        let index = match self.rotation {
+            Rotation::RoundRobin => self.next.fetch_add(1, Ordering::Relaxed) % self.agents.len(),
+            Rotation::Random => self.rng.lock().gen_range(0..self.agents.len()),
+        };
+        Some(self.agents[index].clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_user_agents_from_file() {
+        let path = std::env::temp_dir().join(format!("proxy-rs-user-agents-{}.txt", std::process::id()));
+        std::fs::write(&path, "# Desktop browsers\nMozilla/5.0 (X11; Linux x86_64)\n\n  curl/8.5.0  \n").unwrap();
+        let agents = UserAgents::from_file(&path, Rotation::Random).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        for _ in 0..10 {
+            let agent = agents.next().unwrap();
+            assert!(["Mozilla/5.0 (X11; Linux x86_64)", "curl/8.5.0"].contains(&agent.as_str()));
+        }
+        assert!(UserAgents::default().next().is_none());
+    }
+
+    #[test]
+    fn test_seeded_random_rotation_repeats() {
+        let agents: Vec<String> = (0..10).map(|i| format!("agent-{}", i)).collect();
+        let picks = |seed| {
+            let agents = UserAgents::new(agents.clone(), Rotation::Random).with_seed(seed);
+            (0..20).map(|_| agents.next().unwrap()).collect::<Vec<_>>()
+        };
+        assert_eq!(picks(7), picks(7));
+        assert_ne!(picks(7), picks(8));
+    }
+}
@@ -0,0 +1,90 @@
+//! JSON lines logger for machine-parseable output
+
+use std::io::Write;
+
+use log::{LevelFilter, Log, Metadata, Record};
+use serde_json::json;
+
+/// Crate prefix whose records are emitted, everything else is filtered out
+const CRATE_TARGET: &str = "proxy_rs";
+
+/// Logger writing one JSON object per line to stderr
+pub struct JsonLogger {
+    level: LevelFilter,
+}
+
+impl JsonLogger {
+    pub fn new(level: LevelFilter) -> Self {
+        Self { level }
+    }
+
+    /// Install as the global logger
+    pub fn init(self) -> Result<(), log::SetLoggerError> {
+        log::set_max_level(self.level);
+        log::set_boxed_logger(Box::new(self))
+    }
+
+    /// Render a record as a single JSON line
+    pub fn format_record(record: &Record) -> String {
+        json!({
+            "ts": chrono::Utc::now().to_rfc3339(),
+            "level": record.level().to_string(),
+            "target": record.target(),
+            "msg": record.args().to_string(),
+        })
+        .to_string()
+    }
+}
+
+impl Log for JsonLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level && metadata.target().starts_with(CRATE_TARGET)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            let line = Self::format_record(record);
+            let _ = writeln!(std::io::stderr().lock(), "{}", line);
+        }
+    }
+
+    fn flush(&self) {
+        let _ = std::io::stderr().flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::Level;
+
+    #[test]
+    fn test_format_record_is_json() {
+        let line = JsonLogger::format_record(
+            &Record::builder()
+                .level(Level::Warn)
+                .target("proxy_rs::server")
+                .args(format_args!("pool has {} proxies", 3))
+                .build(),
+        );
+
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert!(value["ts"].is_string());
+        assert_eq!(value["level"], "WARN");
+        assert_eq!(value["target"], "proxy_rs::server");
+        assert_eq!(value["msg"], "pool has 3 proxies");
+        assert!(!line.contains('\n'));
+    }
+
+    #[test]
+    fn test_filters_other_crates() {
+        let logger = JsonLogger::new(LevelFilter::Info);
+        let own = Metadata::builder().level(Level::Info).target("proxy_rs::checker").build();
+        let other = Metadata::builder().level(Level::Info).target("hyper::proto").build();
+        let verbose = Metadata::builder().level(Level::Debug).target("proxy_rs::checker").build();
+
+        assert!(logger.enabled(&own));
+        assert!(!logger.enabled(&other));
+        assert!(!logger.enabled(&verbose));
+    }
+}
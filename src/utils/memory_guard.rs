@@ -0,0 +1,129 @@
+//! Soft memory ceiling turning high memory use into backpressure
+//!
+//! When the resident memory reported through `PerformanceMonitor::update_system_metrics`
+//! gets close to the ceiling, provider fetching pauses and the caches are trimmed until
+//! memory recedes.
+
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use crate::performance::PERFORMANCE_MONITOR;
+
+/// Share of the ceiling at which backpressure starts
+const PRESSURE_RATIO: f64 = 0.9;
+/// Share of the ceiling below which backpressure stops
+const RELIEF_RATIO: f64 = 0.75;
+/// How often a paused provider loop looks at the pressure again
+const PRESSURE_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+static UNDER_PRESSURE: AtomicBool = AtomicBool::new(false);
+
+/// Whether memory is currently close to the ceiling
+pub fn is_under_pressure() -> bool {
+    UNDER_PRESSURE.load(Ordering::Relaxed)
+}
+
+/// Wait while memory is under pressure
+pub async fn wait_for_memory() {
+    if !is_under_pressure() {
+        return;
+    }
+    log::info!("Memory close to its ceiling, pausing providers");
+    while is_under_pressure() && !crate::providers::is_fetching_stopped() {
+        tokio::time::sleep(PRESSURE_POLL_INTERVAL).await;
+    }
+    log::info!("Memory receded, resuming providers");
+}
+
+/// Soft ceiling on the resident memory of the process
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryGuard {
+    ceiling_bytes: u64,
+}
+
+impl MemoryGuard {
+    /// Guard for a ceiling in megabytes, `None` for 0 which disables it
+    pub fn new(ceiling_mb: u64) -> Option<Self> {
+        (ceiling_mb > 0).then(|| Self {
+            ceiling_bytes: ceiling_mb * 1024 * 1024,
+        })
+    }
+
+    /// Whether memory is under pressure after a reading of `usage_bytes`, given whether it
+    /// was before. Pressure starts near the ceiling and only stops well below it, so a
+    /// reading hovering around one threshold does not flap.
+    pub fn under_pressure(&self, usage_bytes: u64, was_under_pressure: bool) -> bool {
+        let threshold = if was_under_pressure { RELIEF_RATIO } else { PRESSURE_RATIO };
+        usage_bytes as f64 >= self.ceiling_bytes as f64 * threshold
+    }
+
+    /// Sample the memory every `interval`, publish it to the performance monitor and
+    /// update the pressure. `shrink` runs on every sample taken under pressure.
+    pub async fn watch<F, Fut>(self, interval: Duration, shrink: F)
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            if let Some(rss) = resident_memory() {
+                let system = PERFORMANCE_MONITOR.get_metrics().await.system_metrics;
+                PERFORMANCE_MONITOR
+                    .update_system_metrics(rss, system.cpu_usage_percent, system.active_connections, system.concurrent_operations)
+                    .await;
+            }
+            let usage = PERFORMANCE_MONITOR.get_metrics().await.system_metrics.memory_usage_bytes;
+
+            let was_under_pressure = is_under_pressure();
+            let pressure = self.under_pressure(usage, was_under_pressure);
+            UNDER_PRESSURE.store(pressure, Ordering::Relaxed);
+            if pressure && !was_under_pressure {
+                log::warn!(
+                    "Memory use {} MB is close to the {} MB ceiling, applying backpressure",
+                    usage / (1024 * 1024),
+                    self.ceiling_bytes / (1024 * 1024)
+                );
+            } else if !pressure && was_under_pressure {
+                log::info!("Memory use down to {} MB, backpressure lifted", usage / (1024 * 1024));
+            }
+            if pressure {
+                shrink().await;
+            }
+        }
+    }
+}
+
+/// Resident memory of the process, where the platform exposes it
+pub fn resident_memory() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pressure_follows_readings() {
+        assert_eq!(MemoryGuard::new(0), None);
+        let guard = MemoryGuard::new(100).unwrap();
+        let mb = |n: u64| n * 1024 * 1024;
+
+        // Rising memory: pressure starts at 90% of the ceiling
+        let mut pressure = false;
+        let mut states = vec![];
+        for reading in [50, 80, 89, 90, 120, 85, 76, 75, 74, 60, 89] {
+            pressure = guard.under_pressure(mb(reading), pressure);
+            states.push(pressure);
+        }
+        // It only lifts below 75%, and needs 90% again to come back
+        assert_eq!(
+            states,
+            vec![false, false, false, true, true, true, true, true, false, false, false]
+        );
+    }
+}
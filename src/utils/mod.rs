@@ -1,6 +1,10 @@
+pub mod cidr;
 pub mod error;
 pub mod geolite_database;
 pub mod http;
+pub mod logger;
+pub mod memory_guard;
+pub mod output_channel;
 pub mod resource_manager;
 pub mod serializer;
 pub mod shutdown;
@@ -0,0 +1,271 @@
+//! Bounded channel between the checkers and the output writer.
+//!
+//! With `Overflow::Block` a full channel makes producers wait like `mpsc::channel`.
+//! With `Overflow::DropOldest` the channel acts as a ring buffer: the oldest pending
+//! result is discarded so discovery keeps running behind a slow writer.
+//!
+//! Closing the receiver (explicitly or by dropping it) fails every pending and
+//! future send, so producers waiting on a full channel are released.
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use concurrent_queue::{ConcurrentQueue, PushError};
+use tokio::sync::Notify;
+
+/// What a producer does when the channel is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overflow {
+    /// Wait until the consumer makes room
+    #[default]
+    Block,
+    /// Discard the oldest pending item
+    DropOldest,
+}
+
+impl std::str::FromStr for Overflow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "block" => Ok(Self::Block),
+            "drop-oldest" => Ok(Self::DropOldest),
+            other => Err(format!("unknown overflow policy: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Shared<T> {
+    queue: ConcurrentQueue<T>,
+    overflow: Overflow,
+    dropped: AtomicUsize,
+    senders: AtomicUsize,
+    item_ready: Notify,
+    space_ready: Notify,
+}
+
+/// Returned when the receiver is gone
+#[derive(Debug, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+impl<T> std::fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "output channel closed")
+    }
+}
+
+impl<T: std::fmt::Debug> std::error::Error for SendError<T> {}
+
+#[derive(Debug)]
+pub struct OutputSender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+#[derive(Debug)]
+pub struct OutputReceiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// Create a channel holding up to `capacity` pending items
+pub fn output_channel<T>(capacity: usize, overflow: Overflow) -> (OutputSender<T>, OutputReceiver<T>) {
+    let shared = Arc::new(Shared {
+        queue: ConcurrentQueue::bounded(capacity.max(1)),
+        overflow,
+        dropped: AtomicUsize::new(0),
+        senders: AtomicUsize::new(1),
+        item_ready: Notify::new(),
+        space_ready: Notify::new(),
+    });
+    (
+        OutputSender {
+            shared: Arc::clone(&shared),
+        },
+        OutputReceiver { shared },
+    )
+}
+
+impl<T> OutputSender<T> {
+    pub async fn send(&self, mut item: T) -> Result<(), SendError<T>> {
+        loop {
+            let space_ready = self.shared.space_ready.notified();
+            let pushed = match self.shared.overflow {
+                Overflow::Block => self.shared.queue.push(item),
+                Overflow::DropOldest => match self.shared.queue.force_push(item) {
+                    Ok(evicted) => {
+                        if evicted.is_some() {
+                            let dropped = self.shared.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+                            if dropped == 1 || dropped.is_multiple_of(100) {
+                                log::warn!("Output is too slow, {} results dropped so far", dropped);
+                            }
+                        }
+                        Ok(())
+                    }
+                    Err(closed) => Err(PushError::Closed(closed.0)),
+                },
+            };
+            match pushed {
+                Ok(()) => {
+                    self.shared.item_ready.notify_one();
+                    return Ok(());
+                }
+                Err(PushError::Full(rejected)) => {
+                    item = rejected;
+                    space_ready.await;
+                }
+                Err(PushError::Closed(rejected)) => return Err(SendError(rejected)),
+            }
+        }
+    }
+
+    /// Whether the receiver stopped accepting items
+    pub fn is_closed(&self) -> bool {
+        self.shared.queue.is_closed()
+    }
+}
+
+impl<T> Clone for OutputSender<T> {
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Ordering::Relaxed);
+        Self {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<T> Drop for OutputSender<T> {
+    fn drop(&mut self) {
+        if self.shared.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.shared.item_ready.notify_one();
+        }
+    }
+}
+
+impl<T> OutputReceiver<T> {
+    /// Next item, or `None` once every sender is gone and the channel is drained
+    pub async fn recv(&mut self) -> Option<T> {
+        loop {
+            let item_ready = self.shared.item_ready.notified();
+            if let Ok(item) = self.shared.queue.pop() {
+                self.shared.space_ready.notify_one();
+                return Some(item);
+            }
+            if self.shared.senders.load(Ordering::Acquire) == 0 {
+                return None;
+            }
+            item_ready.await;
+        }
+    }
+
+    /// Number of items discarded to make room
+    pub fn dropped(&self) -> usize {
+        self.shared.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Stop accepting items, senders get an error from now on
+    pub fn close(&mut self) {
+        self.shared.queue.close();
+        self.shared.space_ready.notify_waiters();
+    }
+}
+
+impl<T> Drop for OutputReceiver<T> {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_drop_oldest_keeps_producers_running() {
+        let (tx, mut rx) = output_channel(4, Overflow::DropOldest);
+        let producers: Vec<_> = (0..4)
+            .map(|worker| {
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    for i in 0..25 {
+                        tx.send(worker * 100 + i).await.unwrap();
+                    }
+                })
+            })
+            .collect();
+
+        // Nothing is consumed while the producers run
+        for producer in producers {
+            tokio::time::timeout(Duration::from_secs(5), producer)
+                .await
+                .expect("producers blocked on a slow consumer")
+                .unwrap();
+        }
+        assert_eq!(rx.dropped(), 96);
+
+        drop(tx);
+        let mut received = vec![];
+        while let Some(item) = rx.recv().await {
+            received.push(item);
+        }
+        assert_eq!(received.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_block_waits_for_consumer() {
+        let (tx, mut rx) = output_channel(1, Overflow::Block);
+        tx.send(1).await.unwrap();
+        assert!(tokio::time::timeout(Duration::from_millis(50), tx.send(2)).await.is_err());
+
+        let producer = tokio::spawn(async move { tx.send(3).await });
+        assert_eq!(rx.recv().await, Some(1));
+        assert_eq!(rx.recv().await, Some(3));
+        producer.await.unwrap().unwrap();
+        assert_eq!(rx.recv().await, None);
+        assert_eq!(rx.dropped(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_close_stops_producers_after_limit() {
+        let limit = 3;
+        let (tx, mut rx) = output_channel(2, Overflow::Block);
+        let produced = Arc::new(AtomicUsize::new(0));
+        let producers: Vec<_> = (0..4)
+            .map(|_| {
+                let tx = tx.clone();
+                let produced = Arc::clone(&produced);
+                tokio::spawn(async move {
+                    while !tx.is_closed() {
+                        let item = produced.fetch_add(1, Ordering::SeqCst);
+                        if tx.send(item).await.is_err() {
+                            break;
+                        }
+                    }
+                })
+            })
+            .collect();
+        drop(tx);
+
+        let mut received = vec![];
+        while let Some(item) = rx.recv().await {
+            received.push(item);
+            if received.len() == limit {
+                rx.close();
+                break;
+            }
+        }
+        let stopped_at = produced.load(Ordering::SeqCst);
+
+        // Producers blocked on the full channel are released and stop right away
+        for producer in producers {
+            tokio::time::timeout(Duration::from_secs(5), producer)
+                .await
+                .expect("producer still running after close")
+                .unwrap();
+        }
+        assert_eq!(received.len(), limit);
+        assert!(produced.load(Ordering::SeqCst) <= stopped_at + 4);
+    }
+}
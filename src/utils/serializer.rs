@@ -1,4 +1,10 @@
-use serde::Serialize;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::providers::queue::ProxyQueue;
+use crate::proxy::Proxy;
+use crate::utils::error::ProxyError;
 
 #[derive(Debug, Serialize)]
 pub struct Geo {
@@ -27,6 +33,11 @@ pub struct ProxyData {
     pub types: Vec<ProxyType>,
     pub avg_resp_time: f64,
     pub error_rate: f64,
+    pub first_seen: chrono::DateTime<chrono::Utc>,
+    pub last_verified: Option<chrono::DateTime<chrono::Utc>>,
+    pub modifies_content: bool,
+    pub tags: Vec<String>,
+    pub degraded: bool,
 }
 
 #[derive(Debug, Serialize)]
@@ -34,3 +45,133 @@ pub struct ProxyType {
     pub proxy_type: String,
     pub level: Option<String>,
 }
+
+/// Proxy waiting in the check queue, as stored in a session snapshot
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueuedProxy {
+    pub host: String,
+    pub port: u16,
+    pub expected_types: Vec<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl From<&Proxy> for QueuedProxy {
+    fn from(proxy: &Proxy) -> Self {
+        Self {
+            host: proxy.host.clone(),
+            port: proxy.port,
+            expected_types: proxy.expected_types.clone(),
+            tags: proxy.tags.clone(),
+        }
+    }
+}
+
+/// Progress of a grab/find run, saved periodically so an interrupted run can be resumed
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SessionSnapshot {
+    pub proxies: Vec<QueuedProxy>,
+    /// Number of proxies already written to the output
+    pub output_count: usize,
+}
+
+impl SessionSnapshot {
+    /// Record the queued proxies without taking them out of the queue; proxies being
+    /// checked are not included.
+    pub fn capture(queue: &ProxyQueue, output_count: usize) -> Self {
+        Self {
+            proxies: queue.snapshot(),
+            output_count,
+        }
+    }
+
+    /// Write the snapshot, replacing the previous one atomically
+    pub async fn save(&self, path: &Path) -> Result<(), ProxyError> {
+        let content = serde_json::to_string(self)?;
+        let tmp_path = path.with_extension("tmp");
+        tokio::fs::write(&tmp_path, content)
+            .await
+            .map_err(|e| ProxyError::Config(format!("Failed to write session snapshot: {}", e)))?;
+        tokio::fs::rename(&tmp_path, path)
+            .await
+            .map_err(|e| ProxyError::Config(format!("Failed to write session snapshot: {}", e)))
+    }
+
+    /// Read a snapshot, a missing or corrupt file yields `None` so the run starts fresh
+    pub async fn load(path: &Path) -> Option<Self> {
+        let content = match tokio::fs::read_to_string(path).await {
+            Ok(content) => content,
+            Err(e) => {
+                log::warn!("Cannot read session snapshot {:?}: {}, starting fresh", path, e);
+                return None;
+            }
+        };
+        match serde_json::from_str(&content) {
+            Ok(snapshot) => Some(snapshot),
+            Err(e) => {
+                log::warn!("Corrupt session snapshot {:?}: {}, starting fresh", path, e);
+                None
+            }
+        }
+    }
+
+    /// Push the saved proxies back into `queue`, returns how many were restored
+    pub async fn restore(self, queue: &ProxyQueue) -> usize {
+        let mut restored = 0;
+        for queued in self.proxies {
+            crate::providers::UNIQUE_PROXIES
+                .write()
+                .await
+                .insert(format!("{}:{}", queued.host, queued.port));
+            if let Some(mut proxy) = Proxy::create(&queued.host, queued.port, queued.expected_types).await {
+                proxy.tags = queued.tags;
+                if queue.push(proxy).is_ok() {
+                    restored += 1;
+                }
+            }
+        }
+        restored
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_session_snapshot_round_trip() {
+        let path = std::env::temp_dir().join(format!("proxy-rs-session-{}.json", std::process::id()));
+        let queue = ProxyQueue::new();
+        for (host, port) in [("10.0.0.1", 8080), ("10.0.0.2", 3128)] {
+            let proxy = Proxy::create(host, port, vec!["HTTP".to_string()]).await.unwrap();
+            queue.push(proxy).unwrap();
+        }
+
+        let snapshot = SessionSnapshot::capture(&queue, 7);
+        assert_eq!(queue.len(), 2);
+        // A worker popping meanwhile sees the queue in its original order
+        assert_eq!(queue.pop().unwrap().host, "10.0.0.1");
+        assert_eq!(SessionSnapshot::capture(&queue, 7).proxies, snapshot.proxies[1..]);
+        snapshot.save(&path).await.unwrap();
+
+        while queue.pop().is_ok() {}
+        let loaded = SessionSnapshot::load(&path).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.output_count, 7);
+        assert_eq!(loaded.proxies, snapshot.proxies);
+        assert_eq!(loaded.restore(&queue).await, 2);
+
+        let restored = SessionSnapshot::capture(&queue, 7);
+        assert_eq!(restored.proxies, snapshot.proxies);
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_snapshot_starts_fresh() {
+        let path = std::env::temp_dir().join(format!("proxy-rs-session-corrupt-{}.json", std::process::id()));
+        std::fs::write(&path, "{not json").unwrap();
+        assert!(SessionSnapshot::load(&path).await.is_none());
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(SessionSnapshot::load(&path).await.is_none());
+    }
+}
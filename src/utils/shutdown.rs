@@ -260,6 +260,19 @@ pub async fn graceful_shutdown(reason: ShutdownReason) -> Result<(), Box<dyn std
     SHUTDOWN_MANAGER.shutdown(reason).await
 }
 
+/// Gracefully shutdown the application with `ShutdownReason::Timeout` once `delay` has elapsed
+pub fn shutdown_after(delay: Duration) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        tokio::time::sleep(delay).await;
+        info!("Maximum runtime of {:?} reached, initiating graceful shutdown", delay);
+        SHUTDOWN_TRIGGERED.store(true, Ordering::SeqCst);
+
+        if let Err(e) = graceful_shutdown(ShutdownReason::Timeout).await {
+            error!("Graceful shutdown failed: {}", e);
+        }
+    })
+}
+
 /// Macro for task registration with graceful shutdown
 #[macro_export]
 macro_rules! task_with_shutdown {
@@ -303,6 +316,22 @@ mod tests {
         assert!(matches!(received_reason, ShutdownReason::Maintenance));
     }
 
+    #[tokio::test]
+    async fn test_shutdown_after_delay() {
+        let mut shutdown_rx = register_for_shutdown("max_runtime_test".to_string()).await;
+
+        let started = std::time::Instant::now();
+        let timer = shutdown_after(Duration::from_millis(200));
+        sleep(Duration::from_millis(50)).await;
+        assert!(shutdown_rx.try_recv().is_err());
+
+        let reason = tokio::time::timeout(Duration::from_secs(2), shutdown_rx.recv()).await.unwrap().unwrap();
+        assert!(matches!(reason, ShutdownReason::Timeout));
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        timer.await.unwrap();
+        assert!(is_shutdown_triggered());
+    }
+
     #[tokio::test]
     async fn test_task_stats() {
         let manager = ShutdownManager::new(ShutdownConfig::default());